@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use crate::doc::compare;
+use crate::test::{Id, Suite, SuiteResult, Test, TestResult};
+
+/// Creates a suite result for the given tests without running them, tests
+/// without a result are left as not run.
+pub fn suite_result(results: &[(&str, Option<TestResult>)]) -> SuiteResult {
+    let mut suite = Suite::new();
+    for (id, _) in results {
+        suite.insert_matched(Test::new(Id::new(*id).unwrap()));
+    }
+
+    let mut result = SuiteResult::new(&suite);
+    for (id, test_result) in results {
+        if let Some(test_result) = test_result {
+            result.set_test_result(Id::new(*id).unwrap(), test_result.clone());
+        }
+    }
+
+    result
+}
+
+/// Creates a passing test result with the given duration.
+pub fn passed(duration: Duration) -> TestResult {
+    let mut result = TestResult::new();
+    result.set_passed_comparison();
+    result.set_duration(duration);
+    result
+}
+
+/// Creates a test result with the given duration which failed comparison on
+/// its first page.
+pub fn failed(duration: Duration) -> TestResult {
+    let mut result = TestResult::new();
+    result.set_failed_comparison(compare::Error {
+        output: 1,
+        reference: 1,
+        pages: vec![(0, compare::PageError::SimpleDeviations { deviations: 1 })],
+    });
+    result.set_duration(duration);
+    result
+}
@@ -1,6 +1,7 @@
 //! Reading and writing configuration from TOML files.
 
 use std::collections::BTreeMap;
+use std::path::Path;
 use std::{fs, io};
 
 use serde::{Deserialize, Serialize};
@@ -13,7 +14,25 @@ use crate::stdx::result::ResultEx;
 // overridable in local configs but still fail on duplicate definitions.
 
 /// All valid keys for this config.
-pub static KEYS: &[&str] = &["test-set"];
+pub static KEYS: &[&str] = &[
+    "test-sets",
+    "max-delta",
+    "max-deviation",
+    "shift-tolerance",
+    "object-store",
+    "exclude-template",
+    "doc-tests",
+    "test-script",
+    "ref-script",
+    "init-ignore",
+    "prepare",
+    "prepare-each",
+    "cleanup",
+    "cleanup-each",
+];
+
+/// Deprecated keys which are still accepted in place of a key in [`KEYS`].
+pub static KEY_ALIASES: &[(&str, &str)] = &[("test-set", "test-sets")];
 
 /// The key used to configure typst-test in the manifest tool config.
 pub const MANIFEST_TOOL_KEY: &str = crate::TOOL_NAME;
@@ -21,11 +40,44 @@ pub const MANIFEST_TOOL_KEY: &str = crate::TOOL_NAME;
 /// The directory name for in which the user config can be found.
 pub const CONFIG_SUB_DIRECTORY: &str = crate::TOOL_NAME;
 
+/// The name of the project config file, this is looked up in the project root.
+pub const PROJECT_CONFIG_FILE: &str = "typst-test.toml";
+
+/// The kind of a [`ConfigLayer`], this is used to report where a value was
+/// configured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Layer {
+    /// Supplied at runtime from the command line, envars or other means.
+    Override,
+
+    /// Found in the project config file (`typst-test.toml`).
+    Project,
+
+    /// Found in the tool section of the manifest (`typst.toml`).
+    Manifest,
+
+    /// Found in the user config directory.
+    User,
+}
+
+impl Layer {
+    /// Returns a kebab-case string representing this layer.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Layer::Override => "override",
+            Layer::Project => "project",
+            Layer::Manifest => "manifest",
+            Layer::User => "user",
+        }
+    }
+}
+
 /// A set of config layers used to retrieve options, configs are looked up in
 /// the following order:
 /// - `override`: supplied at runtime from the command line, envars or other
 ///   means
-/// - `project`: found in the typst.toml manifest
+/// - `project`: found in the `typst-test.toml` file in the project root
+/// - `manifest`: found in the tool section of the typst.toml manifest
 /// - `user`: found in a user config directory
 ///
 /// If none of these configs contain a setting the default is used.
@@ -37,6 +89,9 @@ pub struct Config {
     /// The project config.
     pub project: Option<ConfigLayer>,
 
+    /// The manifest config.
+    pub manifest: Option<ConfigLayer>,
+
     /// The user config.
     pub user: Option<ConfigLayer>,
 }
@@ -47,9 +102,60 @@ impl Config {
         Self {
             override_,
             project: None,
+            manifest: None,
             user: None,
         }
     }
+
+    /// Returns all existing layers in order of precedence.
+    pub fn layers(&self) -> impl Iterator<Item = (Layer, &ConfigLayer)> {
+        [
+            (Layer::Override, &self.override_),
+            (Layer::Project, &self.project),
+            (Layer::Manifest, &self.manifest),
+            (Layer::User, &self.user),
+        ]
+        .into_iter()
+        .filter_map(|(kind, layer)| layer.as_ref().map(|layer| (kind, layer)))
+    }
+
+    /// Resolves a value by returning the first value found by `f` in the
+    /// layers in order of precedence together with the layer it was found in.
+    ///
+    /// # Examples
+    /// ```
+    /// # use std::collections::BTreeMap;
+    /// # use typst_test_lib::config::{Config, ConfigLayer, Layer};
+    /// let mut config = Config::new(None);
+    /// config.user = Some(ConfigLayer {
+    ///     test_sets: Some(BTreeMap::from([("a".into(), "all()".into())])),
+    ///     ..Default::default()
+    /// });
+    ///
+    /// let (_, layer) = config.resolve(|c| c.test_sets.as_ref()).unwrap();
+    /// assert_eq!(layer, Layer::User);
+    /// ```
+    pub fn resolve<'a, T, F>(&'a self, f: F) -> Option<(&'a T, Layer)>
+    where
+        T: ?Sized,
+        F: Fn(&'a ConfigLayer) -> Option<&'a T>,
+    {
+        self.layers()
+            .find_map(|(kind, layer)| f(layer).map(|value| (value, kind)))
+    }
+
+    /// Merges the test set aliases of all layers, an alias in a layer of
+    /// higher precedence overrides an alias of the same name in lower layers.
+    pub fn test_sets(&self) -> BTreeMap<&str, &str> {
+        let mut test_sets = BTreeMap::new();
+        for (_, layer) in self.layers() {
+            for (name, expr) in layer.test_sets.iter().flatten() {
+                test_sets.entry(name.as_str()).or_insert(expr.as_str());
+            }
+        }
+
+        test_sets
+    }
 }
 
 /// A single layer within all configs, a set of values which can be
@@ -59,7 +165,67 @@ impl Config {
 #[serde(rename_all = "kebab-case")]
 pub struct ConfigLayer {
     /// Custom test set definitions.
+    #[serde(alias = "test-set")]
     pub test_sets: Option<BTreeMap<String, String>>,
+
+    /// The default maximum delta in each channel of a pixel used for
+    /// comparisons.
+    pub max_delta: Option<u8>,
+
+    /// The default maximum amount of deviations per page used for
+    /// comparisons.
+    pub max_deviation: Option<usize>,
+
+    /// The default radius in pixels within which shifted content is tolerated
+    /// used for comparisons.
+    pub shift_tolerance: Option<u32>,
+
+    /// Whether to store persistent references by content hash in a shared
+    /// object directory to deduplicate identical pages.
+    pub object_store: Option<bool>,
+
+    /// Whether to exclude tests within the template directory of a template
+    /// package from collection, defaults to `true`.
+    pub exclude_template: Option<bool>,
+
+    /// The markdown files relative to the project root from which typst code
+    /// fences are extracted and compiled as doc tests.
+    pub doc_tests: Option<Vec<String>>,
+
+    /// The file name of test scripts, defaults to `test.typ`.
+    pub test_script: Option<String>,
+
+    /// The file name of reference scripts, defaults to `ref.typ`.
+    pub ref_script: Option<String>,
+
+    /// Additional directories relative to the project root which are ignored
+    /// in the vcs when initializing the test suite.
+    pub init_ignore: Option<Vec<String>>,
+
+    /// A hook to run once before all tests.
+    pub prepare: Option<HookConfig>,
+
+    /// A hook to run before each test.
+    pub prepare_each: Option<HookConfig>,
+
+    /// A hook to run once after all tests.
+    pub cleanup: Option<HookConfig>,
+
+    /// A hook to run after each test.
+    pub cleanup_each: Option<HookConfig>,
+}
+
+/// A hook command, either the path of an executable relative to the project
+/// root, or a program and its arguments which is run without a shell.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum HookConfig {
+    /// The path of an executable relative to the project root.
+    Path(String),
+
+    /// A program followed by its arguments, the program is looked up like a
+    /// command in a shell.
+    Args(Vec<String>),
 }
 
 impl ConfigLayer {
@@ -73,13 +239,25 @@ impl ConfigLayer {
         };
 
         let config = config_dir.join(CONFIG_SUB_DIRECTORY).join("config.toml");
-        let Some(content) =
-            fs::read_to_string(config).ignore(|err| err.kind() == io::ErrorKind::NotFound)?
+        let Some(content) = fs::read_to_string(config).ignore_io_kind(io::ErrorKind::NotFound)?
+        else {
+            return Ok(None);
+        };
+
+        Self::parse(toml::from_str(&content)?).map(Some)
+    }
+
+    /// Reads the project config file in the given project root.
+    ///
+    /// See [`PROJECT_CONFIG_FILE`].
+    pub fn collect_project<P: AsRef<Path>>(root: P) -> Result<Option<Self>, ReadError> {
+        let config = root.as_ref().join(PROJECT_CONFIG_FILE);
+        let Some(content) = fs::read_to_string(config).ignore_io_kind(io::ErrorKind::NotFound)?
         else {
             return Ok(None);
         };
 
-        Ok(toml::from_str(&content)?)
+        Self::parse(toml::from_str(&content)?).map(Some)
     }
 
     /// Parses a config from the tool section of a manifest.
@@ -88,16 +266,53 @@ impl ConfigLayer {
             return Ok(None);
         };
 
-        Self::deserialize(section.clone())
-            .map(Some)
-            .map_err(ReadError::Toml)
+        Self::parse(section.clone()).map(Some)
     }
+
+    /// Parses a config from the given table, returns an error with a
+    /// suggestion for the closest valid key if it contains an unknown key.
+    pub fn parse(table: toml::Table) -> Result<Self, ReadError> {
+        if let Some(key) = table.keys().find(|key| {
+            !KEYS.contains(&key.as_str()) && !KEY_ALIASES.iter().any(|(alias, _)| alias == key)
+        }) {
+            return Err(ReadError::UnknownKey {
+                similar: find_similar_key(key),
+                key: key.clone(),
+            });
+        }
+
+        Ok(Self::deserialize(table)?)
+    }
+}
+
+/// Returns the valid key most similar to the given unknown key, if any is
+/// similar enough.
+fn find_similar_key(key: &str) -> Option<&'static str> {
+    KEYS.iter()
+        .map(|&cand| (cand, strsim::jaro(key, cand)))
+        .filter(|(_, score)| *score > 0.7)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(cand, _)| cand)
 }
 
-/// Returned by [`ConfigLayer::collect_user`] and
-/// [`ConfigLayer::from_manifest`].
+/// Returned by [`ConfigLayer::collect_user`],
+/// [`ConfigLayer::collect_project`], [`ConfigLayer::from_manifest`] and
+/// [`ConfigLayer::parse`].
 #[derive(Debug, Error)]
 pub enum ReadError {
+    /// The config contained an unknown key.
+    #[error(
+        "unknown config key {key:?}{}",
+        .similar.map(|s| format!(", did you mean {s:?}?")).unwrap_or_default()
+    )]
+    UnknownKey {
+        /// The unknown key.
+        key: String,
+
+        /// The valid key most similar to the unknown key, if any.
+        similar: Option<&'static str>,
+    },
+
     /// The given key is not valid or the config.
     #[error("a toml parsing error occurred")]
     Toml(#[from] toml::de::Error),
@@ -106,3 +321,125 @@ pub enum ReadError {
     #[error("an io error occurred")]
     Io(#[from] io::Error),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(test_set: &str) -> Option<ConfigLayer> {
+        Some(ConfigLayer {
+            test_sets: Some(BTreeMap::from([("a".into(), test_set.into())])),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_resolve_precedence() {
+        let mut config = Config::new(None);
+        assert_eq!(config.resolve(|c| c.test_sets.as_ref()), None);
+
+        config.user = layer("user");
+        config.manifest = layer("manifest");
+        let (value, layer_kind) = config.resolve(|c| c.test_sets.as_ref()).unwrap();
+        assert_eq!(value["a"], "manifest");
+        assert_eq!(layer_kind, Layer::Manifest);
+
+        config.project = layer("project");
+        let (value, layer_kind) = config.resolve(|c| c.test_sets.as_ref()).unwrap();
+        assert_eq!(value["a"], "project");
+        assert_eq!(layer_kind, Layer::Project);
+
+        config.override_ = layer("override");
+        let (value, layer_kind) = config.resolve(|c| c.test_sets.as_ref()).unwrap();
+        assert_eq!(value["a"], "override");
+        assert_eq!(layer_kind, Layer::Override);
+    }
+
+    #[test]
+    fn test_test_sets_merged() {
+        let mut config = Config::new(None);
+        assert!(config.test_sets().is_empty());
+
+        config.user = Some(ConfigLayer {
+            test_sets: Some(BTreeMap::from([
+                ("a".into(), "user".into()),
+                ("b".into(), "user".into()),
+            ])),
+            ..Default::default()
+        });
+        config.project = layer("project");
+
+        assert_eq!(
+            config.test_sets(),
+            BTreeMap::from([("a", "project"), ("b", "user")])
+        );
+    }
+
+    #[test]
+    fn test_hook_forms() {
+        let layer: ConfigLayer = toml::from_str(
+            r#"
+            prepare = "scripts/setup.sh"
+            cleanup-each = ["python", "scripts/clean.py"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            layer.prepare,
+            Some(HookConfig::Path("scripts/setup.sh".into()))
+        );
+        assert_eq!(
+            layer.cleanup_each,
+            Some(HookConfig::Args(vec![
+                "python".into(),
+                "scripts/clean.py".into()
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_parse_unknown_key() {
+        let table = |content: &str| toml::from_str::<toml::Table>(content).unwrap();
+
+        let err = ConfigLayer::parse(table("exclude-tempalte = false")).unwrap_err();
+        assert!(matches!(
+            &err,
+            ReadError::UnknownKey { key, similar: Some("exclude-template") }
+                if key == "exclude-tempalte"
+        ));
+        assert_eq!(
+            err.to_string(),
+            r#"unknown config key "exclude-tempalte", did you mean "exclude-template"?"#
+        );
+
+        let err = ConfigLayer::parse(table("zzz = 1")).unwrap_err();
+        assert!(matches!(err, ReadError::UnknownKey { similar: None, .. }));
+        assert_eq!(err.to_string(), r#"unknown config key "zzz""#);
+
+        let layer = ConfigLayer::parse(table("max-delta = 3")).unwrap();
+        assert_eq!(layer.max_delta, Some(3));
+    }
+
+    #[test]
+    fn test_parse_key_alias() {
+        let table = toml::from_str::<toml::Table>("[test-set]\na = 'all()'").unwrap();
+
+        let layer = ConfigLayer::parse(table).unwrap();
+        assert_eq!(
+            layer.test_sets,
+            Some(BTreeMap::from([("a".into(), "all()".into())]))
+        );
+    }
+
+    #[test]
+    fn test_resolve_skips_unset() {
+        let mut config = Config::new(None);
+        config.project = Some(ConfigLayer::default());
+        config.user = layer("user");
+
+        let (value, layer_kind) = config.resolve(|c| c.test_sets.as_ref()).unwrap();
+        assert_eq!(value["a"], "user");
+        assert_eq!(layer_kind, Layer::User);
+    }
+}
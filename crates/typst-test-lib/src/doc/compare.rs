@@ -3,7 +3,7 @@
 use std::fmt::{Debug, Display};
 
 use thiserror::Error;
-use tiny_skia::Pixmap;
+use tiny_skia::{Pixmap, PremultipliedColorU8};
 
 use crate::stdx;
 use crate::stdx::fmt::Term;
@@ -24,6 +24,31 @@ impl Display for Size {
     }
 }
 
+/// A rectangular region of a page in pixels, this is used to exclude regions
+/// of a page from comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Region {
+    /// The horizontal offset of the region from the left edge of the page.
+    pub x: u32,
+
+    /// The vertical offset of the region from the top edge of the page.
+    pub y: u32,
+
+    /// The width of the region.
+    pub width: u32,
+
+    /// The height of the region.
+    pub height: u32,
+}
+
+impl Region {
+    /// Whether the given pixel lies within this region.
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        (self.x..self.x.saturating_add(self.width)).contains(&x)
+            && (self.y..self.y.saturating_add(self.height)).contains(&y)
+    }
+}
+
 /// The strategy to use for visual comparison.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Strategy {
@@ -39,6 +64,43 @@ pub enum Strategy {
         /// accordance to `max_delta` before two pages are considered different.
         max_deviation: usize,
     },
+
+    /// Like [`Strategy::Simple`], but a deviating pixel is not counted if a
+    /// matching pixel is found within the given radius in the reference. This
+    /// tolerates content being shifted by a few pixels.
+    Shift {
+        /// The maximum allowed difference between a channel of two pixels
+        /// before the pixel is considered different, see
+        /// [`Strategy::Simple`].
+        max_delta: u8,
+
+        /// The maximum allowed amount of pixels that can differ per page, see
+        /// [`Strategy::Simple`].
+        max_deviation: usize,
+
+        /// The maximum distance in pixels in each direction which is searched
+        /// for a matching reference pixel.
+        radius: u32,
+    },
+}
+
+impl Strategy {
+    /// Creates a new strategy, if the shift radius is `0` this is a
+    /// [`Strategy::Simple`] strategy, otherwise [`Strategy::Shift`].
+    pub fn new(max_delta: u8, max_deviation: usize, radius: u32) -> Self {
+        if radius == 0 {
+            Self::Simple {
+                max_delta,
+                max_deviation,
+            }
+        } else {
+            Self::Shift {
+                max_delta,
+                max_deviation,
+                radius,
+            }
+        }
+    }
 }
 
 impl Default for Strategy {
@@ -52,20 +114,72 @@ impl Default for Strategy {
 
 /// Compares two pages individually using the given strategy.
 pub fn page(output: &Pixmap, reference: &Pixmap, strategy: Strategy) -> Result<(), PageError> {
+    page_masked(output, reference, strategy, &[])
+}
+
+/// Compares two pages individually using the given strategy, pixels within the
+/// given masks are excluded from the comparison.
+pub fn page_masked(
+    output: &Pixmap,
+    reference: &Pixmap,
+    strategy: Strategy,
+    masks: &[Region],
+) -> Result<(), PageError> {
     match strategy {
         Strategy::Simple {
             max_delta,
             max_deviation,
-        } => page_simple(output, reference, max_delta, max_deviation),
+        } => page_simple(output, reference, max_delta, max_deviation, 0, masks),
+        Strategy::Shift {
+            max_delta,
+            max_deviation,
+            radius,
+        } => page_simple(output, reference, max_delta, max_deviation, radius, masks),
     }
 }
 
-/// Compares two pages individually using [`Strategy::Simple`].
+/// Returns the fraction of pixels which differ between two pages in the range
+/// `0.0..=1.0`. Pages with different dimensions are aligned at their top left
+/// corner, pixels outside of the overlapping area are considered different.
+pub fn page_change(output: &Pixmap, reference: &Pixmap) -> f64 {
+    let width = Ord::max(output.width(), reference.width()) as usize;
+    let height = Ord::max(output.height(), reference.height()) as usize;
+
+    let total = width * height;
+    if total == 0 {
+        return 0.0;
+    }
+
+    let overlap_width = Ord::min(output.width(), reference.width()) as usize;
+    let overlap_height = Ord::min(output.height(), reference.height()) as usize;
+
+    let row = |page: &Pixmap, y: usize| {
+        let start = y * page.width() as usize;
+        &page.pixels()[start..start + overlap_width]
+    };
+
+    let changed_overlap = (0..overlap_height)
+        .map(|y| {
+            Iterator::zip(row(output, y).iter(), row(reference, y).iter())
+                .filter(|(a, b)| a != b)
+                .count()
+        })
+        .sum::<usize>();
+
+    let outside = total - overlap_width * overlap_height;
+
+    (changed_overlap + outside) as f64 / total as f64
+}
+
+/// Compares two pages individually using [`Strategy::Simple`], or
+/// [`Strategy::Shift`] if the radius is not `0`.
 fn page_simple(
     output: &Pixmap,
     reference: &Pixmap,
     max_delta: u8,
     max_deviation: usize,
+    radius: u32,
+    masks: &[Region],
 ) -> Result<(), PageError> {
     if output.width() != reference.width() || output.height() != reference.height() {
         return Err(PageError::Dimensions {
@@ -80,13 +194,27 @@ fn page_simple(
         });
     }
 
+    let width = output.width() as usize;
+    let position = |idx: usize| ((idx % width) as u32, (idx / width) as u32);
+    let is_masked = |idx: usize| {
+        let (x, y) = position(idx);
+        masks.iter().any(|mask| mask.contains(x, y))
+    };
+
+    // NOTE(tinger): the neighborhoods are only searched for pixels which
+    // already deviate, so unshifted pages are compared as fast as before, both
+    // directions are checked to catch content missing from either page
+    let is_shifted = |idx: usize, a: &PremultipliedColorU8, b: &PremultipliedColorU8| {
+        let (x, y) = position(idx);
+        has_match_near(reference, x, y, radius, a, max_delta)
+            && has_match_near(output, x, y, radius, b, max_delta)
+    };
+
     let deviations = Iterator::zip(output.pixels().iter(), reference.pixels().iter())
-        .filter(|(a, b)| {
-            u8::abs_diff(a.red(), b.red()) > max_delta
-                || u8::abs_diff(a.green(), b.green()) > max_delta
-                || u8::abs_diff(a.blue(), b.blue()) > max_delta
-                || u8::abs_diff(a.alpha(), b.alpha()) > max_delta
-        })
+        .enumerate()
+        .filter(|&(idx, _)| masks.is_empty() || !is_masked(idx))
+        .filter(|(_, (a, b))| is_deviation(a, b, max_delta))
+        .filter(|&(idx, (a, b))| radius == 0 || !is_shifted(idx, a, b))
         .count();
 
     if deviations > max_deviation {
@@ -96,6 +224,32 @@ fn page_simple(
     Ok(())
 }
 
+/// Whether the given pixels differ by more than the given delta in any channel.
+fn is_deviation(a: &PremultipliedColorU8, b: &PremultipliedColorU8, max_delta: u8) -> bool {
+    u8::abs_diff(a.red(), b.red()) > max_delta
+        || u8::abs_diff(a.green(), b.green()) > max_delta
+        || u8::abs_diff(a.blue(), b.blue()) > max_delta
+        || u8::abs_diff(a.alpha(), b.alpha()) > max_delta
+}
+
+/// Whether the given page contains a pixel matching the given pixel within the
+/// given radius around the given position.
+fn has_match_near(
+    page: &Pixmap,
+    x: u32,
+    y: u32,
+    radius: u32,
+    pixel: &PremultipliedColorU8,
+    max_delta: u8,
+) -> bool {
+    let xs = x.saturating_sub(radius)..=Ord::min(x.saturating_add(radius), page.width() - 1);
+    let ys = y.saturating_sub(radius)..=Ord::min(y.saturating_add(radius), page.height() - 1);
+
+    ys.flat_map(|y| xs.clone().map(move |x| (x, y)))
+        .filter_map(|(x, y)| page.pixel(x, y))
+        .any(|other| !is_deviation(pixel, &other, max_delta))
+}
+
 /// An error describing why a document comparison failed.
 #[derive(Debug, Clone, Error)]
 pub struct Error {
@@ -109,6 +263,42 @@ pub struct Error {
     pub pages: Vec<(usize, PageError)>,
 }
 
+impl Error {
+    /// Returns the scale factor between the output and reference pages if the
+    /// page counts match and all failed pages differ only in their dimensions
+    /// by the same factor, allowing for one pixel of rounding.
+    ///
+    /// This usually means that the references were rendered at a different
+    /// pixel per inch than the output.
+    pub fn uniform_scale(&self) -> Option<f64> {
+        if self.output != self.reference || self.pages.is_empty() {
+            return None;
+        }
+
+        let mut scale = None;
+        for (_, error) in &self.pages {
+            let PageError::Dimensions { output, reference } = error else {
+                return None;
+            };
+
+            if reference.width == 0 || reference.height == 0 {
+                return None;
+            }
+
+            let page_scale = *scale.get_or_insert(output.width as f64 / reference.width as f64);
+            let fits = |output: u32, reference: u32| {
+                (output as f64 - reference as f64 * page_scale).abs() <= 1.0
+            };
+
+            if !fits(output.width, reference.width) || !fits(output.height, reference.height) {
+                return None;
+            }
+        }
+
+        scale.filter(|&scale| (scale - 1.0).abs() > 0.01)
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.output != self.reference {
@@ -150,7 +340,8 @@ pub enum PageError {
         reference: Size,
     },
 
-    /// The pages differed according to [`Strategy::Simple`].
+    /// The pages differed according to [`Strategy::Simple`] or
+    /// [`Strategy::Shift`].
     #[error(
         "content differed in at least {} {}",
         deviations,
@@ -165,8 +356,6 @@ pub enum PageError {
 
 #[cfg(test)]
 mod tests {
-    use tiny_skia::PremultipliedColorU8;
-
     use super::*;
 
     fn images() -> [Pixmap; 2] {
@@ -182,6 +371,16 @@ mod tests {
         [a, b]
     }
 
+    #[test]
+    fn test_page_change() {
+        let [a, b] = images();
+        assert_eq!(page_change(&a, &a), 0.0);
+        assert_eq!(page_change(&a, &b), 0.4);
+        assert_eq!(page_change(&a, &Pixmap::new(5, 1).unwrap()), 0.5);
+        assert_eq!(page_change(&b, &Pixmap::new(5, 1).unwrap()), 0.9);
+        assert_eq!(page_change(&Pixmap::new(10, 2).unwrap(), &a), 0.5);
+    }
+
     #[test]
     fn test_page_simple_below_max_delta() {
         let [a, b] = images();
@@ -225,4 +424,116 @@ mod tests {
             Err(PageError::SimpleDeviations { deviations: 4 })
         ))
     }
+
+    #[test]
+    fn test_error_uniform_scale() {
+        let dimensions = |output: (u32, u32), reference: (u32, u32)| PageError::Dimensions {
+            output: Size {
+                width: output.0,
+                height: output.1,
+            },
+            reference: Size {
+                width: reference.0,
+                height: reference.1,
+            },
+        };
+        let error = |pages: Vec<PageError>| Error {
+            output: 2,
+            reference: 2,
+            pages: pages.into_iter().enumerate().collect(),
+        };
+
+        assert_eq!(
+            error(vec![
+                dimensions((1190, 1684), (595, 842)),
+                dimensions((1191, 1684), (595, 842)),
+            ])
+            .uniform_scale(),
+            Some(2.0),
+        );
+
+        // different factors per axis
+        assert_eq!(
+            error(vec![dimensions((1190, 842), (595, 842))]).uniform_scale(),
+            None,
+        );
+
+        // content failures are not caused by ppi
+        assert_eq!(
+            error(vec![
+                dimensions((1190, 1684), (595, 842)),
+                PageError::SimpleDeviations { deviations: 1 },
+            ])
+            .uniform_scale(),
+            None,
+        );
+
+        // page count mismatch
+        assert_eq!(
+            Error {
+                output: 1,
+                reference: 2,
+                pages: vec![(0, dimensions((1190, 1684), (595, 842)))],
+            }
+            .uniform_scale(),
+            None,
+        );
+    }
+
+    #[test]
+    fn test_page_masked() {
+        let [a, b] = images();
+        let strict = Strategy::default();
+
+        let covering = Region {
+            x: 0,
+            y: 0,
+            width: 4,
+            height: 1,
+        };
+        assert!(page_masked(&a, &b, strict, &[covering]).is_ok());
+
+        let partial = Region {
+            x: 2,
+            y: 0,
+            width: 8,
+            height: 1,
+        };
+        assert!(matches!(
+            page_masked(&a, &b, strict, &[partial]),
+            Err(PageError::SimpleDeviations { deviations: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_page_shift() {
+        let black = PremultipliedColorU8::from_rgba(0, 0, 0, 255).unwrap();
+        let line = |x: usize| {
+            let mut page = Pixmap::new(10, 3).unwrap();
+            for y in 0..3 {
+                page.pixels_mut()[y * 10 + x] = black;
+            }
+            page
+        };
+
+        let (a, b) = (line(4), line(5));
+
+        assert!(matches!(
+            page(&a, &b, Strategy::new(0, 0, 0)),
+            Err(PageError::SimpleDeviations { deviations: 6 })
+        ));
+        assert!(page(&a, &b, Strategy::new(0, 0, 1)).is_ok());
+
+        // shifts beyond the radius are still deviations
+        assert!(matches!(
+            page(&a, &line(7), Strategy::new(0, 0, 1)),
+            Err(PageError::SimpleDeviations { deviations: 6 })
+        ));
+
+        // missing content is not hidden by matching background pixels
+        assert!(matches!(
+            page(&Pixmap::new(10, 3).unwrap(), &b, Strategy::new(0, 0, 1)),
+            Err(PageError::SimpleDeviations { deviations: 3 })
+        ));
+    }
 }
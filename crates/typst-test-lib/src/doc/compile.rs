@@ -1,17 +1,20 @@
 //! Wrappers around [`typst::compile`] for easier error handling.
 
+use std::collections::BTreeSet;
 use std::fmt::Debug;
 
-use ecow::EcoVec;
+use ecow::{eco_format, EcoString, EcoVec};
 use thiserror::Error;
-use typst::diag::{FileResult, SourceDiagnostic, Warned};
-use typst::foundations::{Bytes, Datetime};
+use typst::diag::{FileResult, Severity, SourceDiagnostic, Warned};
+use typst::foundations::{Bytes, Datetime, Dict, IntoValue};
+use typst::layout::{Frame, FrameItem};
 use typst::model::Document;
-use typst::syntax::{FileId, Source};
+use typst::syntax::{FileId, Source, Span};
 use typst::text::{Font, FontBook};
 use typst::utils::LazyHash;
 use typst::{Library, World};
 
+use crate::library::augmented_library;
 use crate::stdx::fmt::Term;
 
 /// An error which may occur during compilation. This struct only exists to
@@ -20,6 +23,109 @@ use crate::stdx::fmt::Term;
 #[error("compilation failed with {} {}", .0.len(), Term::simple("error").with(.0.len()))]
 pub struct Error(pub EcoVec<SourceDiagnostic>);
 
+/// Whether the given diagnostic is a warning about an unknown font family, in
+/// which case typst silently falls back to another font.
+pub fn is_unknown_font_warning(diagnostic: &SourceDiagnostic) -> bool {
+    diagnostic.severity == Severity::Warning
+        && diagnostic.message.starts_with("unknown font family")
+}
+
+/// The message prefix of warnings created by [`font_fallback_warning`].
+const FONT_FALLBACK_MESSAGE: &str = "font fallback was used for";
+
+/// Whether the given diagnostic is a warning about font fallback, either
+/// because of an unknown font family or because of characters which are not
+/// covered by the requested font families, see [`fallback_chars`].
+pub fn is_font_fallback_warning(diagnostic: &SourceDiagnostic) -> bool {
+    is_unknown_font_warning(diagnostic)
+        || (diagnostic.severity == Severity::Warning
+            && diagnostic.message.starts_with(FONT_FALLBACK_MESSAGE))
+}
+
+/// Creates a warning about font fallback for the given characters.
+pub fn font_fallback_warning(chars: &BTreeSet<char>) -> SourceDiagnostic {
+    let chars = chars
+        .iter()
+        .map(|c| format!("{c:?}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    SourceDiagnostic::warning(
+        Span::detached(),
+        eco_format!("{FONT_FALLBACK_MESSAGE} {chars}"),
+    )
+    .with_hint("none of the requested font families contain glyphs for these characters")
+}
+
+/// Compiles the source again with font fallback disabled and collects the
+/// characters which were shaped with a fallback font in the given document,
+/// i.e. those which none of the requested font families cover. Characters
+/// which no font covers at all are not included.
+pub fn fallback_chars(source: &Source, world: &dyn World, document: &Document) -> BTreeSet<char> {
+    let strict = Source::new(
+        source.id(),
+        format!("#set text(fallback: false)\n{}", source.text()),
+    );
+
+    let Ok(strict) = compile(strict, world).output else {
+        return BTreeSet::new();
+    };
+
+    let missing = missing_chars(document);
+    missing_chars(&strict)
+        .difference(&missing)
+        .copied()
+        .collect()
+}
+
+/// Collects the characters of all glyphs in the given document which were
+/// shaped without any font covering them.
+fn missing_chars(document: &Document) -> BTreeSet<char> {
+    fn collect(frame: &Frame, chars: &mut BTreeSet<char>) {
+        for (_, item) in frame.items() {
+            match item {
+                FrameItem::Group(group) => collect(&group.frame, chars),
+                FrameItem::Text(text) => {
+                    for glyph in text.glyphs.iter().filter(|glyph| glyph.id == 0) {
+                        chars.extend(text.text[glyph.range()].chars());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut chars = BTreeSet::new();
+    for page in &document.pages {
+        collect(&page.frame, &mut chars);
+    }
+
+    chars
+}
+
+/// Collects the families of all fonts used in the given document, this can be
+/// used to find out which fonts were substituted for unknown font families.
+pub fn used_font_families(document: &Document) -> BTreeSet<EcoString> {
+    fn collect(frame: &Frame, families: &mut BTreeSet<EcoString>) {
+        for (_, item) in frame.items() {
+            match item {
+                FrameItem::Group(group) => collect(&group.frame, families),
+                FrameItem::Text(text) => {
+                    families.insert(text.font.info().family.as_str().into());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut families = BTreeSet::new();
+    for page in &document.pages {
+        collect(&page.frame, &mut families);
+    }
+
+    families
+}
+
 /// Compiles a source with the given global world.
 pub fn compile(source: Source, world: &dyn World) -> Warned<Result<Document, Error>> {
     struct TestWorldAdapter<'s, 'w> {
@@ -72,6 +178,59 @@ pub fn compile(source: Source, world: &dyn World) -> Warned<Result<Document, Err
     }
 }
 
+/// A world which delegates to another world, but additionally sets the
+/// `variant` key in `sys.inputs` for compiling a specific test variant.
+pub struct VariantWorld<'w> {
+    /// The world to delegate to.
+    world: &'w dyn World,
+    /// Typst's standard library with the variant input.
+    library: LazyHash<Library>,
+}
+
+impl<'w> VariantWorld<'w> {
+    /// Create a new world for the given variant, the given inputs should be
+    /// those of the delegate world, the variant is added to them.
+    pub fn new(world: &'w dyn World, inputs: &Dict, variant: &str) -> Self {
+        let mut inputs = inputs.clone();
+        inputs.insert("variant".into(), variant.into_value());
+
+        Self {
+            world,
+            library: LazyHash::new(augmented_library(|builder| builder.with_inputs(inputs))),
+        }
+    }
+}
+
+impl World for VariantWorld<'_> {
+    fn library(&self) -> &LazyHash<Library> {
+        &self.library
+    }
+
+    fn book(&self) -> &LazyHash<FontBook> {
+        self.world.book()
+    }
+
+    fn main(&self) -> FileId {
+        self.world.main()
+    }
+
+    fn source(&self, id: FileId) -> FileResult<Source> {
+        self.world.source(id)
+    }
+
+    fn file(&self, id: FileId) -> FileResult<Bytes> {
+        self.world.file(id)
+    }
+
+    fn font(&self, index: usize) -> Option<Font> {
+        self.world.font(index)
+    }
+
+    fn today(&self, offset: Option<i64>) -> Option<Datetime> {
+        self.world.today(offset)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -85,6 +244,55 @@ mod tests {
         compile(source, &world).output.unwrap();
     }
 
+    #[test]
+    fn test_compile_inputs() {
+        let mut inputs = Dict::new();
+        inputs.insert("key".into(), "value".into_value());
+
+        let world = GlobalTestWorld::new(
+            "".into(),
+            augmented_library(|builder| builder.with_inputs(inputs)),
+        );
+        let source = Source::detached("#assert.eq(sys.inputs.key, \"value\")\n#sys.inputs.key");
+        compile(source, &world).output.unwrap();
+
+        let world = GlobalTestWorld::default();
+        let source = Source::detached("#sys.inputs.key");
+        assert!(compile(source, &world).output.is_err());
+    }
+
+    #[test]
+    fn test_compile_unknown_font() {
+        let world = GlobalTestWorld::default();
+        let source = Source::detached("#set text(font: \"does-not-exist\")\nHello World");
+
+        let Warned { output, warnings } = compile(source, &world);
+        assert!(warnings.iter().any(is_unknown_font_warning));
+        assert!(used_font_families(&output.unwrap()).contains("Libertinus Serif"));
+    }
+
+    #[test]
+    fn test_fallback_chars() {
+        let world = GlobalTestWorld::default();
+
+        let source = Source::detached("Hello World");
+        let doc = compile(source.clone(), &world).output.unwrap();
+        assert!(fallback_chars(&source, &world, &doc).is_empty());
+
+        // Libertinus Serif doesn't cover braille patterns
+        let source = Source::detached("Hello \u{283F}");
+        let doc = compile(source.clone(), &world).output.unwrap();
+        assert_eq!(
+            fallback_chars(&source, &world, &doc),
+            BTreeSet::from(['\u{283F}'])
+        );
+
+        // font fallback which is disabled by the document itself isn't reported
+        let source = Source::detached("#set text(fallback: false)\nHello \u{283F}");
+        let doc = compile(source.clone(), &world).output.unwrap();
+        assert!(fallback_chars(&source, &world, &doc).is_empty());
+    }
+
     #[test]
     #[should_panic]
     fn test_compile_failure() {
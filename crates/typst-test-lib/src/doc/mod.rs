@@ -1,11 +1,16 @@
 //! On-disk management of reference documents reeference documents are stored as
 //! individual pages in PNG format.
 
+use std::borrow::Borrow;
 use std::collections::{BTreeMap, BTreeSet};
-use std::path::Path;
+use std::fs::File;
+use std::iter::Sum;
+use std::ops::AddAssign;
+use std::path::{Path, PathBuf};
 use std::{fs, io, iter};
 
-use ecow::EcoVec;
+use ecow::{EcoString, EcoVec};
+use rayon::prelude::*;
 use thiserror::Error;
 use tiny_skia::Pixmap;
 use typst::diag::Warned;
@@ -13,16 +18,36 @@ use typst::model::Document as TypstDocument;
 use typst::syntax::Source;
 use typst::World;
 
-use self::compare::Strategy;
+use self::compare::{Size, Strategy};
 use self::render::Origin;
+use crate::stdx;
 
 pub mod compare;
 pub mod compile;
 pub mod render;
+pub mod store;
 
 /// The extension used in the page storage, each page is stored separately with it.
 pub const PAGE_EXTENSION: &str = "png";
 
+/// The factor by which the dimensions of a page may differ from the most
+/// common page dimensions of a document before they are considered
+/// inconsistent, see [`Document::inconsistent_pages`].
+pub const PAGE_DIMENSION_TOLERANCE: f64 = 1.5;
+
+/// Returns the hash of the given page, this is stable across runs and
+/// platforms and is used to name pages in an [`ObjectStore`](store::ObjectStore).
+pub fn page_hash(page: &Pixmap) -> u128 {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&page.width().to_le_bytes());
+    hasher.update(&page.height().to_le_bytes());
+    hasher.update(page.data());
+
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&hasher.finalize().as_bytes()[..16]);
+    u128::from_be_bytes(bytes)
+}
+
 /// A document that was rendered from an in-memory compilation, or loaded from disk.
 #[derive(Debug, Clone)]
 pub struct Document {
@@ -55,11 +80,7 @@ impl Document {
 
     /// Creates a new rendered document from a compiled one.
     pub fn render(doc: TypstDocument, pixel_per_pt: f32) -> Self {
-        let buffers = doc
-            .pages
-            .iter()
-            .map(|page| typst_render::render(page, pixel_per_pt))
-            .collect();
+        let buffers = render::render_pages(&doc, pixel_per_pt).collect();
 
         Self {
             doc: Some(doc),
@@ -71,110 +92,210 @@ impl Document {
     /// document will have no inner document set because it was created only
     /// from pixel buffers.
     ///
-    /// Diff images are created pair-wise in order using [`render::page_diff`].
+    /// Diff images are created pair-wise in order using [`render::page_diff`],
+    /// if one document has more pages than the other, the excess pages are
+    /// kept as is.
     pub fn render_diff(base: &Self, change: &Self, origin: Origin) -> Self {
-        let buffers = iter::zip(&base.buffers, &change.buffers)
-            .map(|(base, change)| render::page_diff(base, change, origin))
+        let len = Ord::max(base.buffers.len(), change.buffers.len());
+        let buffers = (0..len)
+            .map(|idx| {
+                let pages = (base.buffers.get(idx), change.buffers.get(idx));
+                match pages {
+                    (Some(base), Some(change)) => render::page_diff(base, change, origin),
+                    (Some(page), None) | (None, Some(page)) => page.clone(),
+                    (None, None) => unreachable!(),
+                }
+            })
             .collect();
 
         Self { doc: None, buffers }
     }
 
     /// Collects the reference document in the given directory.
+    ///
+    /// Pages are decoded in parallel, if more than one page fails to decode,
+    /// the error of the first page is returned. Pages with inconsistent dimensions are logged as a warning, but don't fail
+    /// loading, see [`Document::inconsistent_pages`].
     pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self, LoadError> {
-        let mut buffers = BTreeMap::new();
+        let dir = dir.as_ref();
+        let paths = page_paths(dir)?;
+        check_page_numbers(&paths)?;
+
+        // NOTE(tinger): the pages are ordered by key and must not have any
+        // page keys missing, rayon preserves this order when collecting
+        let buffers: Vec<_> = paths
+            .into_values()
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .map(Pixmap::load_png)
+            .collect();
 
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
+        let doc = Self {
+            doc: None,
+            buffers: buffers.into_iter().collect::<Result<_, _>>()?,
+        };
 
-            if !entry.file_type()?.is_file() {
-                tracing::trace!(entry = ?path, "ignoring non-file entry in reference directory");
-                continue;
-            }
+        let inconsistent = doc.inconsistent_pages();
+        if !inconsistent.is_empty() {
+            tracing::warn!(?dir, pages = ?inconsistent, "reference pages have inconsistent dimensions");
+        }
 
-            if path.extension().is_none()
-                || path.extension().is_some_and(|ext| ext != PAGE_EXTENSION)
-            {
-                tracing::trace!(entry = ?path, "ignoring non-PNG entry in reference directory");
-                continue;
-            }
+        Ok(doc)
+    }
 
-            let Some(page) = path
-                .file_stem()
-                .and_then(|s| s.to_str())
-                .and_then(|s| s.parse().ok())
-                .filter(|&num| num != 0)
-            else {
-                tracing::trace!(
-                    entry = ?path,
-                    "ignoring non-numeric or invalid filename in reference directory",
-                );
-                continue;
-            };
+    /// Collects the pages of the reference document in the given directory
+    /// without decoding them, the pages are decoded one at a time while
+    /// iterating, see [`Document::load`].
+    pub fn load_pages<P: AsRef<Path>>(dir: P) -> Result<Pages, LoadError> {
+        let paths = page_paths(dir.as_ref())?;
+        check_page_numbers(&paths)?;
 
-            buffers.insert(page, Pixmap::load_png(path)?);
-        }
+        Ok(Pages::new(paths.into_values().collect()))
+    }
 
-        // check we got pages starting at 1
-        match buffers.first_key_value() {
-            Some((min, _)) if *min != 1 => {
-                return Err(LoadError::MissingPages(buffers.into_keys().collect()));
-            }
-            Some(_) => {}
-            None => {
-                return Err(LoadError::MissingPages(buffers.into_keys().collect()));
-            }
+    /// Reads the page count and page dimensions of the reference document in
+    /// the given directory without decoding the pages, only the PNG headers
+    /// are read.
+    pub fn load_metadata<P: AsRef<Path>>(dir: P) -> Result<Metadata, LoadError> {
+        let paths = page_paths(dir.as_ref())?;
+        check_page_numbers(&paths)?;
+
+        let mut pages = Vec::with_capacity(paths.len());
+        for path in paths.into_values() {
+            let decoder = png::Decoder::new(File::open(path)?);
+            let reader = decoder.read_info()?;
+            let info = reader.info();
+
+            pages.push(Size {
+                width: info.width,
+                height: info.height,
+            });
         }
 
-        // check we got pages ending in the page count
-        match buffers.last_key_value() {
-            Some((max, _)) if *max != buffers.len() => {
-                return Err(LoadError::MissingPages(buffers.into_keys().collect()));
-            }
-            Some(_) => {}
-            None => {
-                return Err(LoadError::MissingPages(buffers.into_keys().collect()));
-            }
-        }
+        Ok(Metadata { pages })
+    }
 
-        Ok(Self {
-            doc: None,
-            // NOTE(tinger): the pages are ordered by key and must not have any
-            // page keys missing
-            buffers: buffers.into_values().collect(),
-        })
+    /// Renders diffs only for the pages with the given 0-based indices, pages
+    /// which are not in both documents are skipped, see [`Document::render_diff`].
+    pub fn render_diff_pages(
+        base: &Self,
+        change: &Self,
+        origin: Origin,
+        pages: &[usize],
+    ) -> Vec<(usize, Pixmap)> {
+        pages
+            .iter()
+            .filter_map(|&idx| {
+                let base = base.buffers.get(idx)?;
+                let change = change.buffers.get(idx)?;
+                Some((idx, render::page_diff(base, change, origin)))
+            })
+            .collect()
     }
 
-    /// Saves a single page within the given directory with the given 1-based page
-    /// number.
+    /// Saves the pages of this document within the given directory with their
+    /// 1-based page numbers.
     ///
-    /// # Panics
-    /// Panics if `num == 0`.
+    /// Pages are encoded in parallel, if more than one page fails to be
+    /// saved, the error of the first page is returned. Returns the sizes of
+    /// the written pages.
     pub fn save<P: AsRef<Path>>(
         &self,
         dir: P,
         optimize_options: Option<&oxipng::Options>,
-    ) -> Result<(), SaveError> {
-        for (num, page) in self
-            .buffers
-            .iter()
-            .enumerate()
-            .map(|(idx, page)| (idx + 1, page))
-        {
+    ) -> Result<SaveStats, SaveError> {
+        let pages: Vec<_> = self.buffers.iter().enumerate().collect();
+        save_pages(dir.as_ref(), &pages, optimize_options)
+    }
+
+    /// Saves only the given pages with their 0-based indices within the given
+    /// directory with their 1-based page numbers, see [`Document::save`].
+    pub fn save_pages<P: AsRef<Path>>(
+        dir: P,
+        pages: &[(usize, Pixmap)],
+        optimize_options: Option<&oxipng::Options>,
+    ) -> Result<SaveStats, SaveError> {
+        let pages: Vec<_> = pages.iter().map(|(idx, page)| (*idx, page)).collect();
+        save_pages(dir.as_ref(), &pages, optimize_options)
+    }
+}
+
+/// Saves the given pages in parallel, returning the error of the first page
+/// in the given order.
+fn save_pages(
+    dir: &Path,
+    pages: &[(usize, &Pixmap)],
+    optimize_options: Option<&oxipng::Options>,
+) -> Result<SaveStats, SaveError> {
+    let results: Vec<_> = pages
+        .par_iter()
+        .map(|&(idx, page)| -> Result<SaveStats, SaveError> {
             let path = dir
-                .as_ref()
-                .join(num.to_string())
+                .join((idx + 1).to_string())
                 .with_extension(PAGE_EXTENSION);
 
-            if let Some(options) = optimize_options {
-                oxipng::optimize_from_memory(page.data(), options)?;
-            } else {
-                page.save_png(path)?;
-            }
-        }
+            let (png, stats) = encode_page(page, optimize_options)?;
+            stdx::fs::write_atomic(path, png)?;
+            Ok(stats)
+        })
+        .collect();
+
+    results.into_iter().sum()
+}
+
+/// Encodes the given page as a PNG, optionally optimizing it, returns the
+/// encoded page and its sizes.
+pub(crate) fn encode_page(
+    page: &Pixmap,
+    optimize_options: Option<&oxipng::Options>,
+) -> Result<(Vec<u8>, SaveStats), SaveError> {
+    let mut png = page.encode_png()?;
+    let unoptimized = png.len() as u64;
+
+    if let Some(options) = optimize_options {
+        png = oxipng::optimize_from_memory(&png, options)?;
+    }
+
+    let stats = SaveStats {
+        written: png.len() as u64,
+        unoptimized,
+    };
+
+    Ok((png, stats))
+}
 
-        Ok(())
+/// The sizes of the pages written when saving a document, see
+/// [`Document::save`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SaveStats {
+    /// The number of bytes written.
+    pub written: u64,
+
+    /// The number of bytes which would have been written without
+    /// optimization, this is equal to `written` if no optimization ran.
+    pub unoptimized: u64,
+}
+
+impl SaveStats {
+    /// The number of bytes saved by optimization.
+    pub fn saved(&self) -> u64 {
+        self.unoptimized.saturating_sub(self.written)
+    }
+}
+
+impl AddAssign for SaveStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.written += rhs.written;
+        self.unoptimized += rhs.unoptimized;
+    }
+}
+
+impl Sum for SaveStats {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |mut acc, stats| {
+            acc += stats;
+            acc
+        })
     }
 }
 
@@ -188,6 +309,110 @@ impl Document {
     pub fn buffers(&self) -> &[Pixmap] {
         &self.buffers
     }
+
+    /// The number of pages in this document.
+    pub fn page_count(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Returns the 0-based indices of all pages whose dimensions differ from
+    /// the most common page dimensions of this document by more than
+    /// [`PAGE_DIMENSION_TOLERANCE`], this is often a sign of a page which was
+    /// rendered with the wrong pixel-per-pt.
+    ///
+    /// The orientation of pages is ignored, such that landscape pages in an
+    /// otherwise portrait document are not considered inconsistent.
+    pub fn inconsistent_pages(&self) -> Vec<usize> {
+        let sizes: Vec<_> = self
+            .buffers
+            .iter()
+            .map(|page| {
+                let (width, height) = (page.width(), page.height());
+                (width.min(height), width.max(height))
+            })
+            .collect();
+
+        let mut counts = BTreeMap::new();
+        for size in &sizes {
+            *counts.entry(*size).or_insert(0usize) += 1;
+        }
+
+        // NOTE(tinger): on ties the dimensions of the earlier page win, this
+        // way a single mismatched page in a two page document is the later one
+        let mut common = None;
+        let mut max = 0;
+        for size in &sizes {
+            if counts[size] > max {
+                max = counts[size];
+                common = Some(*size);
+            }
+        }
+
+        let Some((short, long)) = common else {
+            return vec![];
+        };
+
+        let deviates = |a: u32, b: u32| {
+            let (a, b) = (f64::from(a.max(1)), f64::from(b.max(1)));
+            f64::max(a / b, b / a) > PAGE_DIMENSION_TOLERANCE
+        };
+
+        sizes
+            .iter()
+            .enumerate()
+            .filter(|(_, &(s, l))| deviates(s, short) || deviates(l, long))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+}
+
+/// The pages of a reference document on disk which are decoded one at a time
+/// while iterating, see [`Document::load_pages`].
+#[derive(Debug, Clone)]
+pub struct Pages {
+    paths: std::vec::IntoIter<PathBuf>,
+}
+
+impl Pages {
+    /// Creates new lazy pages from the given page paths in order.
+    pub(crate) fn new(paths: Vec<PathBuf>) -> Self {
+        Self {
+            paths: paths.into_iter(),
+        }
+    }
+}
+
+impl Iterator for Pages {
+    type Item = Result<Pixmap, LoadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.paths.next().map(|path| Ok(Pixmap::load_png(path)?))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.paths.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Pages {}
+
+/// The metadata of a reference document on disk, see
+/// [`Document::load_metadata`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Metadata {
+    pages: Vec<Size>,
+}
+
+impl Metadata {
+    /// The number of pages in the document.
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// The dimensions of the pages in the document in order.
+    pub fn pages(&self) -> &[Size] {
+        &self.pages
+    }
 }
 
 impl Document {
@@ -201,39 +426,260 @@ impl Document {
         strategy: Strategy,
         fail_fast: bool,
     ) -> Result<(), compare::Error> {
-        let output_len = outputs.buffers.len();
-        let reference_len = references.buffers.len();
+        compare_pages(
+            outputs.buffers.iter(),
+            references.buffers.iter(),
+            strategy,
+            fail_fast,
+        )
+    }
+
+    /// Returns the fraction of pixels which differ between two documents in
+    /// the range `0.0..=1.0`, this is the average of [`compare::page_change`]
+    /// over all pages. Pages without a counterpart are considered entirely
+    /// different.
+    pub fn change(&self, reference: &Self) -> f64 {
+        let pages = Ord::max(self.buffers.len(), reference.buffers.len());
+        if pages == 0 {
+            return 0.0;
+        }
 
-        let max_cap = Ord::min(output_len, reference_len);
+        let changed: f64 = iter::zip(&self.buffers, &reference.buffers)
+            .map(|(a, b)| compare::page_change(a, b))
+            .sum();
 
-        let mut page_errors = if !fail_fast || max_cap <= 32 {
-            Vec::with_capacity(max_cap)
-        } else {
-            vec![]
+        let missing = pages - Ord::min(self.buffers.len(), reference.buffers.len());
+
+        (changed + missing as f64) / pages as f64
+    }
+}
+
+/// Compares the given pages pair-wise, see [`Document::compare`].
+fn compare_pages<O, R>(
+    outputs: O,
+    references: R,
+    strategy: Strategy,
+    fail_fast: bool,
+) -> Result<(), compare::Error>
+where
+    O: ExactSizeIterator,
+    O::Item: Borrow<Pixmap>,
+    R: ExactSizeIterator,
+    R::Item: Borrow<Pixmap>,
+{
+    let output_len = outputs.len();
+    let reference_len = references.len();
+
+    let max_cap = Ord::min(output_len, reference_len);
+
+    let mut page_errors = if !fail_fast || max_cap <= 32 {
+        Vec::with_capacity(max_cap)
+    } else {
+        vec![]
+    };
+
+    for (idx, (a, b)) in iter::zip(outputs, references).enumerate() {
+        let (a, b) = (a.borrow(), b.borrow());
+        if let Err(err) = compare::page(a, b, strategy) {
+            page_errors.push((idx, err));
+
+            if fail_fast {
+                break;
+            }
+        }
+    }
+
+    if !page_errors.is_empty() || output_len != reference_len {
+        page_errors.shrink_to_fit();
+        return Err(compare::Error {
+            output: output_len,
+            reference: reference_len,
+            pages: page_errors,
+        });
+    }
+
+    Ok(())
+}
+
+/// Returns whether the given reference directory contains any pages, either
+/// directly or through an [`ObjectStore`](store::ObjectStore) manifest. A
+/// directory which doesn't exist contains no pages.
+pub fn has_pages<P: AsRef<Path>>(dir: P) -> io::Result<bool> {
+    let dir = dir.as_ref();
+
+    if !dir.try_exists()? {
+        return Ok(false);
+    }
+
+    Ok(store::is_object_store_dir(dir) || !page_paths(dir)?.is_empty())
+}
+
+/// Ensures the given pages start at 1 and end in the page count.
+fn check_page_numbers(pages: &BTreeMap<usize, PathBuf>) -> Result<(), LoadError> {
+    match (pages.first_key_value(), pages.last_key_value()) {
+        (Some((min, _)), Some((max, _))) if *min == 1 && *max == pages.len() => Ok(()),
+        _ => Err(LoadError::MissingPages(pages.keys().copied().collect())),
+    }
+}
+
+/// Collects the paths of all pages in the given directory by their 1-based page
+/// number, ignoring entries which are not pages.
+pub fn page_paths(dir: &Path) -> io::Result<BTreeMap<usize, PathBuf>> {
+    let mut pages = BTreeMap::new();
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if !entry.file_type()?.is_file() {
+            tracing::trace!(entry = ?path, "ignoring non-file entry in reference directory");
+            continue;
+        }
+
+        if path.extension().is_none() || path.extension().is_some_and(|ext| ext != PAGE_EXTENSION) {
+            tracing::trace!(entry = ?path, "ignoring non-PNG entry in reference directory");
+            continue;
+        }
+
+        let Some(page) = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.parse().ok())
+            .filter(|&num| num != 0)
+        else {
+            tracing::trace!(
+                entry = ?path,
+                "ignoring non-numeric or invalid filename in reference directory",
+            );
+            continue;
         };
 
-        for (idx, (a, b)) in iter::zip(&outputs.buffers, &references.buffers).enumerate() {
-            if let Err(err) = compare::page(a, b, strategy) {
-                page_errors.push((idx, err));
+        pages.insert(page, path);
+    }
+
+    Ok(pages)
+}
 
-                if fail_fast {
-                    break;
-                }
+/// Renumbers the pages in the given directory such that they are contiguous
+/// starting at 1, keeping their order. Returns the old and new page numbers
+/// of all pages which were renamed, if `dry_run` is `true` nothing is renamed.
+///
+/// This can be used to repair reference directories for which
+/// [`Document::load`] returns [`LoadError::MissingPages`].
+pub fn repair_page_numbers<P: AsRef<Path>>(
+    dir: P,
+    dry_run: bool,
+) -> io::Result<Vec<(usize, usize)>> {
+    fn inner(dir: &Path, dry_run: bool) -> io::Result<Vec<(usize, usize)>> {
+        let mut renamed = vec![];
+
+        // NOTE(tinger): the new page numbers are never larger than the old
+        // ones, so renaming in ascending order never overwrites a page
+        for (new, (old, path)) in (1..).zip(page_paths(dir)?) {
+            if new == old {
+                continue;
             }
+
+            if !dry_run {
+                fs::rename(
+                    &path,
+                    dir.join(new.to_string()).with_extension(PAGE_EXTENSION),
+                )?;
+            }
+
+            renamed.push((old, new));
         }
 
-        if !page_errors.is_empty() || output_len != reference_len {
-            page_errors.shrink_to_fit();
-            return Err(compare::Error {
-                output: output_len,
-                reference: reference_len,
-                pages: page_errors,
-            });
+        Ok(renamed)
+    }
+
+    inner(dir.as_ref(), dry_run)
+}
+
+/// Converts pages stored with legacy file names in the given directory to the
+/// contiguous `N.png` scheme expected by [`Document::load`]. Returns the old
+/// file names and new page numbers of all pages which were renamed, if
+/// `dry_run` is `true` nothing is renamed.
+///
+/// Legacy pages are PNG files which are ignored by [`Document::load`], such as
+/// `page-1.png` or zero-based `0.png`. Pages are ordered by the trailing
+/// number in their name, a single unnumbered file like `ref.png` is treated as
+/// the only page. If there are no legacy pages, nothing is renamed.
+pub fn convert_legacy_pages<P: AsRef<Path>>(
+    dir: P,
+    dry_run: bool,
+) -> io::Result<Vec<(String, usize)>> {
+    fn trailing_number(stem: &str) -> Option<usize> {
+        let digits = stem.len() - stem.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+        stem[stem.len() - digits..].parse().ok()
+    }
+
+    fn inner(dir: &Path, dry_run: bool) -> io::Result<Vec<(String, usize)>> {
+        let canonical = page_paths(dir)?;
+
+        let mut pages = vec![];
+        let mut has_legacy = false;
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+
+            if !entry.file_type()?.is_file()
+                || !path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case(PAGE_EXTENSION))
+            {
+                continue;
+            }
+
+            let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+
+            has_legacy |= !canonical.values().any(|p| *p == path);
+            pages.push((trailing_number(stem), stem.to_owned(), path));
+        }
+
+        if !has_legacy {
+            return Ok(vec![]);
         }
 
-        Ok(())
+        pages.sort();
+
+        // NOTE(tinger): new names may collide with old ones, so we move all
+        // pages to temporary names first
+        let mut renamed = vec![];
+        let mut temporary = vec![];
+        for (new, (_, _, path)) in (1..).zip(pages) {
+            let name = path
+                .file_name()
+                .and_then(|s| s.to_str())
+                .unwrap_or_default()
+                .to_owned();
+
+            let new_path = dir.join(new.to_string()).with_extension(PAGE_EXTENSION);
+            if path == new_path {
+                continue;
+            }
+
+            if !dry_run {
+                let tmp = dir.join(format!(".{new}.tmp"));
+                fs::rename(&path, &tmp)?;
+                temporary.push((tmp, new_path));
+            }
+
+            renamed.push((name, new));
+        }
+
+        for (tmp, new_path) in temporary {
+            fs::rename(tmp, new_path)?;
+        }
+
+        Ok(renamed)
     }
+
+    inner(dir.as_ref(), dry_run)
 }
+
 /// Returned by [`Document::load`].
 #[derive(Debug, Error)]
 pub enum LoadError {
@@ -242,6 +688,16 @@ pub enum LoadError {
     #[error("one or more pages were missing, found: {0:?}")]
     MissingPages(BTreeSet<usize>),
 
+    /// An object listed in the manifest of an
+    /// [`ObjectStore`](store::ObjectStore) was missing.
+    #[error("the object {0:?} was missing")]
+    MissingObject(EcoString),
+
+    /// A name in the manifest of an [`ObjectStore`](store::ObjectStore) was
+    /// not a valid object name, see [`store::is_object_name`].
+    #[error("the object name {0:?} is invalid")]
+    InvalidObject(EcoString),
+
     /// A page could not be decoded.
     #[error("a page could not be decoded")]
     Page(#[from] png::DecodingError),
@@ -273,13 +729,132 @@ mod tests {
 
     use super::*;
     use crate::_dev;
+    use crate::_dev::GlobalTestWorld;
+
+    #[test]
+    fn test_repair_page_numbers() {
+        _dev::fs::TempEnv::run(
+            |root| {
+                root.setup_file("1.png", "a")
+                    .setup_file("2.png", "b")
+                    .setup_file("4.png", "c")
+                    .setup_file("7.png", "d")
+            },
+            |root| {
+                assert_eq!(repair_page_numbers(root, true).unwrap(), [(4, 3), (7, 4)]);
+                assert_eq!(repair_page_numbers(root, false).unwrap(), [(4, 3), (7, 4)]);
+                assert!(repair_page_numbers(root, false).unwrap().is_empty());
+            },
+            |root| {
+                root.expect_file_content("1.png", "a")
+                    .expect_file_content("2.png", "b")
+                    .expect_file_content("3.png", "c")
+                    .expect_file_content("4.png", "d")
+            },
+        );
+    }
+
+    #[test]
+    fn test_convert_legacy_pages() {
+        _dev::fs::TempEnv::run(
+            |root| {
+                root.setup_file("a/page-0.png", "a")
+                    .setup_file("a/page-1.png", "b")
+                    .setup_file("a/page-2.png", "c")
+                    .setup_file("b/ref.png", "d")
+                    .setup_file("c/1.png", "e")
+                    .setup_file("c/2.png", "f")
+            },
+            |root| {
+                assert_eq!(
+                    convert_legacy_pages(root.join("a"), true).unwrap(),
+                    [
+                        ("page-0.png".to_owned(), 1),
+                        ("page-1.png".to_owned(), 2),
+                        ("page-2.png".to_owned(), 3),
+                    ],
+                );
+                assert_eq!(
+                    convert_legacy_pages(root.join("a"), false).unwrap().len(),
+                    3
+                );
+                assert_eq!(
+                    convert_legacy_pages(root.join("b"), false).unwrap(),
+                    [("ref.png".to_owned(), 1)],
+                );
+                assert!(convert_legacy_pages(root.join("c"), false)
+                    .unwrap()
+                    .is_empty());
+            },
+            |root| {
+                root.expect_file_content("a/1.png", "a")
+                    .expect_file_content("a/2.png", "b")
+                    .expect_file_content("a/3.png", "c")
+                    .expect_file_content("b/1.png", "d")
+                    .expect_file_content("c/1.png", "e")
+                    .expect_file_content("c/2.png", "f")
+            },
+        );
+    }
+
+    #[test]
+    fn test_has_pages() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_dir("empty")
+                    .setup_file("other/notes.txt", "")
+                    .setup_file("pages/1.png", "")
+                    .setup_file("objects/objects.txt", "")
+            },
+            |root| {
+                assert!(!has_pages(root.join("missing")).unwrap());
+                assert!(!has_pages(root.join("empty")).unwrap());
+                assert!(!has_pages(root.join("other")).unwrap());
+                assert!(has_pages(root.join("pages")).unwrap());
+                assert!(has_pages(root.join("objects")).unwrap());
+            },
+        );
+    }
+
+    #[test]
+    fn test_document_change() {
+        let page = Pixmap::new(10, 10).unwrap();
+        let mut changed = page.clone();
+        changed.fill(tiny_skia::Color::WHITE);
+
+        let doc = Document::new([page.clone(), page.clone()]);
+        assert_eq!(doc.change(&doc), 0.0);
+        assert_eq!(doc.change(&Document::new([page.clone(), changed])), 0.5);
+        assert_eq!(doc.change(&Document::new([page])), 0.5);
+        assert_eq!(Document::new([]).change(&Document::new([])), 0.0);
+    }
+
+    #[test]
+    fn test_document_load_pages() {
+        let doc = Document::new([Pixmap::new(10, 10).unwrap(), Pixmap::new(20, 10).unwrap()]);
+
+        _dev::fs::TempEnv::run_no_check(
+            |root| root.setup_dir("pages").setup_file_empty("missing/2.png"),
+            |root| {
+                doc.save(root.join("pages"), None).unwrap();
+
+                let pages = Document::load_pages(root.join("pages")).unwrap();
+                assert_eq!(pages.len(), 2);
+
+                let pages: Vec<_> = pages.map(Result::unwrap).collect();
+                assert_eq!(pages, doc.buffers());
+
+                assert!(matches!(
+                    Document::load_pages(root.join("missing")),
+                    Err(LoadError::MissingPages(_))
+                ));
+            },
+        );
+    }
 
     #[test]
     fn test_document_save() {
-        let doc = Document {
-            doc: None,
-            buffers: eco_vec![Pixmap::new(10, 10).unwrap(); 3],
-        };
+        let doc = Document::new(eco_vec![Pixmap::new(10, 10).unwrap(); 3]);
 
         _dev::fs::TempEnv::run(
             |root| root,
@@ -294,6 +869,130 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_page_hash() {
+        let page = Pixmap::new(10, 10).unwrap();
+        let mut changed = page.clone();
+        changed.fill(tiny_skia::Color::WHITE);
+
+        assert_eq!(page_hash(&page), page_hash(&page.clone()));
+        assert_ne!(page_hash(&page), page_hash(&changed));
+
+        // the same pixel data with different dimensions must not collide
+        assert_ne!(
+            page_hash(&Pixmap::new(2, 8).unwrap()),
+            page_hash(&Pixmap::new(8, 2).unwrap()),
+        );
+    }
+
+    #[test]
+    fn test_document_render_diff_pages() {
+        let world = GlobalTestWorld::default();
+        let doc = |source| {
+            Document::compile(Source::detached(source), &world, 1.0)
+                .output
+                .unwrap()
+        };
+
+        let output = doc("A\n#pagebreak()\nB\n#pagebreak()\nC\n#pagebreak()\nD\n#pagebreak()\nE");
+        let reference =
+            doc("A\n#pagebreak()\nB\n#pagebreak()\nX\n#pagebreak()\nD\n#pagebreak()\nE");
+
+        let error = Document::compare(
+            output.clone(),
+            reference.clone(),
+            Strategy::default(),
+            false,
+        )
+        .unwrap_err();
+        let failed: Vec<_> = error.pages.iter().map(|(idx, _)| *idx).collect();
+        assert_eq!(failed, [2]);
+
+        let diff = Document::render_diff_pages(&reference, &output, Origin::default(), &failed);
+
+        _dev::fs::TempEnv::run(
+            |root| root,
+            |root| {
+                Document::save_pages(root, &diff, None).unwrap();
+            },
+            |root| root.expect_file_content("3.png", diff[0].1.encode_png().unwrap()),
+        );
+    }
+
+    #[test]
+    fn test_document_save_load_order() {
+        let doc = Document::new((1..=16).map(|n| Pixmap::new(n, 17 - n).unwrap()));
+
+        _dev::fs::TempEnv::run_no_check(
+            |root| root,
+            |root| {
+                doc.save(root, None).unwrap();
+
+                for (idx, page) in doc.buffers.iter().enumerate() {
+                    let path = root.join(format!("{}.png", idx + 1));
+                    assert_eq!(fs::read(path).unwrap(), page.encode_png().unwrap());
+                }
+
+                let loaded = Document::load(root).unwrap();
+                assert_eq!(loaded.buffers, doc.buffers);
+            },
+        );
+    }
+
+    #[test]
+    fn test_document_load_metadata() {
+        let buffers = [
+            Pixmap::new(10, 20).unwrap(),
+            Pixmap::new(30, 5).unwrap(),
+            Pixmap::new(1, 1).unwrap(),
+        ];
+
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_file("1.png", buffers[0].encode_png().unwrap())
+                    .setup_file("2.png", buffers[1].encode_png().unwrap())
+                    .setup_file("3.png", buffers[2].encode_png().unwrap())
+            },
+            |root| {
+                let metadata = Document::load_metadata(root).unwrap();
+                let doc = Document::load(root).unwrap();
+
+                assert_eq!(metadata.page_count(), doc.page_count());
+                for (size, page) in iter::zip(metadata.pages(), doc.buffers()) {
+                    assert_eq!(size.width, page.width());
+                    assert_eq!(size.height, page.height());
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_document_inconsistent_pages() {
+        let page = Pixmap::new(100, 200).unwrap();
+        let landscape = Pixmap::new(200, 100).unwrap();
+        let scaled = Pixmap::new(200, 400).unwrap();
+
+        let doc = Document::new([page.clone(), landscape.clone(), page.clone()]);
+        assert!(doc.inconsistent_pages().is_empty());
+
+        let doc = Document::new([page.clone(), scaled.clone(), page.clone()]);
+        assert_eq!(doc.inconsistent_pages(), [1]);
+
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_file("1.png", page.encode_png().unwrap())
+                    .setup_file("2.png", page.encode_png().unwrap())
+                    .setup_file("3.png", scaled.encode_png().unwrap())
+            },
+            |root| {
+                let doc = Document::load(root).unwrap();
+                assert_eq!(doc.inconsistent_pages(), [2]);
+            },
+        );
+
+        assert!(Document::new([]).inconsistent_pages().is_empty());
+    }
+
     #[test]
     fn test_document_load() {
         let buffers = eco_vec![Pixmap::new(10, 10).unwrap(); 3];
@@ -2,7 +2,12 @@
 
 use std::cmp::Ordering;
 
-use tiny_skia::{BlendMode, FilterQuality, Pixmap, PixmapPaint, Transform};
+use tiny_skia::{
+    BlendMode, FilterQuality, IntRect, Pixmap, PixmapPaint, PremultipliedColorU8, Transform,
+};
+use typst::model::Document as TypstDocument;
+
+use crate::doc::compare::Region;
 
 /// The origin of a documents page, this is used for comparisons of pages with
 /// different dimensions.
@@ -68,6 +73,16 @@ pub fn ppi_to_ppp(pixel_per_inch: f32) -> f32 {
     pixel_per_inch / PPP_TO_PPI_FACTOR
 }
 
+/// Lazily renders the pages of a compiled document in order.
+pub fn render_pages(
+    doc: &TypstDocument,
+    pixel_per_pt: f32,
+) -> impl ExactSizeIterator<Item = Pixmap> + '_ {
+    doc.pages
+        .iter()
+        .map(move |page| typst_render::render(page, pixel_per_pt))
+}
+
 /// Render the visual diff of two pages. If the pages do not have matching
 /// dimensions, then the origin is used to align them, regions without overlap
 /// will simply be colored black.
@@ -121,9 +136,188 @@ pub fn page_diff(base: &Pixmap, change: &Pixmap, origin: Origin) -> Pixmap {
     diff
 }
 
+/// Returns the bounding box of all pixels which differ from the background
+/// color of a page, the background color is the color of the top left pixel.
+/// Returns `None` if the page is uniformly colored.
+pub fn content_bounds(pixmap: &Pixmap) -> Option<IntRect> {
+    let background = *pixmap.pixels().first()?;
+    let width = pixmap.width() as usize;
+
+    let mut bounds: Option<(usize, usize, usize, usize)> = None;
+    for (idx, pixel) in pixmap.pixels().iter().enumerate() {
+        if *pixel == background {
+            continue;
+        }
+
+        let (x, y) = (idx % width, idx / width);
+        bounds = Some(match bounds {
+            Some((left, top, right, bottom)) => (
+                Ord::min(left, x),
+                Ord::min(top, y),
+                Ord::max(right, x),
+                Ord::max(bottom, y),
+            ),
+            None => (x, y, x, y),
+        });
+    }
+
+    let (left, top, right, bottom) = bounds?;
+    IntRect::from_ltrb(left as i32, top as i32, right as i32 + 1, bottom as i32 + 1)
+}
+
+/// Crops uniformly colored borders off a page, see [`content_bounds`].
+/// Uniformly colored pages are reduced to a single pixel of their background
+/// color.
+pub fn trim_whitespace(pixmap: &Pixmap) -> Pixmap {
+    let bounds = content_bounds(pixmap)
+        .unwrap_or_else(|| IntRect::from_xywh(0, 0, 1, 1).expect("must be valid"));
+
+    pixmap
+        .clone_rect(bounds)
+        .expect("bounds must be within pixmap")
+}
+
+/// Fills the given regions of a page with its background color, the color of
+/// the top left pixel. This hides masked content from both comparison and
+/// [whitespace trimming][trim_whitespace], so masks must be applied first.
+pub fn mask(pixmap: &Pixmap, masks: &[Region]) -> Pixmap {
+    let mut masked = pixmap.clone();
+    let Some(&background) = pixmap.pixels().first() else {
+        return masked;
+    };
+
+    let width = pixmap.width() as usize;
+    for (idx, pixel) in masked.pixels_mut().iter_mut().enumerate() {
+        let (x, y) = ((idx % width) as u32, (idx / width) as u32);
+        if masks.iter().any(|mask| mask.contains(x, y)) {
+            *pixel = background;
+        }
+    }
+
+    masked
+}
+
+/// Converts a page to grayscale using its luminance, this can be used to
+/// compare pages regardless of their colors. The alpha channel is kept as is.
+pub fn grayscale(pixmap: &Pixmap) -> Pixmap {
+    let mut gray = pixmap.clone();
+
+    for pixel in gray.pixels_mut() {
+        // NOTE(tinger): these are the Rec. 709 luma weights scaled to sum up
+        // to 256, because the weights sum up to 1, the luminance of a
+        // premultiplied pixel never exceeds its alpha
+        let luminance =
+            (pixel.red() as u32 * 54 + pixel.green() as u32 * 183 + pixel.blue() as u32 * 19) >> 8;
+        let luminance = luminance as u8;
+
+        *pixel = PremultipliedColorU8::from_rgba(luminance, luminance, luminance, pixel.alpha())
+            .expect("luminance must not exceed alpha");
+    }
+
+    gray
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tiny_skia::Color;
+
+    use crate::doc::compare::{self, PageError, Strategy};
+
+    fn page_with_content(width: u32, height: u32, x: u32, y: u32) -> Pixmap {
+        let mut pixmap = Pixmap::new(width, height).unwrap();
+        pixmap.fill(tiny_skia::Color::WHITE);
+
+        let red = tiny_skia::PremultipliedColorU8::from_rgba(255, 0, 0, 255).unwrap();
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1), (2, 1)] {
+            let idx = ((y + dy) * width + x + dx) as usize;
+            pixmap.pixels_mut()[idx] = red;
+        }
+
+        pixmap
+    }
+
+    #[test]
+    fn test_content_bounds() {
+        let page = page_with_content(10, 10, 3, 4);
+        assert_eq!(content_bounds(&page), IntRect::from_xywh(3, 4, 3, 2));
+
+        let blank = Pixmap::new(10, 10).unwrap();
+        assert_eq!(content_bounds(&blank), None);
+        assert_eq!(trim_whitespace(&blank).width(), 1);
+        assert_eq!(trim_whitespace(&blank).height(), 1);
+    }
+
+    #[test]
+    fn test_trim_whitespace_different_canvas() {
+        let output = page_with_content(10, 10, 3, 4);
+        let reference = page_with_content(20, 15, 12, 1);
+
+        assert!(matches!(
+            compare::page(&output, &reference, Strategy::default()),
+            Err(PageError::Dimensions { .. })
+        ));
+
+        assert!(compare::page(
+            &trim_whitespace(&output),
+            &trim_whitespace(&reference),
+            Strategy::default(),
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_mask_before_trim() {
+        let output = page_with_content(20, 20, 3, 4);
+        let mut reference = page_with_content(20, 20, 3, 4);
+        let red = tiny_skia::PremultipliedColorU8::from_rgba(255, 0, 0, 255).unwrap();
+        reference.pixels_mut()[15 * 20 + 15] = red;
+
+        // the changed pixel widens the content bounds of the reference
+        assert!(compare::page(
+            &trim_whitespace(&output),
+            &trim_whitespace(&reference),
+            Strategy::default(),
+        )
+        .is_err());
+
+        let masks = [Region {
+            x: 10,
+            y: 10,
+            width: 10,
+            height: 10,
+        }];
+        assert!(compare::page(
+            &trim_whitespace(&mask(&output, &masks)),
+            &trim_whitespace(&mask(&reference, &masks)),
+            Strategy::default(),
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_grayscale() {
+        // NOTE(tinger): these have the same luminance, but a different hue
+        let red = Color::from_rgba8(183, 0, 0, 255);
+        let green = Color::from_rgba8(0, 54, 0, 255);
+
+        let mut output = Pixmap::new(10, 10).unwrap();
+        let mut reference = Pixmap::new(10, 10).unwrap();
+        output.fill(red);
+        reference.fill(green);
+
+        assert!(matches!(
+            compare::page(&output, &reference, Strategy::default()),
+            Err(PageError::SimpleDeviations { deviations: 100 })
+        ));
+
+        assert!(compare::page(
+            &grayscale(&output),
+            &grayscale(&reference),
+            Strategy::default(),
+        )
+        .is_ok());
+    }
 
     #[test]
     fn test_page_diff_top_left() {
@@ -0,0 +1,415 @@
+//! Storage backends for persistent reference documents.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use ecow::EcoString;
+use tiny_skia::Pixmap;
+
+use super::{Document, LoadError, Pages, SaveError, SaveStats, PAGE_EXTENSION};
+use crate::stdx;
+
+/// The name of the manifest file in reference directories managed by an
+/// [`ObjectStore`].
+pub const MANIFEST_FILE: &str = "objects.txt";
+
+/// A storage backend for the pages of persistent reference documents.
+pub trait Store {
+    /// Saves the pages of the given document for the given reference
+    /// directory, the directory must exist. Returns the sizes of the pages
+    /// which were actually written.
+    fn save(
+        &self,
+        dir: &Path,
+        doc: &Document,
+        optimize_options: Option<&oxipng::Options>,
+    ) -> Result<SaveStats, SaveError>;
+
+    /// Loads the pages stored for the given reference directory.
+    fn load(&self, dir: &Path) -> Result<Document, LoadError>;
+
+    /// Collects the pages stored for the given reference directory without
+    /// decoding them, the pages are decoded one at a time while iterating.
+    fn load_pages(&self, dir: &Path) -> Result<Pages, LoadError>;
+}
+
+/// Stores each page as an individual PNG inside the reference directory, see
+/// [`Document::save`] and [`Document::load`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct DirectStore;
+
+impl Store for DirectStore {
+    fn save(
+        &self,
+        dir: &Path,
+        doc: &Document,
+        optimize_options: Option<&oxipng::Options>,
+    ) -> Result<SaveStats, SaveError> {
+        doc.save(dir, optimize_options)
+    }
+
+    fn load(&self, dir: &Path) -> Result<Document, LoadError> {
+        Document::load(dir)
+    }
+
+    fn load_pages(&self, dir: &Path) -> Result<Pages, LoadError> {
+        Document::load_pages(dir)
+    }
+}
+
+/// Stores pages by their content hash in a shared object directory, reference
+/// directories only contain a manifest listing the objects of their pages in
+/// order. Identical pages across tests are stored only once.
+///
+/// Objects are not removed when references change or are deleted, see
+/// [`ObjectStore::prune_unreferenced_objects`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ObjectStore {
+    objects: PathBuf,
+}
+
+impl ObjectStore {
+    /// Creates a new object store using the given object directory, see
+    /// [`Paths::objects_dir`](crate::project::Paths::objects_dir).
+    pub fn new<P: Into<PathBuf>>(objects: P) -> Self {
+        Self {
+            objects: objects.into(),
+        }
+    }
+
+    /// The directory in which the objects are stored.
+    pub fn objects_dir(&self) -> &Path {
+        &self.objects
+    }
+
+    /// Returns the name of the object for the given page.
+    pub fn object_name(page: &Pixmap) -> String {
+        format!("{:032x}", super::page_hash(page))
+    }
+
+    /// Returns the path of the object with the given name, or `None` if the
+    /// name is not a valid object name, see [`is_object_name`].
+    pub fn object_path(&self, name: &str) -> Option<PathBuf> {
+        is_object_name(name).then(|| self.object_path_unchecked(name))
+    }
+
+    fn object_path_unchecked(&self, name: &str) -> PathBuf {
+        self.objects.join(format!("{name}.{PAGE_EXTENSION}"))
+    }
+
+    /// Reads the manifest of the given reference directory and returns the
+    /// names and paths of its objects in order.
+    fn objects(&self, dir: &Path) -> Result<Vec<(EcoString, PathBuf)>, LoadError> {
+        let manifest = fs::read_to_string(dir.join(MANIFEST_FILE))?;
+
+        let mut objects = vec![];
+        for name in manifest_names(&manifest) {
+            let Some(path) = self.object_path(name) else {
+                return Err(LoadError::InvalidObject(name.into()));
+            };
+
+            if !path.try_exists()? {
+                return Err(LoadError::MissingObject(name.into()));
+            }
+
+            objects.push((name.into(), path));
+        }
+
+        if objects.is_empty() {
+            return Err(LoadError::MissingPages(BTreeSet::new()));
+        }
+
+        Ok(objects)
+    }
+
+    /// Recursively collects the names of all objects referenced by the
+    /// manifests of reference directories in the given directory. Invalid
+    /// object names are skipped.
+    pub fn collect_referenced_objects(dir: &Path) -> io::Result<BTreeSet<EcoString>> {
+        fn inner(dir: &Path, referenced: &mut BTreeSet<EcoString>) -> io::Result<()> {
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                let path = entry.path();
+
+                if entry.metadata()?.is_dir() {
+                    inner(&path, referenced)?;
+                } else if entry.file_name() == MANIFEST_FILE {
+                    let manifest = fs::read_to_string(&path)?;
+                    referenced.extend(
+                        manifest_names(&manifest)
+                            .filter(|name| is_object_name(name))
+                            .map(EcoString::from),
+                    );
+                }
+            }
+
+            Ok(())
+        }
+
+        let mut referenced = BTreeSet::new();
+        match inner(dir, &mut referenced) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            res => res?,
+        }
+
+        Ok(referenced)
+    }
+
+    /// Collects the paths of all objects in this store which are not
+    /// referenced by any manifest in the given directory, usually the test
+    /// root, see [`ObjectStore::collect_referenced_objects`].
+    ///
+    /// Files in the object directory which are not objects are ignored.
+    pub fn collect_unreferenced_objects(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let referenced = Self::collect_referenced_objects(dir)?;
+
+        let entries = match fs::read_dir(&self.objects) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+
+        let mut unreferenced = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == PAGE_EXTENSION) {
+                let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                    continue;
+                };
+
+                if is_object_name(name) && !referenced.contains(name) {
+                    tracing::debug!(?path, "found unreferenced object");
+                    unreferenced.push(path);
+                }
+            }
+        }
+
+        unreferenced.sort();
+        Ok(unreferenced)
+    }
+
+    /// Removes all objects found by
+    /// [`ObjectStore::collect_unreferenced_objects`] and returns their paths.
+    pub fn prune_unreferenced_objects(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let unreferenced = self.collect_unreferenced_objects(dir)?;
+
+        for path in &unreferenced {
+            stdx::fs::remove_file(path)?;
+        }
+
+        Ok(unreferenced)
+    }
+}
+
+/// Returns the non-empty lines of the given manifest.
+fn manifest_names(manifest: &str) -> impl Iterator<Item = &str> {
+    manifest.lines().map(str::trim).filter(|l| !l.is_empty())
+}
+
+/// Returns whether the given name is a valid object name, i.e. exactly 32
+/// lowercase hexadecimal digits as returned by [`ObjectStore::object_name`].
+pub fn is_object_name(name: &str) -> bool {
+    name.len() == 32
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+impl Store for ObjectStore {
+    fn save(
+        &self,
+        dir: &Path,
+        doc: &Document,
+        optimize_options: Option<&oxipng::Options>,
+    ) -> Result<SaveStats, SaveError> {
+        stdx::fs::create_dir(&self.objects, true)?;
+
+        let mut manifest = String::new();
+        let mut stats = SaveStats::default();
+        for page in doc.buffers() {
+            let name = Self::object_name(page);
+            let path = self.object_path_unchecked(&name);
+
+            // NOTE(tinger): deduplicated pages aren't written and don't count
+            // towards the stats
+            if !path.try_exists()? {
+                let (png, page_stats) = super::encode_page(page, optimize_options)?;
+                stdx::fs::write_atomic(path, png)?;
+                stats += page_stats;
+            }
+
+            manifest.push_str(&name);
+            manifest.push('\n');
+        }
+
+        stdx::fs::write_atomic(dir.join(MANIFEST_FILE), manifest)?;
+
+        Ok(stats)
+    }
+
+    fn load(&self, dir: &Path) -> Result<Document, LoadError> {
+        let objects = self.objects(dir)?;
+
+        let mut buffers = Vec::with_capacity(objects.len());
+        for (_, path) in objects {
+            buffers.push(Pixmap::load_png(path)?);
+        }
+
+        Ok(Document::new(buffers))
+    }
+
+    fn load_pages(&self, dir: &Path) -> Result<Pages, LoadError> {
+        let objects = self.objects(dir)?;
+        Ok(Pages::new(
+            objects.into_iter().map(|(_, path)| path).collect(),
+        ))
+    }
+}
+
+/// Returns whether the given reference directory is managed by an
+/// [`ObjectStore`].
+pub fn is_object_store_dir(dir: &Path) -> bool {
+    dir.join(MANIFEST_FILE).is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use tiny_skia::PremultipliedColorU8;
+
+    use super::*;
+    use crate::_dev;
+
+    #[test]
+    fn test_object_store_deduplicate() {
+        let shared = Pixmap::new(10, 10).unwrap();
+        let mut unique = Pixmap::new(10, 10).unwrap();
+        unique.pixels_mut()[0] = PremultipliedColorU8::from_rgba(255, 0, 0, 255).unwrap();
+
+        let a = Document::new([shared.clone(), unique.clone()]);
+        let b = Document::new([shared.clone()]);
+
+        let shared_name = ObjectStore::object_name(&shared);
+        let unique_name = ObjectStore::object_name(&unique);
+
+        _dev::fs::TempEnv::run(
+            |root| root.setup_dir("a").setup_dir("b"),
+            |root| {
+                let store = ObjectStore::new(root.join("objects"));
+                store.save(&root.join("a"), &a, None).unwrap();
+                store.save(&root.join("b"), &b, None).unwrap();
+
+                let loaded_a = store.load(&root.join("a")).unwrap();
+                let loaded_b = store.load(&root.join("b")).unwrap();
+                assert_eq!(loaded_a.buffers(), a.buffers());
+                assert_eq!(loaded_b.buffers(), b.buffers());
+
+                let pages = store.load_pages(&root.join("a")).unwrap();
+                let pages: Vec<_> = pages.map(Result::unwrap).collect();
+                assert_eq!(pages, a.buffers());
+            },
+            |root| {
+                root.expect_file_content("a/objects.txt", format!("{shared_name}\n{unique_name}\n"))
+                    .expect_file_content("b/objects.txt", format!("{shared_name}\n"))
+                    .expect_file_content(
+                        format!("objects/{shared_name}.png"),
+                        shared.encode_png().unwrap(),
+                    )
+                    .expect_file_content(
+                        format!("objects/{unique_name}.png"),
+                        unique.encode_png().unwrap(),
+                    )
+            },
+        );
+    }
+
+    #[test]
+    fn test_object_store_missing_object() {
+        let name = "deadbeef".repeat(4);
+
+        _dev::fs::TempEnv::run_no_check(
+            |root| root.setup_file("a/objects.txt", format!("{name}\n")),
+            |root| {
+                let store = ObjectStore::new(root.join("objects"));
+                assert!(matches!(
+                    store.load(&root.join("a")),
+                    Err(LoadError::MissingObject(missing)) if missing == name
+                ));
+                assert!(matches!(
+                    store.load_pages(&root.join("a")),
+                    Err(LoadError::MissingObject(missing)) if missing == name
+                ));
+            },
+        );
+    }
+
+    #[test]
+    fn test_object_store_invalid_object() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_file("objects/secret.png", "")
+                    .setup_file("a/objects.txt", "../objects/secret\n")
+                    .setup_file("b/objects.txt", format!("{}\n", "DEADBEEF".repeat(4)))
+            },
+            |root| {
+                let store = ObjectStore::new(root.join("objects"));
+                assert!(matches!(
+                    store.load(&root.join("a")),
+                    Err(LoadError::InvalidObject(name)) if name == "../objects/secret"
+                ));
+                assert!(matches!(
+                    store.load_pages(&root.join("b")),
+                    Err(LoadError::InvalidObject(_))
+                ));
+            },
+        );
+    }
+
+    #[test]
+    fn test_is_object_name() {
+        assert!(is_object_name(&"0123456789abcdef".repeat(2)));
+        assert!(!is_object_name(&"0123456789ABCDEF".repeat(2)));
+        assert!(!is_object_name("0123456789abcdef"));
+        assert!(!is_object_name(&"0123456789abcdeg".repeat(2)));
+        assert!(!is_object_name(""));
+    }
+
+    #[test]
+    fn test_object_store_prune_unreferenced_objects() {
+        let kept = Pixmap::new(10, 10).unwrap();
+        let removed = Pixmap::new(5, 5).unwrap();
+
+        let kept_name = ObjectStore::object_name(&kept);
+        let removed_name = ObjectStore::object_name(&removed);
+
+        _dev::fs::TempEnv::run(
+            |root| root.setup_dir("tests/a/ref").setup_dir("tests/b/ref"),
+            |root| {
+                let store = ObjectStore::new(root.join("objects"));
+                let doc = Document::new([kept.clone()]);
+                store.save(&root.join("tests/a/ref"), &doc, None).unwrap();
+                let doc = Document::new([removed.clone()]);
+                store.save(&root.join("tests/b/ref"), &doc, None).unwrap();
+
+                // the reference of b was deleted, but its object remains
+                fs::remove_dir_all(root.join("tests/b")).unwrap();
+                fs::write(root.join("objects/unrelated.txt"), "").unwrap();
+
+                assert_eq!(
+                    store
+                        .prune_unreferenced_objects(&root.join("tests"))
+                        .unwrap(),
+                    [store.object_path(&removed_name).unwrap()],
+                );
+            },
+            |root| {
+                root.expect_file_content("tests/a/ref/objects.txt", format!("{kept_name}\n"))
+                    .expect_file_content(
+                        format!("objects/{kept_name}.png"),
+                        kept.encode_png().unwrap(),
+                    )
+                    .expect_file_empty("objects/unrelated.txt")
+            },
+        );
+    }
+}
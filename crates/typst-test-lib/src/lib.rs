@@ -4,6 +4,7 @@ pub mod config;
 pub mod doc;
 pub mod library;
 pub mod project;
+pub mod runner;
 pub mod stdx;
 pub mod test;
 pub mod test_set;
@@ -12,5 +13,6 @@ pub mod test_set;
 /// manifest tool sections, , and more.
 pub const TOOL_NAME: &str = "typst-test";
 
-#[cfg(test)]
+#[cfg(any(test, feature = "dev"))]
+#[doc(hidden)]
 pub mod _dev;
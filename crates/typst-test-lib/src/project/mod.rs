@@ -6,8 +6,9 @@ use std::{fs, io};
 use thiserror::Error;
 use typst::syntax::package::{PackageInfo, PackageManifest, TemplateInfo};
 
-use crate::test::Id;
-use crate::{config, test};
+use crate::config::Config;
+use crate::test::{Id, Reference, Test};
+use crate::{config, stdx, test};
 
 mod vcs;
 
@@ -17,12 +18,30 @@ pub use vcs::{Kind as VcsKind, Vcs};
 /// automatically.
 pub const MANIFEST_FILE: &str = "typst.toml";
 
+/// The name of the test root directory within the project root.
+const TEST_ROOT: &str = "tests";
+
+/// The name of the project-local tool directory within the project root.
+const TOOL_DIR: &str = ".typst-test";
+
+/// The name of the example test created by [`Project::init`].
+pub const EXAMPLE_TEST: &str = "example";
+
+/// The default file name of test scripts, see [`Paths::test_script`].
+pub const DEFAULT_TEST_SCRIPT: &str = "test.typ";
+
+/// The default file name of reference scripts, see
+/// [`Paths::test_ref_script`].
+pub const DEFAULT_REF_SCRIPT: &str = "ref.typ";
+
 /// An object which contains various paths relevant for handling on-disk
 /// operations and path transformations.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Paths {
     project: PathBuf,
     vcs: Option<PathBuf>,
+    test_script: String,
+    ref_script: String,
 }
 
 impl Paths {
@@ -37,8 +56,24 @@ impl Paths {
         Self {
             project: project.into(),
             vcs: vcs.into(),
+            test_script: DEFAULT_TEST_SCRIPT.into(),
+            ref_script: DEFAULT_REF_SCRIPT.into(),
         }
     }
+
+    /// Sets the file name of test scripts, defaults to
+    /// [`DEFAULT_TEST_SCRIPT`].
+    pub fn with_test_script<S: Into<String>>(mut self, name: S) -> Self {
+        self.test_script = name.into();
+        self
+    }
+
+    /// Sets the file name of reference scripts, defaults to
+    /// [`DEFAULT_REF_SCRIPT`].
+    pub fn with_ref_script<S: Into<String>>(mut self, name: S) -> Self {
+        self.ref_script = name.into();
+        self
+    }
 }
 
 impl Paths {
@@ -60,7 +95,30 @@ impl Paths {
     ///
     /// The test root is used to resolve test identifiers.
     pub fn test_root(&self) -> PathBuf {
-        self.project.join("tests")
+        self.project.join(TEST_ROOT)
+    }
+
+    /// Returns the path to the project-local tool directory (`.typst-test`),
+    /// this contains both tracked and untracked state.
+    pub fn tool_dir(&self) -> PathBuf {
+        self.project.join(TOOL_DIR)
+    }
+
+    /// Returns the path to the project-local cache directory, this is ignored
+    /// in the Vcs, see [`Project::create_cache_dir`].
+    ///
+    /// Features which need to persist untracked state between runs should
+    /// store it here instead of using ad-hoc paths.
+    // NOTE(tinger): the object store is tracked, so the cache can't be the
+    // tool directory itself
+    pub fn cache_dir(&self) -> PathBuf {
+        self.tool_dir().join("cache")
+    }
+
+    /// Returns the path to the shared object directory used by
+    /// [`ObjectStore`](crate::doc::store::ObjectStore).
+    pub fn objects_dir(&self) -> PathBuf {
+        self.tool_dir().join("objects")
     }
 
     /// Returns the path to the test template, that is, the source template to
@@ -85,17 +143,35 @@ impl Paths {
         dir
     }
 
+    /// Returns the file name of test scripts.
+    pub fn test_script_name(&self) -> &str {
+        &self.test_script
+    }
+
+    /// Returns the file name of reference scripts.
+    pub fn ref_script_name(&self) -> &str {
+        &self.ref_script
+    }
+
     /// Create a path to the test script for the given identifier.
     pub fn test_script(&self, id: &Id) -> PathBuf {
         let mut dir = self.test_dir(id);
-        dir.push("test.typ");
+        dir.push(&self.test_script);
         dir
     }
 
+    /// Create a path to the script of a single-file test for the given
+    /// identifier, that is a script in place of the test directory.
+    pub fn test_file_script(&self, id: &Id) -> PathBuf {
+        let mut path = self.test_dir(id);
+        path.as_mut_os_string().push(".typ");
+        path
+    }
+
     /// Create a path to the reference script for the given identifier.
     pub fn test_ref_script(&self, id: &Id) -> PathBuf {
         let mut dir = self.test_dir(id);
-        dir.push("ref.typ");
+        dir.push(&self.ref_script);
         dir
     }
 
@@ -119,6 +195,30 @@ impl Paths {
         dir.push("diff");
         dir
     }
+
+    /// Create a path to the reference directory of a variant for the given
+    /// identifier.
+    pub fn test_ref_variant_dir(&self, id: &Id, variant: &str) -> PathBuf {
+        let mut dir = self.test_ref_dir(id);
+        dir.push(variant);
+        dir
+    }
+
+    /// Create a path to the output directory of a variant for the given
+    /// identifier.
+    pub fn test_out_variant_dir(&self, id: &Id, variant: &str) -> PathBuf {
+        let mut dir = self.test_out_dir(id);
+        dir.push(variant);
+        dir
+    }
+
+    /// Create a path to the difference directory of a variant for the given
+    /// identifier.
+    pub fn test_diff_variant_dir(&self, id: &Id, variant: &str) -> PathBuf {
+        let mut dir = self.test_diff_dir(id);
+        dir.push(variant);
+        dir
+    }
 }
 
 /// A handle for managing typst projects both on-disk and in-memory.
@@ -126,16 +226,23 @@ impl Paths {
 pub struct Project {
     manifest: Option<PackageManifest>,
     paths: Paths,
-    vcs: Option<Vcs>,
+    vcs: Vcs,
+    root_candidates: Vec<PathBuf>,
+    is_fallback_root: bool,
 }
 
 impl Project {
-    /// Create a new project with the given parameters.
+    /// Create a new project with the given parameters, if no Vcs is given a
+    /// [null Vcs](Vcs::null) is used.
     pub fn new(manifest: Option<PackageManifest>, paths: Paths, vcs: Option<Vcs>) -> Self {
+        let vcs = vcs.unwrap_or_else(|| Vcs::null(paths.project_root()));
+
         Self {
             manifest,
             paths,
             vcs,
+            root_candidates: vec![],
+            is_fallback_root: false,
         }
     }
 
@@ -148,53 +255,84 @@ impl Project {
     /// looking for a manifest, otherwise it will assume the directory itself is
     /// the project root.
     ///
+    /// When looking for a manifest, all manifests up to the Vcs root are
+    /// considered, the nearest enclosing manifest is preferred. If the nearest
+    /// manifest has no test root, but an ancestor's has, then that ancestor is
+    /// chosen instead. All directories containing a manifest are available
+    /// through [`Project::root_candidates`].
     pub fn discover<P: AsRef<Path>>(
         dir: P,
         is_project_root: bool,
     ) -> Result<Option<Self>, DiscoverError> {
-        let dir = dir.as_ref();
+        Self::discover_inner(dir.as_ref(), is_project_root, true)
+    }
 
-        let mut project_root = is_project_root.then(|| dir.to_path_buf());
-        let mut manifest = None;
+    /// Attempt to discover the current project from the given directory like
+    /// [`Project::discover`], but always chooses the nearest manifest, even if
+    /// it has no test root.
+    ///
+    /// This is used for projects which are about to be initialized.
+    pub fn discover_nearest<P: AsRef<Path>>(
+        dir: P,
+        is_project_root: bool,
+    ) -> Result<Option<Self>, DiscoverError> {
+        Self::discover_inner(dir.as_ref(), is_project_root, false)
+    }
+
+    fn discover_inner(
+        dir: &Path,
+        is_project_root: bool,
+        fallback: bool,
+    ) -> Result<Option<Self>, DiscoverError> {
+        let mut candidates = vec![];
         let mut vcs_root = None;
         let mut vcs = None;
 
         for dir in dir.ancestors() {
-            if project_root.is_none() {
-                let manifest_file = dir.join(MANIFEST_FILE);
-                if manifest_file.try_exists()? {
-                    project_root = Some(dir.to_path_buf());
-
-                    tracing::debug!(?manifest_file, "reading manifest");
-                    manifest = Some(toml::from_str(&fs::read_to_string(manifest_file)?)?);
-                }
+            if !is_project_root && dir.join(MANIFEST_FILE).try_exists()? {
+                tracing::debug!(?dir, "found manifest");
+                candidates.push(dir.to_path_buf());
             }
 
             if vcs.is_none() {
                 if let Some(found) = Vcs::try_new(dir)? {
                     tracing::debug!(?found, "found vcs");
+                    vcs_root = Some(dir.to_path_buf());
                     vcs = Some(found);
                 }
-                vcs_root = Some(dir.to_path_buf());
             }
 
-            if project_root.is_some() && vcs.is_some() {
+            // NOTE(tinger): manifests outside the vcs root are not considered
+            // as long as we found one within it
+            if vcs.is_some() && (is_project_root || !candidates.is_empty()) {
                 break;
             }
         }
 
-        let Some(project) = project_root else {
-            return Ok(None);
+        let (project, is_fallback_root) = if is_project_root {
+            (dir.to_path_buf(), false)
+        } else {
+            let Some(project) = choose_root(&candidates, fallback)? else {
+                return Ok(None);
+            };
+
+            let is_fallback_root = candidates.first().is_some_and(|nearest| nearest != project);
+            (project.to_path_buf(), is_fallback_root)
         };
 
-        Ok(Some(Self {
-            manifest,
-            paths: Paths {
-                project,
-                vcs: vcs_root,
-            },
-            vcs,
-        }))
+        let manifest_file = project.join(MANIFEST_FILE);
+        let manifest = if manifest_file.try_exists()? {
+            tracing::debug!(?manifest_file, "reading manifest");
+            Some(toml::from_str(&fs::read_to_string(manifest_file)?)?)
+        } else {
+            None
+        };
+
+        let mut this = Self::new(manifest, Paths::new(project, vcs_root), vcs);
+        this.root_candidates = candidates;
+        this.is_fallback_root = is_fallback_root;
+
+        Ok(Some(this))
     }
 }
 
@@ -204,16 +342,49 @@ impl Project {
         self.manifest.as_ref()
     }
 
+    /// Returns the directories containing a manifest which were considered as
+    /// the project root during [discovery](Project::discover), nearest first.
+    ///
+    /// If there is more than one candidate, the chosen root may be ambiguous.
+    pub fn root_candidates(&self) -> &[PathBuf] {
+        &self.root_candidates
+    }
+
+    /// Whether the nearest manifest was passed over during
+    /// [discovery](Project::discover) because it had no test root, in which
+    /// case an ancestor's manifest was chosen instead.
+    pub fn is_fallback_root(&self) -> bool {
+        self.is_fallback_root
+    }
+
     /// Returns the paths for this project, these are used in various low-level
     /// on-disk operations to correctly manipulate tests.
     pub fn paths(&self) -> &Paths {
         &self.paths
     }
 
+    /// Applies the settings of the given config which affect the layout of
+    /// this project, such as the names of test and reference scripts.
+    pub fn configure(&mut self, config: &Config) {
+        if let Some((name, _)) = config.resolve(|c| c.test_script.as_ref()) {
+            self.paths.test_script = name.clone();
+        }
+
+        if let Some((name, _)) = config.resolve(|c| c.ref_script.as_ref()) {
+            self.paths.ref_script = name.clone();
+        }
+    }
+
     /// Returns the [`Vcs`] this project is managed by or `None` if no supported
     /// Vcs was found.
     pub fn vcs(&self) -> Option<&Vcs> {
-        self.vcs.as_ref()
+        Some(&self.vcs).filter(|vcs| vcs.kind() != VcsKind::None)
+    }
+
+    /// Returns the [`Vcs`] this project is managed by or a [null Vcs](Vcs::null)
+    /// if no supported Vcs was found.
+    pub fn vcs_or_null(&self) -> &Vcs {
+        &self.vcs
     }
 
     /// Returns the package info if this project is a package.
@@ -228,6 +399,89 @@ impl Project {
             .as_ref()
             .and_then(|m| m.template.as_ref().map(|t| (t, &m.package)))
     }
+
+    /// Returns the absolute path to the template directory if this project is
+    /// a template package.
+    pub fn template_dir(&self) -> Option<PathBuf> {
+        self.manifest_template_info()
+            .map(|(template, _)| self.paths.project_root().join(template.path.as_str()))
+    }
+}
+
+impl Project {
+    /// Initializes the test suite of this project by creating the test root
+    /// with an example test, the temporary directories of the example test are
+    /// ignored in the given Vcs.
+    pub fn init(&self, vcs: &Vcs) -> Result<Test, InitError> {
+        self.init_with(vcs, None)
+    }
+
+    /// Initializes the test suite of this project like [`Project::init`], but
+    /// creates the example test with the given references instead of the
+    /// bundled ones if there are any, see [`Reference::compile_default`].
+    pub fn init_with(&self, vcs: &Vcs, reference: Option<Reference>) -> Result<Test, InitError> {
+        let test_root = self.paths.test_root();
+        if test_root.try_exists()? && fs::read_dir(&test_root)?.next().is_some() {
+            return Err(InitError::AlreadyInitialized);
+        }
+
+        stdx::fs::create_dir(&test_root, true)?;
+
+        let id = Id::new(EXAMPLE_TEST).expect("the example test id is valid");
+        let test = match reference {
+            Some(reference) => Test::create_default_with(&self.paths, id, reference)?,
+            None => Test::create_default(&self.paths, id)?,
+        };
+        test.create_temporary_directories(&self.paths)?;
+        test.ignore_temporary_directories(&self.paths, vcs)?;
+
+        self.create_cache_dir(vcs)?;
+
+        Ok(test)
+    }
+
+    /// Ignores the given directories relative to the project root in the given
+    /// Vcs, creating them if they don't exist, see [`ConfigLayer::init_ignore`].
+    ///
+    /// [`ConfigLayer::init_ignore`]: crate::config::ConfigLayer::init_ignore
+    pub fn ignore_dirs<S: AsRef<Path>>(&self, vcs: &Vcs, dirs: &[S]) -> io::Result<()> {
+        let root = self.paths.project_root();
+        let dirs: Vec<_> = dirs.iter().map(|dir| root.join(dir)).collect();
+        vcs.ignore_dirs(dirs.iter().map(PathBuf::as_path))
+    }
+
+    /// Creates the cache directory of this project if it doesn't exist and
+    /// ignores it in the given Vcs. Returns the path to the cache directory.
+    pub fn create_cache_dir(&self, vcs: &Vcs) -> io::Result<PathBuf> {
+        let cache_dir = self.paths.cache_dir();
+        stdx::fs::create_dir(&cache_dir, true)?;
+        vcs.ignore_dir(&cache_dir)?;
+
+        Ok(cache_dir)
+    }
+}
+
+/// Chooses the project root from the given candidates, ordered nearest first.
+/// If `fallback` is `true` and the nearest candidate has no test root, the
+/// nearest ancestor with one is chosen instead.
+fn choose_root(candidates: &[PathBuf], fallback: bool) -> io::Result<Option<&Path>> {
+    let Some(nearest) = candidates.first() else {
+        return Ok(None);
+    };
+
+    let has_tests = |dir: &Path| dir.join(TEST_ROOT).try_exists();
+    if !fallback || has_tests(nearest)? {
+        return Ok(Some(nearest));
+    }
+
+    for candidate in &candidates[1..] {
+        if has_tests(candidate)? {
+            tracing::debug!(?nearest, ?candidate, "nearest manifest has no test root");
+            return Ok(Some(candidate));
+        }
+    }
+
+    Ok(Some(nearest))
 }
 
 /// Returned by [`Project::discover`].
@@ -250,9 +504,164 @@ pub enum DiscoverError {
     Io(#[from] io::Error),
 }
 
+/// Returned by [`Project::init`].
+#[derive(Debug, Error)]
+pub enum InitError {
+    /// The test root already exists and is not empty.
+    #[error("the test root already exists and is not empty")]
+    AlreadyInitialized,
+
+    /// An error occurred while creating the example test.
+    #[error("an error occurred while creating the example test")]
+    Create(#[from] test::CreateError),
+
+    /// An io error occurred.
+    #[error("an io error occurred")]
+    Io(#[from] io::Error),
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::_dev;
+    use crate::test::DEFAULT_TEST_INPUT;
+
+    #[test]
+    fn test_init_git() {
+        _dev::fs::TempEnv::run(
+            |root| root,
+            |root| {
+                let project = Project::new(None, Paths::new(root, None), None);
+                let vcs = Vcs::new(root, VcsKind::Git);
+                project.init(&vcs).unwrap();
+                assert!(matches!(
+                    project.init(&vcs),
+                    Err(InitError::AlreadyInitialized)
+                ));
+            },
+            |root| {
+                root.expect_file_content("tests/example/test.typ", DEFAULT_TEST_INPUT)
+                    .expect_file("tests/example/ref/1.png")
+                    .expect_file_content(
+                        "tests/example/out/.gitignore",
+                        "# generated by typst-test, do not edit\n**\n",
+                    )
+                    .expect_file_content(
+                        "tests/example/diff/.gitignore",
+                        "# generated by typst-test, do not edit\n**\n",
+                    )
+                    .expect_file_content(
+                        ".typst-test/cache/.gitignore",
+                        "# generated by typst-test, do not edit\n**\n",
+                    )
+            },
+        );
+    }
+
+    #[test]
+    fn test_init_none() {
+        _dev::fs::TempEnv::run(
+            |root| root,
+            |root| {
+                let project = Project::new(None, Paths::new(root, None), None);
+                project.init(&Vcs::null(root)).unwrap();
+            },
+            |root| {
+                root.expect_file_content("tests/example/test.typ", DEFAULT_TEST_INPUT)
+                    .expect_file("tests/example/ref/1.png")
+                    .expect_dir("tests/example/out")
+                    .expect_dir("tests/example/diff")
+                    .expect_dir(".typst-test/cache")
+            },
+        );
+    }
+
+    #[test]
+    fn test_ignore_dirs_git() {
+        _dev::fs::TempEnv::run(
+            |root| root.setup_file("build/main.pdf", "pdf"),
+            |root| {
+                let project = Project::new(None, Paths::new(root, None), None);
+                let vcs = Vcs::new(root, VcsKind::Git);
+                project.ignore_dirs(&vcs, &["build", "dist/tmp"]).unwrap();
+            },
+            |root| {
+                root.expect_file_content("build/main.pdf", "pdf")
+                    .expect_file_content(
+                        "build/.gitignore",
+                        "# generated by typst-test, do not edit\n**\n",
+                    )
+                    .expect_file_content(
+                        "dist/tmp/.gitignore",
+                        "# generated by typst-test, do not edit\n**\n",
+                    )
+            },
+        );
+    }
+
+    #[test]
+    fn test_cache_dir() {
+        let paths = Paths::new("root", None);
+        assert_eq!(paths.tool_dir(), Path::new("root/.typst-test"));
+        assert_eq!(paths.cache_dir(), Path::new("root/.typst-test/cache"));
+        assert!(!paths.objects_dir().starts_with(paths.cache_dir()));
+    }
+
+    #[test]
+    fn test_create_cache_dir() {
+        _dev::fs::TempEnv::run(
+            |root| root,
+            |root| {
+                let project = Project::new(None, Paths::new(root, None), None);
+                let vcs = Vcs::new(root, VcsKind::Git);
+                let cache_dir = project.create_cache_dir(&vcs).unwrap();
+                assert_eq!(cache_dir, root.join(".typst-test/cache"));
+
+                // creating it again is a no-op
+                project.create_cache_dir(&vcs).unwrap();
+            },
+            |root| {
+                root.expect_file_content(
+                    ".typst-test/cache/.gitignore",
+                    "# generated by typst-test, do not edit\n**\n",
+                )
+            },
+        );
+    }
+
+    #[test]
+    fn test_discover_mercurial() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| root.setup_dir(".hg").setup_dir("project"),
+            |root| {
+                let project = Project::discover(root.join("project"), true)
+                    .unwrap()
+                    .unwrap();
+
+                assert_eq!(project.vcs().map(Vcs::kind), Some(VcsKind::Mercurial));
+                assert_eq!(project.paths().vcs_root(), Some(root));
+            },
+        );
+    }
+
+    #[test]
+    fn test_discover_no_vcs_root() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| root.setup_dir("project"),
+            |root| {
+                let project = Project::discover(root.join("project"), true)
+                    .unwrap()
+                    .unwrap();
+
+                // NOTE(tinger): the temp dir may itself be inside a repository,
+                // if so we must have found its actual root
+                match project.vcs() {
+                    Some(vcs) => assert_eq!(project.paths().vcs_root(), Some(vcs.root())),
+                    None => assert_eq!(project.paths().vcs_root(), None),
+                }
+            },
+        );
+    }
 
     #[test]
     fn test_paths() {
@@ -267,6 +676,10 @@ mod tests {
             paths.test_script(&id),
             PathBuf::from_iter(["root", "tests", "a", "b", "test.typ"])
         );
+        assert_eq!(
+            paths.test_file_script(&id),
+            PathBuf::from_iter(["root", "tests", "a", "b.typ"])
+        );
         assert_eq!(
             paths.test_ref_script(&id),
             PathBuf::from_iter(["root", "tests", "a", "b", "ref.typ"])
@@ -283,5 +696,136 @@ mod tests {
             paths.test_diff_dir(&id),
             PathBuf::from_iter(["root", "tests", "a", "b", "diff"])
         );
+        assert_eq!(
+            paths.test_ref_variant_dir(&id, "dark"),
+            PathBuf::from_iter(["root", "tests", "a", "b", "ref", "dark"])
+        );
+        assert_eq!(
+            paths.test_out_variant_dir(&id, "dark"),
+            PathBuf::from_iter(["root", "tests", "a", "b", "out", "dark"])
+        );
+        assert_eq!(
+            paths.test_diff_variant_dir(&id, "dark"),
+            PathBuf::from_iter(["root", "tests", "a", "b", "diff", "dark"])
+        );
+    }
+
+    #[test]
+    fn test_paths_script_names() {
+        let id = Id::new("a/b").unwrap();
+
+        let mut project = Project::new(None, Paths::new("root", None), None);
+        project.configure(&Config::new(None));
+        assert_eq!(project.paths().test_script_name(), DEFAULT_TEST_SCRIPT);
+        assert_eq!(project.paths().ref_script_name(), DEFAULT_REF_SCRIPT);
+
+        let mut config = Config::new(None);
+        config.project = Some(config::ConfigLayer {
+            test_script: Some("main.typ".into()),
+            ref_script: Some("expected.typ".into()),
+            ..Default::default()
+        });
+        project.configure(&config);
+
+        assert_eq!(
+            project.paths().test_script(&id),
+            PathBuf::from_iter(["root", "tests", "a", "b", "main.typ"])
+        );
+        assert_eq!(
+            project.paths().test_ref_script(&id),
+            PathBuf::from_iter(["root", "tests", "a", "b", "expected.typ"])
+        );
+    }
+
+    const MANIFEST: &str =
+        "[package]\nname = \"a\"\nversion = \"0.1.0\"\nentrypoint = \"lib.typ\"\n";
+
+    fn discover(root: &Path, dir: &str) -> Project {
+        Project::discover(root.join(dir), false).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_discover_nested_prefers_nearest() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_dir(".git")
+                    .setup_file("typst.toml", MANIFEST)
+                    .setup_dir("tests")
+                    .setup_file("packages/b/typst.toml", MANIFEST)
+                    .setup_dir("packages/b/tests")
+                    .setup_dir("packages/b/src")
+            },
+            |root| {
+                let project = discover(root, "packages/b/src");
+                assert_eq!(project.paths().project_root(), root.join("packages/b"));
+                assert!(!project.is_fallback_root());
+                assert!(project.manifest().is_some());
+                assert_eq!(project.paths().vcs_root(), Some(root));
+                assert_eq!(
+                    project.root_candidates(),
+                    [root.join("packages/b"), root.to_path_buf()]
+                );
+
+                let project = discover(root, "packages");
+                assert_eq!(project.paths().project_root(), root);
+                assert_eq!(project.root_candidates(), [root.to_path_buf()]);
+            },
+        );
+    }
+
+    #[test]
+    fn test_discover_nested_without_tests() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_dir(".git")
+                    .setup_file("typst.toml", MANIFEST)
+                    .setup_dir("tests")
+                    .setup_file("tests/fixture/typst.toml", MANIFEST)
+            },
+            |root| {
+                let project = discover(root, "tests/fixture");
+                assert_eq!(project.paths().project_root(), root);
+                assert!(project.is_fallback_root());
+                assert_eq!(
+                    project.root_candidates(),
+                    [root.join("tests/fixture"), root.to_path_buf()]
+                );
+
+                let project = Project::discover_nearest(root.join("tests/fixture"), false)
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(project.paths().project_root(), root.join("tests/fixture"));
+                assert!(!project.is_fallback_root());
+            },
+        );
+    }
+
+    #[test]
+    fn test_discover_nested_stops_at_vcs_root() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_file("typst.toml", MANIFEST)
+                    .setup_dir("repo/.git")
+                    .setup_file("repo/typst.toml", MANIFEST)
+            },
+            |root| {
+                let project = discover(root, "repo");
+                assert_eq!(project.paths().project_root(), root.join("repo"));
+                assert_eq!(project.root_candidates(), [root.join("repo")]);
+            },
+        );
+    }
+
+    #[test]
+    fn test_discover_explicit_root_reads_manifest() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| root.setup_dir(".git").setup_file("typst.toml", MANIFEST),
+            |root| {
+                let project = Project::discover(root, true).unwrap().unwrap();
+                assert_eq!(project.paths().project_root(), root);
+                assert!(project.manifest().is_some());
+                assert!(project.root_candidates().is_empty());
+            },
+        );
     }
 }
@@ -3,6 +3,7 @@
 //! supports `.gitignore` and `.hgignore` based VCS' as well as auto discovery
 //! of Git, Mercurial and Jujutsu through their hidden repository directories.
 
+use std::collections::BTreeSet;
 use std::fmt::{self, Debug, Display};
 use std::path::{Path, PathBuf};
 use std::{fs, io};
@@ -19,8 +20,14 @@ const GITIGNORE_CONTENT: &str = "# generated by typst-test, do not edit\n**\n";
 /// The name of the mercurial ignore file.
 const HGIGNORE_NAME: &str = ".hgignore";
 
-/// The content of the generated mercurial ignore file.
-const HGIGNORE_CONTENT: &str = "# generated by typst-test, do not edit\nregex: glob\n**\n";
+/// The first line of the generated section in the mercurial ignore file.
+const HGIGNORE_BEGIN: &str = "# begin generated by typst-test, do not edit";
+
+/// The syntax line of the generated section in the mercurial ignore file.
+const HGIGNORE_SYNTAX: &str = "syntax: glob";
+
+/// The last line of the generated section in the mercurial ignore file.
+const HGIGNORE_END: &str = "# end generated by typst-test";
 
 /// The kind of [`Vcs`] in use.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -31,10 +38,16 @@ pub enum Kind {
     /// like Jujutsu.
     Git,
 
-    /// Uses `.hgignore` files to ignore ephemeral storage directories.
+    /// Uses the `.hgignore` file at the repository root to ignore ephemeral
+    /// storage directories, the patterns are kept in a generated section at
+    /// the end of the file.
     ///
     /// This means it can also be used by Vcs' which support `.hgignore` files.
     Mercurial,
+
+    /// Doesn't ignore any directories, this is used when no supported Vcs was
+    /// found, see [`Vcs::null`].
+    None,
 }
 
 /// A version control system, this is used to handle persistent storage of
@@ -58,9 +71,21 @@ impl Vcs {
         }
     }
 
+    /// Creates a new Vcs which doesn't ignore any directories, all operations on
+    /// it are no-ops.
+    pub fn null<I>(root: I) -> Self
+    where
+        I: Into<PathBuf>,
+    {
+        Self::new(root, Kind::None)
+    }
+
     /// Checks the given directory for a Vcs, returning it a vcs is rooted here.
+    ///
+    /// Git and Jujutsu repositories are detected by their `.git` and `.jj`
+    /// directories, Mercurial repositories by their `.hg` directory.
     pub fn try_new(root: &Path) -> io::Result<Option<Self>> {
-        if root.join(".git").try_exists()? && root.join(".jj").try_exists()? {
+        if root.join(".git").try_exists()? || root.join(".jj").try_exists()? {
             Ok(Some(Self::new(root, Kind::Git)))
         } else if root.join(".hg").try_exists()? {
             Ok(Some(Self::new(root, Kind::Mercurial)))
@@ -81,35 +106,157 @@ impl Vcs {
         self.kind
     }
 
-    /// Ignore the given directory.
+    /// The glob pattern ignoring the given directory in the root `.hgignore`
+    /// file.
+    fn hgignore_pattern(&self, path: &Path) -> io::Result<String> {
+        let relative = path.strip_prefix(&self.root).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} is not inside the repository at {}",
+                    path.display(),
+                    self.root.display(),
+                ),
+            )
+        })?;
+
+        let mut pattern = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        pattern.push_str("/**");
+
+        Ok(pattern)
+    }
+
+    /// Reads the root `.hgignore` file, returning the lines outside of the
+    /// generated section and the patterns inside it.
+    fn read_hgignore(&self) -> io::Result<(Vec<String>, BTreeSet<String>)> {
+        let content = fs::read_to_string(self.root.join(HGIGNORE_NAME))
+            .ignore_io_kind(io::ErrorKind::NotFound)?
+            .unwrap_or_default();
+
+        let mut lines = vec![];
+        let mut patterns = BTreeSet::new();
+        let mut in_section = false;
+        for line in content.lines() {
+            match line {
+                HGIGNORE_BEGIN => in_section = true,
+                HGIGNORE_END => in_section = false,
+                HGIGNORE_SYNTAX if in_section => {}
+                _ if in_section => {
+                    patterns.insert(line.to_owned());
+                }
+                _ => lines.push(line.to_owned()),
+            }
+        }
+
+        Ok((lines, patterns))
+    }
+
+    /// Writes the root `.hgignore` file with the generated section at the
+    /// end, the section is omitted if there are no patterns.
+    fn write_hgignore(&self, lines: &[String], patterns: &BTreeSet<String>) -> io::Result<()> {
+        let mut content = String::new();
+        for line in lines {
+            content.push_str(line);
+            content.push('\n');
+        }
+
+        if !patterns.is_empty() {
+            for line in [HGIGNORE_BEGIN, HGIGNORE_SYNTAX]
+                .into_iter()
+                .chain(patterns.iter().map(String::as_str))
+                .chain([HGIGNORE_END])
+            {
+                content.push_str(line);
+                content.push('\n');
+            }
+        }
+
+        stdx::fs::write_atomic(self.root.join(HGIGNORE_NAME), content)
+    }
+
+    /// Ignore the given directory, this is a no-op if it is already ignored
+    /// with the expected content.
     pub fn ignore_dir(&self, path: &Path) -> io::Result<()> {
+        self.ignore_dirs([path])
+    }
+
+    /// Ignore all given directories at once, directories which are already
+    /// ignored with the expected content are not written to again.
+    pub fn ignore_dirs<'p, I>(&self, paths: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = &'p Path>,
+    {
         match self.kind {
             Kind::Git => {
-                let gitignore = path.join(GITIGNORE_NAME);
-                stdx::fs::create_dir(path, true)?;
-                fs::write(gitignore, GITIGNORE_CONTENT)?;
+                let mut seen = BTreeSet::new();
+                for path in paths {
+                    if !seen.insert(path) {
+                        continue;
+                    }
+
+                    let file = path.join(GITIGNORE_NAME);
+                    let existing =
+                        fs::read_to_string(&file).ignore_io_kind(io::ErrorKind::NotFound)?;
+
+                    if existing.as_deref() == Some(GITIGNORE_CONTENT) {
+                        continue;
+                    }
+
+                    stdx::fs::create_dir(path, true)?;
+                    stdx::fs::write_atomic(file, GITIGNORE_CONTENT)?;
+                }
             }
             Kind::Mercurial => {
-                let hgignore = path.join(HGIGNORE_NAME);
-                stdx::fs::create_dir(path, true)?;
-                fs::write(hgignore, HGIGNORE_CONTENT)?;
+                let (lines, mut patterns) = self.read_hgignore()?;
+
+                let mut changed = false;
+                for path in paths {
+                    stdx::fs::create_dir(path, true)?;
+                    changed |= patterns.insert(self.hgignore_pattern(path)?);
+                }
+
+                if changed {
+                    self.write_hgignore(&lines, &patterns)?;
+                }
             }
+            Kind::None => {}
         }
 
         Ok(())
     }
 
+    /// Whether the given directory is ignored by this Vcs, i.e. whether it
+    /// contains a `.gitignore` file or has a pattern in the root `.hgignore`
+    /// file.
+    pub fn is_ignored_dir(&self, path: &Path) -> io::Result<bool> {
+        match self.kind {
+            Kind::Git => path.join(GITIGNORE_NAME).try_exists(),
+            Kind::Mercurial => {
+                let (_, patterns) = self.read_hgignore()?;
+                Ok(patterns.contains(&self.hgignore_pattern(path)?))
+            }
+            Kind::None => Ok(false),
+        }
+    }
+
     /// Unignore the given directory.
     pub fn unignore_dir(&self, path: &Path) -> io::Result<()> {
         match self.kind {
             Kind::Git => {
                 let gitignore = path.join(GITIGNORE_NAME);
-                fs::remove_file(gitignore).ignore(|e| e.kind() == io::ErrorKind::NotFound)?;
+                fs::remove_file(gitignore).ignore_io_kind(io::ErrorKind::NotFound)?;
             }
             Kind::Mercurial => {
-                let hgignore = path.join(HGIGNORE_NAME);
-                fs::remove_file(hgignore).ignore(|e| e.kind() == io::ErrorKind::NotFound)?;
+                let (lines, mut patterns) = self.read_hgignore()?;
+                if patterns.remove(&self.hgignore_pattern(path)?) {
+                    self.write_hgignore(&lines, &patterns)?;
+                }
             }
+            Kind::None => {}
         }
 
         Ok(())
@@ -121,6 +268,7 @@ impl Display for Vcs {
         f.pad(match self.kind {
             Kind::Git => "Git",
             Kind::Mercurial => "Mercurial",
+            Kind::None => "none",
         })
     }
 }
@@ -132,6 +280,40 @@ mod tests {
     use crate::project::test::Id;
     use crate::project::Paths;
 
+    #[test]
+    fn test_try_new() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_dir("git/.git")
+                    .setup_dir("jj/.jj")
+                    .setup_dir("hg/.hg")
+                    .setup_dir("none")
+            },
+            |root| {
+                let kind = |dir| Vcs::try_new(&root.join(dir)).unwrap().map(|vcs| vcs.kind());
+                assert_eq!(kind("git"), Some(Kind::Git));
+                assert_eq!(kind("jj"), Some(Kind::Git));
+                assert_eq!(kind("hg"), Some(Kind::Mercurial));
+                assert_eq!(kind("none"), None);
+            },
+        );
+    }
+
+    #[test]
+    fn test_is_ignored_dir() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_file("ignored/.gitignore", GITIGNORE_CONTENT)
+                    .setup_dir("not-ignored")
+            },
+            |root| {
+                let vcs = Vcs::new(root, Kind::Git);
+                assert!(vcs.is_ignored_dir(&root.join("ignored")).unwrap());
+                assert!(!vcs.is_ignored_dir(&root.join("not-ignored")).unwrap());
+            },
+        );
+    }
+
     #[test]
     fn test_git_ignore_dir_create() {
         _dev::fs::TempEnv::run(
@@ -199,4 +381,145 @@ mod tests {
             |root| root.expect_dir("tests/fancy/out"),
         );
     }
+
+    #[test]
+    fn test_hg_ignore_dir_create() {
+        _dev::fs::TempEnv::run(
+            |root| root.setup_dir(".hg").setup_dir("tests/fancy/out"),
+            |root| {
+                let paths = Paths::new(root, None);
+                let vcs = Vcs::try_new(root).unwrap().unwrap();
+                vcs.ignore_dir(&paths.test_out_dir(&Id::new("fancy").unwrap()))
+                    .unwrap();
+                assert!(vcs
+                    .is_ignored_dir(&paths.test_out_dir(&Id::new("fancy").unwrap()))
+                    .unwrap());
+            },
+            |root| {
+                root.expect_dir(".hg")
+                    .expect_dir("tests/fancy/out")
+                    .expect_file_content(
+                        ".hgignore",
+                        "# begin generated by typst-test, do not edit\n\
+                        syntax: glob\n\
+                        tests/fancy/out/**\n\
+                        # end generated by typst-test\n",
+                    )
+            },
+        );
+    }
+
+    #[test]
+    fn test_hg_ignore_dirs_keep_user_patterns() {
+        _dev::fs::TempEnv::run(
+            |root| {
+                root.setup_file(
+                    ".hgignore",
+                    "syntax: regexp\n\
+                    \\.orig$\n\
+                    # begin generated by typst-test, do not edit\n\
+                    syntax: glob\n\
+                    tests/fancy/out/**\n\
+                    # end generated by typst-test\n",
+                )
+            },
+            |root| {
+                let paths = Paths::new(root, None);
+                let id = Id::new("fancy").unwrap();
+                let out = paths.test_out_dir(&id);
+                let diff = paths.test_diff_dir(&id);
+
+                let vcs = Vcs::new(root, Kind::Mercurial);
+                vcs.ignore_dirs([out.as_path(), diff.as_path()]).unwrap();
+                assert!(vcs.is_ignored_dir(&diff).unwrap());
+                assert!(!vcs.is_ignored_dir(&paths.test_ref_dir(&id)).unwrap());
+            },
+            |root| {
+                root.expect_dir("tests/fancy/out")
+                    .expect_dir("tests/fancy/diff")
+                    .expect_file_content(
+                        ".hgignore",
+                        "syntax: regexp\n\
+                        \\.orig$\n\
+                        # begin generated by typst-test, do not edit\n\
+                        syntax: glob\n\
+                        tests/fancy/diff/**\n\
+                        tests/fancy/out/**\n\
+                        # end generated by typst-test\n",
+                    )
+            },
+        );
+    }
+
+    #[test]
+    fn test_hg_unignore_dir_remove() {
+        _dev::fs::TempEnv::run(
+            |root| {
+                root.setup_dir("tests/fancy/out").setup_file(
+                    ".hgignore",
+                    "build\n\
+                    # begin generated by typst-test, do not edit\n\
+                    syntax: glob\n\
+                    tests/fancy/out/**\n\
+                    # end generated by typst-test\n",
+                )
+            },
+            |root| {
+                let paths = Paths::new(root, None);
+                let vcs = Vcs::new(root, Kind::Mercurial);
+                vcs.unignore_dir(&paths.test_out_dir(&Id::new("fancy").unwrap()))
+                    .unwrap();
+            },
+            |root| {
+                root.expect_dir("tests/fancy/out")
+                    .expect_file_content(".hgignore", "build\n")
+            },
+        );
+    }
+
+    #[test]
+    fn test_null_no_op() {
+        _dev::fs::TempEnv::run(
+            |root| {
+                root.setup_dir("tests/fancy/out")
+                    .setup_file("tests/fancy/diff/.gitignore", GITIGNORE_CONTENT)
+            },
+            |root| {
+                let paths = Paths::new(root, None);
+                let id = Id::new("fancy").unwrap();
+                let vcs = Vcs::null(root);
+                vcs.ignore_dir(&paths.test_out_dir(&id)).unwrap();
+                vcs.unignore_dir(&paths.test_diff_dir(&id)).unwrap();
+                assert!(!vcs.is_ignored_dir(&paths.test_out_dir(&id)).unwrap());
+                assert!(!vcs.is_ignored_dir(&paths.test_diff_dir(&id)).unwrap());
+            },
+            |root| {
+                root.expect_dir("tests/fancy/out")
+                    .expect_file_content("tests/fancy/diff/.gitignore", GITIGNORE_CONTENT)
+            },
+        );
+    }
+
+    #[test]
+    fn test_git_ignore_dirs_idempotent() {
+        _dev::fs::TempEnv::run(
+            |root| root.setup_file("tests/fancy/diff/.gitignore", "blah blah"),
+            |root| {
+                let paths = Paths::new(root, None);
+                let id = Id::new("fancy").unwrap();
+                let out = paths.test_out_dir(&id);
+                let diff = paths.test_diff_dir(&id);
+
+                let vcs = Vcs::new(root, Kind::Git);
+                for _ in 0..2 {
+                    vcs.ignore_dirs([out.as_path(), diff.as_path(), out.as_path()])
+                        .unwrap();
+                }
+            },
+            |root| {
+                root.expect_file_content("tests/fancy/out/.gitignore", GITIGNORE_CONTENT)
+                    .expect_file_content("tests/fancy/diff/.gitignore", GITIGNORE_CONTENT)
+            },
+        );
+    }
 }
@@ -0,0 +1,255 @@
+//! Running user supplied hooks before and after tests.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+
+use thiserror::Error;
+
+use crate::config::{Config, HookConfig, Layer};
+use crate::project::Project;
+use crate::test::Test;
+
+/// The environment variable containing the project root.
+pub const ENV_ROOT: &str = "TYPST_TEST_ROOT";
+
+/// The environment variable containing the id of the current test, this is
+/// only set for per-test hooks.
+pub const ENV_ID: &str = "TYPST_TEST_ID";
+
+/// The environment variable containing the output directory of the current
+/// test, this is only set for per-test hooks.
+pub const ENV_OUT_DIR: &str = "TYPST_TEST_OUT_DIR";
+
+/// The environment variables passed to a hook.
+pub type Env = BTreeMap<&'static str, OsString>;
+
+/// A hook command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hook {
+    /// An executable relative to the project root.
+    Path(PathBuf),
+
+    /// A program which is looked up in `PATH` and its arguments.
+    Args {
+        /// The program to run.
+        program: String,
+
+        /// The arguments passed to the program.
+        args: Vec<String>,
+    },
+}
+
+impl Hook {
+    /// Creates a hook from its config value.
+    pub fn from_config(config: &HookConfig) -> Result<Self, ConfigError> {
+        Ok(match config {
+            HookConfig::Path(path) => Self::Path(path.into()),
+            HookConfig::Args(args) => {
+                let Some((program, args)) = args.split_first() else {
+                    return Err(ConfigError::EmptyArgs);
+                };
+
+                Self::Args {
+                    program: program.clone(),
+                    args: args.to_vec(),
+                }
+            }
+        })
+    }
+
+    /// The program run by this hook, used for reporting.
+    pub fn program(&self) -> &Path {
+        match self {
+            Self::Path(path) => path,
+            Self::Args { program, .. } => Path::new(program),
+        }
+    }
+
+    /// Creates the command for this hook, it is run in the given project
+    /// root.
+    pub fn command(&self, root: &Path) -> Command {
+        let mut command = match self {
+            Self::Path(path) => Command::new(root.join(path)),
+            Self::Args { program, args } => {
+                let mut command = Command::new(program);
+                command.args(args);
+                command
+            }
+        };
+
+        command.current_dir(root);
+        command
+    }
+}
+
+/// The hooks configured for a test run.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Hooks {
+    /// The hook to run once before all tests.
+    pub prepare: Option<Hook>,
+
+    /// The hook to run before each test.
+    pub prepare_each: Option<Hook>,
+
+    /// The hook to run once after all tests.
+    pub cleanup: Option<Hook>,
+
+    /// The hook to run after each test.
+    pub cleanup_each: Option<Hook>,
+}
+
+impl Hooks {
+    /// Resolves the hooks from the given config.
+    pub fn from_config(config: &Config) -> Result<Self, ConfigError> {
+        let hook = |hook: Option<(&HookConfig, Layer)>| {
+            hook.map(|(hook, _)| Hook::from_config(hook)).transpose()
+        };
+
+        Ok(Self {
+            prepare: hook(config.resolve(|c| c.prepare.as_ref()))?,
+            prepare_each: hook(config.resolve(|c| c.prepare_each.as_ref()))?,
+            cleanup: hook(config.resolve(|c| c.cleanup.as_ref()))?,
+            cleanup_each: hook(config.resolve(|c| c.cleanup_each.as_ref()))?,
+        })
+    }
+}
+
+/// The environment of hooks which run once for all tests.
+pub fn env(project: &Project) -> Env {
+    BTreeMap::from([(ENV_ROOT, project.paths().project_root().into())])
+}
+
+/// The environment of hooks which run for the given test.
+pub fn test_env(project: &Project, test: &Test) -> Env {
+    let mut env = env(project);
+    env.insert(ENV_ID, test.id().as_str().into());
+    env.insert(ENV_OUT_DIR, project.paths().test_out_dir(test.id()).into());
+    env
+}
+
+/// Returned by [`Hook::from_config`] and [`Hooks::from_config`].
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    /// A hook was given as an empty argument array.
+    #[error("hook argument array must contain at least the program")]
+    EmptyArgs,
+}
+
+/// Returned by [`run`].
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The program of the hook could not be found.
+    #[error("couldn't find hook program {program:?}")]
+    NotFound {
+        /// The program of the hook.
+        program: PathBuf,
+    },
+
+    /// The hook exited unsuccessfully.
+    #[error("hook {program:?} failed with {status}")]
+    Failed {
+        /// The program of the hook.
+        program: PathBuf,
+
+        /// The exit status of the hook.
+        status: ExitStatus,
+
+        /// The captured stdout of the hook.
+        stdout: String,
+
+        /// The captured stderr of the hook.
+        stderr: String,
+    },
+
+    /// An io error occurred while running the hook.
+    #[error("couldn't run hook {program:?}")]
+    Io {
+        /// The program of the hook.
+        program: PathBuf,
+
+        /// The inner error.
+        #[source]
+        error: io::Error,
+    },
+}
+
+/// Runs the given hook in the given project root with the additional
+/// environment variables, its output is captured.
+pub fn run(hook: &Hook, root: &Path, env: &Env) -> Result<(), Error> {
+    tracing::debug!(?hook, ?env, "running hook");
+
+    let program = hook.program().to_path_buf();
+    let output = match hook.command(root).envs(env).output() {
+        Ok(output) => output,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            return Err(Error::NotFound { program });
+        }
+        Err(error) => return Err(Error::Io { program, error }),
+    };
+
+    if !output.status.success() {
+        return Err(Error::Failed {
+            program,
+            status: output.status,
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_path_command() {
+        let hook = Hook::from_config(&HookConfig::Path("scripts/setup.sh".into())).unwrap();
+        let command = hook.command(Path::new("root"));
+
+        assert_eq!(command.get_program(), Path::new("root/scripts/setup.sh"));
+        assert_eq!(command.get_args().count(), 0);
+        assert_eq!(command.get_current_dir(), Some(Path::new("root")));
+    }
+
+    #[test]
+    fn test_hook_args_command() {
+        let hook = Hook::from_config(&HookConfig::Args(vec![
+            "python".into(),
+            "scripts/setup.py".into(),
+            "--fast".into(),
+        ]))
+        .unwrap();
+        let command = hook.command(Path::new("root"));
+
+        assert_eq!(command.get_program(), "python");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["scripts/setup.py", "--fast"]
+        );
+        assert_eq!(command.get_current_dir(), Some(Path::new("root")));
+
+        assert!(matches!(
+            Hook::from_config(&HookConfig::Args(vec![])),
+            Err(ConfigError::EmptyArgs)
+        ));
+    }
+
+    #[test]
+    fn test_hook_program_not_found() {
+        let hook = Hook::Args {
+            program: "typst-test-missing-hook-program".into(),
+            args: vec![],
+        };
+
+        let err = run(&hook, &std::env::temp_dir(), &Env::new()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "couldn't find hook program \"typst-test-missing-hook-program\""
+        );
+    }
+}
@@ -0,0 +1,2344 @@
+//! Running test suites.
+//!
+//! The [`Runner`] compiles, compares and updates tests, it runs their hooks and
+//! exports their documents. Progress is reported to a [`Reporter`] as tests
+//! complete, [`run_suite`] runs a suite without reporting anything.
+//!
+//! Failures which abort a run are returned as a [`RunError`], failed tests can
+//! be turned into one using [`RunError::from_result`].
+
+use std::borrow::{Borrow, Cow};
+use std::io;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use ecow::{eco_format, EcoString};
+use thiserror::Error;
+use tiny_skia::Pixmap;
+use typst::diag::{Severity, SourceDiagnostic, Warned};
+use typst::foundations::Dict;
+use typst::model::Document as TypstDocument;
+use typst::syntax::{Source, Span};
+use typst::World;
+
+use self::hook::Hook;
+use crate::doc::compare::{self, Strategy};
+use crate::doc::compile::{self, VariantWorld};
+use crate::doc::render::{self, Origin};
+use crate::doc::store::{DirectStore, ObjectStore, Store};
+use crate::doc::{self, Document, LoadError, Pages, SaveError};
+use crate::project::Project;
+use crate::stdx;
+use crate::stdx::fmt::Term;
+use crate::test::{Id, Kind, Suite, SuiteResult, Test, TestResult, TestResultKind};
+
+pub mod hook;
+
+/// The name of the metadata sidecar written next to exported documents.
+pub const METADATA_FILE: &str = "metadata.json";
+
+/// Serializes the metadata of an exported output document rendered at the
+/// given pixel-per-pt.
+pub type ExportMetadata = fn(&Document, f32) -> io::Result<Vec<u8>>;
+
+/// The action to take for each test.
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// Compile and optionally compare tests.
+    Run {
+        /// The strategy to use when comparing documents.
+        strategy: Option<Strategy>,
+
+        /// Whether to export temporaries.
+        export: bool,
+
+        /// The origin at which to render diff images of different dimensions.
+        origin: Origin,
+    },
+
+    /// Compile and update test references.
+    Update {
+        /// Whether to export temporaries.
+        export: bool,
+
+        /// The maximum fraction of pixels which may change before an update
+        /// is refused, `None` updates unconditionally.
+        max_change: Option<f64>,
+
+        /// Whether to create references for compile-only tests, turning them
+        /// into persistent tests once they compiled successfully.
+        create_missing: bool,
+
+        /// The origin at which to render diff images of different dimensions.
+        origin: Origin,
+    },
+}
+
+/// The configuration of a test run.
+#[derive(Debug, Clone)]
+pub struct RunConfig<'c> {
+    /// Whether to promote warnings to errors.
+    pub promote_warnings: bool,
+
+    /// Whether to promote unknown font family warnings to errors.
+    pub hermetic_fonts: bool,
+
+    /// Whether to warn about characters shaped with fallback fonts and add the
+    /// used fonts to font fallback warnings.
+    pub warn_font_fallback: bool,
+
+    /// Whether to promote font fallback warnings to errors.
+    pub error_on_font_fallback: bool,
+
+    /// The options to optimize reference documents with, `None` doesn't
+    /// optimize them.
+    pub optimize: Option<&'c oxipng::Options>,
+
+    /// Writes a metadata sidecar next to exported output documents, `None`
+    /// doesn't write any.
+    pub export_metadata: Option<ExportMetadata>,
+
+    /// Whether to stop after the first failure.
+    pub fail_fast: bool,
+
+    /// The maximum duration of the whole run, once exceeded no new tests are
+    /// started and the run is cancelled.
+    pub timeout: Option<Duration>,
+
+    /// The pixel-per-pt to use when rendering documents.
+    pub pixel_per_pt: f32,
+
+    /// Whether to crop uniformly colored borders off pages before comparing.
+    pub trim_whitespace: bool,
+
+    /// Whether to convert pages to grayscale before comparing.
+    pub compare_grayscale: bool,
+
+    /// Whether to save persistent references in the project's object store.
+    pub object_store: bool,
+
+    /// Whether to treat persistent tests without any references as
+    /// compile-only tests instead of failing them.
+    pub no_references_ok: bool,
+
+    /// Whether to render and compare pages one at a time to reduce peak memory
+    /// usage, this disables exports when running tests.
+    pub low_memory: bool,
+
+    /// Whether to only render and export difference documents for tests
+    /// which failed their comparison.
+    pub diff_only_on_failure: bool,
+
+    /// Whether to never render or export difference documents, regardless of
+    /// the other export settings.
+    pub no_diff: bool,
+
+    /// Whether io errors of individual tests fail only that test instead of
+    /// aborting the whole run.
+    pub keep_going_on_io_errors: bool,
+
+    /// Whether to clear and recreate the temporary directories of each test
+    /// before running it.
+    pub clear_temporary_directories: bool,
+
+    /// The hooks to run before and after tests.
+    pub hooks: hook::Hooks,
+
+    /// The action to take for each test.
+    pub action: Action,
+
+    /// The inputs of the given world, these are used to create the worlds for
+    /// test variants.
+    pub inputs: Dict,
+
+    /// A cancellation flag used to abort a test run, it is set once the
+    /// timeout is exceeded.
+    pub cancellation: Option<&'c AtomicBool>,
+}
+
+impl Default for RunConfig<'_> {
+    fn default() -> Self {
+        Self {
+            promote_warnings: false,
+            hermetic_fonts: false,
+            warn_font_fallback: false,
+            error_on_font_fallback: false,
+            optimize: None,
+            export_metadata: None,
+            fail_fast: false,
+            timeout: None,
+            pixel_per_pt: render::DEFAULT_PIXEL_PER_PT,
+            trim_whitespace: false,
+            compare_grayscale: false,
+            object_store: false,
+            no_references_ok: false,
+            low_memory: false,
+            diff_only_on_failure: false,
+            no_diff: false,
+            keep_going_on_io_errors: false,
+            clear_temporary_directories: false,
+            hooks: hook::Hooks::default(),
+            action: Action::Run {
+                strategy: Some(Strategy::default()),
+                export: false,
+                origin: Origin::default(),
+            },
+            inputs: Dict::new(),
+            cancellation: None,
+        }
+    }
+}
+
+impl RunConfig<'_> {
+    /// Whether the run was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .is_some_and(|cancellation| cancellation.load(Ordering::SeqCst))
+    }
+
+    /// Cancels the run, this does nothing if there is no cancellation flag.
+    fn cancel(&self) {
+        if let Some(cancellation) = self.cancellation {
+            cancellation.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Receives the progress of a test run, all methods do nothing by default.
+pub trait Reporter {
+    /// Reports the start of a test run.
+    fn report_start(&self, _result: &SuiteResult) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Reports the end of a test run.
+    fn report_end(&self, _suite: &Suite, _result: &SuiteResult) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Clears the last status reported with [`Reporter::report_status`].
+    fn clear_status(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Reports the current status of an ongoing test run.
+    fn report_status(&self, _result: &SuiteResult) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Report that a test has passed.
+    fn report_test_pass(&self, _test: &Test, _result: &TestResult) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Report that a test has failed, `diff_hint` is set if difference
+    /// documents may have been exported for it.
+    fn report_test_fail(
+        &self,
+        _test: &Test,
+        _result: &TestResult,
+        _diff_hint: bool,
+    ) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Report that the global timeout was exceeded, the tests which were not
+    /// run are left cancelled in the given result.
+    fn report_timeout(&self, _timeout: Duration, _result: &SuiteResult) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Report that a hook which runs once for all tests has failed.
+    fn report_hook_fail(
+        &self,
+        _hook: &str,
+        _code: Option<i32>,
+        _stdout: &str,
+        _stderr: &str,
+    ) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Reporter for () {}
+
+/// Returned by [`Runner::run`], [`TestRunner::run`] and [`run_suite`], or
+/// created from a failed test result using [`RunError::from_result`].
+#[derive(Debug, Error)]
+pub enum RunError {
+    /// A test or its reference failed to compile.
+    #[error(
+        "compilation of {} {id} failed",
+        if *reference { "reference of test" } else { "test" }
+    )]
+    Compilation {
+        /// The id of the test.
+        id: Id,
+
+        /// Whether the reference failed to compile.
+        reference: bool,
+
+        /// The inner error.
+        #[source]
+        error: compile::Error,
+    },
+
+    /// A test's output differed from its reference.
+    #[error("comparison of test {id} failed")]
+    Comparison {
+        /// The id of the test.
+        id: Id,
+
+        /// The inner error.
+        #[source]
+        error: compare::Error,
+    },
+
+    /// A test's reference was not updated because the change exceeded the
+    /// allowed threshold.
+    #[error(
+        "update of test {id} would have changed {:.1}% of the reference",
+        change * 100.0
+    )]
+    Update {
+        /// The id of the test.
+        id: Id,
+
+        /// The fraction of pixels which would have changed.
+        change: f64,
+    },
+
+    /// A hook exited unsuccessfully, hooks of single tests only fail their
+    /// test and are not returned as an error.
+    #[error("hook {hook} failed")]
+    Hook {
+        /// The id of the test, this is `None` for hooks which run once for all
+        /// tests.
+        id: Option<Id>,
+
+        /// The program of the hook.
+        hook: EcoString,
+
+        /// The exit code of the hook, if it wasn't terminated by a signal.
+        code: Option<i32>,
+    },
+
+    /// A hook could not be run.
+    #[error("couldn't run hook")]
+    RunHook(#[source] hook::Error),
+
+    /// A compile-only test was updated without creating missing references.
+    #[error("attempted to update compile-only test {0}")]
+    UpdateCompileOnly(Id),
+
+    /// The reference source of an ephemeral test was missing.
+    #[error("couldn't find reference source for test {0}")]
+    MissingReferenceSource(Id),
+
+    /// The reference documents of a persistent test were missing.
+    #[error("couldn't find reference documents for test {0}")]
+    MissingReferenceDocuments(Id),
+
+    /// The reference documents of a persistent test couldn't be loaded.
+    #[error("couldn't load reference documents for test {id}")]
+    LoadReferenceDocuments {
+        /// The id of the test.
+        id: Id,
+
+        /// The inner error.
+        #[source]
+        error: LoadError,
+    },
+
+    /// The documents of a test couldn't be saved.
+    #[error("couldn't save documents for test {id}")]
+    SaveDocuments {
+        /// The id of the test.
+        id: Id,
+
+        /// The inner error.
+        #[source]
+        error: SaveError,
+    },
+
+    /// An io error occurred.
+    #[error("an io error occurred")]
+    Io(#[from] io::Error),
+}
+
+impl RunError {
+    /// Creates the error describing why the given test result failed, returns
+    /// `None` if the test didn't fail.
+    pub fn from_result(id: &Id, result: &TestResult) -> Option<Self> {
+        let id = id.clone();
+
+        Some(match result.kind()? {
+            TestResultKind::FailedCompilation { error, reference } => Self::Compilation {
+                id,
+                reference: *reference,
+                error: error.clone(),
+            },
+            TestResultKind::FailedComparison(error) => Self::Comparison {
+                id,
+                error: error.clone(),
+            },
+            TestResultKind::FailedUpdate { change } => Self::Update {
+                id,
+                change: *change,
+            },
+            TestResultKind::FailedHook { hook, code, .. } => Self::Hook {
+                id: Some(id),
+                hook: hook.clone(),
+                code: *code,
+            },
+            TestResultKind::FailedIo { kind, error } => {
+                Self::Io(io::Error::new(*kind, error.as_str()))
+            }
+            TestResultKind::Cancelled
+            | TestResultKind::Filtered
+            | TestResultKind::PassedCompilation
+            | TestResultKind::PassedComparison => return None,
+        })
+    }
+
+    /// The kind of the first io error in the source chain of this error and
+    /// the messages of the chain, returns `None` if there is no io error.
+    fn io_error(&self) -> Option<(io::ErrorKind, EcoString)> {
+        let chain = iter::successors(Some(self as &(dyn std::error::Error + 'static)), |err| {
+            err.source()
+        });
+
+        let kind = chain
+            .clone()
+            .find_map(|err| err.downcast_ref::<io::Error>())?
+            .kind();
+
+        // NOTE(tinger): the message of a plain io error adds nothing to the
+        // message of its source
+        let skip = usize::from(matches!(self, Self::Io(_)));
+        let message = chain
+            .skip(skip)
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(": ");
+
+        Some((kind, message.into()))
+    }
+}
+
+/// Why a test stopped before running to completion.
+enum Stop {
+    /// The test failed, its result was set accordingly.
+    Failed,
+
+    /// The test was cancelled, its result was set accordingly.
+    Cancelled,
+
+    /// An error occurred which may abort the whole run.
+    Error(RunError),
+}
+
+impl From<RunError> for Stop {
+    fn from(err: RunError) -> Self {
+        Self::Error(err)
+    }
+}
+
+impl From<io::Error> for Stop {
+    fn from(err: io::Error) -> Self {
+        Self::Error(err.into())
+    }
+}
+
+/// Runs all matched tests of the given suite without reporting any progress,
+/// tests are compiled using the given world.
+///
+/// If the run is cancelled, the remaining tests are left cancelled in the
+/// result.
+pub fn run_suite(
+    project: &Project,
+    suite: &Suite,
+    world: &dyn World,
+    config: RunConfig<'_>,
+) -> Result<SuiteResult, RunError> {
+    Runner::new(project, suite, world, config).run(&())
+}
+
+/// Runs the matched tests of a suite.
+pub struct Runner<'c, 'p> {
+    project: &'p Project,
+    suite: &'p Suite,
+    world: &'p dyn World,
+    result: SuiteResult,
+    config: RunConfig<'c>,
+}
+
+impl<'c, 'p> Runner<'c, 'p> {
+    /// Creates a new runner for the given suite, tests are compiled using the
+    /// given world.
+    pub fn new(
+        project: &'p Project,
+        suite: &'p Suite,
+        world: &'p dyn World,
+        config: RunConfig<'c>,
+    ) -> Self {
+        Self {
+            project,
+            result: SuiteResult::new(suite),
+            suite,
+            world,
+            config,
+        }
+    }
+
+    /// The config of this runner.
+    pub fn config(&self) -> &RunConfig<'c> {
+        &self.config
+    }
+
+    /// Creates a runner for a single test, the test doesn't need to be part of
+    /// the suite.
+    pub fn test<'s>(&'s self, test: &'p Test) -> TestRunner<'c, 's, 'p> {
+        TestRunner {
+            project_runner: self,
+            test,
+            variant: None,
+            result: TestResult::new(),
+        }
+    }
+
+    fn run_inner(&mut self, reporter: &dyn Reporter) -> Result<(), RunError> {
+        reporter.report_status(&self.result)?;
+
+        for (id, test) in self.suite.matched() {
+            if self.config.is_cancelled() {
+                return Ok(());
+            }
+
+            if let Some(timeout) = self.config.timeout {
+                if self.result.timestamp().elapsed() >= timeout {
+                    self.config.cancel();
+                    reporter.clear_status()?;
+                    reporter.report_timeout(timeout, &self.result)?;
+                    return Ok(());
+                }
+            }
+
+            let result = self.test(test).run()?;
+            if result.is_cancelled() {
+                return Ok(());
+            }
+
+            reporter.clear_status()?;
+            match result.kind() {
+                Some(
+                    TestResultKind::FailedCompilation { .. }
+                    | TestResultKind::FailedComparison(..)
+                    | TestResultKind::FailedUpdate { .. }
+                    | TestResultKind::FailedHook { .. }
+                    | TestResultKind::FailedIo { .. },
+                ) => {
+                    reporter.report_test_fail(test, &result, !self.config.no_diff)?;
+                }
+                Some(TestResultKind::PassedCompilation | TestResultKind::PassedComparison) => {
+                    reporter.report_test_pass(test, &result)?;
+                }
+                _ => unreachable!(),
+            }
+            reporter.report_status(&self.result)?;
+
+            self.result.set_test_result(id.clone(), result);
+        }
+
+        reporter.clear_status()?;
+
+        Ok(())
+    }
+
+    /// Runs a hook which runs once for all tests, failures are reported and
+    /// abort the run.
+    fn run_hook(
+        &self,
+        reporter: &dyn Reporter,
+        hook: &Hook,
+        env: &hook::Env,
+    ) -> Result<(), RunError> {
+        match hook::run(hook, self.project.paths().project_root(), env) {
+            Ok(()) => Ok(()),
+            Err(hook::Error::Failed {
+                program,
+                status,
+                stdout,
+                stderr,
+            }) => {
+                let program = program.to_string_lossy();
+                reporter.report_hook_fail(&program, status.code(), &stdout, &stderr)?;
+
+                Err(RunError::Hook {
+                    id: None,
+                    hook: program.into(),
+                    code: status.code(),
+                })
+            }
+            Err(err) => Err(RunError::RunHook(err)),
+        }
+    }
+
+    /// Ignores the temporary directories of all matched tests in the vcs at
+    /// once, this is done after running the tests, as clearing their
+    /// directories also removes any ignore files within them.
+    fn ignore_temporary_directories(&self) -> io::Result<()> {
+        let paths = self.project.paths();
+        let dirs: Vec<_> = self
+            .suite
+            .matched()
+            .values()
+            .flat_map(|test| test.temporary_directories(paths))
+            .filter(|dir| dir.is_dir())
+            .collect();
+
+        self.project
+            .vcs_or_null()
+            .ignore_dirs(dirs.iter().map(PathBuf::as_path))
+    }
+
+    /// Runs all matched tests of the suite and reports their progress to the
+    /// given reporter.
+    ///
+    /// If the run is cancelled, the remaining tests are left cancelled in the
+    /// result.
+    pub fn run(mut self, reporter: &dyn Reporter) -> Result<SuiteResult, RunError> {
+        self.result.start();
+        reporter.report_start(&self.result)?;
+
+        let env = hook::env(self.project);
+        let res = match &self.config.hooks.prepare {
+            Some(prepare) => self.run_hook(reporter, prepare, &env),
+            None => Ok(()),
+        };
+
+        let res = res.and_then(|_| {
+            let res = self.run_inner(reporter);
+            let ignore = match self.config.clear_temporary_directories {
+                true => self.ignore_temporary_directories().map_err(Into::into),
+                false => Ok(()),
+            };
+            let cleanup = match &self.config.hooks.cleanup {
+                Some(cleanup) => self.run_hook(reporter, cleanup, &env),
+                None => Ok(()),
+            };
+
+            res.and(ignore).and(cleanup)
+        });
+
+        self.result.end();
+        reporter.report_end(self.suite, &self.result)?;
+
+        res?;
+
+        Ok(self.result)
+    }
+}
+
+/// Runs a single test and all of its variants.
+pub struct TestRunner<'c, 's, 'p> {
+    project_runner: &'s Runner<'c, 'p>,
+    test: &'p Test,
+    variant: Option<EcoString>,
+    result: TestResult,
+}
+
+impl TestRunner<'_, '_, '_> {
+    /// Runs the test, failures of the test itself are returned in its result.
+    pub fn run(mut self) -> Result<TestResult, RunError> {
+        self.result.start();
+        let res = self.prepare().and_then(|_| {
+            let res = self.run_inner();
+            let cleanup = self.cleanup();
+            res.and(cleanup)
+        });
+        self.result.end();
+
+        let err = match res {
+            Ok(()) | Err(Stop::Failed | Stop::Cancelled) => return Ok(self.result),
+            Err(Stop::Error(err)) => err,
+        };
+
+        if self.project_runner.config.keep_going_on_io_errors {
+            if let Some((kind, message)) = err.io_error() {
+                tracing::warn!(test = ?self.test.id(), ?err, "io error in test");
+                self.result.set_failed_io(kind, message);
+                return Ok(self.result);
+            }
+        }
+
+        Err(err)
+    }
+
+    fn run_inner(&mut self) -> Result<(), Stop> {
+        let variants = self.test.variants();
+        if variants.is_empty() {
+            return self.run_variant();
+        }
+
+        for variant in variants {
+            tracing::trace!(test = ?self.test.id(), ?variant, "running variant");
+
+            self.variant = Some(variant.clone());
+            self.result.set_variant(self.variant.clone());
+            self.run_variant()?;
+        }
+
+        self.variant = None;
+        self.result.set_variant(None);
+
+        Ok(())
+    }
+
+    fn run_variant(&mut self) -> Result<(), Stop> {
+        // TODO(tinger): don't exit early if there are still exports possible
+
+        let paths = self.project_runner.project.paths();
+        let vcs = self.project_runner.project.vcs_or_null();
+
+        match self.project_runner.config.action {
+            Action::Run {
+                strategy,
+                export,
+                origin,
+            } => {
+                let strategy = strategy.map(|strategy| self.test.compare_strategy(strategy));
+
+                if self.project_runner.config.low_memory {
+                    return self.run_variant_low_memory(strategy);
+                }
+
+                let output = self.load_out_src()?;
+                let output = self.compile_out_doc(output)?;
+                let output = self.render_doc(output);
+
+                if export {
+                    self.export_out_doc(&output)?;
+                }
+
+                match self.test.kind() {
+                    Kind::Ephemeral => {
+                        let reference = self.load_ref_src()?;
+                        let reference = self.compile_ref_doc(reference)?;
+                        let reference = self.render_doc(reference);
+
+                        if export {
+                            self.export_ref_doc(&reference)?;
+                        }
+
+                        self.compare_and_export_diff(
+                            &output, &reference, strategy, export, origin,
+                        )?;
+                    }
+                    Kind::Persistent if self.is_missing_references()? => {}
+                    Kind::Persistent => {
+                        let reference = self.load_ref_doc()?;
+
+                        self.compare_and_export_diff(
+                            &output, &reference, strategy, export, origin,
+                        )?;
+                    }
+                    Kind::CompileOnly => {}
+                }
+            }
+            Action::Update {
+                export,
+                max_change,
+                origin,
+                create_missing,
+            } => match self.test.kind() {
+                Kind::Ephemeral => {
+                    let output = self.load_out_src()?;
+                    let output = self.compile_out_doc(output)?;
+                    let output = self.render_doc(output);
+
+                    if export {
+                        self.export_out_doc(&output)?;
+                    }
+                }
+                Kind::CompileOnly if !create_missing => {
+                    return Err(RunError::UpdateCompileOnly(self.test.id().clone()).into());
+                }
+                Kind::Persistent | Kind::CompileOnly => {
+                    let output = self.load_out_src()?;
+                    let output = self.compile_out_doc(output)?;
+                    let output = self.render_doc(output);
+
+                    if let Some(max_change) = max_change {
+                        self.guard_update(&output, max_change)?;
+                    }
+
+                    let optimize_options = self.project_runner.config.optimize;
+
+                    let store: Box<dyn Store> = if self.project_runner.config.object_store {
+                        Box::new(ObjectStore::new(paths.objects_dir()))
+                    } else {
+                        Box::new(DirectStore)
+                    };
+
+                    let stats = match &self.variant {
+                        Some(variant) => self.test.create_variant_reference_documents(
+                            paths,
+                            variant,
+                            &output,
+                            &*store,
+                            optimize_options,
+                        ),
+                        None => self.test.create_reference_documents(
+                            paths,
+                            vcs,
+                            &output,
+                            &*store,
+                            optimize_options,
+                        ),
+                    };
+                    let stats = stats.map_err(|error| RunError::SaveDocuments {
+                        id: self.test.id().clone(),
+                        error,
+                    })?;
+                    self.result.add_save_stats(stats);
+
+                    // NOTE(tinger): the references of compile-only tests may
+                    // have been ignored, they are tracked once they exist
+                    if self.test.kind().is_compile_only() {
+                        self.test.unignore_reference_documents(paths, vcs)?;
+
+                        // there are no previous references to compare to
+                        if export {
+                            self.export_out_doc(&output)?;
+                        }
+                    } else if export {
+                        let reference = self.load_ref_doc()?;
+                        self.export_out_doc(&reference)?;
+
+                        if !self.project_runner.config.no_diff {
+                            let diff = Document::render_diff(&reference, &output, origin);
+                            self.export_diff_doc(&diff)?;
+                        }
+                    }
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    fn prepare(&mut self) -> Result<(), Stop> {
+        if self.project_runner.config.clear_temporary_directories {
+            tracing::trace!(test = ?self.test.id(), "clearing temporary directories");
+
+            self.test
+                .create_temporary_directories(self.project_runner.project.paths())?;
+        }
+
+        let runner = self.project_runner;
+        if let Some(prepare) = &runner.config.hooks.prepare_each {
+            self.run_hook(prepare)?;
+        }
+
+        let test = self.test;
+        if let Some(prepare) = test.prepare_hook() {
+            self.run_hook(&self.test_hook(prepare))?;
+        }
+
+        Ok(())
+    }
+
+    fn cleanup(&mut self) -> Result<(), Stop> {
+        let test = self.test;
+        if let Some(cleanup) = test.cleanup_hook() {
+            self.run_hook(&self.test_hook(cleanup))?;
+        }
+
+        let runner = self.project_runner;
+        if let Some(cleanup) = &runner.config.hooks.cleanup_each {
+            self.run_hook(cleanup)?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates a hook given in an annotation of this test, it is relative to
+    /// the test directory.
+    fn test_hook(&self, hook: &str) -> Hook {
+        let paths = self.project_runner.project.paths();
+        Hook::Path(paths.test_dir(self.test.id()).join(hook))
+    }
+
+    /// Runs the given per-test hook with the environment of this test, a
+    /// failing hook fails the test unless it has already failed.
+    fn run_hook(&mut self, hook: &Hook) -> Result<(), Stop> {
+        let project = self.project_runner.project;
+        let env = hook::test_env(project, self.test);
+
+        match hook::run(hook, project.paths().project_root(), &env) {
+            Ok(()) => Ok(()),
+            Err(hook::Error::Failed {
+                program,
+                status,
+                stdout,
+                stderr,
+            }) => {
+                if !self.result.is_fail() {
+                    self.result.set_failed_hook(
+                        program.to_string_lossy().into(),
+                        status.code(),
+                        stdout.into(),
+                        stderr.into(),
+                    );
+                }
+
+                Err(Stop::Failed)
+            }
+            Err(err) => Err(RunError::RunHook(err).into()),
+        }
+    }
+
+    /// The reference directory of the current variant.
+    fn ref_dir(&self) -> PathBuf {
+        let paths = self.project_runner.project.paths();
+        match &self.variant {
+            Some(variant) => paths.test_ref_variant_dir(self.test.id(), variant),
+            None => paths.test_ref_dir(self.test.id()),
+        }
+    }
+
+    /// The output directory of the current variant.
+    fn out_dir(&self) -> PathBuf {
+        let paths = self.project_runner.project.paths();
+        match &self.variant {
+            Some(variant) => paths.test_out_variant_dir(self.test.id(), variant),
+            None => paths.test_out_dir(self.test.id()),
+        }
+    }
+
+    /// The difference directory of the current variant.
+    fn diff_dir(&self) -> PathBuf {
+        let paths = self.project_runner.project.paths();
+        match &self.variant {
+            Some(variant) => paths.test_diff_variant_dir(self.test.id(), variant),
+            None => paths.test_diff_dir(self.test.id()),
+        }
+    }
+
+    /// Whether the references of a persistent test are missing and it should
+    /// be treated like a compile-only test instead.
+    fn is_missing_references(&self) -> io::Result<bool> {
+        if !self.project_runner.config.no_references_ok {
+            return Ok(false);
+        }
+
+        let missing = !doc::has_pages(self.ref_dir())?;
+        if missing {
+            tracing::debug!(test = ?self.test.id(), "treating test without references as compile-only");
+        }
+
+        Ok(missing)
+    }
+
+    /// Renders and compares pages one at a time instead of rendering whole
+    /// documents up front, no documents are exported in this mode.
+    fn run_variant_low_memory(&mut self, strategy: Option<Strategy>) -> Result<(), Stop> {
+        let pixel_per_pt = self.project_runner.config.pixel_per_pt;
+
+        let output = self.load_out_src()?;
+        let output = self.compile_out_doc(output)?;
+
+        match self.test.kind() {
+            Kind::Ephemeral => {
+                let reference = self.load_ref_src()?;
+                let reference = self.compile_ref_doc(reference)?;
+
+                if let Some(strategy) = strategy {
+                    self.compare_pages(
+                        render::render_pages(&output, pixel_per_pt),
+                        render::render_pages(&reference, pixel_per_pt).map(Ok),
+                        strategy,
+                    )?;
+                }
+            }
+            Kind::Persistent if self.is_missing_references()? => {}
+            Kind::Persistent => {
+                let reference = self.load_ref_pages()?;
+
+                if let Some(strategy) = strategy {
+                    self.compare_pages(
+                        render::render_pages(&output, pixel_per_pt),
+                        reference,
+                        strategy,
+                    )?;
+                }
+            }
+            Kind::CompileOnly => {}
+        }
+
+        Ok(())
+    }
+
+    fn load_out_src(&mut self) -> io::Result<Source> {
+        tracing::trace!(test = ?self.test.id(), "loading output source");
+        self.test.load_source(self.project_runner.project.paths())
+    }
+
+    fn load_ref_src(&mut self) -> Result<Source, RunError> {
+        tracing::trace!(test = ?self.test.id(), "loading reference source");
+
+        self.test
+            .load_reference_source(self.project_runner.project.paths())?
+            .ok_or_else(|| RunError::MissingReferenceSource(self.test.id().clone()))
+    }
+
+    fn load_ref_doc(&mut self) -> Result<Document, RunError> {
+        tracing::trace!(test = ?self.test.id(), "loading reference document");
+
+        let paths = self.project_runner.project.paths();
+        let reference = match &self.variant {
+            Some(variant) => self.test.load_variant_reference_documents(paths, variant),
+            None => self.test.load_reference_documents(paths),
+        };
+
+        let reference = reference
+            .map_err(|error| RunError::LoadReferenceDocuments {
+                id: self.test.id().clone(),
+                error,
+            })?
+            .ok_or_else(|| RunError::MissingReferenceDocuments(self.test.id().clone()))?;
+
+        // NOTE(tinger): this is not an error, pages may legitimately differ in
+        // size, but a single page at a different scale is usually a mistake
+        let inconsistent = reference.inconsistent_pages();
+        if !inconsistent.is_empty() {
+            let pages = inconsistent
+                .iter()
+                .map(|idx| (idx + 1).to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            self.result.add_warning(
+                SourceDiagnostic::warning(
+                    Span::detached(),
+                    eco_format!(
+                        "the dimensions of reference {} {pages} are inconsistent with the other pages",
+                        Term::simple("page").with(inconsistent.len()),
+                    ),
+                )
+                .with_hint("one of the pages may have been rendered with a different ppi"),
+            );
+        }
+
+        Ok(reference)
+    }
+
+    /// Collects the reference pages without decoding them, this is used to
+    /// decode and compare one page at a time in low memory mode.
+    fn load_ref_pages(&mut self) -> Result<Pages, RunError> {
+        tracing::trace!(test = ?self.test.id(), "loading reference pages");
+
+        let paths = self.project_runner.project.paths();
+        let reference = match &self.variant {
+            Some(variant) => self.test.load_variant_reference_pages(paths, variant),
+            None => self.test.load_reference_pages(paths),
+        };
+
+        reference
+            .map_err(|error| RunError::LoadReferenceDocuments {
+                id: self.test.id().clone(),
+                error,
+            })?
+            .ok_or_else(|| RunError::MissingReferenceDocuments(self.test.id().clone()))
+    }
+
+    fn render_doc(&mut self, doc: TypstDocument) -> Document {
+        tracing::trace!(test = ?self.test.id(), "rendering document");
+        Document::render(doc, self.project_runner.config.pixel_per_pt)
+    }
+
+    fn compile_out_doc(&mut self, output: Source) -> Result<TypstDocument, Stop> {
+        tracing::trace!(test = ?self.test.id(), "compiling output document");
+        self.compile_inner(output, false)
+    }
+
+    fn compile_ref_doc(&mut self, reference: Source) -> Result<TypstDocument, Stop> {
+        tracing::trace!(test = ?self.test.id(), "compiling reference document");
+        self.compile_inner(reference, true)
+    }
+
+    fn compile_inner(&mut self, source: Source, reference: bool) -> Result<TypstDocument, Stop> {
+        let config = &self.project_runner.config;
+
+        let variant_world;
+        let world: &dyn World = match &self.variant {
+            Some(variant) => {
+                variant_world =
+                    VariantWorld::new(self.project_runner.world, &config.inputs, variant);
+                &variant_world
+            }
+            None => self.project_runner.world,
+        };
+
+        let Warned {
+            output,
+            mut warnings,
+        } = compile::compile(source.clone(), world);
+
+        if config.warn_font_fallback || config.error_on_font_fallback {
+            if let Ok(doc) = &output {
+                let chars = compile::fallback_chars(&source, world, doc);
+                if !chars.is_empty() {
+                    warnings.push(compile::font_fallback_warning(&chars));
+                }
+            }
+        }
+
+        let used_fonts = output
+            .as_ref()
+            .ok()
+            .filter(|_| config.warn_font_fallback)
+            .map(|doc| {
+                let families = compile::used_font_families(doc);
+                families
+                    .iter()
+                    .map(EcoString::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            });
+
+        let (promoted, warnings): (Vec<_>, Vec<_>) = warnings
+            .into_iter()
+            .map(|mut warning| {
+                let is_unknown = compile::is_unknown_font_warning(&warning);
+                let is_fallback = compile::is_font_fallback_warning(&warning);
+                if let Some(used_fonts) = used_fonts.as_ref().filter(|_| is_fallback) {
+                    warning.hint(eco_format!("the document used these fonts: {used_fonts}"));
+                }
+
+                if config.promote_warnings {
+                    warning.severity = Severity::Error;
+                    warning.with_hint("this warning was promoted to an error")
+                } else if config.hermetic_fonts && is_unknown {
+                    warning.severity = Severity::Error;
+                    warning
+                        .with_hint("only embedded fonts and font paths are used for hermetic runs")
+                } else if config.error_on_font_fallback && is_fallback {
+                    warning.severity = Severity::Error;
+                    warning.with_hint("font fallback was promoted to an error")
+                } else {
+                    warning
+                }
+            })
+            .partition(|warning| warning.severity == Severity::Error);
+
+        self.result.set_warnings(warnings);
+
+        let err = match output {
+            Ok(doc) if promoted.is_empty() => {
+                self.result.set_passed_compilation();
+                return Ok(doc);
+            }
+            Ok(_) => compile::Error(promoted.into()),
+            Err(mut err) => {
+                err.0.extend(promoted);
+                err
+            }
+        };
+
+        if reference {
+            self.result.set_failed_reference_compilation(err);
+        } else {
+            self.result.set_failed_test_compilation(err);
+        }
+
+        Err(Stop::Failed)
+    }
+
+    /// Saves the given document into the given directory, the directory is
+    /// created if it doesn't exist.
+    fn save_doc(&self, dir: &Path, doc: &Document) -> Result<(), RunError> {
+        stdx::fs::create_dir(dir, true)?;
+        doc.save(dir, None)
+            .map_err(|error| RunError::SaveDocuments {
+                id: self.test.id().clone(),
+                error,
+            })?;
+
+        Ok(())
+    }
+
+    fn export_ref_doc(&mut self, reference: &Document) -> Result<(), RunError> {
+        tracing::trace!(test = ?self.test.id(), "saving reference document");
+        self.save_doc(&self.ref_dir(), reference)
+    }
+
+    fn export_out_doc(&mut self, output: &Document) -> Result<(), RunError> {
+        // NOTE(tinger): single-file tests have no directory to export into
+        if self.test.is_single_file() {
+            tracing::trace!(test = ?self.test.id(), "not saving output of single-file test");
+            return Ok(());
+        }
+
+        tracing::trace!(test = ?self.test.id(), "saving output document");
+
+        let dir = self.out_dir();
+        self.save_doc(&dir, output)?;
+
+        let config = &self.project_runner.config;
+        if let Some(export_metadata) = config.export_metadata {
+            let metadata = export_metadata(output, config.pixel_per_pt)?;
+            stdx::fs::write_atomic(dir.join(METADATA_FILE), metadata)?;
+        }
+
+        Ok(())
+    }
+
+    fn export_diff_doc(&mut self, doc: &Document) -> Result<(), RunError> {
+        tracing::trace!(test = ?self.test.id(), "saving difference document");
+        self.save_doc(&self.diff_dir(), doc)
+    }
+
+    fn export_diff_pages(&mut self, pages: &[(usize, Pixmap)]) -> Result<(), RunError> {
+        tracing::trace!(test = ?self.test.id(), "saving difference pages");
+
+        let dir = self.diff_dir();
+        stdx::fs::create_dir(&dir, true)?;
+        Document::save_pages(dir, pages, None).map_err(|error| RunError::SaveDocuments {
+            id: self.test.id().clone(),
+            error,
+        })?;
+
+        Ok(())
+    }
+
+    /// Refuses to update the reference if the output would change more than
+    /// the given fraction of it.
+    fn guard_update(&mut self, output: &Document, max_change: f64) -> Result<(), Stop> {
+        tracing::trace!(test = ?self.test.id(), "checking update threshold");
+
+        // NOTE(tinger): new tests and variants don't have any references yet,
+        // their reference directory may not exist or be empty
+        if !doc::has_pages(self.ref_dir())? {
+            return Ok(());
+        }
+
+        let reference = self.load_ref_doc()?;
+        let change = output.change(&reference);
+
+        if change > max_change {
+            self.result.set_failed_update(change);
+            return Err(Stop::Failed);
+        }
+
+        Ok(())
+    }
+
+    /// Compares the documents if a strategy is given and exports their
+    /// difference document, if diffs are only exported on failure, passing
+    /// comparisons don't render a difference document at all.
+    ///
+    /// If the comparison failed, only the pages which failed are rendered,
+    /// unless the page counts differ, in which case all pages are rendered.
+    fn compare_and_export_diff(
+        &mut self,
+        output: &Document,
+        reference: &Document,
+        strategy: Option<Strategy>,
+        export: bool,
+        origin: Origin,
+    ) -> Result<(), Stop> {
+        let res = match strategy {
+            Some(strategy) => self.compare_pages(
+                output.buffers().iter(),
+                reference.buffers().iter().map(Ok),
+                strategy,
+            ),
+            None => Ok(()),
+        };
+
+        if !export || self.project_runner.config.no_diff || matches!(res, Err(Stop::Cancelled)) {
+            return res;
+        }
+
+        let failed = res.is_err()
+            && matches!(
+                self.result.kind(),
+                Some(TestResultKind::FailedComparison(..))
+            );
+
+        // NOTE(tinger): pages without a counterpart are not reported as page
+        // errors, so we can only restrict the diff if the page counts match
+        let failed_pages = match self.result.kind() {
+            Some(TestResultKind::FailedComparison(err))
+                if failed && err.output == err.reference =>
+            {
+                Some(err.pages.iter().map(|(idx, _)| *idx).collect::<Vec<_>>())
+            }
+            _ => None,
+        };
+
+        match failed_pages {
+            Some(pages) => {
+                tracing::trace!(test = ?self.test.id(), ?pages, "rendering difference pages");
+                let diff = Document::render_diff_pages(reference, output, origin, &pages);
+                self.export_diff_pages(&diff)?;
+            }
+            None if failed || !self.project_runner.config.diff_only_on_failure => {
+                tracing::trace!(test = ?self.test.id(), "rendering difference document");
+                let diff = Document::render_diff(reference, output, origin);
+                self.export_diff_doc(&diff)?;
+            }
+            None => {}
+        }
+
+        res
+    }
+
+    /// Compares pages lazily, this is used to render or decode and compare one
+    /// page at a time in low memory mode.
+    fn compare_pages<O, R, P>(
+        &mut self,
+        outputs: O,
+        references: R,
+        strategy: Strategy,
+    ) -> Result<(), Stop>
+    where
+        O: ExactSizeIterator,
+        O::Item: Borrow<Pixmap>,
+        R: ExactSizeIterator<Item = Result<P, LoadError>>,
+        P: Borrow<Pixmap>,
+    {
+        tracing::trace!(test = ?self.test.id(), "comparing");
+
+        let config = &self.project_runner.config;
+        let output_len = outputs.len();
+        let reference_len = references.len();
+        let mut pages = Vec::with_capacity(Ord::min(output_len, reference_len));
+
+        for (idx, (output, reference)) in outputs.zip(references).enumerate() {
+            // NOTE(tinger): comparing large documents can take a while, so we
+            // check for cancellation between pages
+            if config.is_cancelled() {
+                tracing::debug!(test = ?self.test.id(), page = idx, "comparison cancelled");
+                self.result.set_cancelled();
+                return Err(Stop::Cancelled);
+            }
+
+            let reference = reference.map_err(|error| RunError::LoadReferenceDocuments {
+                id: self.test.id().clone(),
+                error,
+            })?;
+
+            let (output, reference) = (output.borrow(), reference.borrow());
+            let (mut output, mut reference) = (Cow::Borrowed(output), Cow::Borrowed(reference));
+
+            // NOTE(tinger): masks refer to the rendered pages, so they are
+            // applied before trimming changes their coordinates
+            let masks = self.test.masks(idx);
+            if !masks.is_empty() {
+                output = Cow::Owned(render::mask(&output, &masks));
+                reference = Cow::Owned(render::mask(&reference, &masks));
+            }
+            if config.trim_whitespace {
+                output = Cow::Owned(render::trim_whitespace(&output));
+                reference = Cow::Owned(render::trim_whitespace(&reference));
+            }
+            if config.compare_grayscale {
+                output = Cow::Owned(render::grayscale(&output));
+                reference = Cow::Owned(render::grayscale(&reference));
+            }
+
+            match compare::page(&output, &reference, strategy) {
+                Ok(_) => {}
+                Err(err) if config.fail_fast => {
+                    pages.push((idx, err));
+                    break;
+                }
+                Err(err) => pages.push((idx, err)),
+            }
+        }
+
+        if !pages.is_empty() || output_len != reference_len {
+            self.result.set_failed_comparison(compare::Error {
+                output: output_len,
+                reference: reference_len,
+                pages,
+            });
+
+            return Err(Stop::Failed);
+        }
+
+        self.result.set_passed_comparison();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+    use crate::_dev::fs::TempEnv;
+    use crate::_dev::GlobalTestWorld;
+    use crate::library::augmented_library;
+    use crate::project::Paths;
+    use crate::test_set::{eval, TestSet};
+
+    fn project(root: &Path) -> Project {
+        Project::new(None, Paths::new(root, None), None)
+    }
+
+    fn world(root: &Path) -> GlobalTestWorld {
+        GlobalTestWorld::new(root.to_path_buf(), augmented_library(|builder| builder))
+    }
+
+    fn suite(project: &Project) -> Suite {
+        Suite::collect(
+            project.paths(),
+            &TestSet::new(eval::Context::empty(), eval::Set::built_in_all()),
+        )
+        .unwrap()
+    }
+
+    /// A config which exports and clears temporary directories like the
+    /// command line interface does.
+    fn config() -> RunConfig<'static> {
+        RunConfig {
+            clear_temporary_directories: true,
+            action: Action::Run {
+                strategy: Some(Strategy::default()),
+                export: true,
+                origin: Origin::default(),
+            },
+            ..RunConfig::default()
+        }
+    }
+
+    fn update_config(max_change: Option<f64>) -> RunConfig<'static> {
+        RunConfig {
+            action: Action::Update {
+                export: false,
+                max_change,
+                create_missing: false,
+                origin: Origin::default(),
+            },
+            ..config()
+        }
+    }
+
+    fn try_run_test(root: &Path, config: RunConfig<'_>, id: &str) -> Result<TestResult, RunError> {
+        let project = project(root);
+        let (suite, world) = (Suite::new(), world(root));
+        let test = Test::try_collect(project.paths(), Id::new(id).unwrap())
+            .unwrap()
+            .unwrap();
+
+        Runner::new(&project, &suite, &world, config)
+            .test(&test)
+            .run()
+    }
+
+    fn run_test(root: &Path, config: RunConfig<'_>, id: &str) -> TestResult {
+        try_run_test(root, config, id).unwrap()
+    }
+
+    fn failed_pages(result: &TestResult) -> Vec<usize> {
+        let Some(TestResultKind::FailedComparison(err)) = result.kind() else {
+            panic!("expected a failed comparison, got {:?}", result.kind());
+        };
+
+        err.pages.iter().map(|(idx, _)| *idx).collect()
+    }
+
+    #[test]
+    fn test_run_suite() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/pass/test.typ", "Hello")
+                    .setup_file("tests/pass/ref.typ", "Hello")
+                    .setup_file("tests/fail/test.typ", "Hello")
+                    .setup_file("tests/fail/ref.typ", "World")
+                    .setup_file("tests/compile-only/test.typ", "Hello")
+                    .setup_file("tests/error/test.typ", "#panic()")
+            },
+            |root| {
+                let project = project(root);
+                let result = run_suite(
+                    &project,
+                    &suite(&project),
+                    &world(root),
+                    RunConfig::default(),
+                )
+                .unwrap();
+
+                let kind = |id| result.results()[&Id::new(id).unwrap()].kind();
+
+                assert_eq!(result.run(), 4);
+                assert_eq!(result.passed(), 2);
+                assert_eq!(result.failed(), 2);
+                assert!(matches!(
+                    kind("pass"),
+                    Some(TestResultKind::PassedComparison)
+                ));
+                assert!(matches!(
+                    kind("fail"),
+                    Some(TestResultKind::FailedComparison(_))
+                ));
+                assert!(matches!(
+                    kind("compile-only"),
+                    Some(TestResultKind::PassedCompilation)
+                ));
+                assert!(matches!(
+                    kind("error"),
+                    Some(TestResultKind::FailedCompilation {
+                        reference: false,
+                        ..
+                    })
+                ));
+
+                // nothing is exported by default
+                assert!(!root.join("tests/pass/out").try_exists().unwrap());
+            },
+        );
+    }
+
+    #[test]
+    fn test_mask_before_trim() {
+        let script = |dy| {
+            format!(
+                "#set page(width: 100pt, height: 100pt, margin: 0pt)\n\
+                #place(dx: 20pt, dy: 20pt, square(size: 10pt, fill: black))\n\
+                #place(dx: 60pt, dy: {dy}pt, square(size: 10pt, fill: black))"
+            )
+        };
+        let masked = format!("/// [mask: page=1 x=50 y=50 w=50 h=50]\n{}", script(60));
+
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/masked/test.typ", &masked)
+                    .setup_file("tests/masked/ref.typ", script(70))
+                    .setup_file("tests/unmasked/test.typ", script(60))
+                    .setup_file("tests/unmasked/ref.typ", script(70))
+            },
+            |root| {
+                // the moved square changes the trimmed size of the page, the
+                // mask refers to the untrimmed page and hides it before trimming
+                let config = RunConfig {
+                    pixel_per_pt: 1.0,
+                    trim_whitespace: true,
+                    ..config()
+                };
+
+                assert!(run_test(root, config.clone(), "masked").is_pass());
+                assert!(run_test(root, config, "unmasked").is_fail());
+            },
+        );
+    }
+
+    #[test]
+    fn test_diff_only_on_failure() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/pass/test.typ", "Hello")
+                    .setup_file("tests/pass/ref.typ", "Hello")
+                    .setup_file("tests/fail/test.typ", "Hello")
+                    .setup_file("tests/fail/ref.typ", "World")
+            },
+            |root| {
+                let config = RunConfig {
+                    diff_only_on_failure: true,
+                    ..config()
+                };
+
+                assert!(run_test(root, config.clone(), "pass").is_pass());
+                assert!(run_test(root, config, "fail").is_fail());
+
+                assert!(!root.join("tests/pass/diff/1.png").exists());
+                assert!(root.join("tests/pass/out/1.png").exists());
+                assert!(root.join("tests/fail/diff/1.png").exists());
+            },
+        );
+    }
+
+    #[test]
+    fn test_no_diff() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/pass/test.typ", "Hello")
+                    .setup_file("tests/pass/ref.typ", "Hello")
+                    .setup_file("tests/fail/test.typ", "A #pagebreak() B")
+                    .setup_file("tests/fail/ref.typ", "A #pagebreak() X")
+            },
+            |root| {
+                let config = RunConfig {
+                    no_diff: true,
+                    ..config()
+                };
+
+                assert!(run_test(root, config.clone(), "pass").is_pass());
+
+                // the failed pages are still reported
+                let result = run_test(root, config, "fail");
+                assert_eq!(failed_pages(&result), [1]);
+
+                // the diff directories are created, but stay empty
+                let is_empty = |dir: &str| {
+                    fs::read_dir(root.join(dir)).map_or(true, |mut dir| dir.next().is_none())
+                };
+
+                assert!(root.join("tests/pass/out/1.png").exists());
+                assert!(root.join("tests/fail/out/1.png").exists());
+                assert!(is_empty("tests/pass/diff"));
+                assert!(is_empty("tests/fail/diff"));
+            },
+        );
+    }
+
+    #[test]
+    fn test_diff_only_failed_pages() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file(
+                    "tests/pages/test.typ",
+                    "A #pagebreak() B #pagebreak() C #pagebreak() D #pagebreak() E",
+                )
+                .setup_file(
+                    "tests/pages/ref.typ",
+                    "A #pagebreak() B #pagebreak() X #pagebreak() D #pagebreak() E",
+                )
+            },
+            |root| {
+                assert!(run_test(root, config(), "pages").is_fail());
+
+                for page in 1..=5 {
+                    assert!(root.join(format!("tests/pages/out/{page}.png")).exists());
+                    assert_eq!(
+                        root.join(format!("tests/pages/diff/{page}.png")).exists(),
+                        page == 3,
+                    );
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_diff_page_count_mismatch() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/pages/test.typ", "A #pagebreak() B #pagebreak() C")
+                    .setup_file("tests/pages/ref.typ", "A #pagebreak() B")
+            },
+            |root| {
+                let config = RunConfig {
+                    diff_only_on_failure: true,
+                    ..config()
+                };
+
+                let result = run_test(root, config, "pages");
+                assert!(failed_pages(&result).is_empty());
+
+                for page in 1..=3 {
+                    assert!(root.join(format!("tests/pages/diff/{page}.png")).exists());
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_export_metadata() {
+        fn export(doc: &Document, pixel_per_pt: f32) -> io::Result<Vec<u8>> {
+            Ok(format!("{} {pixel_per_pt}", doc.page_count()).into_bytes())
+        }
+
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/meta/test.typ", "A #pagebreak() B")
+                    .setup_file("tests/meta/ref.typ", "A #pagebreak() B")
+            },
+            |root| {
+                assert!(run_test(root, config(), "meta").is_pass());
+                assert!(!root.join("tests/meta/out").join(METADATA_FILE).exists());
+
+                let config = RunConfig {
+                    export_metadata: Some(export),
+                    pixel_per_pt: 2.0,
+                    ..config()
+                };
+                assert!(run_test(root, config, "meta").is_pass());
+                assert_eq!(
+                    fs::read_to_string(root.join("tests/meta/out").join(METADATA_FILE)).unwrap(),
+                    "2 2"
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn test_font_fallback() {
+        // the embedded Libertinus Serif doesn't cover braille patterns
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/covered/test.typ", "Hello")
+                    .setup_file("tests/fallback/test.typ", "Hello \u{283F}")
+                    .setup_file(
+                        "tests/unknown/test.typ",
+                        "#set text(font: \"does-not-exist\")\nHello",
+                    )
+            },
+            |root| {
+                let warn = RunConfig {
+                    warn_font_fallback: true,
+                    ..config()
+                };
+
+                let result = run_test(root, warn.clone(), "covered");
+                assert!(result.is_pass());
+                assert!(result.warnings().is_empty());
+
+                let result = run_test(root, warn.clone(), "fallback");
+                assert!(result.is_pass());
+                assert!(result
+                    .warnings()
+                    .iter()
+                    .any(compile::is_font_fallback_warning));
+
+                let error = RunConfig {
+                    error_on_font_fallback: true,
+                    ..warn
+                };
+                assert!(run_test(root, error.clone(), "covered").is_pass());
+                assert!(run_test(root, error.clone(), "fallback").is_fail());
+                assert!(run_test(root, error, "unknown").is_fail());
+
+                // hermetic runs only fail on unknown font families
+                let hermetic = RunConfig {
+                    hermetic_fonts: true,
+                    ..config()
+                };
+                assert!(run_test(root, hermetic.clone(), "fallback").is_pass());
+                assert!(run_test(root, hermetic, "unknown").is_fail());
+            },
+        );
+    }
+
+    #[test]
+    fn test_no_references_ok() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/empty/test.typ", "Hello")
+                    .setup_dir("tests/empty/ref")
+                    .setup_file("tests/broken/test.typ", "#panic()")
+                    .setup_dir("tests/broken/ref")
+            },
+            |root| {
+                // without the flag the missing references are an error
+                assert!(matches!(
+                    try_run_test(root, config(), "empty"),
+                    Err(RunError::MissingReferenceDocuments(_))
+                ));
+
+                let config = RunConfig {
+                    no_references_ok: true,
+                    ..config()
+                };
+                assert!(matches!(
+                    run_test(root, config.clone(), "empty").kind(),
+                    Some(TestResultKind::PassedCompilation)
+                ));
+
+                // the test is still compiled like a compile-only test
+                assert!(run_test(root, config, "broken").is_fail());
+            },
+        );
+    }
+
+    #[test]
+    fn test_hermetic_fonts() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/known/test.typ", "Hello").setup_file(
+                    "tests/unknown/test.typ",
+                    "#set text(font: \"does-not-exist\")\nHello",
+                )
+            },
+            |root| {
+                let result = run_test(root, config(), "unknown");
+                assert!(result.is_pass());
+                assert!(result
+                    .warnings()
+                    .iter()
+                    .any(compile::is_unknown_font_warning));
+
+                let config = RunConfig {
+                    hermetic_fonts: true,
+                    ..config()
+                };
+                assert!(run_test(root, config.clone(), "known").is_pass());
+
+                // the unknown family fails only this test with the promoted
+                // warning
+                let result = run_test(root, config, "unknown");
+                let errors = result.errors().expect("compilation must fail");
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].severity, Severity::Error);
+                assert!(errors[0].message.starts_with("unknown font family"));
+                assert!(errors[0]
+                    .hints
+                    .iter()
+                    .any(|hint| hint.contains("hermetic runs")));
+            },
+        );
+    }
+
+    #[test]
+    fn test_compare_ppi_mismatch() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file(
+                    "tests/ppi/test.typ",
+                    "#set page(width: 100pt, height: 50pt)\nHello",
+                )
+                .setup_dir("tests/ppi/ref")
+            },
+            |root| {
+                assert!(run_test(root, update_config(Some(0.5)), "ppi").is_pass());
+
+                let config = RunConfig {
+                    pixel_per_pt: render::ppi_to_ppp(288.0),
+                    ..config()
+                };
+                let result = run_test(root, config, "ppi");
+
+                let Some(TestResultKind::FailedComparison(error)) = result.kind() else {
+                    panic!("expected comparison failure, got {:?}", result.kind());
+                };
+                assert_eq!(error.uniform_scale(), Some(2.0));
+            },
+        );
+    }
+
+    #[test]
+    fn test_guard_update() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file(
+                    "tests/guard/test.typ",
+                    "#set page(width: 100pt, height: 50pt, margin: 0pt)\nHello",
+                )
+                .setup_dir("tests/guard/ref")
+            },
+            |root| {
+                let update = update_config(Some(0.5));
+
+                // an empty reference directory doesn't trip the guard
+                assert!(run_test(root, update.clone(), "guard").is_pass());
+
+                // a slightly larger page is not an entirely different page
+                let script = root.join("tests/guard/test.typ");
+                fs::write(
+                    &script,
+                    "#set page(width: 100pt, height: 52pt, margin: 0pt)\nHello",
+                )
+                .unwrap();
+                assert!(run_test(root, update.clone(), "guard").is_pass());
+
+                fs::write(
+                    &script,
+                    "#set page(width: 100pt, height: 52pt, margin: 0pt, fill: black)",
+                )
+                .unwrap();
+                let result = run_test(root, update, "guard");
+                assert!(
+                    matches!(result.kind(), Some(TestResultKind::FailedUpdate { .. })),
+                    "expected update failure, got {:?}",
+                    result.kind()
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn test_low_memory_persistent() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/pages/test.typ", "A #pagebreak() B #pagebreak() C")
+                    .setup_dir("tests/pages/ref")
+            },
+            |root| {
+                assert!(run_test(root, update_config(None), "pages").is_pass());
+
+                let config = RunConfig {
+                    low_memory: true,
+                    ..config()
+                };
+                assert!(run_test(root, config.clone(), "pages").is_pass());
+
+                fs::write(
+                    root.join("tests/pages/test.typ"),
+                    "A #pagebreak() X #pagebreak() C",
+                )
+                .unwrap();
+
+                let result = run_test(root, config, "pages");
+                assert_eq!(failed_pages(&result), [1]);
+            },
+        );
+    }
+
+    #[test]
+    fn test_inconsistent_reference_pages() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file(
+                    "tests/pages/test.typ",
+                    "#set page(width: 100pt, height: 50pt)\nA\n#pagebreak()\nB",
+                )
+                .setup_dir("tests/pages/ref")
+            },
+            |root| {
+                assert!(run_test(root, update_config(Some(0.5)), "pages").is_pass());
+
+                let result = run_test(root, config(), "pages");
+                assert!(result.warnings().is_empty());
+
+                // simulate the second page having been rendered at twice the
+                // ppi
+                let page = root.join("tests/pages/ref/2.png");
+                let buffer = Pixmap::load_png(&page).unwrap();
+                let scaled = Pixmap::new(buffer.width() * 2, buffer.height() * 2).unwrap();
+                fs::write(&page, scaled.encode_png().unwrap()).unwrap();
+
+                let result = run_test(root, config(), "pages");
+                assert_eq!(result.warnings().len(), 1);
+                assert_eq!(
+                    result.warnings()[0].message,
+                    "the dimensions of reference page 2 are inconsistent with the other pages"
+                );
+            },
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_prepare_each_hook_env() {
+        use std::os::unix::fs::PermissionsExt;
+
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file(
+                    "hook.sh",
+                    "#!/bin/sh\n\
+                    echo \"$TYPST_TEST_ID\" > \"$TYPST_TEST_OUT_DIR/../hook-id.txt\"\n\
+                    echo \"$TYPST_TEST_ROOT\" > \"$TYPST_TEST_OUT_DIR/../hook-root.txt\"\n",
+                )
+                .setup_file("tests/foo/bar/test.typ", "Hello")
+            },
+            |root| {
+                fs::set_permissions(root.join("hook.sh"), fs::Permissions::from_mode(0o755))
+                    .unwrap();
+
+                let config = RunConfig {
+                    hooks: hook::Hooks {
+                        prepare_each: Some(Hook::Path("hook.sh".into())),
+                        ..Default::default()
+                    },
+                    ..config()
+                };
+                assert!(run_test(root, config, "foo/bar").is_pass());
+
+                let dir = root.join("tests/foo/bar");
+                assert_eq!(
+                    fs::read_to_string(dir.join("hook-id.txt")).unwrap(),
+                    "foo/bar\n"
+                );
+                assert_eq!(
+                    fs::read_to_string(dir.join("hook-root.txt"))
+                        .unwrap()
+                        .trim_end(),
+                    root.to_str().unwrap(),
+                );
+            },
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_prepare_each_hook_failure() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/a/test.typ", "Hello")
+                    .setup_file("tests/b/test.typ", "Hello")
+            },
+            |root| {
+                let config = RunConfig {
+                    hooks: hook::Hooks {
+                        prepare_each: Some(Hook::Args {
+                            program: "sh".into(),
+                            args: vec![
+                                "-c".into(),
+                                "[ \"$TYPST_TEST_ID\" = b ] || { echo missing fixture >&2; exit 3; }"
+                                    .into(),
+                            ],
+                        }),
+                        ..Default::default()
+                    },
+                    ..config()
+                };
+
+                let result = run_test(root, config.clone(), "a");
+                let Some(TestResultKind::FailedHook {
+                    hook,
+                    code,
+                    stdout,
+                    stderr,
+                }) = result.kind()
+                else {
+                    panic!("expected hook failure, got {:?}", result.kind());
+                };
+                assert_eq!(hook, "sh");
+                assert_eq!(*code, Some(3));
+                assert_eq!(stdout, "");
+                assert_eq!(stderr, "missing fixture\n");
+
+                assert!(run_test(root, config, "b").is_pass());
+            },
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_prepare_annotation_hook() {
+        use std::os::unix::fs::PermissionsExt;
+
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file(
+                    "tests/ok/test.typ",
+                    "/// [prepare: ./setup.sh]\n#read(\"data.txt\")",
+                )
+                .setup_file(
+                    "tests/ok/setup.sh",
+                    "#!/bin/sh\necho Hello > tests/ok/data.txt\n",
+                )
+                .setup_file("tests/broken/test.typ", "/// [prepare: ./setup.sh]\nHello")
+                .setup_file("tests/broken/setup.sh", "#!/bin/sh\necho broken\nexit 1\n")
+            },
+            |root| {
+                for id in ["ok", "broken"] {
+                    let hook = root.join("tests").join(id).join("setup.sh");
+                    fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+                }
+
+                assert!(run_test(root, config(), "ok").is_pass());
+                assert!(root.join("tests/ok/data.txt").exists());
+
+                let result = run_test(root, config(), "broken");
+                let Some(TestResultKind::FailedHook { stdout, code, .. }) = result.kind() else {
+                    panic!("expected hook failure, got {:?}", result.kind());
+                };
+                assert_eq!(*code, Some(1));
+                assert_eq!(stdout, "broken\n");
+            },
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_timeout_global() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/a/test.typ", "Hello")
+                    .setup_file("tests/b/test.typ", "Hello")
+                    .setup_file("tests/c/test.typ", "Hello")
+            },
+            |root| {
+                let project = project(root);
+                let cancellation = AtomicBool::new(false);
+                let config = RunConfig {
+                    timeout: Some(Duration::from_millis(100)),
+                    hooks: hook::Hooks {
+                        prepare_each: Some(Hook::Args {
+                            program: "sh".into(),
+                            args: vec!["-c".into(), "sleep 0.3".into()],
+                        }),
+                        ..Default::default()
+                    },
+                    cancellation: Some(&cancellation),
+                    ..config()
+                };
+
+                let result = run_suite(&project, &suite(&project), &world(root), config).unwrap();
+
+                assert!(cancellation.load(Ordering::SeqCst));
+                assert_eq!(result.passed(), 1);
+                assert!(result.results()[&Id::new("a").unwrap()].is_pass());
+                assert!(result.results()[&Id::new("b").unwrap()].is_cancelled());
+                assert!(result.results()[&Id::new("c").unwrap()].is_cancelled());
+                assert!(!result.is_complete_pass());
+            },
+        );
+    }
+
+    #[test]
+    fn test_keep_going_on_io_errors() {
+        // the out directory can't be cleared if it's a file
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/a/test.typ", "Hello")
+                    .setup_file("tests/broken/test.typ", "Hello")
+                    .setup_file("tests/broken/out", "")
+                    .setup_file("tests/c/test.typ", "Hello")
+            },
+            |root| {
+                let project = project(root);
+                let (suite, world) = (suite(&project), world(root));
+
+                assert!(matches!(
+                    run_suite(&project, &suite, &world, config()),
+                    Err(RunError::Io(_))
+                ));
+
+                let config = RunConfig {
+                    keep_going_on_io_errors: true,
+                    ..config()
+                };
+                let result = run_suite(&project, &suite, &world, config).unwrap();
+
+                assert_eq!(result.passed(), 2);
+                assert_eq!(result.failed(), 1);
+                assert!(result.results()[&Id::new("a").unwrap()].is_pass());
+                assert!(result.results()[&Id::new("c").unwrap()].is_pass());
+                assert!(matches!(
+                    result.results()[&Id::new("broken").unwrap()].kind(),
+                    Some(TestResultKind::FailedIo { kind, .. }) if *kind != io::ErrorKind::Other
+                ));
+            },
+        );
+    }
+
+    #[test]
+    fn test_ignore_temporary_directories_once() {
+        const MANIFEST: &str = r#"
+            [package]
+            name = "ignored"
+            version = "0.1.0"
+            entrypoint = "lib.typ"
+        "#;
+
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file(crate::project::MANIFEST_FILE, MANIFEST)
+                    .setup_file(".hg/requires", "")
+                    .setup_file(".hgignore", "user-pattern\n")
+                    .setup_file("tests/a/test.typ", "Hello")
+                    .setup_file("tests/b/test.typ", "Hello")
+            },
+            |root| {
+                let project = Project::discover(root, false).unwrap().unwrap();
+                let (suite, world) = (suite(&project), world(root));
+
+                for _ in 0..2 {
+                    let result = run_suite(&project, &suite, &world, config()).unwrap();
+                    assert!(result.is_complete_pass());
+                }
+
+                let hgignore = fs::read_to_string(root.join(".hgignore")).unwrap();
+                assert!(hgignore.starts_with("user-pattern\n"));
+                for dir in ["tests/a/out", "tests/a/diff", "tests/b/out", "tests/b/diff"] {
+                    let count = hgignore.lines().filter(|line| line.contains(dir)).count();
+                    assert_eq!(count, 1, "{dir} in {hgignore:?}");
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_compare_cancelled() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/pages/test.typ", "A #pagebreak() B #pagebreak() C")
+                    .setup_file("tests/pages/ref.typ", "A #pagebreak() X #pagebreak() C")
+            },
+            |root| {
+                let cancellation = AtomicBool::new(true);
+                let config = RunConfig {
+                    cancellation: Some(&cancellation),
+                    ..config()
+                };
+                let result = run_test(root, config, "pages");
+
+                assert!(result.is_cancelled());
+                assert!(!result.is_fail());
+                assert!(!root.join("tests/pages/diff/1.png").exists());
+            },
+        );
+    }
+
+    #[test]
+    fn test_run_error_variants() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/pass/test.typ", "Hello")
+                    .setup_file("tests/fail/test.typ", "Hello")
+                    .setup_file("tests/fail/ref.typ", "World")
+                    .setup_file("tests/error/test.typ", "#panic()")
+                    .setup_file("tests/ref-error/test.typ", "Hello")
+                    .setup_file("tests/ref-error/ref.typ", "#panic()")
+                    .setup_file("tests/corrupt/test.typ", "Hello")
+                    .setup_file("tests/corrupt/ref/1.png", "not a png")
+                    .setup_file("tests/save/test.typ", "Hello")
+                    .setup_file("tests/save/out/1.png/keep", "")
+            },
+            |root| {
+                let run = |id| {
+                    let result = run_test(root, config(), id);
+                    RunError::from_result(&Id::new(id).unwrap(), &result)
+                };
+
+                assert!(run("pass").is_none());
+                assert!(matches!(run("fail"), Some(RunError::Comparison { .. })));
+                assert!(matches!(
+                    run("error"),
+                    Some(RunError::Compilation {
+                        reference: false,
+                        ..
+                    })
+                ));
+                assert!(matches!(
+                    run("ref-error"),
+                    Some(RunError::Compilation {
+                        reference: true,
+                        ..
+                    })
+                ));
+                assert!(matches!(
+                    try_run_test(root, config(), "corrupt"),
+                    Err(RunError::LoadReferenceDocuments { .. })
+                ));
+                assert!(matches!(
+                    try_run_test(root, update_config(None), "pass"),
+                    Err(RunError::UpdateCompileOnly(_))
+                ));
+
+                // an output page can't replace a directory
+                let keep = RunConfig {
+                    clear_temporary_directories: false,
+                    ..config()
+                };
+                assert!(matches!(
+                    try_run_test(root, keep, "save"),
+                    Err(RunError::SaveDocuments { .. })
+                ));
+
+                // a suite level hook which can't be started aborts the run
+                let project = project(root);
+                let missing = RunConfig {
+                    hooks: hook::Hooks {
+                        prepare: Some(Hook::Args {
+                            program: "typst-test-missing-hook".into(),
+                            args: vec![],
+                        }),
+                        ..Default::default()
+                    },
+                    ..RunConfig::default()
+                };
+                assert!(matches!(
+                    run_suite(&project, &suite(&project), &world(root), missing),
+                    Err(RunError::RunHook(hook::Error::NotFound { .. }))
+                ));
+
+                // the script of a test vanished after collection
+                let (suite, world) = (Suite::new(), world(root));
+                let test = Test::try_collect(project.paths(), Id::new("pass").unwrap())
+                    .unwrap()
+                    .unwrap();
+                fs::remove_file(root.join("tests/pass/test.typ")).unwrap();
+                assert!(matches!(
+                    Runner::new(&project, &suite, &world, config())
+                        .test(&test)
+                        .run(),
+                    Err(RunError::Io(_))
+                ));
+            },
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_error_suite_hook() {
+        TempEnv::run_no_check(
+            |root| root.setup_file("tests/a/test.typ", "Hello"),
+            |root| {
+                let project = project(root);
+                let config = RunConfig {
+                    hooks: hook::Hooks {
+                        cleanup: Some(Hook::Args {
+                            program: "sh".into(),
+                            args: vec!["-c".into(), "exit 2".into()],
+                        }),
+                        ..Default::default()
+                    },
+                    ..config()
+                };
+
+                let err = run_suite(&project, &suite(&project), &world(root), config).unwrap_err();
+                assert!(matches!(
+                    err,
+                    RunError::Hook {
+                        id: None,
+                        code: Some(2),
+                        ..
+                    }
+                ));
+                assert_eq!(err.to_string(), "hook sh failed");
+            },
+        );
+    }
+
+    #[test]
+    fn test_run_error_from_result() {
+        let id = Id::new("test").unwrap();
+
+        let mut result = TestResult::new();
+        assert!(RunError::from_result(&id, &result).is_none());
+
+        result.set_passed_comparison();
+        assert!(RunError::from_result(&id, &result).is_none());
+
+        result.set_failed_update(0.5);
+        let err = RunError::from_result(&id, &result).unwrap();
+        assert!(matches!(err, RunError::Update { change, .. } if change == 0.5));
+        assert_eq!(
+            err.to_string(),
+            "update of test test would have changed 50.0% of the reference"
+        );
+
+        result.set_failed_hook("prepare.sh".into(), Some(1), "".into(), "".into());
+        assert!(matches!(
+            RunError::from_result(&id, &result),
+            Some(RunError::Hook {
+                id: Some(_),
+                code: Some(1),
+                ..
+            })
+        ));
+
+        result.set_failed_io(io::ErrorKind::PermissionDenied, "permission denied".into());
+        let Some(RunError::Io(err)) = RunError::from_result(&id, &result) else {
+            panic!("expected an io error");
+        };
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(err.to_string(), "permission denied");
+
+        assert_eq!(
+            RunError::MissingReferenceSource(id).to_string(),
+            "couldn't find reference source for test test"
+        );
+    }
+}
@@ -78,6 +78,19 @@ impl<'a> Term<'a> {
         Self::Explicit { singular, plural }
     }
 
+    /// Creates a term for a word whose plural is not formed by appending an
+    /// `s`, this is equivalent to [`Term::new`].
+    ///
+    /// # Examples
+    /// ```
+    /// # use typst_test_lib::stdx::fmt::Term;
+    /// assert_eq!(Term::irregular("child", "children").with(1).to_string(), "child");
+    /// assert_eq!(Term::irregular("child", "children").with(2).to_string(), "children");
+    /// ```
+    pub const fn irregular(singular: &'a str, plural: &'a str) -> Self {
+        Self::new(singular, plural)
+    }
+
     /// Formats this term with the given value.
     ///
     /// # Examples
@@ -112,6 +125,44 @@ impl Display for PluralDisplay<'_> {
     }
 }
 
+/// Displays a number of bytes in a human readable form using binary units,
+/// values of at least 1 KiB are displayed with one decimal place.
+///
+/// # Examples
+/// ```
+/// # use typst_test_lib::stdx::fmt::ByteSize;
+/// assert_eq!(ByteSize(512).to_string(), "512 B");
+/// assert_eq!(ByteSize(1536).to_string(), "1.5 KiB");
+/// assert_eq!(ByteSize(3 * 1024 * 1024).to_string(), "3.0 MiB");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ByteSize(pub u64);
+
+impl ByteSize {
+    const UNITS: &'static [&'static str] = &["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+}
+
+impl Display for ByteSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0 < 1024 {
+            return write!(f, "{} {}", self.0, Self::UNITS[0]);
+        }
+
+        // NOTE(tinger): the unit is chosen by the rounded value, otherwise
+        // values just below the next unit are displayed as `1024.0`
+        let rounded = |value: f64| (value * 10.0).round() / 10.0;
+
+        let mut value = self.0 as f64;
+        let mut unit = 0;
+        while rounded(value) >= 1024.0 && unit < Self::UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+
+        write!(f, "{value:.1} {}", Self::UNITS[unit])
+    }
+}
+
 /// Displays a sequence of elements as comma separated list with a final
 /// separator.
 ///
@@ -273,3 +324,35 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_term_simple() {
+        let term = Term::simple("test");
+        assert_eq!(term.with(0).to_string(), "tests");
+        assert_eq!(term.with(1).to_string(), "test");
+        assert_eq!(term.with(2).to_string(), "tests");
+    }
+
+    #[test]
+    fn test_term_irregular() {
+        let term = Term::irregular("index", "indices");
+        assert_eq!(term.with(0).to_string(), "indices");
+        assert_eq!(term.with(1).to_string(), "index");
+        assert_eq!(term.with(2).to_string(), "indices");
+    }
+
+    #[test]
+    fn test_byte_size() {
+        assert_eq!(ByteSize(0).to_string(), "0 B");
+        assert_eq!(ByteSize(1023).to_string(), "1023 B");
+        assert_eq!(ByteSize(1024).to_string(), "1.0 KiB");
+        assert_eq!(ByteSize(1024 * 1024 - 1).to_string(), "1.0 MiB");
+        assert_eq!(ByteSize(1024 * 1024 - 103).to_string(), "1023.9 KiB");
+        assert_eq!(ByteSize(5 * 1024 * 1024 * 1024).to_string(), "5.0 GiB");
+        assert_eq!(ByteSize(u64::MAX).to_string(), "16.0 EiB");
+    }
+}
@@ -1,13 +1,107 @@
 //! Helper functions and types for managing and manipulating the filesystem.
 
-use std::io::ErrorKind;
-use std::path::Path;
-use std::{fs, io};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{fs, io, thread};
 
 use crate::stdx::result::ResultEx;
 
+/// Configures how often an operation is retried on transient errors and how
+/// long to wait between attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RetryConfig {
+    /// The maximum number of attempts, including the first one.
+    pub attempts: usize,
+
+    /// The time to wait before the first retry, this is doubled after each
+    /// failed retry.
+    pub backoff: Duration,
+}
+
+impl RetryConfig {
+    /// A config which does not retry at all.
+    pub const NONE: Self = Self {
+        attempts: 1,
+        backoff: Duration::ZERO,
+    };
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_millis(10),
+        }
+    }
+}
+
+/// Returns whether the given error is likely transient and the operation may
+/// succeed if retried. This is mostly relevant on Windows, where files may be
+/// temporarily locked by other processes like virus scanners or indexers.
+pub fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::PermissionDenied | ErrorKind::Interrupted | ErrorKind::WouldBlock
+    )
+}
+
+/// Runs the given operation, retrying it according to the given config if it
+/// fails with a [transient][is_transient] error.
+///
+/// # Example
+/// ```no_run
+/// # use typst_test_lib::stdx::fs::{retry, RetryConfig};
+/// let content = retry(RetryConfig::default(), || std::fs::read("foo.txt"))?;
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub fn retry<T, F>(config: RetryConfig, f: F) -> io::Result<T>
+where
+    F: FnMut() -> io::Result<T>,
+{
+    retry_if(config, is_transient, f)
+}
+
+/// Runs the given operation, retrying it according to the given config if it
+/// fails with an error for which the given predicate returns `true`.
+///
+/// # Example
+/// ```no_run
+/// # use std::io::ErrorKind;
+/// # use typst_test_lib::stdx::fs::{retry_if, RetryConfig};
+/// let content = retry_if(
+///     RetryConfig::default(),
+///     |err| err.kind() == ErrorKind::NotFound,
+///     || std::fs::read("foo.txt"),
+/// )?;
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub fn retry_if<T, P, F>(config: RetryConfig, mut predicate: P, mut f: F) -> io::Result<T>
+where
+    P: FnMut(&io::Error) -> bool,
+    F: FnMut() -> io::Result<T>,
+{
+    let mut backoff = config.backoff;
+    let mut attempt = 1;
+
+    loop {
+        match f() {
+            Err(err) if attempt < config.attempts && predicate(&err) => {
+                tracing::debug!(?err, attempt, ?backoff, "retrying after error");
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
 /// Creates a new directory and its parent directories if `all` is specified,
-/// but doesn't fail if it already exists.
+/// but doesn't fail if it already exists. Transient errors are retried using
+/// the default [`RetryConfig`].
 ///
 /// # Example
 /// ```no_run
@@ -21,12 +115,14 @@ where
     P: AsRef<Path>,
 {
     fn inner(path: &Path, all: bool) -> io::Result<()> {
-        let res = if all {
-            fs::create_dir_all(path)
-        } else {
-            fs::create_dir(path)
-        };
-        res.ignore_default(|e| e.kind() == ErrorKind::AlreadyExists)
+        let res = retry(RetryConfig::default(), || {
+            if all {
+                fs::create_dir_all(path)
+            } else {
+                fs::create_dir(path)
+            }
+        });
+        res.ok_if(|e| e.kind() == ErrorKind::AlreadyExists)
     }
 
     inner(path.as_ref(), all)
@@ -46,13 +142,14 @@ where
     P: AsRef<Path>,
 {
     fn inner(path: &Path) -> io::Result<()> {
-        std::fs::remove_file(path).ignore_default(|e| e.kind() == ErrorKind::NotFound)
+        std::fs::remove_file(path).ok_if(|e| e.kind() == ErrorKind::NotFound)
     }
 
     inner(path.as_ref())
 }
 
-/// Removes a directory, but doesn't fail if it doesn't exist.
+/// Removes a directory, but doesn't fail if it doesn't exist. Transient errors
+/// are retried using the default [`RetryConfig`].
 ///
 /// # Example
 /// ```no_run
@@ -66,11 +163,13 @@ where
     P: AsRef<Path>,
 {
     fn inner(path: &Path, all: bool) -> io::Result<()> {
-        let res = if all {
-            fs::remove_dir_all(path)
-        } else {
-            fs::remove_dir(path)
-        };
+        let res = retry(RetryConfig::default(), || {
+            if all {
+                fs::remove_dir_all(path)
+            } else {
+                fs::remove_dir(path)
+            }
+        });
         res.ignore_default(|e| {
             if e.kind() == ErrorKind::NotFound {
                 let parent_exists = path
@@ -109,7 +208,7 @@ where
         let res = remove_dir(path, true);
         if all {
             // if there was nothing to clear, then we simply go on to creation
-            res.ignore_default(|e| e.kind() == io::ErrorKind::NotFound)?;
+            res.ok_if(|e| e.kind() == io::ErrorKind::NotFound)?;
         } else {
             res?;
         }
@@ -119,6 +218,142 @@ where
     inner(path.as_ref(), all)
 }
 
+/// Writes the given bytes to a file atomically by first writing them to a
+/// temporary file in the same directory and then renaming it to `path`. This
+/// ensures that the file is either fully written or not touched at all.
+///
+/// # Example
+/// ```no_run
+/// # use typst_test_lib::stdx::fs::write_atomic;
+/// write_atomic("foo.txt", "Hello World")?;
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub fn write_atomic<P, C>(path: P, content: C) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    C: AsRef<[u8]>,
+{
+    fn inner(path: &Path, content: &[u8]) -> io::Result<()> {
+        let temp = temp_sibling(path)?;
+
+        let res = File::options()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+            .and_then(|mut file| {
+                file.write_all(content)?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&temp, path));
+
+        // NOTE(tinger): the original error is more useful than a failure to
+        // clean up after it, so the latter is only logged
+        if res.is_err() {
+            if let Err(err) = remove_file(&temp) {
+                tracing::warn!(?err, ?temp, "couldn't remove temporary file");
+            }
+        }
+
+        res
+    }
+
+    inner(path.as_ref(), content.as_ref())
+}
+
+/// Returns a path to a unique hidden temporary file next to the given path.
+fn temp_sibling(path: &Path) -> io::Result<PathBuf> {
+    let Some(name) = path.file_name() else {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("path {path:?} has no file name"),
+        ));
+    };
+
+    let mut temp = OsString::from(".");
+    temp.push(name);
+    temp.push(format!(".{}.tmp", uuid::Uuid::new_v4().simple()));
+
+    Ok(path.with_file_name(temp))
+}
+
+/// Returns the accumulated size of all files within a directory in bytes.
+/// Symlinks are not followed, if the directory doesn't exist `0` is returned.
+///
+/// # Example
+/// ```no_run
+/// # use typst_test_lib::stdx::fs::dir_size;
+/// let size = dir_size("foo")?;
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub fn dir_size<P>(path: P) -> io::Result<u64>
+where
+    P: AsRef<Path>,
+{
+    fn inner(path: &Path) -> io::Result<u64> {
+        let Some(entries) = fs::read_dir(path).ignore_io_kind(ErrorKind::NotFound)? else {
+            return Ok(0);
+        };
+
+        let mut size = 0;
+        for entry in entries {
+            let entry = entry?;
+
+            // NOTE(tinger): DirEntry::metadata does not traverse symlinks, so
+            // we can't run into symlink loops here
+            let meta = entry.metadata()?;
+
+            if meta.is_dir() {
+                size += inner(&entry.path())?;
+            } else if meta.is_file() {
+                size += meta.len();
+            }
+        }
+
+        Ok(size)
+    }
+
+    inner(path.as_ref())
+}
+
+/// Returns the number of files within a directory and its sub directories.
+/// Symlinks are not followed, if the directory doesn't exist `0` is returned.
+///
+/// # Example
+/// ```no_run
+/// # use typst_test_lib::stdx::fs::count_files;
+/// let count = count_files("foo")?;
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub fn count_files<P>(path: P) -> io::Result<usize>
+where
+    P: AsRef<Path>,
+{
+    fn inner(path: &Path) -> io::Result<usize> {
+        let Some(entries) = fs::read_dir(path).ignore_io_kind(ErrorKind::NotFound)? else {
+            return Ok(0);
+        };
+
+        let mut count = 0;
+        for entry in entries {
+            let entry = entry?;
+
+            // NOTE(tinger): DirEntry::file_type does not traverse symlinks, so
+            // we can't run into symlink loops here
+            let file_type = entry.file_type()?;
+
+            if file_type.is_dir() {
+                count += inner(&entry.path())?;
+            } else if file_type.is_file() {
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    inner(path.as_ref())
+}
+
 /// Returns the lexical common ancestor of two paths if there is any.
 ///
 /// # Example
@@ -160,3 +395,174 @@ pub fn is_ancestor_of<P: AsRef<Path>, Q: AsRef<Path>>(base: P, path: Q) -> bool
 
     inner(base.as_ref(), path.as_ref())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::_dev;
+
+    fn retry_config(attempts: usize) -> RetryConfig {
+        RetryConfig {
+            attempts,
+            backoff: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_retry_transient() {
+        let mut calls = 0;
+        let res = retry(retry_config(3), || {
+            calls += 1;
+            if calls <= 2 {
+                Err(io::Error::from(ErrorKind::PermissionDenied))
+            } else {
+                Ok(calls)
+            }
+        });
+
+        assert_eq!(res.unwrap(), 3);
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_retry_exhausted() {
+        let mut calls = 0;
+        let res = retry(retry_config(2), || {
+            calls += 1;
+            Err::<(), _>(io::Error::from(ErrorKind::PermissionDenied))
+        });
+
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::PermissionDenied);
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn test_retry_not_transient() {
+        let mut calls = 0;
+        let res = retry(retry_config(3), || {
+            calls += 1;
+            Err::<(), _>(io::Error::from(ErrorKind::NotFound))
+        });
+
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_retry_if_predicate() {
+        let mut calls = 0;
+        let res = retry_if(
+            retry_config(3),
+            |err| err.kind() == ErrorKind::NotFound,
+            || {
+                calls += 1;
+                if calls == 1 {
+                    Err(io::Error::from(ErrorKind::NotFound))
+                } else {
+                    Err(io::Error::from(ErrorKind::PermissionDenied))
+                }
+            },
+        );
+
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::PermissionDenied);
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn test_write_atomic_create() {
+        _dev::fs::TempEnv::run(
+            |root| root.setup_dir("foo"),
+            |root| {
+                write_atomic(root.join("foo/bar.txt"), "Hello World").unwrap();
+            },
+            |root| root.expect_file_content("foo/bar.txt", "Hello World"),
+        );
+    }
+
+    #[test]
+    fn test_write_atomic_truncate() {
+        _dev::fs::TempEnv::run(
+            |root| root.setup_file("foo/bar.txt", "Hello World, this is longer"),
+            |root| {
+                write_atomic(root.join("foo/bar.txt"), "Hello World").unwrap();
+            },
+            |root| root.expect_file_content("foo/bar.txt", "Hello World"),
+        );
+    }
+
+    #[test]
+    fn test_write_atomic_missing_parent() {
+        _dev::fs::TempEnv::run(
+            |root| root.setup_dir("foo"),
+            |root| {
+                write_atomic(root.join("foo/bar/baz.txt"), "Hello World").unwrap_err();
+            },
+            |root| root.expect_dir("foo"),
+        );
+    }
+
+    #[test]
+    fn test_dir_size_missing() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| root,
+            |root| {
+                assert_eq!(dir_size(root.join("foo")).unwrap(), 0);
+            },
+        );
+    }
+
+    #[test]
+    fn test_dir_size_empty() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| root.setup_dir("foo"),
+            |root| {
+                assert_eq!(dir_size(root.join("foo")).unwrap(), 0);
+            },
+        );
+    }
+
+    #[test]
+    fn test_dir_size_nested() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_file("foo/a.txt", "Hello")
+                    .setup_file("foo/bar/b.txt", "World!")
+                    .setup_dir("foo/baz")
+            },
+            |root| {
+                assert_eq!(dir_size(root.join("foo")).unwrap(), 11);
+                assert_eq!(dir_size(root.join("foo/bar")).unwrap(), 6);
+                assert_eq!(dir_size(root.join("foo/baz")).unwrap(), 0);
+            },
+        );
+    }
+
+    #[test]
+    fn test_count_files_empty() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| root.setup_dir("foo"),
+            |root| {
+                assert_eq!(count_files(root.join("foo")).unwrap(), 0);
+                assert_eq!(count_files(root.join("bar")).unwrap(), 0);
+            },
+        );
+    }
+
+    #[test]
+    fn test_count_files_nested() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_file_empty("foo/a.txt")
+                    .setup_file_empty("foo/b.txt")
+                    .setup_file_empty("foo/bar/c.txt")
+                    .setup_file_empty("foo/bar/qux/d.txt")
+                    .setup_dir("foo/baz")
+            },
+            |root| {
+                assert_eq!(count_files(root.join("foo")).unwrap(), 4);
+                assert_eq!(count_files(root.join("foo/bar")).unwrap(), 2);
+                assert_eq!(count_files(root.join("foo/baz")).unwrap(), 0);
+            },
+        );
+    }
+}
@@ -1,5 +1,8 @@
 //! Extensions for [`Result`] type.
 
+use std::borrow::Borrow;
+use std::io;
+
 /// Extensions for the [`Result`] type.
 pub trait ResultEx<T, E> {
     /// Ignores the subset of the error for which the `check` returns true,
@@ -89,6 +92,46 @@ pub trait ResultEx<T, E> {
     where
         F: FnOnce(&E) -> bool,
         G: FnOnce(&E) -> T;
+
+    /// Ignores io errors of the given kind, returning `None` instead.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use std::fs;
+    /// # use std::io::ErrorKind;
+    /// use typst_test_lib::stdx::result::ResultEx;
+    /// // if foo doesn't exist we get None
+    /// // if another error is returned it is propagated
+    /// assert_eq!(
+    ///     fs::read_to_string("foo.txt").ignore_io_kind(ErrorKind::NotFound)?,
+    ///     Some(String::from("foo")),
+    /// );
+    /// assert_eq!(
+    ///     fs::read_to_string("not-found.txt").ignore_io_kind(ErrorKind::NotFound)?,
+    ///     None,
+    /// );
+    /// # Ok::<_, Box<dyn std::error::Error>>(())
+    /// ```
+    fn ignore_io_kind(self, kind: io::ErrorKind) -> Result<Option<T>, E>
+    where
+        E: Borrow<io::Error>;
+
+    /// Discards the value and ignores the subset of the error for which the
+    /// `check` returns true, returning `Ok(())` instead.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use std::fs;
+    /// # use std::io::ErrorKind;
+    /// use typst_test_lib::stdx::result::ResultEx;
+    /// // if foo doesn't exist we get Ok(())
+    /// // if another error is returned it is propagated
+    /// fs::remove_file("not-found.txt").ok_if(|e| e.kind() == ErrorKind::NotFound)?;
+    /// # Ok::<_, Box<dyn std::error::Error>>(())
+    /// ```
+    fn ok_if<F>(self, check: F) -> Result<(), E>
+    where
+        F: FnOnce(&E) -> bool;
 }
 
 impl<T, E> ResultEx<T, E> for Result<T, E> {
@@ -117,4 +160,55 @@ impl<T, E> ResultEx<T, E> for Result<T, E> {
             x => x,
         }
     }
+
+    fn ignore_io_kind(self, kind: io::ErrorKind) -> Result<Option<T>, E>
+    where
+        E: Borrow<io::Error>,
+    {
+        self.ignore(|e| e.borrow().kind() == kind)
+    }
+
+    fn ok_if<F>(self, check: F) -> Result<(), E>
+    where
+        F: FnOnce(&E) -> bool,
+    {
+        self.map(|_| ()).ignore_default(check)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use super::*;
+
+    #[test]
+    fn test_ignore_io_kind() {
+        let ok: io::Result<i32> = Ok(1);
+        assert_eq!(ok.ignore_io_kind(ErrorKind::NotFound).unwrap(), Some(1));
+
+        let ignored: io::Result<i32> = Err(ErrorKind::NotFound.into());
+        assert_eq!(ignored.ignore_io_kind(ErrorKind::NotFound).unwrap(), None);
+
+        let propagated: io::Result<i32> = Err(ErrorKind::PermissionDenied.into());
+        assert_eq!(
+            propagated
+                .ignore_io_kind(ErrorKind::NotFound)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::PermissionDenied,
+        );
+    }
+
+    #[test]
+    fn test_ok_if() {
+        let ok: Result<i32, i32> = Ok(1);
+        assert_eq!(ok.ok_if(|&e| e == 0), Ok(()));
+
+        let ignored: Result<i32, i32> = Err(0);
+        assert_eq!(ignored.ok_if(|&e| e == 0), Ok(()));
+
+        let propagated: Result<i32, i32> = Err(1);
+        assert_eq!(propagated.ok_if(|&e| e == 0), Err(1));
+    }
 }
@@ -2,9 +2,11 @@
 
 use std::str::FromStr;
 
-use ecow::EcoString;
+use ecow::{eco_format, EcoString, EcoVec};
 use thiserror::Error;
 
+use crate::doc::compare::Region;
+
 /// An error which may occur while parsing an annotation.
 #[derive(Debug, Error)]
 pub enum ParseAnnotationError {
@@ -16,6 +18,10 @@ pub enum ParseAnnotationError {
     #[error("unknown or invalid annotation identifier: {0:?}")]
     Unknown(EcoString),
 
+    /// An argument of the annotation was unknown or invalid.
+    #[error("unknown or invalid annotation argument: {0:?}")]
+    Argument(EcoString),
+
     /// The annotation was otherwise malformed.
     #[error("the annotation was malformed")]
     Other,
@@ -32,12 +38,142 @@ pub enum ParseAnnotationError {
 /// ...
 /// ```
 ///
-/// Each annotation is on it's own line and may have optional arguments.
+/// Each annotation is on it's own line and may have optional arguments
+/// separated from the identifier by a colon:
+/// ```typst
+/// /// [compare: max-delta=2 max-deviation=50]
+/// /// [skip: broken until typst/typst#1234 is fixed]
+/// ```
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Annotation {
     /// The ignored annotation, this can be used to exclude a test by virtue of
-    /// the `ignored` test set.
-    Skip,
+    /// the `ignored` test set. It may carry the reason for skipping the test.
+    Skip(Option<EcoString>),
+
+    /// Overrides the comparison thresholds for this test, unset values fall
+    /// back to those given on the command line or in the config.
+    Compare {
+        /// The maximum delta in each channel of a pixel.
+        max_delta: Option<u8>,
+
+        /// The maximum amount of deviations per page.
+        max_deviation: Option<usize>,
+    },
+
+    /// Runs the test once per variant, each variant is compiled with the
+    /// `variant` key in `sys.inputs` set to its name and compared against its
+    /// own references.
+    Variants(EcoVec<EcoString>),
+
+    /// Excludes a rectangular region of a page from comparison, this is useful
+    /// for inherently non-deterministic content like timestamps.
+    Mask {
+        /// The one-based page number the mask applies to.
+        page: usize,
+
+        /// The masked region in pixels of the rendered page, this is applied
+        /// before whitespace is trimmed.
+        region: Region,
+    },
+
+    /// A hook to run before this test, the path is relative to the test
+    /// directory.
+    Prepare(EcoString),
+
+    /// A hook to run after this test, the path is relative to the test
+    /// directory.
+    Cleanup(EcoString),
+}
+
+impl Annotation {
+    /// Parses the arguments of a compare annotation.
+    fn parse_compare(args: &str) -> Result<Self, ParseAnnotationError> {
+        let mut max_delta = None;
+        let mut max_deviation = None;
+
+        for arg in args.split_whitespace() {
+            let invalid = || ParseAnnotationError::Argument(arg.into());
+            let (key, value) = arg.split_once('=').ok_or_else(invalid)?;
+
+            match key {
+                "max-delta" => max_delta = Some(value.parse().map_err(|_| invalid())?),
+                "max-deviation" => max_deviation = Some(value.parse().map_err(|_| invalid())?),
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(Annotation::Compare {
+            max_delta,
+            max_deviation,
+        })
+    }
+
+    /// Parses the arguments of a mask annotation.
+    fn parse_mask(args: &str) -> Result<Self, ParseAnnotationError> {
+        let mut page = None;
+        let mut x = None;
+        let mut y = None;
+        let mut width = None;
+        let mut height = None;
+
+        for arg in args.split_whitespace() {
+            let invalid = || ParseAnnotationError::Argument(arg.into());
+            let (key, value) = arg.split_once('=').ok_or_else(invalid)?;
+
+            let target = match key {
+                "x" => &mut x,
+                "y" => &mut y,
+                "w" => &mut width,
+                "h" => &mut height,
+                "page" => {
+                    page = Some(value.parse().map_err(|_| invalid())?);
+                    continue;
+                }
+                _ => return Err(invalid()),
+            };
+
+            *target = Some(value.parse().map_err(|_| invalid())?);
+        }
+
+        let missing = |key: &str| ParseAnnotationError::Argument(eco_format!("missing {key}"));
+
+        let page: usize = page.ok_or_else(|| missing("page"))?;
+        if page == 0 {
+            return Err(ParseAnnotationError::Argument("page=0".into()));
+        }
+
+        Ok(Annotation::Mask {
+            page,
+            region: Region {
+                x: x.ok_or_else(|| missing("x"))?,
+                y: y.ok_or_else(|| missing("y"))?,
+                width: width.ok_or_else(|| missing("w"))?,
+                height: height.ok_or_else(|| missing("h"))?,
+            },
+        })
+    }
+
+    /// Parses the arguments of a variants annotation.
+    fn parse_variants(args: &str) -> Result<Self, ParseAnnotationError> {
+        let mut variants = EcoVec::new();
+
+        for variant in args.split(',').map(str::trim) {
+            // NOTE(tinger): variant names are used as directory names, so we
+            // keep them simple
+            let is_valid = !variant.is_empty()
+                && variant
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+            if !is_valid || variants.contains(&variant.into()) {
+                return Err(ParseAnnotationError::Argument(variant.into()));
+            }
+
+            variants.push(variant.into());
+        }
+
+        Ok(Annotation::Variants(variants))
+    }
 }
 
 impl FromStr for Annotation {
@@ -52,10 +188,24 @@ impl FromStr for Annotation {
             return Err(ParseAnnotationError::MissingDelimiter);
         };
 
-        let id = rest.trim();
+        let (id, args) = match rest.split_once(':') {
+            Some((id, args)) => (id.trim(), Some(args.trim())),
+            None => (rest.trim(), None),
+        };
 
-        match id {
-            "skip" => Ok(Annotation::Skip),
+        match (id, args) {
+            ("skip", None) => Ok(Annotation::Skip(None)),
+            ("skip", Some(reason)) => Ok(Annotation::Skip(
+                (!reason.is_empty()).then(|| reason.into()),
+            )),
+            ("compare", Some(args)) => Annotation::parse_compare(args),
+            ("variants", Some(args)) => Annotation::parse_variants(args),
+            ("mask", Some(args)) => Annotation::parse_mask(args),
+            ("prepare", Some(hook)) if !hook.is_empty() => Ok(Annotation::Prepare(hook.into())),
+            ("cleanup", Some(hook)) if !hook.is_empty() => Ok(Annotation::Cleanup(hook.into())),
+            ("compare" | "variants" | "mask" | "prepare" | "cleanup", _) => {
+                Err(ParseAnnotationError::Other)
+            }
             _ => Err(ParseAnnotationError::Unknown(id.into())),
         }
     }
@@ -63,14 +213,106 @@ impl FromStr for Annotation {
 
 #[cfg(test)]
 mod tests {
+    use ecow::eco_vec;
+
     use super::*;
 
     #[test]
     fn test_annotation_from_str() {
-        assert_eq!(Annotation::from_str("[skip]").unwrap(), Annotation::Skip);
-        assert_eq!(Annotation::from_str("[ skip  ]").unwrap(), Annotation::Skip);
+        assert_eq!(
+            Annotation::from_str("[skip]").unwrap(),
+            Annotation::Skip(None)
+        );
+        assert_eq!(
+            Annotation::from_str("[ skip  ]").unwrap(),
+            Annotation::Skip(None)
+        );
 
         assert!(Annotation::from_str("[ skip  ").is_err());
         assert!(Annotation::from_str("[unknown]").is_err());
     }
+
+    #[test]
+    fn test_annotation_skip_reason_from_str() {
+        assert_eq!(
+            Annotation::from_str("[skip: flaky on: windows]").unwrap(),
+            Annotation::Skip(Some("flaky on: windows".into()))
+        );
+        assert_eq!(
+            Annotation::from_str("[skip:  ]").unwrap(),
+            Annotation::Skip(None)
+        );
+    }
+
+    #[test]
+    fn test_annotation_compare_from_str() {
+        assert_eq!(
+            Annotation::from_str("[compare: max-delta=2 max-deviation=50]").unwrap(),
+            Annotation::Compare {
+                max_delta: Some(2),
+                max_deviation: Some(50),
+            }
+        );
+        assert_eq!(
+            Annotation::from_str("[compare: max-deviation=3]").unwrap(),
+            Annotation::Compare {
+                max_delta: None,
+                max_deviation: Some(3),
+            }
+        );
+
+        assert!(Annotation::from_str("[compare]").is_err());
+        assert!(Annotation::from_str("[compare: max-delta=256]").is_err());
+        assert!(Annotation::from_str("[compare: unknown=1]").is_err());
+    }
+
+    #[test]
+    fn test_annotation_hooks_from_str() {
+        assert_eq!(
+            Annotation::from_str("[prepare: ./setup.sh]").unwrap(),
+            Annotation::Prepare("./setup.sh".into())
+        );
+        assert_eq!(
+            Annotation::from_str("[cleanup: teardown.sh]").unwrap(),
+            Annotation::Cleanup("teardown.sh".into())
+        );
+
+        assert!(Annotation::from_str("[prepare]").is_err());
+        assert!(Annotation::from_str("[cleanup:  ]").is_err());
+    }
+
+    #[test]
+    fn test_annotation_variants_from_str() {
+        assert_eq!(
+            Annotation::from_str("[variants: light, dark]").unwrap(),
+            Annotation::Variants(eco_vec!["light".into(), "dark".into()])
+        );
+
+        assert!(Annotation::from_str("[variants]").is_err());
+        assert!(Annotation::from_str("[variants: light,]").is_err());
+        assert!(Annotation::from_str("[variants: light, light]").is_err());
+        assert!(Annotation::from_str("[variants: ../escape]").is_err());
+    }
+
+    #[test]
+    fn test_annotation_mask_from_str() {
+        assert_eq!(
+            Annotation::from_str("[mask: page=1 x=0 y=5 w=100 h=20]").unwrap(),
+            Annotation::Mask {
+                page: 1,
+                region: Region {
+                    x: 0,
+                    y: 5,
+                    width: 100,
+                    height: 20,
+                },
+            }
+        );
+
+        assert!(Annotation::from_str("[mask]").is_err());
+        assert!(Annotation::from_str("[mask: page=0 x=0 y=0 w=1 h=1]").is_err());
+        assert!(Annotation::from_str("[mask: page=1 x=0 y=0 w=1]").is_err());
+        assert!(Annotation::from_str("[mask: page=1 x=-1 y=0 w=1 h=1]").is_err());
+        assert!(Annotation::from_str("[mask: page=1 x=0 y=0 w=1 h=1 z=2]").is_err());
+    }
 }
@@ -0,0 +1,270 @@
+//! Extracting typst examples from markdown documentation.
+
+use std::path::{Component, Path, PathBuf};
+use std::{fs, io};
+
+use typst::syntax::{FileId, Source, VirtualPath};
+
+use super::{Id, Test};
+use crate::project::Paths;
+
+/// The info string languages of code fences which are extracted.
+const LANGUAGES: &[&str] = &["typ", "typst"];
+
+/// The id component under which all doc tests are nested.
+const ID_PREFIX: &str = "doc";
+
+/// A compile-only test extracted from a typst code fence in a markdown file,
+/// these tests only exist in memory.
+#[derive(Debug, Clone)]
+pub struct DocTest {
+    test: Test,
+    path: PathBuf,
+    line: usize,
+    source: Source,
+}
+
+impl DocTest {
+    /// Reads the given markdown files and extracts their doc tests, the files
+    /// are relative to the project root.
+    pub fn collect<P: AsRef<Path>>(paths: &Paths, files: &[P]) -> io::Result<Vec<Self>> {
+        let mut tests = vec![];
+        for file in files {
+            let file = file.as_ref();
+            let content = fs::read_to_string(paths.project_root().join(file))?;
+            tests.extend(Self::extract(file, &content));
+        }
+
+        Ok(tests)
+    }
+
+    /// Extracts the doc tests from the given markdown content, `path` is the
+    /// path of the markdown file relative to the project root.
+    ///
+    /// Only fences with a `typ` or `typst` info string are extracted, each
+    /// test is identified by the file and the line its fence starts on.
+    pub fn extract(path: &Path, content: &str) -> Vec<Self> {
+        let prefix = id_prefix(path);
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        fences(content)
+            .into_iter()
+            .map(|(line, code)| {
+                let id = Id::new(format!("{prefix}{}line-{line}", Id::SEPARATOR))
+                    .expect("sanitized components are valid");
+
+                // NOTE(tinger): each fence gets its own file id next to the
+                // markdown file, this way relative imports resolve as expected
+                let vpath =
+                    VirtualPath::new(path.with_file_name(format!("{file_name}-{line}.typ")));
+
+                Self {
+                    test: Test::new(id),
+                    path: path.to_path_buf(),
+                    line,
+                    source: Source::new(FileId::new(None, vpath), code),
+                }
+            })
+            .collect()
+    }
+}
+
+impl DocTest {
+    /// The compile-only test of this doc test.
+    pub fn test(&self) -> &Test {
+        &self.test
+    }
+
+    /// The path of the markdown file relative to the project root.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The line of the opening fence, starting at 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The source of the code fence.
+    pub fn source(&self) -> &Source {
+        &self.source
+    }
+}
+
+/// Creates the id prefix for the doc tests of the given file, components
+/// are sanitized to be valid id components.
+fn id_prefix(path: &Path) -> String {
+    let mut prefix = String::from(ID_PREFIX);
+
+    for component in path.components() {
+        let Component::Normal(component) = component else {
+            continue;
+        };
+
+        let component: String = component
+            .to_string_lossy()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+
+        prefix.push_str(Id::SEPARATOR);
+        if !component.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            prefix.push('x');
+        }
+        prefix.push_str(&component);
+    }
+
+    prefix
+}
+
+/// An open code fence.
+struct Fence {
+    marker: char,
+    len: usize,
+    indent: usize,
+    line: usize,
+    typst: bool,
+    code: String,
+}
+
+/// Returns the start line and content of all typst code fences in the given
+/// markdown content. Unclosed fences extend to the end of the content.
+fn fences(content: &str) -> Vec<(usize, String)> {
+    let mut fences = vec![];
+    let mut open: Option<Fence> = None;
+
+    for (idx, line) in content.lines().enumerate() {
+        let trimmed = line.trim_start_matches(' ');
+        let indent = line.len() - trimmed.len();
+
+        if let Some(fence) = &mut open {
+            let closing = fence_marker(trimmed)
+                .filter(|&(marker, len)| marker == fence.marker && len >= fence.len)
+                .is_some_and(|(_, len)| trimmed[len..].trim().is_empty());
+
+            if indent <= 3 && closing {
+                let fence = open.take().expect("the fence is open");
+                if fence.typst {
+                    fences.push((fence.line, fence.code));
+                }
+            } else if fence.typst {
+                let strip = indent.min(fence.indent);
+                fence.code.push_str(&line[strip..]);
+                fence.code.push('\n');
+            }
+
+            continue;
+        }
+
+        if indent > 3 {
+            continue;
+        }
+
+        if let Some((marker, len)) = fence_marker(trimmed) {
+            let info = trimmed[len..].trim();
+
+            // NOTE(tinger): backtick fences can't contain backticks in their
+            // info string
+            if marker == '`' && info.contains('`') {
+                continue;
+            }
+
+            let language = info
+                .split(|c: char| c.is_whitespace() || c == ',' || c == '{')
+                .next()
+                .unwrap_or_default();
+
+            open = Some(Fence {
+                marker,
+                len,
+                indent,
+                line: idx + 1,
+                typst: LANGUAGES.contains(&language),
+                code: String::new(),
+            });
+        }
+    }
+
+    if let Some(fence) = open {
+        if fence.typst {
+            fences.push((fence.line, fence.code));
+        }
+    }
+
+    fences
+}
+
+/// Returns the marker and length of the fence this line starts with, if any.
+fn fence_marker(line: &str) -> Option<(char, usize)> {
+    let marker = line.chars().next().filter(|&c| c == '`' || c == '~')?;
+    let len = line.chars().take_while(|&c| c == marker).count();
+
+    (len >= 3).then_some((marker, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract() {
+        let content = "\
+# Example
+
+```typ
+#import \"lib.typ\": add
+#add(1, 2)
+```
+
+```rust
+fn main() {}
+```
+
+  ````typst
+  ```typ
+  nested
+  ```
+  ````
+
+~~~typ
+unclosed
+";
+
+        let tests = DocTest::extract(Path::new("docs/1-intro.md"), content);
+        let tests: Vec<_> = tests
+            .iter()
+            .map(|test| (test.test().id().as_str(), test.line(), test.source().text()))
+            .collect();
+
+        assert_eq!(
+            tests,
+            [
+                (
+                    "doc/docs/x1-intro-md/line-3",
+                    3,
+                    "#import \"lib.typ\": add\n#add(1, 2)\n"
+                ),
+                ("doc/docs/x1-intro-md/line-12", 12, "```typ\nnested\n```\n"),
+                ("doc/docs/x1-intro-md/line-18", 18, "unclosed\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_source_path() {
+        let tests = DocTest::extract(Path::new("docs/README.md"), "```typ\nHello\n```\n");
+        assert_eq!(
+            tests[0].source().id().vpath().as_rootless_path(),
+            Path::new("docs/README.md-1.typ")
+        );
+    }
+}
@@ -87,6 +87,44 @@ impl Id {
         inner(path.as_ref())
     }
 
+    /// Turns this string into an id after normalizing it, empty components
+    /// caused by redundant, leading or trailing separators are removed.
+    ///
+    /// # Examples
+    /// ```
+    /// # use typst_test_lib::test::Id;
+    /// let id = Id::normalize("/a//b/c/")?;
+    /// assert_eq!(id, "a/b/c");
+    /// # Ok::<_, Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// # Errors
+    /// Returns an error if a component wasn't valid, was a relative component
+    /// like `.` or `..`, or if there were no components at all.
+    pub fn normalize<S: AsRef<str>>(string: S) -> Result<Self, ParseIdError> {
+        let mut id = EcoString::new();
+
+        for component in string.as_ref().split(Self::SEPARATOR) {
+            match component {
+                "" => continue,
+                "." | ".." => return Err(ParseIdError::RelativeComponent),
+                _ => Self::validate_component(component)?,
+            }
+
+            if !id.is_empty() {
+                id.push_str(Self::SEPARATOR);
+            }
+
+            id.push_str(component);
+        }
+
+        if id.is_empty() {
+            return Err(ParseIdError::Empty);
+        }
+
+        Ok(Self(id))
+    }
+
     /// Turns this string into an id without validating it.
     ///
     /// # Safety
@@ -201,6 +239,42 @@ impl Id {
         c.rest
     }
 
+    /// The parent of this id, this is the id of the module containing it or
+    /// `None` if it has only one component.
+    ///
+    /// # Examples
+    /// ```
+    /// # use typst_test_lib::test::Id;
+    /// let id = Id::new("a/b/c")?;
+    /// assert_eq!(id.parent(), Some(Id::new("a/b")?));
+    /// assert_eq!(Id::new("a")?.parent(), None);
+    /// # Ok::<_, Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn parent(&self) -> Option<Self> {
+        let module = self.module();
+        (!module.is_empty()).then(|| Self(module.into()))
+    }
+
+    /// Whether this id is a strict ancestor of the given id, i.e. whether the
+    /// given id is contained in the module of this id. An id is not an
+    /// ancestor of itself.
+    ///
+    /// # Examples
+    /// ```
+    /// # use typst_test_lib::test::Id;
+    /// let id = Id::new("a/b")?;
+    /// assert!( id.is_ancestor_of(&Id::new("a/b/c")?));
+    /// assert!(!id.is_ancestor_of(&Id::new("a/b")?));
+    /// assert!(!id.is_ancestor_of(&Id::new("a/bc")?));
+    /// # Ok::<_, Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn is_ancestor_of(&self, other: &Id) -> bool {
+        other
+            .as_str()
+            .strip_prefix(self.as_str())
+            .is_some_and(|rest| rest.starts_with(Self::SEPARATOR))
+    }
+
     /// The ancestors of this id, this corresponds to the ancestors of the
     /// test's path.
     ///
@@ -443,6 +517,10 @@ pub enum ParseIdError {
     /// An id contained empty or no fragments.
     #[error("id contained empty or no fragments")]
     Empty,
+
+    /// An id contained a relative component like `.` or `..`.
+    #[error("id contained a relative component")]
+    RelativeComponent,
 }
 
 #[cfg(test)]
@@ -501,4 +579,63 @@ mod tests {
         assert!(Id::new("1a").is_err());
         assert!(Id::new("").is_err());
     }
+
+    #[test]
+    fn test_normalize() {
+        let tests = [
+            ("a/b/c", "a/b/c"),
+            ("a//b", "a/b"),
+            ("a/b/", "a/b"),
+            ("/a", "a"),
+            ("//a///b//", "a/b"),
+        ];
+
+        for (id, normalized) in tests {
+            assert_eq!(Id::normalize(id).unwrap(), normalized);
+        }
+    }
+
+    #[test]
+    fn test_normalize_invalid() {
+        assert!(matches!(Id::normalize(""), Err(ParseIdError::Empty)));
+        assert!(matches!(Id::normalize("//"), Err(ParseIdError::Empty)));
+        assert!(matches!(
+            Id::normalize("a/./b"),
+            Err(ParseIdError::RelativeComponent)
+        ));
+        assert!(matches!(
+            Id::normalize("a/../b"),
+            Err(ParseIdError::RelativeComponent)
+        ));
+        assert!(matches!(
+            Id::normalize("a/1b"),
+            Err(ParseIdError::InvalidFragment)
+        ));
+    }
+
+    #[test]
+    fn test_parent() {
+        let tests = [("a/b/c", Some("a/b")), ("a/b", Some("a")), ("a", None)];
+
+        for (id, parent) in tests {
+            assert_eq!(
+                Id(id.into()).parent(),
+                parent.map(|parent| Id(parent.into()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_ancestor_of() {
+        let id = |id: &str| Id(id.into());
+
+        assert!(id("a").is_ancestor_of(&id("a/b")));
+        assert!(id("a").is_ancestor_of(&id("a/b/c")));
+        assert!(id("a/b").is_ancestor_of(&id("a/b/c")));
+
+        assert!(!id("a").is_ancestor_of(&id("a")));
+        assert!(!id("a").is_ancestor_of(&id("ab/c")));
+        assert!(!id("a/b").is_ancestor_of(&id("a")));
+        assert!(!id("b").is_ancestor_of(&id("a/b")));
+    }
 }
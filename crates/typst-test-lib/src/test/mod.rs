@@ -1,24 +1,34 @@
 //! Test loading and on-disk manipulation.
 
+use std::collections::BTreeSet;
 use std::fmt::Debug;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use ecow::{eco_vec, EcoString, EcoVec};
 use thiserror::Error;
 use tiny_skia::Pixmap;
+use typst::diag::Warned;
 use typst::syntax::{FileId, Source, VirtualPath};
+use typst::World;
 
-use crate::doc::{Document, LoadError, SaveError};
+use crate::doc::compare::{Region, Strategy};
+use crate::doc::store::{self, DirectStore, ObjectStore, Store};
+use crate::doc::{compile, Document, LoadError, Pages, SaveError, SaveStats};
 use crate::project::{Paths, Vcs};
+use crate::stdx::fs::RetryConfig;
 use crate::{doc, stdx};
 
 mod annotation;
+mod doc_test;
 mod id;
 mod result;
 mod suite;
 
 pub use self::annotation::{Annotation, ParseAnnotationError};
+pub use self::doc_test::DocTest;
 pub use self::id::{Id, ParseIdError};
 pub use self::result::{Kind as TestResultKind, SuiteResult, TestResult};
 pub use self::suite::{CollectError as CollectSuiteError, Suite};
@@ -29,6 +39,13 @@ pub const DEFAULT_TEST_INPUT: &str = include_str!("../../../../assets/default-te
 /// The default test output as a compressed PNG.
 pub const DEFAULT_TEST_OUTPUT: &[u8] = include_bytes!("../../../../assets/default-test/test.png");
 
+/// How often and with which backoff operations on temporary directories are
+/// attempted if they race with concurrent operations on the same directories.
+const TEMPORARY_DIRECTORY_RETRY: RetryConfig = RetryConfig {
+    attempts: 5,
+    backoff: Duration::from_millis(5),
+};
+
 /// References for a test.
 #[derive(Debug, Clone)]
 pub enum Reference {
@@ -40,6 +57,56 @@ pub enum Reference {
     Persistent(Document, Option<Box<oxipng::Options>>),
 }
 
+impl Reference {
+    /// Loads the persistent references of the given test from disk, the
+    /// references must have at least one page and their pages must be
+    /// numbered contiguously.
+    pub fn load_persistent(paths: &Paths, id: &Id) -> Result<Self, LoadReferenceError> {
+        let dir = paths.test_ref_dir(id);
+        if !dir.try_exists()? {
+            return Err(LoadReferenceError::Missing(id.clone()));
+        }
+
+        let doc = load_documents(paths, &dir).map_err(|error| match error {
+            LoadError::MissingPages(pages) if pages.is_empty() => {
+                LoadReferenceError::Empty(id.clone())
+            }
+            LoadError::MissingPages(pages) => LoadReferenceError::Gapped {
+                id: id.clone(),
+                pages,
+            },
+            error => LoadReferenceError::Load {
+                id: id.clone(),
+                error,
+            },
+        })?;
+
+        // NOTE(tinger): the object store doesn't reject empty manifests
+        if doc.buffers().is_empty() {
+            return Err(LoadReferenceError::Empty(id.clone()));
+        }
+
+        Ok(Self::Persistent(doc, None))
+    }
+
+    /// Compiles [`DEFAULT_TEST_INPUT`] into persistent references with the
+    /// current typst version, this can be used instead of the bundled
+    /// [`DEFAULT_TEST_OUTPUT`], which may differ from the output of newer
+    /// typst versions.
+    pub fn compile_default(
+        world: &dyn World,
+        pixel_per_pt: f32,
+        optimize_options: Option<Box<oxipng::Options>>,
+    ) -> Result<Self, compile::Error> {
+        let Warned {
+            output,
+            warnings: _,
+        } = Document::compile(Source::detached(DEFAULT_TEST_INPUT), world, pixel_per_pt);
+
+        Ok(Self::Persistent(output?, optimize_options))
+    }
+}
+
 /// The kind of a unit test.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Kind {
@@ -56,6 +123,9 @@ pub enum Kind {
 }
 
 impl Kind {
+    /// All test kinds.
+    pub const ALL: [Kind; 3] = [Kind::Ephemeral, Kind::Persistent, Kind::CompileOnly];
+
     /// Whether this kind is is ephemeral.
     pub fn is_ephemeral(self) -> bool {
         matches!(self, Kind::Ephemeral)
@@ -97,11 +167,16 @@ impl Reference {
 /// [`Test::create_default`]. If a test was created using [`Test::new`] it can
 /// be persisted to disk using one of its `make_*` methods.
 ///
+/// Top-level scripts in the test root are single-file tests, these are
+/// always compile-only and can't have references.
+///
 /// This type is cheap to clone.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Test {
     id: Id,
     kind: Kind,
+    single_file: bool,
+    script: Option<EcoString>,
     annotations: EcoVec<Annotation>,
 }
 
@@ -111,19 +186,44 @@ impl Test {
         Self {
             id,
             kind: Kind::CompileOnly,
+            single_file: false,
+            script: None,
+            annotations: eco_vec![],
+        }
+    }
+
+    /// Creates a new single-file test for a script outside the test root, like
+    /// the entrypoint of a package. The script path is relative to the project
+    /// root.
+    pub fn new_script<S: Into<EcoString>>(id: Id, script: S) -> Self {
+        Self {
+            id,
+            kind: Kind::CompileOnly,
+            single_file: true,
+            script: Some(script.into()),
             annotations: eco_vec![],
         }
     }
 
     /// Attempt to load a test, returns `None` if no test could be found.
+    ///
+    /// If there is no test directory for the given id, a single-file test is
+    /// looked up instead, see [`Paths::test_file_script`].
     pub fn try_collect(paths: &Paths, id: Id) -> Result<Option<Test>, CollectError> {
         let test_script = paths.test_script(&id);
 
-        if !test_script.try_exists()? {
-            return Ok(None);
-        }
+        let (test_script, single_file) = if test_script.try_exists()? {
+            (test_script, false)
+        } else {
+            match single_file_script(paths, &id)? {
+                Some(test_script) => (test_script, true),
+                None => return Ok(None),
+            }
+        };
 
-        let kind = if paths.test_ref_script(&id).try_exists()? {
+        let kind = if single_file {
+            Kind::CompileOnly
+        } else if paths.test_ref_script(&id).try_exists()? {
             Kind::Ephemeral
         } else if paths.test_ref_dir(&id).try_exists()? {
             Kind::Persistent
@@ -150,11 +250,29 @@ impl Test {
         Ok(Some(Test {
             id,
             kind,
+            single_file,
+            script: None,
             annotations,
         }))
     }
 }
 
+/// Returns the script of the single-file test with the given id if it exists,
+/// only top-level scripts other than the test template are tests. If a
+/// directory of the same name exists it takes precedence.
+fn single_file_script(paths: &Paths, id: &Id) -> io::Result<Option<PathBuf>> {
+    if id.components().count() != 1 || paths.test_dir(id).try_exists()? {
+        return Ok(None);
+    }
+
+    let script = paths.test_file_script(id);
+    if script == paths.template() || !script.try_exists()? || !script.is_file() {
+        return Ok(None);
+    }
+
+    Ok(Some(script))
+}
+
 impl Test {
     /// The id of this test.
     pub fn id(&self) -> &Id {
@@ -166,6 +284,24 @@ impl Test {
         self.kind
     }
 
+    /// Whether this is a single-file test, i.e. a script in the test root
+    /// without a test directory or one created with [`Test::new_script`].
+    /// These tests are always compile-only.
+    pub fn is_single_file(&self) -> bool {
+        self.single_file
+    }
+
+    /// The path to the script of this test.
+    pub fn script(&self, paths: &Paths) -> PathBuf {
+        if let Some(script) = &self.script {
+            paths.project_root().join(script.as_str())
+        } else if self.single_file {
+            paths.test_file_script(&self.id)
+        } else {
+            paths.test_script(&self.id)
+        }
+    }
+
     /// This test's annotations.
     pub fn annotations(&self) -> &[Annotation] {
         &self.annotations
@@ -173,27 +309,135 @@ impl Test {
 
     /// Whether this test has a skip annotation.
     pub fn is_skip(&self) -> bool {
-        self.annotations.contains(&Annotation::Skip)
+        self.annotations
+            .iter()
+            .any(|annotation| matches!(annotation, Annotation::Skip(_)))
+    }
+
+    /// The reason given on this test's skip annotation, this is `None` if the
+    /// test is not skipped or no reason was given.
+    pub fn skip_reason(&self) -> Option<&str> {
+        self.annotations
+            .iter()
+            .find_map(|annotation| match annotation {
+                Annotation::Skip(reason) => reason.as_deref(),
+                _ => None,
+            })
+    }
+
+    /// The hook given on this test's prepare annotation, relative to the test
+    /// directory.
+    pub fn prepare_hook(&self) -> Option<&str> {
+        self.annotations
+            .iter()
+            .find_map(|annotation| match annotation {
+                Annotation::Prepare(hook) => Some(hook.as_str()),
+                _ => None,
+            })
+    }
+
+    /// The hook given on this test's cleanup annotation, relative to the test
+    /// directory.
+    pub fn cleanup_hook(&self) -> Option<&str> {
+        self.annotations
+            .iter()
+            .find_map(|annotation| match annotation {
+                Annotation::Cleanup(hook) => Some(hook.as_str()),
+                _ => None,
+            })
+    }
+
+    /// This test's variants, this is empty if the test has no variants
+    /// annotation.
+    pub fn variants(&self) -> &[EcoString] {
+        self.annotations
+            .iter()
+            .find_map(|annotation| match annotation {
+                Annotation::Variants(variants) => Some(variants.as_slice()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// The regions of the page at the given zero-based index which are
+    /// excluded from comparison by this test's mask annotations.
+    pub fn masks(&self, page: usize) -> Vec<Region> {
+        self.annotations
+            .iter()
+            .filter_map(|annotation| match annotation {
+                Annotation::Mask { page: p, region } if *p == page + 1 => Some(*region),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Applies this test's compare annotations on top of the given strategy,
+    /// annotated values take precedence.
+    pub fn compare_strategy(&self, strategy: Strategy) -> Strategy {
+        self.annotations
+            .iter()
+            .fold(strategy, |strategy, annotation| {
+                match (strategy, annotation) {
+                    (
+                        Strategy::Simple {
+                            max_delta,
+                            max_deviation,
+                        },
+                        Annotation::Compare {
+                            max_delta: delta,
+                            max_deviation: deviation,
+                        },
+                    ) => Strategy::Simple {
+                        max_delta: delta.unwrap_or(max_delta),
+                        max_deviation: deviation.unwrap_or(max_deviation),
+                    },
+                    (
+                        Strategy::Shift {
+                            max_delta,
+                            max_deviation,
+                            radius,
+                        },
+                        Annotation::Compare {
+                            max_delta: delta,
+                            max_deviation: deviation,
+                        },
+                    ) => Strategy::Shift {
+                        max_delta: delta.unwrap_or(max_delta),
+                        max_deviation: deviation.unwrap_or(max_deviation),
+                        radius,
+                    },
+                    (strategy, _) => strategy,
+                }
+            })
     }
 }
 
 impl Test {
     /// Creates a new default test on disk.
     pub fn create_default(paths: &Paths, id: Id) -> Result<Test, CreateError> {
-        Self::create(
+        Self::create_default_with(
             paths,
             id,
-            DEFAULT_TEST_INPUT,
             // NOTE(tinger): this image is already optimized
-            Some(Reference::Persistent(
+            Reference::Persistent(
                 Document::new([
                     Pixmap::decode_png(DEFAULT_TEST_OUTPUT).expect("bytes come from a valid PNG")
                 ]),
                 None,
-            )),
+            ),
         )
     }
 
+    /// Creates a new default test on disk with the given references instead
+    /// of the bundled ones, see [`Reference::compile_default`].
+    pub fn create_default_with(
+        paths: &Paths,
+        id: Id,
+        reference: Reference,
+    ) -> Result<Test, CreateError> {
+        Self::create(paths, id, DEFAULT_TEST_INPUT, Some(reference))
+    }
+
     /// Creates a new test on disk.
     pub fn create(
         paths: &Paths,
@@ -229,6 +473,8 @@ impl Test {
         let this = Self {
             id,
             kind,
+            single_file: false,
+            script: None,
             annotations,
         };
 
@@ -237,7 +483,13 @@ impl Test {
                 this.create_reference_script(paths, reference.as_str())?;
             }
             Some(Reference::Persistent(reference, options)) => {
-                this.create_reference_documents(paths, None, &reference, options.as_deref())?;
+                this.create_reference_documents(
+                    paths,
+                    &Vcs::null(paths.project_root()),
+                    &reference,
+                    &DirectStore,
+                    options.as_deref(),
+                )?;
             }
             None => {}
         }
@@ -246,27 +498,36 @@ impl Test {
     }
 
     /// Creates this test's temporary directories, if they don't exist yet.
-    pub fn create_temporary_directories(&self, paths: &Paths, vcs: Option<&Vcs>) -> io::Result<()> {
-        self.delete_temporary_directories(paths)?;
-
-        if self.kind.is_ephemeral() {
-            stdx::fs::create_dir(paths.test_ref_dir(&self.id), true)?;
+    ///
+    /// This may run concurrently with other calls for the same test, entries
+    /// which are created or removed in the meantime cause a retry after an
+    /// increasing backoff.
+    ///
+    /// The directories are not ignored in the vcs, this is left to the caller,
+    /// see [`Test::ignore_temporary_directories`].
+    pub fn create_temporary_directories(&self, paths: &Paths) -> io::Result<()> {
+        if self.single_file {
+            return Ok(());
         }
 
-        stdx::fs::create_dir(paths.test_out_dir(&self.id), true)?;
-        stdx::fs::create_dir(paths.test_diff_dir(&self.id), true)?;
+        stdx::fs::retry_if(TEMPORARY_DIRECTORY_RETRY, is_race, || {
+            self.delete_temporary_directories(paths)?;
 
-        if let Some(vcs) = vcs {
-            self.ignore_temporary_directories(paths, vcs)?;
-        }
+            if self.kind.is_ephemeral() {
+                stdx::fs::create_dir(paths.test_ref_dir(&self.id), true)?;
+            }
 
-        Ok(())
+            stdx::fs::create_dir(paths.test_out_dir(&self.id), true)?;
+            stdx::fs::create_dir(paths.test_diff_dir(&self.id), true)?;
+
+            Ok(())
+        })
     }
 
     /// Creates this test's main script, this will truncate the file if it
     /// already exists.
     pub fn create_script(&self, paths: &Paths, source: &str) -> io::Result<()> {
-        std::fs::write(paths.test_script(&self.id), source)?;
+        std::fs::write(self.script(paths), source)?;
         Ok(())
     }
 
@@ -277,14 +538,16 @@ impl Test {
         Ok(())
     }
 
-    /// Creates this test's persistent references.
+    /// Creates this test's persistent references in the given store, returns
+    /// the sizes of the written pages.
     pub fn create_reference_documents(
         &self,
         paths: &Paths,
-        vcs: Option<&Vcs>,
+        vcs: &Vcs,
         reference: &Document,
+        store: &dyn Store,
         optimize_options: Option<&oxipng::Options>,
-    ) -> Result<(), SaveError> {
+    ) -> Result<SaveStats, SaveError> {
         // NOTE(tinger): if there are already more pages than we want to create,
         // the surplus pages would persist and make every comparison fail due to
         // a page count mismatch, so we clear them to be sure.
@@ -292,24 +555,48 @@ impl Test {
 
         let ref_dir = paths.test_ref_dir(&self.id);
         stdx::fs::create_dir(&ref_dir, true)?;
-        reference.save(&ref_dir, optimize_options)?;
+        let stats = store.save(&ref_dir, reference, optimize_options)?;
 
         if self.kind().is_ephemeral() {
-            if let Some(vcs) = vcs {
-                self.ignore_reference_documents(paths, vcs)?;
-            }
+            self.ignore_reference_documents(paths, vcs)?;
         }
 
-        Ok(())
+        Ok(stats)
+    }
+
+    /// Removes any previous references of the given variant, if they exist and
+    /// saves the given pages as its references in the given store, returns
+    /// the sizes of the written pages.
+    pub fn create_variant_reference_documents(
+        &self,
+        paths: &Paths,
+        variant: &str,
+        reference: &Document,
+        store: &dyn Store,
+        optimize_options: Option<&oxipng::Options>,
+    ) -> Result<SaveStats, SaveError> {
+        let ref_dir = paths.test_ref_variant_dir(&self.id, variant);
+        stdx::fs::remove_dir(&ref_dir, true)?;
+        stdx::fs::create_dir(&ref_dir, true)?;
+        let stats = store.save(&ref_dir, reference, optimize_options)?;
+
+        Ok(stats)
     }
 
     /// Deletes this test's directories and scripts, if they exist.
     pub fn delete(&self, paths: &Paths) -> io::Result<()> {
+        // NOTE(tinger): single-file tests have neither a test directory nor
+        // references, removing those would fail on the missing directory
+        if self.single_file {
+            stdx::fs::remove_file(self.script(paths))?;
+            return Ok(());
+        }
+
         self.delete_reference_documents(paths)?;
         self.delete_reference_script(paths)?;
         self.delete_temporary_directories(paths)?;
 
-        stdx::fs::remove_file(paths.test_script(&self.id))?;
+        stdx::fs::remove_file(self.script(paths))?;
         stdx::fs::remove_dir(paths.test_dir(&self.id), true)?;
 
         Ok(())
@@ -317,18 +604,22 @@ impl Test {
 
     /// Deletes this test's temporary directories, if they exist.
     pub fn delete_temporary_directories(&self, paths: &Paths) -> io::Result<()> {
+        if self.single_file {
+            return Ok(());
+        }
+
         if self.kind.is_ephemeral() {
-            stdx::fs::remove_dir(paths.test_ref_dir(&self.id), true)?;
+            remove_temporary_dir(&paths.test_ref_dir(&self.id))?;
         }
 
-        stdx::fs::remove_dir(paths.test_out_dir(&self.id), true)?;
-        stdx::fs::remove_dir(paths.test_diff_dir(&self.id), true)?;
+        remove_temporary_dir(&paths.test_out_dir(&self.id))?;
+        remove_temporary_dir(&paths.test_diff_dir(&self.id))?;
         Ok(())
     }
 
     /// Deletes this test's main script, if it exists.
     pub fn delete_script(&self, paths: &Paths) -> io::Result<()> {
-        stdx::fs::remove_file(paths.test_script(&self.id))?;
+        stdx::fs::remove_file(self.script(paths))?;
         Ok(())
     }
 
@@ -344,16 +635,28 @@ impl Test {
         Ok(())
     }
 
-    /// Ignores this test's temporary directories in the vcs.
-    pub fn ignore_temporary_directories(&self, paths: &Paths, vcs: &Vcs) -> io::Result<()> {
+    /// The paths of this test's temporary directories, this is empty for
+    /// single file tests.
+    pub fn temporary_directories(&self, paths: &Paths) -> Vec<PathBuf> {
+        if self.single_file {
+            return vec![];
+        }
+
+        let mut dirs = vec![paths.test_out_dir(&self.id), paths.test_diff_dir(&self.id)];
         if self.kind.is_ephemeral() {
-            vcs.ignore_dir(&paths.test_ref_dir(&self.id))?;
+            dirs.push(paths.test_ref_dir(&self.id));
         }
 
-        vcs.ignore_dir(&paths.test_out_dir(&self.id))?;
-        vcs.ignore_dir(&paths.test_diff_dir(&self.id))?;
+        dirs
+    }
 
-        Ok(())
+    /// Ignores this test's temporary directories in the vcs.
+    ///
+    /// To ignore the directories of many tests at once, pass their
+    /// [`Test::temporary_directories`] to [`Vcs::ignore_dirs`] instead.
+    pub fn ignore_temporary_directories(&self, paths: &Paths, vcs: &Vcs) -> io::Result<()> {
+        let dirs = self.temporary_directories(paths);
+        vcs.ignore_dirs(dirs.iter().map(PathBuf::as_path))
     }
 
     /// Ignores this test's persistent reference documents in the vcs.
@@ -370,12 +673,13 @@ impl Test {
 
     /// Removes any previous references, if they exist and creates a reference
     /// script by copying the test script.
-    pub fn make_ephemeral(&mut self, paths: &Paths, vcs: Option<&Vcs>) -> io::Result<()> {
+    ///
+    /// Returns an error for single-file tests, these can't have references.
+    pub fn make_ephemeral(&mut self, paths: &Paths, vcs: &Vcs) -> io::Result<()> {
+        self.ensure_not_single_file()?;
         self.delete_reference_script(paths)?;
         self.delete_reference_documents(paths)?;
-        if let Some(vcs) = vcs {
-            self.ignore_reference_documents(paths, vcs)?;
-        }
+        self.ignore_reference_documents(paths, vcs)?;
 
         std::fs::copy(paths.test_script(&self.id), paths.test_ref_script(&self.id))?;
 
@@ -385,38 +689,50 @@ impl Test {
 
     /// Removes any previous references, if they exist and creates persistent
     /// references from the given pages.
+    ///
+    /// Returns an error for single-file tests, these can't have references.
     pub fn make_persistent(
         &mut self,
         paths: &Paths,
-        vcs: Option<&Vcs>,
+        vcs: &Vcs,
         reference: &Document,
         optimize_options: Option<&oxipng::Options>,
     ) -> Result<(), SaveError> {
+        self.ensure_not_single_file()?;
         self.delete_reference_script(paths)?;
-        self.create_reference_documents(paths, vcs, reference, optimize_options)?;
-        if let Some(vcs) = vcs {
-            self.unignore_reference_documents(paths, vcs)?;
-        }
+        self.create_reference_documents(paths, vcs, reference, &DirectStore, optimize_options)?;
+        self.unignore_reference_documents(paths, vcs)?;
 
         self.kind = Kind::Persistent;
         Ok(())
     }
 
     /// Removes any previous references, if they exist.
-    pub fn make_compile_only(&mut self, paths: &Paths, vcs: Option<&Vcs>) -> io::Result<()> {
+    pub fn make_compile_only(&mut self, paths: &Paths, vcs: &Vcs) -> io::Result<()> {
         self.delete_reference_documents(paths)?;
         self.delete_reference_script(paths)?;
-        if let Some(vcs) = vcs {
-            self.ignore_reference_documents(paths, vcs)?;
-        }
+        self.ignore_reference_documents(paths, vcs)?;
 
         self.kind = Kind::CompileOnly;
         Ok(())
     }
 
+    /// Returns an error if this is a single-file test, these can't have
+    /// references.
+    fn ensure_not_single_file(&self) -> io::Result<()> {
+        if self.single_file {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("single-file test {} can't have references", self.id),
+            ));
+        }
+
+        Ok(())
+    }
+
     /// Loads the test script source of this test.
     pub fn load_source(&self, paths: &Paths) -> io::Result<Source> {
-        let test_script = paths.test_script(&self.id);
+        let test_script = self.script(paths);
 
         Ok(Source::new(
             FileId::new(
@@ -453,12 +769,74 @@ impl Test {
     }
 
     /// Loads the persistent reference pages of this test, if they exist.
+    ///
+    /// References saved in an [`ObjectStore`] are detected by their manifest
+    /// and loaded from the project's object directory.
     pub fn load_reference_documents(&self, paths: &Paths) -> Result<Option<Document>, LoadError> {
         match self.kind {
-            Kind::Persistent => Document::load(paths.test_ref_dir(&self.id)).map(Some),
+            Kind::Persistent => load_documents(paths, &paths.test_ref_dir(&self.id)).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Loads the persistent reference pages of the given variant of this test,
+    /// if there are any.
+    pub fn load_variant_reference_documents(
+        &self,
+        paths: &Paths,
+        variant: &str,
+    ) -> Result<Option<Document>, LoadError> {
+        match self.kind {
+            Kind::Persistent => {
+                load_documents(paths, &paths.test_ref_variant_dir(&self.id, variant)).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Collects the persistent reference pages of this test without decoding
+    /// them, if there are any, see [`Test::load_reference_documents`].
+    pub fn load_reference_pages(&self, paths: &Paths) -> Result<Option<Pages>, LoadError> {
+        match self.kind {
+            Kind::Persistent => load_pages(paths, &paths.test_ref_dir(&self.id)).map(Some),
             _ => Ok(None),
         }
     }
+
+    /// Collects the persistent reference pages of the given variant of this
+    /// test without decoding them, if there are any.
+    pub fn load_variant_reference_pages(
+        &self,
+        paths: &Paths,
+        variant: &str,
+    ) -> Result<Option<Pages>, LoadError> {
+        match self.kind {
+            Kind::Persistent => {
+                load_pages(paths, &paths.test_ref_variant_dir(&self.id, variant)).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Loads the documents in the given reference directory from the store it was
+/// saved in.
+fn load_documents(paths: &Paths, dir: &Path) -> Result<Document, LoadError> {
+    if store::is_object_store_dir(dir) {
+        ObjectStore::new(paths.objects_dir()).load(dir)
+    } else {
+        DirectStore.load(dir)
+    }
+}
+
+/// Collects the pages in the given reference directory from the store it was
+/// saved in without decoding them.
+fn load_pages(paths: &Paths, dir: &Path) -> Result<Pages, LoadError> {
+    if store::is_object_store_dir(dir) {
+        ObjectStore::new(paths.objects_dir()).load_pages(dir)
+    } else {
+        DirectStore.load_pages(dir)
+    }
 }
 
 /// Returned by [`Test::create`].
@@ -477,6 +855,43 @@ pub enum CreateError {
     Io(#[from] io::Error),
 }
 
+/// Returned by [`Reference::load_persistent`].
+#[derive(Debug, Error)]
+pub enum LoadReferenceError {
+    /// The reference directory of the test didn't exist.
+    #[error("test {0} has no reference directory")]
+    Missing(Id),
+
+    /// The reference directory of the test contained no pages.
+    #[error("test {0} has no reference pages")]
+    Empty(Id),
+
+    /// The reference pages of the test were not numbered contiguously.
+    #[error("test {id} has missing reference pages, found only {pages:?}")]
+    Gapped {
+        /// The id of the test.
+        id: Id,
+
+        /// The physical page numbers which were found.
+        pages: BTreeSet<usize>,
+    },
+
+    /// The references of the test couldn't be loaded.
+    #[error("couldn't load references of test {id}")]
+    Load {
+        /// The id of the test.
+        id: Id,
+
+        /// The inner error.
+        #[source]
+        error: LoadError,
+    },
+
+    /// An io error occurred.
+    #[error("an io error occurred")]
+    Io(#[from] io::Error),
+}
+
 /// Returned by [`Test::try_collect`].
 #[derive(Debug, Error)]
 pub enum CollectError {
@@ -489,11 +904,37 @@ pub enum CollectError {
     Io(#[from] io::Error),
 }
 
+/// Whether the given error is likely caused by a concurrent operation
+/// creating or removing the same entries.
+fn is_race(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::NotFound | io::ErrorKind::AlreadyExists
+    )
+}
+
+/// Removes a temporary directory, this succeeds if the directory was removed
+/// concurrently, even if its entries vanished while it was being removed.
+fn remove_temporary_dir(path: &Path) -> io::Result<()> {
+    // NOTE(tinger): entries added concurrently fail the removal with
+    // `DirectoryNotEmpty`, which we can't match on with our MSRV, so we retry
+    // any error as long as the directory exists
+    stdx::fs::retry_if(
+        TEMPORARY_DIRECTORY_RETRY,
+        |_| true,
+        || match stdx::fs::remove_dir(path, true) {
+            Err(_) if !path.try_exists()? => Ok(()),
+            res => res,
+        },
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::_dev;
     use crate::_dev::fs::Setup;
+    use crate::_dev::GlobalTestWorld;
 
     fn id(id: &str) -> Id {
         Id::new(id).unwrap()
@@ -503,6 +944,225 @@ mod tests {
         Test::new(id(test_id))
     }
 
+    #[test]
+    fn test_compare_strategy() {
+        let a = Pixmap::new(10, 1).unwrap();
+        let mut b = Pixmap::new(10, 1).unwrap();
+        let red = tiny_skia::PremultipliedColorU8::from_rgba(128, 0, 0, 128).unwrap();
+        b.pixels_mut()[..4].fill(red);
+
+        let annotated = Test {
+            id: id("annotated"),
+            kind: Kind::Persistent,
+            single_file: false,
+            script: None,
+            annotations: eco_vec![Annotation::Compare {
+                max_delta: None,
+                max_deviation: Some(4),
+            }],
+        };
+
+        let strict = Strategy::default();
+        assert!(doc::compare::page(&a, &b, test("plain").compare_strategy(strict)).is_err());
+        assert!(doc::compare::page(&a, &b, annotated.compare_strategy(strict)).is_ok());
+    }
+
+    #[test]
+    fn test_masks() {
+        let mut a = Pixmap::new(10, 2).unwrap();
+        let mut b = Pixmap::new(10, 2).unwrap();
+        let red = tiny_skia::PremultipliedColorU8::from_rgba(128, 0, 0, 128).unwrap();
+        a.pixels_mut()[..4].fill(red);
+        b.pixels_mut()[10..14].fill(red);
+
+        let region = |y| Region {
+            x: 0,
+            y,
+            width: 10,
+            height: 1,
+        };
+        let masked = Test {
+            id: id("masked"),
+            kind: Kind::Persistent,
+            single_file: false,
+            script: None,
+            annotations: eco_vec![
+                Annotation::Mask {
+                    page: 1,
+                    region: region(0),
+                },
+                Annotation::Mask {
+                    page: 1,
+                    region: region(1),
+                },
+                Annotation::Mask {
+                    page: 2,
+                    region: region(0),
+                },
+            ],
+        };
+
+        assert_eq!(masked.masks(0), [region(0), region(1)]);
+        assert_eq!(masked.masks(1), [region(0)]);
+        assert!(masked.masks(2).is_empty());
+
+        let strict = Strategy::default();
+        assert!(doc::compare::page_masked(&a, &b, strict, &masked.masks(0)).is_ok());
+        assert!(doc::compare::page_masked(&a, &b, strict, &masked.masks(1)).is_err());
+    }
+
+    #[test]
+    fn test_variants() {
+        _dev::fs::TempEnv::run(
+            |root| {
+                root.setup_file(
+                    "tests/variants/test.typ",
+                    "/// [variants: light, dark]\nHello World",
+                )
+                .setup_dir("tests/variants/ref")
+            },
+            |root| {
+                let paths = Paths::new(root, None);
+                let test = Test::try_collect(&paths, id("variants")).unwrap().unwrap();
+                assert_eq!(
+                    test.variants(),
+                    [EcoString::from("light"), EcoString::from("dark")]
+                );
+
+                let light = Document::new([Pixmap::new(1, 1).unwrap()]);
+                let dark = Document::new([Pixmap::new(2, 2).unwrap(), Pixmap::new(2, 2).unwrap()]);
+
+                test.create_variant_reference_documents(
+                    &paths,
+                    "light",
+                    &light,
+                    &DirectStore,
+                    None,
+                )
+                .unwrap();
+                test.create_variant_reference_documents(&paths, "dark", &dark, &DirectStore, None)
+                    .unwrap();
+
+                let loaded = test
+                    .load_variant_reference_documents(&paths, "dark")
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(loaded.buffers().len(), 2);
+            },
+            |root| {
+                root.expect_file_content(
+                    "tests/variants/test.typ",
+                    "/// [variants: light, dark]\nHello World",
+                )
+                .expect_file("tests/variants/ref/light/1.png")
+                .expect_file("tests/variants/ref/dark/1.png")
+                .expect_file("tests/variants/ref/dark/2.png")
+            },
+        );
+    }
+
+    #[test]
+    fn test_object_store_shared_pages() {
+        let shared = Pixmap::decode_png(DEFAULT_TEST_OUTPUT).unwrap();
+        let other = Pixmap::new(2, 2).unwrap();
+        let name = ObjectStore::object_name(&shared);
+
+        _dev::fs::TempEnv::run(
+            |root| {
+                root.setup_file("tests/a/test.typ", "Hello World")
+                    .setup_dir("tests/a/ref")
+                    .setup_file("tests/b/test.typ", "Hello World")
+                    .setup_dir("tests/b/ref")
+            },
+            |root| {
+                let paths = Paths::new(root, None);
+                let store = ObjectStore::new(paths.objects_dir());
+
+                let a = Test::try_collect(&paths, id("a")).unwrap().unwrap();
+                let b = Test::try_collect(&paths, id("b")).unwrap().unwrap();
+
+                let a_doc = Document::new([shared.clone(), other.clone()]);
+                let b_doc = Document::new([shared.clone()]);
+                a.create_reference_documents(&paths, &Vcs::null(root), &a_doc, &store, None)
+                    .unwrap();
+                b.create_reference_documents(&paths, &Vcs::null(root), &b_doc, &store, None)
+                    .unwrap();
+
+                let a_loaded = a.load_reference_documents(&paths).unwrap().unwrap();
+                let b_loaded = b.load_reference_documents(&paths).unwrap().unwrap();
+                assert_eq!(a_loaded.buffers(), a_doc.buffers());
+                assert_eq!(b_loaded.buffers(), b_doc.buffers());
+            },
+            |root| {
+                root.expect_file_content("tests/a/test.typ", "Hello World")
+                    .expect_file("tests/a/ref/objects.txt")
+                    .expect_file_content("tests/b/test.typ", "Hello World")
+                    .expect_file_content("tests/b/ref/objects.txt", format!("{name}\n"))
+                    .expect_file(format!(".typst-test/objects/{name}.png"))
+                    .expect_file(format!(
+                        ".typst-test/objects/{}.png",
+                        ObjectStore::object_name(&other),
+                    ))
+            },
+        );
+    }
+
+    #[test]
+    fn test_load_reference_documents_missing_pages() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/persistent/test.typ", "Hello World")
+                    .setup_file("tests/persistent/ref/2.png", DEFAULT_TEST_OUTPUT)
+            },
+            |root| {
+                let paths = Paths::new(root, None);
+                let test = Test::try_collect(&paths, id("persistent"))
+                    .unwrap()
+                    .unwrap();
+
+                assert!(matches!(
+                    test.load_reference_documents(&paths),
+                    Err(LoadError::MissingPages(pages)) if pages == BTreeSet::from([2]),
+                ));
+            },
+        );
+    }
+
+    #[test]
+    fn test_reference_load_persistent() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/valid/ref/1.png", DEFAULT_TEST_OUTPUT)
+                    .setup_file("tests/valid/ref/2.png", DEFAULT_TEST_OUTPUT)
+                    .setup_dir("tests/empty/ref")
+                    .setup_file("tests/gapped/ref/1.png", DEFAULT_TEST_OUTPUT)
+                    .setup_file("tests/gapped/ref/3.png", DEFAULT_TEST_OUTPUT)
+                    .setup_dir("tests/missing")
+            },
+            |root| {
+                let paths = Paths::new(root, None);
+                let load = |test| Reference::load_persistent(&paths, &id(test));
+
+                assert!(matches!(
+                    load("valid"),
+                    Ok(Reference::Persistent(doc, None)) if doc.buffers().len() == 2,
+                ));
+                assert!(matches!(
+                    load("empty"),
+                    Err(LoadReferenceError::Empty(id)) if id.as_str() == "empty",
+                ));
+                assert!(matches!(
+                    load("gapped"),
+                    Err(LoadReferenceError::Gapped { pages, .. }) if pages == BTreeSet::from([1, 3]),
+                ));
+                assert!(matches!(
+                    load("missing"),
+                    Err(LoadReferenceError::Missing(_)),
+                ));
+            },
+        );
+    }
+
     fn setup_all(root: &mut Setup) -> &mut Setup {
         root.setup_file("tests/compile-only/test.typ", "Hello World")
             .setup_file("tests/ephemeral/test.typ", "Hello World")
@@ -549,15 +1209,46 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_create_default_compiled() {
+        let world = GlobalTestWorld::default();
+
+        _dev::fs::TempEnv::run_no_check(
+            |root| root.setup_dir("tests"),
+            |root| {
+                let paths = Paths::new(root, None);
+                let reference = Reference::compile_default(&world, 1.0, None).unwrap();
+                Test::create_default_with(&paths, id("default"), reference).unwrap();
+
+                let fresh = Document::compile(Source::detached(DEFAULT_TEST_INPUT), &world, 1.0)
+                    .output
+                    .unwrap();
+                let reference = Document::load(paths.test_ref_dir(&id("default"))).unwrap();
+
+                assert_eq!(
+                    std::fs::read_to_string(paths.test_script(&id("default"))).unwrap(),
+                    DEFAULT_TEST_INPUT
+                );
+                assert!(Document::compare(fresh, reference, Strategy::default(), false).is_ok());
+            },
+        );
+    }
+
     #[test]
     fn test_make_ephemeral() {
         _dev::fs::TempEnv::run(
             setup_all,
             |root| {
                 let paths = Paths::new(root, None);
-                test("compile-only").make_ephemeral(&paths, None).unwrap();
-                test("ephemeral").make_ephemeral(&paths, None).unwrap();
-                test("persistent").make_ephemeral(&paths, None).unwrap();
+                test("compile-only")
+                    .make_ephemeral(&paths, &Vcs::null(root))
+                    .unwrap();
+                test("ephemeral")
+                    .make_ephemeral(&paths, &Vcs::null(root))
+                    .unwrap();
+                test("persistent")
+                    .make_ephemeral(&paths, &Vcs::null(root))
+                    .unwrap();
             },
             |root| {
                 root.expect_file_content("tests/compile-only/test.typ", "Hello World")
@@ -570,6 +1261,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_make_ephemeral_single_file() {
+        _dev::fs::TempEnv::run(
+            |root| root.setup_file("tests/single.typ", "Hello World"),
+            |root| {
+                let paths = Paths::new(root, None);
+                let mut test = Test::try_collect(&paths, id("single")).unwrap().unwrap();
+                assert!(test.is_single_file());
+                assert_eq!(test.kind(), Kind::CompileOnly);
+
+                let err = test.make_ephemeral(&paths, &Vcs::null(root)).unwrap_err();
+                assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            },
+            |root| root.expect_file_content("tests/single.typ", "Hello World"),
+        );
+    }
+
     #[test]
     fn test_make_persistent() {
         _dev::fs::TempEnv::run(
@@ -577,15 +1285,15 @@ mod tests {
             |root| {
                 let paths = Paths::new(root, None);
                 test("compile-only")
-                    .make_persistent(&paths, None, &Document::new([]), None)
+                    .make_persistent(&paths, &Vcs::null(root), &Document::new([]), None)
                     .unwrap();
 
                 test("ephemeral")
-                    .make_persistent(&paths, None, &Document::new([]), None)
+                    .make_persistent(&paths, &Vcs::null(root), &Document::new([]), None)
                     .unwrap();
 
                 test("persistent")
-                    .make_persistent(&paths, None, &Document::new([]), None)
+                    .make_persistent(&paths, &Vcs::null(root), &Document::new([]), None)
                     .unwrap();
             },
             |root| {
@@ -606,10 +1314,14 @@ mod tests {
             |root| {
                 let paths = Paths::new(root, None);
                 test("compile-only")
-                    .make_compile_only(&paths, None)
+                    .make_compile_only(&paths, &Vcs::null(root))
+                    .unwrap();
+                test("ephemeral")
+                    .make_compile_only(&paths, &Vcs::null(root))
+                    .unwrap();
+                test("persistent")
+                    .make_compile_only(&paths, &Vcs::null(root))
                     .unwrap();
-                test("ephemeral").make_compile_only(&paths, None).unwrap();
-                test("persistent").make_compile_only(&paths, None).unwrap();
             },
             |root| {
                 root.expect_file_content("tests/compile-only/test.typ", "Hello World")
@@ -619,6 +1331,67 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_create_temporary_directories_null_vcs() {
+        _dev::fs::TempEnv::run(
+            |root| root.setup_file("tests/fancy/test.typ", "Hello World"),
+            |root| {
+                let paths = Paths::new(root, None);
+                let mut test = test("fancy");
+                test.kind = Kind::Ephemeral;
+                test.create_temporary_directories(&paths).unwrap();
+            },
+            |root| {
+                root.expect_file_content("tests/fancy/test.typ", "Hello World")
+                    .expect_dir("tests/fancy/ref")
+                    .expect_dir("tests/fancy/out")
+                    .expect_dir("tests/fancy/diff")
+            },
+        );
+    }
+
+    #[test]
+    fn test_create_temporary_directories_concurrent() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                for idx in 0..16 {
+                    root.setup_file(format!("tests/nested/test-{idx}/test.typ"), "Hello");
+                }
+                root
+            },
+            |root| {
+                let paths = Paths::new(root, None);
+                let tests: Vec<_> = (0..16)
+                    .map(|idx| {
+                        let mut test = test(&format!("nested/test-{idx}"));
+                        test.kind = Kind::Ephemeral;
+                        test
+                    })
+                    .collect();
+
+                // every thread clears all tests, so each test's directories
+                // are created and deleted concurrently
+                std::thread::scope(|scope| {
+                    for _ in 0..4 {
+                        scope.spawn(|| {
+                            for _ in 0..4 {
+                                for test in &tests {
+                                    test.create_temporary_directories(&paths).unwrap();
+                                }
+                            }
+                        });
+                    }
+                });
+
+                for test in &tests {
+                    assert!(paths.test_ref_dir(test.id()).is_dir());
+                    assert!(paths.test_out_dir(test.id()).is_dir());
+                    assert!(paths.test_diff_dir(test.id()).is_dir());
+                }
+            },
+        );
+    }
+
     #[test]
     fn test_load_sources() {
         _dev::fs::TempEnv::run_no_check(
@@ -655,4 +1428,39 @@ mod tests {
             },
         );
     }
+
+    #[test]
+    fn test_delete_single_file() {
+        _dev::fs::TempEnv::run(
+            |root| {
+                root.setup_file("tests/foo.typ", "Hello World")
+                    .setup_file("tests/bar/test.typ", "Hello World")
+            },
+            |root| {
+                let paths = Paths::new(root, None);
+                let test = Test::try_collect(&paths, id("foo")).unwrap().unwrap();
+                assert!(test.is_single_file());
+                test.delete(&paths).unwrap();
+            },
+            |root| root.expect_file_content("tests/bar/test.typ", "Hello World"),
+        );
+    }
+
+    #[test]
+    fn test_load_source_script() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| root.setup_file("src/lib.typ", "Hello World"),
+            |root| {
+                let paths = Paths::new(root, None);
+
+                let test = Test::new_script(id("entrypoint"), "src/lib.typ");
+                assert!(test.is_single_file());
+                assert_eq!(test.kind(), Kind::CompileOnly);
+
+                let source = test.load_source(&paths).unwrap();
+                assert_eq!(source.text(), "Hello World");
+                assert_eq!(source.id().vpath(), &VirtualPath::new("src/lib.typ"));
+            },
+        );
+    }
 }
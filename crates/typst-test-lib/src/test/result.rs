@@ -1,14 +1,15 @@
 //! Test results.
 
 use std::collections::BTreeMap;
+use std::io;
 use std::time::{Duration, Instant};
 
-use ecow::{eco_vec, EcoVec};
+use ecow::{eco_vec, EcoString, EcoVec};
 use typst::diag::SourceDiagnostic;
 use uuid::Uuid;
 
 use super::{Id, Suite};
-use crate::doc::{compare, compile};
+use crate::doc::{compare, compile, SaveStats};
 
 /// The result kind of a single test kind.
 #[derive(Debug, Clone, Default)]
@@ -34,6 +35,39 @@ pub enum Kind {
     /// The test passed compilation, but failed comparison.
     FailedComparison(compare::Error),
 
+    /// The test passed compilation, but its reference was not updated because
+    /// the change exceeded the allowed threshold.
+    FailedUpdate {
+        /// The fraction of pixels which would have changed, see
+        /// [`Document::change`](crate::doc::Document::change).
+        change: f64,
+    },
+
+    /// A prepare or cleanup hook of the test exited unsuccessfully.
+    FailedHook {
+        /// The program of the hook.
+        hook: EcoString,
+
+        /// The exit code of the hook, if it wasn't terminated by a signal.
+        code: Option<i32>,
+
+        /// The captured stdout of the hook.
+        stdout: EcoString,
+
+        /// The captured stderr of the hook.
+        stderr: EcoString,
+    },
+
+    /// An io error occurred while running the test, this is only recorded if
+    /// the run continues after such errors.
+    FailedIo {
+        /// The kind of the underlying io error.
+        kind: io::ErrorKind,
+
+        /// The displayed error and its causes.
+        error: EcoString,
+    },
+
     /// The test passed compilation, but did not run comparison.
     PassedCompilation,
 
@@ -45,7 +79,9 @@ pub enum Kind {
 #[derive(Debug, Clone)]
 pub struct TestResult {
     kind: Option<Kind>,
+    variant: Option<EcoString>,
     warnings: EcoVec<SourceDiagnostic>,
+    save_stats: SaveStats,
     timestamp: Instant,
     duration: Duration,
 }
@@ -55,7 +91,9 @@ impl TestResult {
     pub fn new() -> Self {
         Self {
             kind: None,
+            variant: None,
             warnings: eco_vec![],
+            save_stats: SaveStats::default(),
             timestamp: Instant::now(),
             duration: Duration::ZERO,
         }
@@ -65,7 +103,9 @@ impl TestResult {
     pub fn filtered() -> Self {
         Self {
             kind: Some(Kind::Filtered),
+            variant: None,
             warnings: eco_vec![],
+            save_stats: SaveStats::default(),
             timestamp: Instant::now(),
             duration: Duration::ZERO,
         }
@@ -78,11 +118,23 @@ impl TestResult {
         self.kind.as_ref()
     }
 
+    /// The variant of the test this result refers to, if the test has
+    /// variants and one of them failed.
+    pub fn variant(&self) -> Option<&str> {
+        self.variant.as_deref()
+    }
+
     /// The warnings of the test emitted by the compiler.
     pub fn warnings(&self) -> &[SourceDiagnostic] {
         &self.warnings
     }
 
+    /// The sizes of the references written for this test, this is only
+    /// non-zero for updated tests.
+    pub fn save_stats(&self) -> SaveStats {
+        self.save_stats
+    }
+
     /// The timestamp at which the suite run started.
     pub fn timestamp(&self) -> Instant {
         self.timestamp
@@ -116,7 +168,13 @@ impl TestResult {
     pub fn is_fail(&self) -> bool {
         matches!(
             &self.kind,
-            Some(Kind::FailedCompilation { .. } | Kind::FailedComparison(..)),
+            Some(
+                Kind::FailedCompilation { .. }
+                    | Kind::FailedComparison(..)
+                    | Kind::FailedUpdate { .. }
+                    | Kind::FailedHook { .. }
+                    | Kind::FailedIo { .. }
+            ),
         )
     }
 
@@ -143,6 +201,17 @@ impl TestResult {
         self.duration = self.timestamp.elapsed();
     }
 
+    /// Sets the duration directly, this is useful for results which were not
+    /// timed by [`TestResult::start`] and [`TestResult::end`].
+    pub fn set_duration(&mut self, duration: Duration) {
+        self.duration = duration;
+    }
+
+    /// Resets the kind for this test, marking it as cancelled.
+    pub fn set_cancelled(&mut self) {
+        self.kind = None;
+    }
+
     /// Sets the kind for this test to a reference compilation failure.
     pub fn set_failed_reference_compilation(&mut self, error: compile::Error) {
         self.kind = Some(Kind::FailedCompilation {
@@ -169,11 +238,48 @@ impl TestResult {
         self.kind = Some(Kind::FailedComparison(error));
     }
 
+    /// Sets the kind for this test to an update failure.
+    pub fn set_failed_update(&mut self, change: f64) {
+        self.kind = Some(Kind::FailedUpdate { change });
+    }
+
+    /// Sets the kind for this test to a hook failure.
+    pub fn set_failed_hook(
+        &mut self,
+        hook: EcoString,
+        code: Option<i32>,
+        stdout: EcoString,
+        stderr: EcoString,
+    ) {
+        self.kind = Some(Kind::FailedHook {
+            hook,
+            code,
+            stdout,
+            stderr,
+        });
+    }
+
+    /// Sets the kind for this test to an io failure.
+    pub fn set_failed_io(&mut self, kind: io::ErrorKind, error: EcoString) {
+        self.kind = Some(Kind::FailedIo { kind, error });
+    }
+
     /// Sets the kind for this test to a test comparison pass.
     pub fn set_passed_comparison(&mut self) {
         self.kind = Some(Kind::PassedComparison);
     }
 
+    /// Sets the variant this result refers to.
+    pub fn set_variant(&mut self, variant: Option<EcoString>) {
+        self.variant = variant;
+    }
+
+    /// Adds the sizes of written references to this test, these accumulate
+    /// across variants.
+    pub fn add_save_stats(&mut self, stats: SaveStats) {
+        self.save_stats += stats;
+    }
+
     /// Sets the warnings for this test.
     pub fn set_warnings<I>(&mut self, warnings: I)
     where
@@ -181,6 +287,12 @@ impl TestResult {
     {
         self.warnings = warnings.into();
     }
+
+    /// Adds a warning to this test, unlike [`TestResult::set_warnings`] this
+    /// keeps the existing warnings.
+    pub fn add_warning(&mut self, warning: SourceDiagnostic) {
+        self.warnings.push(warning);
+    }
 }
 
 impl Default for TestResult {
@@ -265,6 +377,12 @@ impl SuiteResult {
         self.total() - self.run()
     }
 
+    /// The number of tests in the suite which were expected to run, but were
+    /// not run because the test run was cancelled.
+    pub fn cancelled(&self) -> usize {
+        self.expected() - self.run()
+    }
+
     /// The number of tests in the suite which passed.
     pub fn passed(&self) -> usize {
         self.passed
@@ -293,6 +411,11 @@ impl SuiteResult {
         &self.results
     }
 
+    /// The sizes of the references written across all tests.
+    pub fn save_stats(&self) -> SaveStats {
+        self.results.values().map(TestResult::save_stats).sum()
+    }
+
     /// Whether this suite can be considered a complete pass.
     pub fn is_complete_pass(&self) -> bool {
         self.expected() == self.passed()
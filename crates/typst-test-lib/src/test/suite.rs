@@ -1,7 +1,8 @@
 //! Reading, and filtering of test suites.
 
 use std::collections::BTreeMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::{fs, io};
 
 use thiserror::Error;
@@ -9,8 +10,12 @@ use thiserror::Error;
 use super::{Id, Test};
 use crate::project::Paths;
 use crate::stdx::result::ResultEx;
-use crate::test;
 use crate::test_set::{Error as TestSetError, TestSet};
+use crate::{stdx, test};
+
+/// The names of test directories which are considered orphaned if their parent
+/// has no test script and they contain no tests themselves.
+const ORPHANED_DIRECTORY_NAMES: &[&str] = &["ref", "out", "diff"];
 
 /// A suite of tests.
 #[derive(Debug, Clone)]
@@ -32,8 +37,44 @@ impl Suite {
 
     /// Recursively collects entries in the given directory, separating them
     /// into matched and filtered by the given [`TestSet`].
-    #[tracing::instrument(skip(paths, test_set), fields(test_root = ?paths.test_root()))]
     pub fn collect(paths: &Paths, test_set: &TestSet) -> Result<Self, CollectError> {
+        Self::collect_with_progress(paths, test_set, |_| {})
+    }
+
+    /// Recursively collects entries in the given directory like
+    /// [`Suite::collect`], but invokes the given callback for each test as it
+    /// is discovered. Directories are walked in lexicographic order of their
+    /// names.
+    ///
+    /// This can be used to report progress when collecting large test suites.
+    pub fn collect_with_progress<F>(
+        paths: &Paths,
+        test_set: &TestSet,
+        progress: F,
+    ) -> Result<Self, CollectError>
+    where
+        F: FnMut(&Test),
+    {
+        Self::collect_cancellable(paths, test_set, progress, &AtomicBool::new(false))
+    }
+
+    /// Recursively collects entries in the given directory like
+    /// [`Suite::collect_with_progress`], but checks the given cancellation
+    /// flag before each directory. If the flag is set, collection stops and
+    /// [`CollectError::Cancelled`] is returned.
+    #[tracing::instrument(
+        skip(paths, test_set, progress, cancellation),
+        fields(test_root = ?paths.test_root())
+    )]
+    pub fn collect_cancellable<F>(
+        paths: &Paths,
+        test_set: &TestSet,
+        mut progress: F,
+        cancellation: &AtomicBool,
+    ) -> Result<Self, CollectError>
+    where
+        F: FnMut(&Test),
+    {
         let root = paths.test_root();
 
         let mut this = Self {
@@ -44,75 +85,179 @@ impl Suite {
 
         tracing::debug!("loading test template");
         if let Some(content) =
-            fs::read_to_string(paths.template()).ignore(|e| e.kind() == io::ErrorKind::NotFound)?
+            fs::read_to_string(paths.template()).ignore_io_kind(io::ErrorKind::NotFound)?
         {
             this.template = Some(content);
         }
 
-        match root.read_dir() {
-            Ok(read_dir) => {
-                tracing::debug!("collecting from test root directory");
-                for entry in read_dir {
-                    let entry = entry?;
+        let entries = match sorted_root_entries(&root) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                tracing::debug!("regression test suite empty");
+                return Ok(this);
+            }
+            Err(err) => return Err(err.into()),
+        };
 
-                    if entry.metadata()?.is_dir() {
-                        let abs = entry.path();
-                        let rel = abs
-                            .strip_prefix(paths.test_root())
-                            .expect("entry must be in full");
+        // NOTE(tinger): directories and single-file tests are walked together,
+        // such that progress is reported in the order of their names
+        tracing::debug!("collecting from test root directory");
+        let mut tests = vec![];
+        for entry in entries {
+            match entry {
+                RootEntry::Dir(abs) => {
+                    let rel = abs
+                        .strip_prefix(paths.test_root())
+                        .expect("entry must be in full");
 
-                        this.collect_dir(paths, rel, test_set)?;
-                    }
+                    Self::collect_dir(paths, rel, &mut tests, &mut progress, cancellation)?;
                 }
+                RootEntry::Script(abs) => {
+                    if cancellation.load(Ordering::SeqCst) {
+                        tracing::debug!(path = ?abs, "collection cancelled");
+                        return Err(CollectError::Cancelled);
+                    }
 
-                Ok(this)
+                    let Some(name) = abs.file_stem().and_then(|stem| stem.to_str()) else {
+                        continue;
+                    };
+
+                    let Ok(id) = Id::new(name) else {
+                        tracing::debug!(path = ?abs, "skipping script with invalid test id");
+                        continue;
+                    };
+
+                    if let Some(test) = Test::try_collect(paths, id)? {
+                        progress(&test);
+                        tests.push(test);
+                    }
+                }
             }
-            Err(err) if err.kind() == io::ErrorKind::NotFound => {
-                tracing::debug!("regression test suite empty");
-                Ok(this)
+        }
+
+        // NOTE(tinger): some sets select from all tests, so we match only
+        // after all tests were collected and in a stable order
+        tests.sort_by(|a, b| a.id().cmp(b.id()));
+        let mut test_set = test_set.clone();
+        test_set.bind_tests(tests.iter().cloned());
+
+        for test in tests {
+            if test_set.contains(&test)? {
+                tracing::debug!(id = %test.id(), "matched test");
+                this.matched.insert(test.id().clone(), test);
+            } else {
+                tracing::debug!(id = %test.id(), "filtered test");
+                this.filtered.insert(test.id().clone(), test);
             }
-            Err(err) => Err(err.into()),
         }
+
+        Ok(this)
     }
 
     /// Recursively collect tests in the given directory.
     fn collect_dir(
-        &mut self,
         paths: &Paths,
         dir: &Path,
-        test_set: &TestSet,
+        tests: &mut Vec<Test>,
+        progress: &mut dyn FnMut(&Test),
+        cancellation: &AtomicBool,
     ) -> Result<(), CollectError> {
+        if cancellation.load(Ordering::SeqCst) {
+            tracing::debug!(?dir, "collection cancelled");
+            return Err(CollectError::Cancelled);
+        }
+
         let abs = paths.test_root().join(dir);
 
         tracing::trace!(?dir, "collecting directory");
 
         let id = Id::new_from_path(dir)?;
 
-        if let Some(test) = Test::try_collect(paths, id.clone())? {
-            if test_set.contains(&test)? {
-                tracing::debug!(id = %test.id(), "matched test");
-                self.matched.insert(id, test);
-            } else {
-                tracing::debug!(id = %test.id(), "filtered test");
-                self.filtered.insert(id, test);
-            }
+        if let Some(test) = Test::try_collect(paths, id)? {
+            progress(&test);
+            tests.push(test);
         } else {
-            for entry in fs::read_dir(&abs)? {
+            for abs in sorted_sub_dirs(&abs)? {
+                let rel = abs
+                    .strip_prefix(paths.test_root())
+                    .expect("entry must be in full");
+
+                tracing::trace!(path = ?rel, "reading directory entry");
+                Self::collect_dir(paths, rel, tests, progress, cancellation)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Recursively collects reference, output and difference directories in
+    /// the test root whose parent directory has no test script, i.e. those
+    /// which were left behind after removing a test without typst-test.
+    ///
+    /// Directories with these names which contain tests are regular test
+    /// modules and are never considered orphaned.
+    pub fn collect_orphaned_directories(paths: &Paths) -> io::Result<Vec<PathBuf>> {
+        fn contains_tests(dir: &Path, script: &str) -> io::Result<bool> {
+            if dir.join(script).try_exists()? {
+                return Ok(true);
+            }
+
+            for entry in fs::read_dir(dir)? {
                 let entry = entry?;
+                if entry.metadata()?.is_dir() && contains_tests(&entry.path(), script)? {
+                    return Ok(true);
+                }
+            }
 
-                if entry.metadata()?.is_dir() {
-                    let abs = entry.path();
-                    let rel = abs
-                        .strip_prefix(paths.test_root())
-                        .expect("entry must be in full");
+            Ok(false)
+        }
+
+        fn inner(dir: &Path, script: &str, orphaned: &mut Vec<PathBuf>) -> io::Result<()> {
+            if dir.join(script).try_exists()? {
+                return Ok(());
+            }
+
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                if !entry.metadata()?.is_dir() {
+                    continue;
+                }
 
-                    tracing::trace!(path = ?rel, "reading directory entry");
-                    self.collect_dir(paths, rel, test_set)?;
+                let path = entry.path();
+                if ORPHANED_DIRECTORY_NAMES
+                    .iter()
+                    .any(|&name| entry.file_name() == name)
+                    && !contains_tests(&path, script)?
+                {
+                    tracing::debug!(?path, "found orphaned directory");
+                    orphaned.push(path);
+                } else {
+                    inner(&path, script, orphaned)?;
                 }
             }
+
+            Ok(())
         }
 
-        Ok(())
+        let mut orphaned = vec![];
+        match inner(&paths.test_root(), paths.test_script_name(), &mut orphaned) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            res => res?,
+        }
+
+        Ok(orphaned)
+    }
+
+    /// Removes all directories found by
+    /// [`Suite::collect_orphaned_directories`] and returns them.
+    pub fn prune_orphaned_directories(paths: &Paths) -> io::Result<Vec<PathBuf>> {
+        let orphaned = Self::collect_orphaned_directories(paths)?;
+
+        for dir in &orphaned {
+            stdx::fs::remove_dir(dir, true)?;
+        }
+
+        Ok(orphaned)
     }
 }
 
@@ -145,6 +290,35 @@ impl Suite {
         &self.filtered
     }
 
+    /// Removes all tests in the given directory from this suite, whether they
+    /// were matched or filtered. Returns the ids of the removed tests.
+    ///
+    /// This is used to exclude the template of template packages, which
+    /// shouldn't be part of the regression suite.
+    pub fn exclude_dir(&mut self, paths: &Paths, dir: &Path) -> Vec<Id> {
+        let mut excluded = vec![];
+        for tests in [&mut self.matched, &mut self.filtered] {
+            tests.retain(|id, _| {
+                let keep = !paths.test_dir(id).starts_with(dir);
+                if !keep {
+                    excluded.push(id.clone());
+                }
+                keep
+            });
+        }
+
+        excluded.sort();
+        excluded
+    }
+
+    /// Adds the given test to the matched tests of this suite, this is used
+    /// for tests which aren't collected from the test root, see
+    /// [`Test::new_script`]. Returns the previous test with the same id.
+    pub fn insert_matched(&mut self, test: Test) -> Option<Test> {
+        let filtered = self.filtered.remove(test.id());
+        self.matched.insert(test.id().clone(), test).or(filtered)
+    }
+
     /// The template for new tests in this suite.
     pub fn template(&self) -> Option<&str> {
         self.template.as_deref()
@@ -167,6 +341,64 @@ impl Default for Suite {
     }
 }
 
+/// Reads the sub directories of the given directory sorted by their names,
+/// this ensures tests are discovered in a stable order.
+fn sorted_sub_dirs(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut dirs = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.metadata()?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// An entry directly in the test root, see [`sorted_root_entries`].
+enum RootEntry {
+    /// A directory, this is either a test or a module containing tests.
+    Dir(PathBuf),
+
+    /// A typst script, this is a candidate for a single-file test.
+    Script(PathBuf),
+}
+
+/// Reads the sub directories and typst scripts directly in the given
+/// directory sorted by the ids they would have. Scripts with the same name as
+/// a directory are omitted, since the directory takes precedence.
+fn sorted_root_entries(dir: &Path) -> io::Result<Vec<RootEntry>> {
+    let mut dirs = BTreeMap::new();
+    let mut scripts = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            dirs.insert(entry.file_name(), path);
+        } else if metadata.is_file() && path.extension().is_some_and(|ext| ext == "typ") {
+            let stem = path.file_stem().expect("script has a name").to_owned();
+            scripts.insert(stem, path);
+        }
+    }
+
+    scripts.retain(|stem, _| !dirs.contains_key(stem));
+
+    let mut entries: Vec<_> = dirs
+        .into_iter()
+        .map(|(name, path)| (name, RootEntry::Dir(path)))
+        .chain(
+            scripts
+                .into_iter()
+                .map(|(name, path)| (name, RootEntry::Script(path))),
+        )
+        .collect();
+
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(entries.into_iter().map(|(_, entry)| entry).collect())
+}
+
 /// Returned by [`Suite::collect`].
 #[derive(Debug, Error)]
 pub enum CollectError {
@@ -182,6 +414,10 @@ pub enum CollectError {
     #[error("an error occurred while collecting a test")]
     Test(#[from] test::CollectError),
 
+    /// Collection was cancelled, see [`Suite::collect_cancellable`].
+    #[error("collection was cancelled")]
+    Cancelled,
+
     /// An io error occurred.
     #[error("an io error occurred")]
     Io(#[from] io::Error),
@@ -233,7 +469,11 @@ mod tests {
                     ("compare/ephemeral", Kind::Ephemeral, eco_vec![]),
                     ("compare/ephemeral-store", Kind::Ephemeral, eco_vec![]),
                     ("compare/persistent", Kind::Persistent, eco_vec![]),
-                    ("ignored", Kind::CompileOnly, eco_vec![Annotation::Skip]),
+                    (
+                        "ignored",
+                        Kind::CompileOnly,
+                        eco_vec![Annotation::Skip(None)],
+                    ),
                 ];
 
                 assert_eq!(suite.template, Some("Blah Blah".into()));
@@ -246,4 +486,249 @@ mod tests {
             },
         );
     }
+
+    #[test]
+    fn test_collect_script_names() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/compile-only/main.typ", "Hello World")
+                    .setup_file("tests/ephemeral/main.typ", "Hello World")
+                    .setup_file("tests/ephemeral/expected.typ", "Hello\nWorld")
+                    .setup_file("tests/default/test.typ", "Hello World")
+                    .setup_dir("tests/default/out")
+            },
+            |root| {
+                let paths = Paths::new(root, None)
+                    .with_test_script("main.typ")
+                    .with_ref_script("expected.typ");
+                let suite = Suite::collect(
+                    &paths,
+                    &TestSet::new(eval::Context::empty(), eval::Set::built_in_all()),
+                )
+                .unwrap();
+
+                assert_eq!(
+                    suite
+                        .matched
+                        .values()
+                        .map(|test| (test.id().as_str(), test.kind()))
+                        .collect::<Vec<_>>(),
+                    [
+                        ("compile-only", Kind::CompileOnly),
+                        ("ephemeral", Kind::Ephemeral),
+                    ]
+                );
+
+                // directories with the default script name are not tests
+                assert_eq!(
+                    Suite::collect_orphaned_directories(&paths).unwrap(),
+                    [paths.test_root().join("default/out")]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn test_collect_single_file() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/template.typ", "Blah Blah")
+                    .setup_file("tests/single.typ", "Hello World")
+                    .setup_file("tests/dir/test.typ", "Hello World")
+                    .setup_file("tests/dir.typ", "Shadowed")
+                    .setup_file("tests/nested/single.typ", "Not a test")
+                    .setup_file("tests/invalid id.typ", "Not a test")
+                    .setup_file_empty("tests/single.txt")
+            },
+            |root| {
+                let paths = Paths::new(root, None);
+                let suite = Suite::collect(
+                    &paths,
+                    &TestSet::new(eval::Context::empty(), eval::Set::built_in_all()),
+                )
+                .unwrap();
+
+                assert_eq!(
+                    suite
+                        .matched
+                        .values()
+                        .map(|test| (test.id().as_str(), test.kind(), test.is_single_file()))
+                        .collect::<Vec<_>>(),
+                    [
+                        ("dir", Kind::CompileOnly, false),
+                        ("single", Kind::CompileOnly, true),
+                    ]
+                );
+
+                let test = &suite.matched["single"];
+                assert_eq!(test.script(&paths), paths.test_root().join("single.typ"));
+                assert_eq!(test.load_source(&paths).unwrap().text(), "Hello World");
+            },
+        );
+    }
+
+    #[test]
+    fn test_collect_parents() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/math.typ", "Shadowed")
+                    .setup_file("tests/math/add/test.typ", "Hello World")
+                    .setup_file("tests/math/sub/int/test.typ", "Hello World")
+                    .setup_file("tests/math/sub/int/nested/test.typ", "Not a test")
+                    .setup_file("tests/math-x/test.typ", "Hello World")
+                    .setup_file("tests/text/test.typ", "Hello World")
+            },
+            |root| {
+                let paths = Paths::new(root, None);
+                let matched = |input: &str| {
+                    let test_set =
+                        TestSet::parse_and_evaluate(eval::Context::with_built_ins(), input)
+                            .unwrap();
+
+                    Suite::collect(&paths, &test_set)
+                        .unwrap()
+                        .matched
+                        .into_keys()
+                        .map(|id| id.to_string())
+                        .collect::<Vec<_>>()
+                };
+
+                // tests are never collected below other tests, so only
+                // patterns find the modules they are nested in
+                assert_eq!(matched("parent(e:'math/sub/int')"), Vec::<String>::new());
+                assert_eq!(matched("parent(all())"), Vec::<String>::new());
+                assert_eq!(matched("e:'math/add'-"), ["math/add"]);
+                assert_eq!(matched("children(e:'math')"), ["math/add", "math/sub/int"]);
+                assert_eq!(matched("e:'math'+"), ["math/add", "math/sub/int"]);
+            },
+        );
+    }
+
+    #[test]
+    fn test_collect_with_progress() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/b/test.typ", "Hello World")
+                    .setup_file("tests/a/test.typ", "Hello World")
+                    .setup_file("tests/c/x/test.typ", "Hello World")
+                    .setup_file("tests/c/w/test.typ", "Hello World")
+                    .setup_file("tests/b2.typ", "Hello World")
+                    .setup_file("tests/c.typ", "Hello World")
+                    .setup_file_empty("tests/d/test.txt")
+            },
+            |root| {
+                let paths = Paths::new(root, None);
+                let mut discovered = vec![];
+                let suite = Suite::collect_with_progress(
+                    &paths,
+                    &TestSet::new(eval::Context::empty(), eval::Set::built_in_all()),
+                    |test| discovered.push(test.id().as_str().to_owned()),
+                )
+                .unwrap();
+
+                // single-file tests are discovered in order with directories,
+                // `c.typ` is shadowed by the `c` module
+                assert_eq!(discovered, ["a", "b", "b2", "c/w", "c/x"]);
+                assert_eq!(suite.matched().len(), discovered.len());
+            },
+        );
+    }
+
+    #[test]
+    fn test_collect_cancellable() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/a/test.typ", "Hello World")
+                    .setup_file("tests/b/test.typ", "Hello World")
+                    .setup_file("tests/c/test.typ", "Hello World")
+            },
+            |root| {
+                let paths = Paths::new(root, None);
+                let cancellation = AtomicBool::new(false);
+                let mut discovered = vec![];
+                let err = Suite::collect_cancellable(
+                    &paths,
+                    &TestSet::new(eval::Context::empty(), eval::Set::built_in_all()),
+                    |test| {
+                        discovered.push(test.id().as_str().to_owned());
+                        cancellation.store(true, Ordering::SeqCst);
+                    },
+                    &cancellation,
+                )
+                .unwrap_err();
+
+                assert!(matches!(err, CollectError::Cancelled));
+                assert_eq!(discovered, ["a"]);
+            },
+        );
+    }
+
+    #[test]
+    fn test_collect_orphaned_directories() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/persistent/test.typ", "Hello World")
+                    .setup_dir("tests/persistent/ref")
+                    .setup_dir("tests/persistent/out")
+                    .setup_dir("tests/orphaned/ref")
+                    .setup_dir("tests/nested/orphaned/out")
+                    // test modules which happen to share a name
+                    .setup_file("tests/out/foo/test.typ", "Hello World")
+                    .setup_file("tests/diff/test.typ", "Hello World")
+                    .setup_file("tests/module/ref/nested/test.typ", "Hello World")
+            },
+            |root| {
+                let paths = Paths::new(root, None);
+                let orphaned = Suite::collect_orphaned_directories(&paths).unwrap();
+
+                assert_eq!(
+                    orphaned.iter().collect::<std::collections::BTreeSet<_>>(),
+                    [
+                        paths.test_root().join("orphaned/ref"),
+                        paths.test_root().join("nested/orphaned/out"),
+                    ]
+                    .iter()
+                    .collect(),
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn test_collect_orphaned_directories_in_test_modules() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/out/foo/test.typ", "Hello World")
+                    .setup_dir("tests/out/foo/out")
+                    .setup_dir("tests/out/bar/ref")
+            },
+            |root| {
+                let paths = Paths::new(root, None);
+                assert_eq!(
+                    Suite::collect_orphaned_directories(&paths).unwrap(),
+                    [paths.test_root().join("out/bar/ref")]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn test_prune_orphaned_directories() {
+        _dev::fs::TempEnv::run(
+            |root| {
+                root.setup_file("tests/persistent/test.typ", "Hello World")
+                    .setup_dir("tests/persistent/ref")
+                    .setup_file_empty("tests/orphaned/ref/1.png")
+            },
+            |root| {
+                let paths = Paths::new(root, None);
+                Suite::prune_orphaned_directories(&paths).unwrap();
+            },
+            |root| {
+                root.expect_file_content("tests/persistent/test.typ", "Hello World")
+                    .expect_dir("tests/persistent/ref")
+                    .expect_dir("tests/orphaned")
+            },
+        );
+    }
 }
@@ -4,6 +4,8 @@ use std::sync::Arc;
 use ecow::eco_vec;
 
 use super::{Context, Error, Set, TryFromValue, Type, Value};
+use crate::test::Kind;
+use crate::test_set::Pat;
 
 /// The backing implementation for a [`Func`].
 type FuncImpl = Arc<dyn Fn(&Context, &[Value]) -> Result<Value, Error>>;
@@ -69,6 +71,92 @@ impl Func {
         Self::expect_no_args("persistent", ctx, args)?;
         Ok(Value::Set(Set::built_in_persistent()))
     }
+
+    /// Constructor for [`Set::built_in_kind`], the kind is given either as an
+    /// exact string or a pattern matched against the kind names.
+    pub fn built_in_kind(ctx: &Context, args: &[Value]) -> Result<Value, Error> {
+        let [arg] = Self::expect_args_exact::<Value, 1>("kind", ctx, args)?;
+
+        let kinds = match arg {
+            Value::Str(kind) => {
+                let Some(kind) = Kind::ALL.into_iter().find(|k| k.as_str() == kind) else {
+                    return Err(Error::UnknownKind { kind });
+                };
+
+                vec![kind]
+            }
+            Value::Pat(pat) => Kind::ALL
+                .into_iter()
+                .filter(|k| pat.is_match_str(k.as_str()))
+                .collect(),
+            _ => {
+                return Err(Error::TypeMismatch {
+                    expected: eco_vec![Type::Str, Type::Pat],
+                    found: arg.as_type(),
+                })
+            }
+        };
+
+        Ok(Value::Set(Set::built_in_kind(kinds)))
+    }
+
+    /// Constructor for [`Set::built_in_first`].
+    pub fn built_in_first(ctx: &Context, args: &[Value]) -> Result<Value, Error> {
+        let [set, n] = Self::expect_args_exact::<Value, 2>("first", ctx, args)?;
+        Ok(Value::Set(Set::built_in_first(
+            set.expect_type()?,
+            n.expect_type()?,
+        )))
+    }
+
+    /// Constructor for [`Set::built_in_sample`], the seed is optional and
+    /// defaults to `0`.
+    pub fn built_in_sample(ctx: &Context, args: &[Value]) -> Result<Value, Error> {
+        let ([set, n], rest) = Self::expect_args_min::<Value, 2>("sample", ctx, args)?;
+        let seed = match rest.as_slice() {
+            [] => 0,
+            [seed] => seed.expect_type()?,
+            _ => {
+                return Err(Error::InvalidArgumentCount {
+                    func: "sample".into(),
+                    expected: 3,
+                    is_min: false,
+                    found: args.len(),
+                })
+            }
+        };
+
+        Ok(Value::Set(Set::built_in_sample(
+            set.expect_type()?,
+            n.expect_type()?,
+            seed,
+        )))
+    }
+
+    /// Constructor for [`Set::built_in_children`], or
+    /// [`Set::built_in_pattern_children`] if a pattern is given.
+    pub fn built_in_children(ctx: &Context, args: &[Value]) -> Result<Value, Error> {
+        let [arg] = Self::expect_args_exact::<Value, 1>("children", ctx, args)?;
+        Ok(Value::Set(match arg {
+            Value::Pat(pat) => Set::built_in_pattern_children(pat),
+            arg => Set::built_in_children(arg.expect_type()?),
+        }))
+    }
+
+    /// Constructor for [`Set::built_in_parents`].
+    pub fn built_in_parent(ctx: &Context, args: &[Value]) -> Result<Value, Error> {
+        let [set] = Self::expect_args_exact::<Set, 1>("parent", ctx, args)?;
+        Ok(Value::Set(Set::built_in_parents(set)))
+    }
+
+    /// Selects the tests discovered under the test root with the given label.
+    // NOTE(tinger): projects currently have exactly one unlabeled test root, so
+    // there is no label this could match, once tests carry their originating
+    // root this should construct a set checking it
+    pub fn built_in_root(ctx: &Context, args: &[Value]) -> Result<Value, Error> {
+        let [root] = Self::expect_args_exact::<String, 1>("root", ctx, args)?;
+        Err(Error::UnknownRoot { root })
+    }
 }
 
 impl Func {
@@ -162,10 +250,178 @@ impl TryFromValue for Func {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::_dev;
+    use crate::project::Paths;
+    use crate::test::{Id, Test};
+    use crate::test_set::Regex;
 
     const NUM: usize = 0;
     const VAL: Value = Value::Num(NUM);
 
+    #[test]
+    fn test_built_in_kind() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/compile-only/test.typ", "Hello World")
+                    .setup_file("tests/ephemeral/test.typ", "Hello World")
+                    .setup_file("tests/ephemeral/ref.typ", "Hello World")
+                    .setup_file("tests/persistent/test.typ", "Hello World")
+                    .setup_dir("tests/persistent/ref")
+            },
+            |root| {
+                let paths = Paths::new(root, None);
+                let ctx = Context::with_built_ins();
+                let tests = ["compile-only", "ephemeral", "persistent"].map(|id| {
+                    Test::try_collect(&paths, Id::new(id).unwrap())
+                        .unwrap()
+                        .unwrap()
+                });
+
+                let matches = |arg: Value| {
+                    let set: Set = Func::built_in_kind(&ctx, &[arg])
+                        .unwrap()
+                        .expect_type()
+                        .unwrap();
+
+                    tests
+                        .iter()
+                        .filter(|test| set.contains(&ctx, test).unwrap())
+                        .map(|test| test.id().as_str())
+                        .collect::<Vec<_>>()
+                };
+
+                assert_eq!(matches("compile-only".into()), ["compile-only"]);
+                assert_eq!(matches("ephemeral".into()), ["ephemeral"]);
+                assert_eq!(matches("persistent".into()), ["persistent"]);
+                assert_eq!(
+                    matches(Pat::Regex(Regex::new(regex::Regex::new("^(e|p)").unwrap())).into()),
+                    ["ephemeral", "persistent"]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn test_built_in_kind_invalid() {
+        let ctx = Context::with_built_ins();
+
+        let err = Func::built_in_kind(&ctx, &["persitent".into()]).unwrap_err();
+        assert!(matches!(&err, Error::UnknownKind { kind } if kind == "persitent"));
+        assert!(err.to_string().contains("did you mean \"persistent\"?"));
+
+        assert!(matches!(
+            Func::built_in_kind(&ctx, &[VAL]),
+            Err(Error::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            Func::built_in_kind(&ctx, &[]),
+            Err(Error::InvalidArgumentCount { .. })
+        ));
+    }
+
+    fn tests(ids: &[&str]) -> Vec<Test> {
+        ids.iter()
+            .map(|id| Test::new(Id::new(*id).unwrap()))
+            .collect()
+    }
+
+    fn matches(ctx: &Context, set: &Set) -> Vec<String> {
+        ctx.tests()
+            .unwrap()
+            .iter()
+            .filter(|test| set.contains(ctx, test).unwrap())
+            .map(|test| test.id().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_built_in_first() {
+        let mut ctx = Context::with_built_ins();
+        ctx.bind_tests(tests(&["e", "b", "d", "a", "c"]));
+
+        let set: Set = Func::built_in_first(&ctx, &[Set::built_in_all().into(), Value::Num(3)])
+            .unwrap()
+            .expect_type()
+            .unwrap();
+        assert_eq!(matches(&ctx, &set), ["a", "b", "c"]);
+
+        let set: Set = Func::built_in_first(
+            &ctx,
+            &[
+                Set::built_in_comp(Set::built_in_pattern(Pat::Exact("a".into()))).into(),
+                Value::Num(2),
+            ],
+        )
+        .unwrap()
+        .expect_type()
+        .unwrap();
+        assert_eq!(matches(&ctx, &set), ["b", "c"]);
+
+        // without bound tests there is nothing to select from
+        let unbound = Context::with_built_ins();
+        assert!(matches!(
+            set.contains(&unbound, &tests(&["a"])[0]),
+            Err(Error::UnboundTests { func }) if func == "first"
+        ));
+    }
+
+    #[test]
+    fn test_built_in_sample() {
+        let ids: Vec<_> = (0..20).map(|n| format!("test-{n}")).collect();
+        let ids: Vec<_> = ids.iter().map(String::as_str).collect();
+
+        let mut ctx = Context::with_built_ins();
+        ctx.bind_tests(tests(&ids));
+
+        let sample = |args: &[Value]| {
+            let set: Set = Func::built_in_sample(&ctx, args)
+                .unwrap()
+                .expect_type()
+                .unwrap();
+            matches(&ctx, &set)
+        };
+
+        let a = sample(&[Set::built_in_all().into(), Value::Num(5)]);
+        let b = sample(&[Set::built_in_all().into(), Value::Num(5), Value::Num(0)]);
+        let c = sample(&[Set::built_in_all().into(), Value::Num(5), Value::Num(1)]);
+
+        assert_eq!(a.len(), 5);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+
+        assert_eq!(
+            sample(&[Set::built_in_all().into(), Value::Num(50)]).len(),
+            20
+        );
+        assert!(Func::built_in_sample(
+            &ctx,
+            &[
+                Set::built_in_all().into(),
+                Value::Num(5),
+                Value::Num(0),
+                Value::Num(0)
+            ]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_built_in_root_single_root() {
+        let ctx = Context::with_built_ins();
+
+        let err = Func::built_in_root(&ctx, &["integration".into()]).unwrap_err();
+        assert!(matches!(&err, Error::UnknownRoot { root } if root == "integration"));
+        assert_eq!(
+            err.to_string(),
+            "unknown test root \"integration\", this project has a single unlabeled test root"
+        );
+
+        assert!(matches!(
+            Func::built_in_root(&ctx, &[VAL]),
+            Err(Error::TypeMismatch { .. })
+        ));
+    }
+
     #[test]
     fn test_expect_args_variadic_min_length() {
         let ctx = Context::empty();
@@ -1,14 +1,17 @@
 //! Test set evaluation and suite matching.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Debug, Display};
+use std::sync::Arc;
 
 use ecow::EcoVec;
 use thiserror::Error;
 
 use super::id::Id;
-use super::parse::{self, Expr, Function, InfixOp, PrefixOp};
+use super::parse::{self, Expr, Function, InfixOp, PostfixOp, PrefixOp};
+use super::TestSetExpr;
 use crate::stdx::fmt::{Separators, Term};
+use crate::test::{Kind, Test};
 
 mod func;
 mod set;
@@ -61,6 +64,21 @@ impl Eval for Expr {
                     PrefixOp::Not => Set::built_in_comp(set),
                 }))
             }
+            Self::Postfix { op, expr } => {
+                // unary postfix operators are only valid for test sets, but
+                // patterns may also match the modules containing tests
+                let value = expr.eval(ctx)?;
+
+                Ok(Value::Set(match (op, value) {
+                    (PostfixOp::Descendants, Value::Pat(pat)) => {
+                        Set::built_in_pattern_descendants(pat)
+                    }
+                    (PostfixOp::Descendants, value) => {
+                        Set::built_in_descendants(value.expect_type()?)
+                    }
+                    (PostfixOp::Ancestors, value) => Set::built_in_ancestors(value.expect_type()?),
+                }))
+            }
             Self::Infix { op, lhs, rhs } => {
                 // binary infix operator is only valid for test sets
                 let lhs: Set = lhs.eval(ctx)?.expect_type()?;
@@ -82,6 +100,10 @@ impl Eval for Expr {
 pub struct Context {
     /// The bindings available for evaluation.
     bindings: BTreeMap<Id, Value>,
+
+    /// All tests a test set is matched against, ordered by their id, if they
+    /// were bound.
+    tests: Option<Arc<[Test]>>,
 }
 
 impl Context {
@@ -89,6 +111,7 @@ impl Context {
     pub fn empty() -> Self {
         Self {
             bindings: BTreeMap::new(),
+            tests: None,
         }
     }
 
@@ -106,11 +129,20 @@ impl Context {
             ("compile-only", Func::built_in_compile_only),
             ("ephemeral", Func::built_in_ephemeral),
             ("persistent", Func::built_in_persistent),
+            ("kind", Func::built_in_kind),
+            ("root", Func::built_in_root),
+            ("first", Func::built_in_first),
+            ("sample", Func::built_in_sample),
+            ("children", Func::built_in_children),
+            ("parent", Func::built_in_parent),
         ] {
             bindings.insert(Id::new(id).unwrap(), Value::Func(Func::new(f)));
         }
 
-        Self { bindings }
+        Self {
+            bindings,
+            tests: None,
+        }
     }
 }
 
@@ -130,6 +162,86 @@ impl Context {
             .ok_or_else(|| Error::UnknownBinding { id: id.into() })
     }
 
+    /// Evaluates and binds the given test set aliases, such that they can be
+    /// used like other identifiers in test set expressions. Aliases may refer
+    /// to other aliases, but not to themselves, neither directly nor
+    /// indirectly.
+    ///
+    /// The `all:` modifier of an alias has no effect.
+    pub fn bind_aliases(&mut self, aliases: &BTreeMap<Id, TestSetExpr>) -> Result<(), Error> {
+        let mut bound = BTreeSet::new();
+        let mut stack = vec![];
+
+        for id in aliases.keys() {
+            self.bind_alias(id, aliases, &mut bound, &mut stack)?;
+        }
+
+        Ok(())
+    }
+
+    /// Binds the given alias after all aliases it refers to, `stack` contains
+    /// the aliases currently being bound and is used to detect cycles.
+    fn bind_alias<'a>(
+        &mut self,
+        id: &'a Id,
+        aliases: &'a BTreeMap<Id, TestSetExpr>,
+        bound: &mut BTreeSet<&'a Id>,
+        stack: &mut Vec<&'a Id>,
+    ) -> Result<(), Error> {
+        if bound.contains(id) {
+            return Ok(());
+        }
+
+        if let Some(start) = stack.iter().position(|other| *other == id) {
+            return Err(Error::RecursiveAlias {
+                cycle: stack[start..]
+                    .iter()
+                    .chain([&id])
+                    .map(|id| id.as_str().into())
+                    .collect(),
+            });
+        }
+
+        let expr = &aliases[id].expr;
+        let mut ids = vec![];
+        expr.identifiers(&mut ids);
+
+        stack.push(id);
+        for (dep, _) in ids.into_iter().filter_map(|id| aliases.get_key_value(id)) {
+            self.bind_alias(dep, aliases, bound, stack)?;
+        }
+        stack.pop();
+
+        let set: Set = expr.eval(self)?.expect_type()?;
+        self.bind(id.clone(), set);
+        bound.insert(id);
+
+        Ok(())
+    }
+
+    /// Binds all tests a test set is matched against, this is required for
+    /// sets which select from other tests like `first(...)`, which fail with
+    /// [`Error::UnboundTests`] if no tests are bound.
+    pub fn bind_tests<I: IntoIterator<Item = Test>>(&mut self, tests: I) {
+        let mut tests: Vec<_> = tests.into_iter().collect();
+        tests.sort_by(|a, b| a.id().cmp(b.id()));
+        self.tests = Some(Arc::from(tests));
+    }
+
+    /// The tests bound by [`Context::bind_tests`] ordered by their id, if any
+    /// were bound.
+    pub fn tests(&self) -> Option<&Arc<[Test]>> {
+        self.tests.as_ref()
+    }
+
+    /// The tests bound by [`Context::bind_tests`] ordered by their id, returns
+    /// an error for the given function if none were bound.
+    pub fn expect_tests(&self, func: &str) -> Result<&Arc<[Test]>, Error> {
+        self.tests
+            .as_ref()
+            .ok_or_else(|| Error::UnboundTests { func: func.into() })
+    }
+
     /// Find similar bindings to the given identifier.
     pub fn find_similar(&self, id: &str) -> Vec<Id> {
         self.bindings
@@ -180,6 +292,32 @@ pub enum Error {
         found: Type,
     },
 
+    /// An unknown test kind was passed to `kind`.
+    UnknownKind {
+        /// The given kind.
+        kind: String,
+    },
+
+    /// An unknown test root label was passed to `root`.
+    UnknownRoot {
+        /// The given label.
+        root: String,
+    },
+
+    /// A test set alias refers to itself.
+    RecursiveAlias {
+        /// The aliases forming the cycle, starting and ending with the same
+        /// alias.
+        cycle: Vec<String>,
+    },
+
+    /// A set which selects from all tests was matched, but no tests were
+    /// bound, see [`Context::bind_tests`].
+    UnboundTests {
+        /// The identifier of the function which created the set.
+        func: String,
+    },
+
     /// A regex pattern could not be parsed.
     Regex(#[from] regex::Error),
 
@@ -230,8 +368,116 @@ impl Display for Error {
                 Separators::comma_or().with(expected.iter().map(|t| format!("<{}>", t.name()))),
                 found.name(),
             ),
+            Error::UnknownKind { kind } => {
+                write!(
+                    f,
+                    "unknown test kind {kind:?}, expected {}",
+                    Separators::comma_or()
+                        .with(Kind::ALL.iter().map(|k| format!("{:?}", k.as_str()))),
+                )?;
+
+                let similar = Kind::ALL
+                    .iter()
+                    .map(|k| (k, strsim::jaro(kind, k.as_str())))
+                    .filter(|(_, score)| *score > 0.7)
+                    .max_by(|(_, a), (_, b)| a.total_cmp(b));
+
+                if let Some((similar, _)) = similar {
+                    write!(f, ", did you mean {:?}?", similar.as_str())?;
+                }
+
+                Ok(())
+            }
+            Error::UnknownRoot { root } => write!(
+                f,
+                "unknown test root {root:?}, this project has a single unlabeled test root"
+            ),
+            Error::RecursiveAlias { cycle } => {
+                write!(f, "test set alias {:?} refers to itself", cycle[0])?;
+                if cycle.len() > 2 {
+                    write!(f, " through {}", cycle.join(" -> "))?;
+                }
+
+                Ok(())
+            }
+            Error::UnboundTests { func } => write!(
+                f,
+                "function {func} selects from all tests, but no tests were bound"
+            ),
             Error::Regex(_) => write!(f, "could not parse regex"),
             Error::Glob(_) => write!(f, "could not parse glob"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::_dev;
+    use crate::project::Paths;
+    use crate::test::Id as TestId;
+
+    fn aliases(aliases: &[(&str, &str)]) -> BTreeMap<Id, TestSetExpr> {
+        aliases
+            .iter()
+            .map(|(id, expr)| (Id::new(*id).unwrap(), TestSetExpr::parse(expr).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_bind_aliases() {
+        _dev::fs::TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/compile-only/test.typ", "Hello World")
+                    .setup_file("tests/ephemeral/test.typ", "Hello World")
+                    .setup_file("tests/ephemeral/ref.typ", "Hello World")
+                    .setup_file("tests/skipped/test.typ", "/// [skip]\nHello World")
+            },
+            |root| {
+                let paths = Paths::new(root, None);
+                let tests = ["compile-only", "ephemeral", "skipped"].map(|id| {
+                    Test::try_collect(&paths, TestId::new(id).unwrap())
+                        .unwrap()
+                        .unwrap()
+                });
+
+                // `a-smoke` refers to `b-base`, which is bound after it
+                let mut ctx = Context::with_built_ins();
+                ctx.bind_aliases(&aliases(&[
+                    ("a-smoke", "b-base & !skip()"),
+                    ("b-base", "compile-only() | skip()"),
+                ]))
+                .unwrap();
+
+                let set: Set = ctx.resolve("a-smoke").unwrap().expect_type().unwrap();
+                let matched: Vec<_> = tests
+                    .iter()
+                    .filter(|test| set.contains(&ctx, test).unwrap())
+                    .map(|test| test.id().as_str())
+                    .collect();
+
+                assert_eq!(matched, ["compile-only"]);
+            },
+        );
+    }
+
+    #[test]
+    fn test_bind_aliases_recursive() {
+        let mut ctx = Context::with_built_ins();
+        let err = ctx
+            .bind_aliases(&aliases(&[("a", "b | skip()"), ("b", "c"), ("c", "!a")]))
+            .unwrap_err();
+        assert!(matches!(&err, Error::RecursiveAlias { cycle } if cycle == &["a", "b", "c", "a"]));
+        assert_eq!(
+            err.to_string(),
+            "test set alias \"a\" refers to itself through a -> b -> c -> a"
+        );
+
+        let mut ctx = Context::with_built_ins();
+        let err = ctx
+            .bind_aliases(&aliases(&[("skip", "skip() & persistent()")]))
+            .unwrap_err();
+        assert!(matches!(&err, Error::RecursiveAlias { cycle } if cycle == &["skip", "skip"]));
+        assert_eq!(err.to_string(), "test set alias \"skip\" refers to itself");
+    }
+}
@@ -1,10 +1,12 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
 use std::fmt::Debug;
 use std::sync::Arc;
 
 use ecow::eco_vec;
 
 use super::{Context, Error, TryFromValue, Type, Value};
-use crate::test::Test;
+use crate::test::{Id, Kind, Test};
 use crate::test_set::Pat;
 
 /// The backing implementation for a [`Set`].
@@ -74,11 +76,229 @@ impl Set {
         Self::new(|_, test| Ok(test.kind().is_persistent()))
     }
 
+    /// Construct a set which contains all tests whose kind is contained in the
+    /// given kinds.
+    ///
+    /// This is the test set created by `kind(...)`.
+    pub fn built_in_kind<I>(kinds: I) -> Self
+    where
+        I: IntoIterator<Item = Kind>,
+    {
+        let kinds: Vec<_> = kinds.into_iter().collect();
+        Self::new(move |_, test| Ok(kinds.contains(&test.kind())))
+    }
+
+    /// Construct a set which contains the first `n` tests of the given set in
+    /// order of their ids.
+    ///
+    /// This is the test set created by `first(set, n)`, see
+    /// [`Context::bind_tests`].
+    pub fn built_in_first(set: Set, n: usize) -> Self {
+        Self::built_in_select("first", set, move |ids| ids.truncate(n))
+    }
+
+    /// Construct a set which contains `n` tests of the given set, which are
+    /// chosen pseudo-randomly but deterministically by the given seed.
+    ///
+    /// This is the test set created by `sample(set, n)`, see
+    /// [`Context::bind_tests`].
+    pub fn built_in_sample(set: Set, n: usize, seed: usize) -> Self {
+        Self::built_in_select("sample", set, move |ids| {
+            ids.sort_by_cached_key(|id| typst::utils::hash128(&(seed, id)));
+            ids.truncate(n);
+        })
+    }
+
+    /// Construct a set which contains a selection of the tests contained in
+    /// the given set, the selection is made once over all tests bound in the
+    /// context and cached for subsequent calls with the same tests, `func` is
+    /// the name reported if no tests are bound.
+    fn built_in_select<F>(func: &'static str, set: Set, select: F) -> Self
+    where
+        F: Fn(&mut Vec<Id>) + 'static,
+    {
+        let cache: RefCell<Option<(Arc<[Test]>, BTreeSet<Id>)>> = RefCell::new(None);
+
+        Self::new(move |ctx, test| {
+            let mut cache = cache.borrow_mut();
+
+            let tests = ctx.expect_tests(func)?;
+            if !cache
+                .as_ref()
+                .is_some_and(|(cached, _)| Arc::ptr_eq(cached, tests))
+            {
+                let mut ids = vec![];
+                for test in tests.iter() {
+                    if set.contains(ctx, test)? {
+                        ids.push(test.id().clone());
+                    }
+                }
+
+                select(&mut ids);
+                *cache = Some((Arc::clone(tests), ids.into_iter().collect()));
+            }
+
+            let (_, selected) = cache.as_ref().expect("cache was filled above");
+            Ok(selected.contains(test.id()))
+        })
+    }
+
     /// Construct a set which contains all tests matching the given pattern.
     ///
+    /// If tests are bound in the context, the pattern is matched against all
+    /// of them once and cached for subsequent calls with the same tests. Tests
+    /// which can't match the literal prefix of the pattern are skipped using
+    /// the order of the bound tests, see [`Pat::literal_prefix`].
+    ///
     /// This is the test set created by pattern literals like `r:'foot-(\w-)+'`.
     pub fn built_in_pattern(pat: Pat) -> Self {
-        Self::new(move |_, test| Ok(pat.is_match(test.id())))
+        let cache: RefCell<Option<(Arc<[Test]>, BTreeSet<Id>)>> = RefCell::new(None);
+
+        Self::new(move |ctx, test| {
+            let Some(tests) = ctx.tests().filter(|tests| {
+                tests
+                    .binary_search_by(|other| other.id().cmp(test.id()))
+                    .is_ok()
+            }) else {
+                return Ok(pat.is_match(test.id()));
+            };
+
+            let mut cache = cache.borrow_mut();
+            if !cache
+                .as_ref()
+                .is_some_and(|(cached, _)| Arc::ptr_eq(cached, tests))
+            {
+                *cache = Some((Arc::clone(tests), Self::index_pattern(&pat, tests)));
+            }
+
+            let (_, matched) = cache.as_ref().expect("cache was filled above");
+            Ok(matched.contains(test.id()))
+        })
+    }
+
+    /// Matches the given pattern against the given tests, which must be
+    /// ordered by their ids, and returns the ids of the matched tests.
+    fn index_pattern(pat: &Pat, tests: &[Test]) -> BTreeSet<Id> {
+        // NOTE(tinger): tests are ordered by their ids, so all tests starting
+        // with the same prefix are adjacent
+        let candidates = match pat.literal_prefix() {
+            Some(prefix) => {
+                let start = tests.partition_point(|test| test.id().as_str() < prefix);
+                let len =
+                    tests[start..].partition_point(|test| test.id().as_str().starts_with(prefix));
+
+                &tests[start..start + len]
+            }
+            None => tests,
+        };
+
+        candidates
+            .iter()
+            .filter(|test| pat.is_match(test.id()))
+            .map(|test| test.id().clone())
+            .collect()
+    }
+
+    /// Construct a set which contains all tests whose id or the id of any of
+    /// their ancestor modules matches the given pattern.
+    ///
+    /// This is the test set created by `pat+`, e.g. `e:'math'+` contains
+    /// `math` and all tests nested below it.
+    pub fn built_in_pattern_descendants(pat: Pat) -> Self {
+        Self::built_in_union(
+            Self::built_in_pattern(pat.clone()),
+            Self::built_in_pattern_children(pat),
+            [],
+        )
+    }
+
+    /// Construct a set which contains all tests contained in the given set and
+    /// all tests nested below them, see [`Context::bind_tests`].
+    ///
+    /// Sets only contain tests, not the modules they are nested in, so this
+    /// only adds tests which are nested below other tests. A collected
+    /// [`Suite`][crate::test::Suite] never contains such tests, to include
+    /// all tests of a module use [`Set::built_in_pattern_descendants`]
+    /// instead.
+    ///
+    /// This is the test set created by `set+`.
+    pub fn built_in_descendants(set: Set) -> Self {
+        Self::built_in_union(set.clone(), Self::built_in_children(set), [])
+    }
+
+    /// Construct a set which contains all tests contained in the given set and
+    /// all tests they are nested in, see [`Context::bind_tests`].
+    ///
+    /// Sets only contain tests, not the modules they are nested in, so this
+    /// only adds tests which have other tests nested below them. A collected
+    /// [`Suite`][crate::test::Suite] never contains such tests.
+    ///
+    /// This is the test set created by `set-`.
+    pub fn built_in_ancestors(set: Set) -> Self {
+        Self::built_in_union(set.clone(), Self::built_in_parents(set), [])
+    }
+
+    /// Construct a set which contains all tests nested below a module whose
+    /// id matches the given pattern, but not the tests matching it directly.
+    ///
+    /// This is the test set created by `children(pat)`.
+    pub fn built_in_pattern_children(pat: Pat) -> Self {
+        Self::new(move |_, test| Ok(test.id().ancestors().skip(1).any(|id| pat.is_match_str(id))))
+    }
+
+    /// Construct a set which contains all tests nested below the tests of the
+    /// given set, but not those tests themselves, see
+    /// [`Context::bind_tests`].
+    ///
+    /// Like [`Set::built_in_descendants`], this only finds tests nested below
+    /// other tests, see [`Set::built_in_pattern_children`] for modules.
+    ///
+    /// This is the test set created by `children(set)`.
+    pub fn built_in_children(set: Set) -> Self {
+        Self::new(move |ctx, test| {
+            let tests = ctx.expect_tests("children")?;
+            let mut parent = test.id().parent();
+            while let Some(id) = parent {
+                if let Ok(idx) = tests.binary_search_by(|test| test.id().cmp(&id)) {
+                    if set.contains(ctx, &tests[idx])? {
+                        return Ok(true);
+                    }
+                }
+
+                parent = id.parent();
+            }
+
+            Ok(false)
+        })
+    }
+
+    /// Construct a set which contains all tests the tests of the given set are
+    /// nested in, but not those tests themselves, see
+    /// [`Context::bind_tests`].
+    ///
+    /// Like [`Set::built_in_ancestors`], this only finds tests which have other
+    /// tests nested below them.
+    ///
+    /// This is the test set created by `parent(set)`.
+    pub fn built_in_parents(set: Set) -> Self {
+        Self::new(move |ctx, test| {
+            // NOTE(tinger): the tests are sorted by id, so all tests nested
+            // below this one form a contiguous range starting with its prefix
+            let tests = ctx.expect_tests("parent")?;
+            let prefix = format!("{}{}", test.id(), Id::SEPARATOR);
+            let start = tests.partition_point(|other| other.id().as_str() < prefix.as_str());
+
+            for other in tests[start..]
+                .iter()
+                .take_while(|other| other.id().as_str().starts_with(&prefix))
+            {
+                if set.contains(ctx, other)? {
+                    return Ok(true);
+                }
+            }
+
+            Ok(false)
+        })
     }
 
     /// Construct a set which contains all tests _not_ contained in the given
@@ -162,3 +382,177 @@ impl TryFromValue for Set {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::test_set::{Glob, Regex, TestSet};
+
+    const IDS: &[&str] = &["math", "math/add", "math/sub/int", "mathx", "text/bold"];
+
+    fn matches(input: &str) -> Vec<String> {
+        let mut set = TestSet::parse_and_evaluate(Context::with_built_ins(), input).unwrap();
+        set.bind_tests(IDS.iter().map(|id| Test::new(Id::new(*id).unwrap())));
+
+        set.ctx()
+            .tests()
+            .unwrap()
+            .iter()
+            .filter(|test| set.contains(test).unwrap())
+            .map(|test| test.id().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_built_in_descendants() {
+        assert_eq!(matches("e:'math'+"), ["math", "math/add", "math/sub/int"]);
+        assert_eq!(matches("e:'math/sub'+"), ["math/sub/int"]);
+        assert_eq!(matches("e:'text' +"), ["text/bold"]);
+        assert_eq!(matches("e:'mat'+"), Vec::<String>::new());
+
+        // sets only contain tests, so descendants are only found through
+        // tests, `text` is a module, but not a test
+        assert_eq!(
+            matches("(e:'math' | e:'text')+"),
+            ["math", "math/add", "math/sub/int"]
+        );
+    }
+
+    fn ids(n: usize) -> Vec<Id> {
+        (0..n)
+            .map(|n| Id::new(format!("m{}/s{}/t{n}", n % 7, n % 13)).unwrap())
+            .collect()
+    }
+
+    fn pats() -> Vec<Pat> {
+        let glob = |pat| Glob::new(glob::Pattern::new(pat).unwrap());
+
+        vec![
+            Pat::Exact("m1/s1/t1".into()),
+            Pat::Exact("m1".into()),
+            Pat::Glob(glob("m3/*")),
+            Pat::Glob(glob("m2/s1[0-2]/*")),
+            Pat::Glob(glob("**/t4*")),
+            Pat::Path(glob("m4/s5")),
+            Pat::Regex(Regex::new(regex::Regex::new("t1.*5$").unwrap())),
+        ]
+    }
+
+    #[test]
+    fn test_built_in_pattern_index() {
+        let ids = ids(1000);
+        let mut ctx = Context::with_built_ins();
+        ctx.bind_tests(ids.iter().cloned().map(Test::new));
+
+        for pat in pats() {
+            let set = Set::built_in_pattern(pat.clone());
+            for test in ctx.tests().unwrap().iter() {
+                assert_eq!(
+                    set.contains(&ctx, test).unwrap(),
+                    pat.is_match(test.id()),
+                    "{pat:?} on {}",
+                    test.id(),
+                );
+            }
+
+            // unbound tests are matched directly
+            let unbound = Test::new(Id::new("m3/unbound").unwrap());
+            assert_eq!(
+                set.contains(&ctx, &unbound).unwrap(),
+                pat.is_match(unbound.id())
+            );
+        }
+    }
+
+    // NOTE(tinger): run with `cargo test --release -- --ignored` to compare
+    // the indexed against the naive matching, this is timing dependent and
+    // therefore not run by default
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_built_in_pattern_index() {
+        let ids = ids(100_000);
+        let mut ctx = Context::with_built_ins();
+        ctx.bind_tests(ids.iter().cloned().map(Test::new));
+        let tests = ctx.tests().unwrap();
+
+        let mut naive_total = Duration::ZERO;
+        let mut indexed_total = Duration::ZERO;
+        for pat in pats() {
+            let start = Instant::now();
+            let naive: BTreeSet<_> = tests
+                .iter()
+                .filter(|test| pat.is_match(test.id()))
+                .map(|test| test.id().clone())
+                .collect();
+            let naive_duration = start.elapsed();
+
+            let start = Instant::now();
+            let indexed = Set::index_pattern(&pat, tests);
+            let indexed_duration = start.elapsed();
+
+            assert_eq!(naive, indexed, "{pat:?}");
+
+            // NOTE(tinger): patterns without a literal prefix are matched
+            // against all tests either way
+            if pat.literal_prefix().is_some() {
+                naive_total += naive_duration;
+                indexed_total += indexed_duration;
+            }
+        }
+
+        assert!(
+            indexed_total < naive_total,
+            "indexed matching took {indexed_total:?}, naive matching took {naive_total:?}",
+        );
+    }
+
+    #[test]
+    fn test_built_in_children() {
+        assert_eq!(matches("children(e:'math')"), ["math/add", "math/sub/int"]);
+        assert_eq!(matches("children(e:'math/sub')"), ["math/sub/int"]);
+        assert_eq!(matches("children(e:'mathx')"), Vec::<String>::new());
+
+        // unlike the descendants operator, the tests themselves are excluded
+        assert_eq!(matches("e:'math'+ ~ children(e:'math')"), ["math"]);
+    }
+
+    #[test]
+    fn test_built_in_parent() {
+        assert_eq!(matches("parent(e:'math/sub/int')"), ["math"]);
+        assert_eq!(matches("parent(e:'math/add' | e:'text/bold')"), ["math"]);
+        assert_eq!(matches("parent(e:'math')"), Vec::<String>::new());
+
+        // unlike the ancestors operator, the tests themselves are excluded
+        assert_eq!(
+            matches("e:'math/add'- ~ parent(e:'math/add')"),
+            ["math/add"]
+        );
+    }
+
+    #[test]
+    fn test_built_in_unbound() {
+        let ctx = Context::with_built_ins();
+        let test = Test::new(Id::new("math/add").unwrap());
+
+        for (set, func) in [
+            (Set::built_in_children(Set::built_in_all()), "children"),
+            (Set::built_in_parents(Set::built_in_all()), "parent"),
+            (Set::built_in_sample(Set::built_in_all(), 1, 0), "sample"),
+        ] {
+            assert!(matches!(
+                set.contains(&ctx, &test),
+                Err(Error::UnboundTests { func: found }) if found == func
+            ));
+        }
+    }
+
+    #[test]
+    fn test_built_in_ancestors() {
+        assert_eq!(matches("e:'math/sub/int'-"), ["math", "math/sub/int"]);
+        assert_eq!(matches("e:'math/add'-"), ["math", "math/add"]);
+        assert_eq!(matches("e:'text/bold'-"), ["text/bold"]);
+        assert_eq!(matches("e:'mathx'-"), ["mathx"]);
+    }
+}
@@ -89,6 +89,12 @@ pub trait TryFromValue: Sized {
     fn try_from_value(value: &Value) -> Result<Self, Error>;
 }
 
+impl TryFromValue for Value {
+    fn try_from_value(value: &Value) -> Result<Self, Error> {
+        Ok(value.clone())
+    }
+}
+
 /// The type of an expression. This is primarily used for diagnostics.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Type {
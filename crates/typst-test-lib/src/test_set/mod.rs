@@ -5,9 +5,14 @@
 //! [reference]: https://tingerrr.github.io/typst-test/reference/test-sets/index.html
 //! [guide]: https://tingerrr.github.io/typst-test/guides/test-sets.html
 
+use std::error::Error as _;
+use std::fmt::Display;
 use std::mem;
+use std::ops::Range;
 use std::str::FromStr;
+use std::sync::Arc;
 
+use pest::error::InputLocation;
 use thiserror::Error;
 
 use self::eval::{Context, Eval, Set};
@@ -17,7 +22,7 @@ pub mod eval;
 mod glob;
 mod id;
 mod num;
-mod parse;
+pub mod parse;
 mod pat;
 mod regex;
 mod str;
@@ -43,17 +48,38 @@ pub struct TestSetExpr {
 impl TestSetExpr {
     /// Parse and evaluate a string into a test set expression.
     pub fn parse<S: AsRef<str>>(input: S) -> Result<Self, Error> {
-        let input = input.as_ref().trim();
+        let original = input.as_ref();
+        let input = original.trim();
 
         let (all, input) = input
             .strip_prefix("all:")
             .map(|rest| (true, rest))
             .unwrap_or((false, input));
 
-        let expr = parse::parse(input)?;
+        // NOTE(tinger): error spans must point into the original input
+        let offset = original.len() - original.trim_start().len() + if all { 4 } else { 0 };
+        let expr = parse::parse(input).map_err(|err| Error {
+            inner: ErrorImpl::Parse(err),
+            offset,
+        })?;
 
         Ok(Self { all, expr })
     }
+
+    /// Adds an implicit `(...) ~ skip()` around the expression, this mirrors
+    /// [`TestSet::add_implicit_skip`] and keeps the `all:` modifier.
+    pub fn add_implicit_skip(&mut self) {
+        let skip = parse::Function {
+            id: Id::new("skip").expect("skip is a valid identifier"),
+            args: vec![],
+        };
+
+        self.expr = parse::Expr::Infix {
+            op: parse::InfixOp::Diff,
+            lhs: Arc::new(self.expr.clone()),
+            rhs: Arc::new(parse::Expr::Func(skip)),
+        };
+    }
 }
 
 impl TestSetExpr {
@@ -61,6 +87,23 @@ impl TestSetExpr {
     pub fn all(&self) -> bool {
         self.all
     }
+
+    /// The parsed expression without the `all:` modifier, this is mostly
+    /// useful for debugging operator precedence.
+    pub fn expr(&self) -> &parse::Expr {
+        &self.expr
+    }
+}
+
+/// Displays the expression in a normalized form, see [`TestSetExpr::parse`].
+impl Display for TestSetExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.all {
+            write!(f, "all:")?;
+        }
+
+        write!(f, "{}", self.expr)
+    }
 }
 
 impl FromStr for TestSetExpr {
@@ -109,6 +152,12 @@ impl TestSet {
         self.set = Set::built_in_diff(mem::take(&mut self.set), Set::built_in_skip());
     }
 
+    /// Binds all tests this set is matched against, see
+    /// [`Context::bind_tests`].
+    pub fn bind_tests<I: IntoIterator<Item = Test>>(&mut self, tests: I) {
+        self.ctx.bind_tests(tests);
+    }
+
     /// Adds an implicit `(...) & set()` around the expression.
     pub fn add_intersection(&mut self, set: Set) {
         self.set = Set::built_in_inter(mem::take(&mut self.set), set, []);
@@ -149,18 +198,101 @@ enum ErrorImpl {
 }
 
 /// Returned by [`TestSet::evaluate`] and [`TestSet::parse_and_evaluate`].
-#[derive(Debug, Error)]
-#[error(transparent)]
-pub struct Error(ErrorImpl);
+#[derive(Debug)]
+pub struct Error {
+    inner: ErrorImpl,
+
+    /// The offset of the parsed expression in the input.
+    offset: usize,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.inner, f)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.inner.source()
+    }
+}
+
+impl Error {
+    /// The byte range in the parsed input at which this error occurred, this
+    /// is only known for parse errors.
+    pub fn span(&self) -> Option<Range<usize>> {
+        let ErrorImpl::Parse(parse::Error::Pest(err)) = &self.inner else {
+            return None;
+        };
+
+        let span = match err.location {
+            InputLocation::Pos(pos) => pos..pos,
+            InputLocation::Span((start, end)) => start..end,
+        };
+
+        Some(span.start + self.offset..span.end + self.offset)
+    }
+
+    /// A short description of what went wrong at the [span][Error::span],
+    /// like the tokens that were expected instead.
+    pub fn detail(&self) -> Option<String> {
+        let ErrorImpl::Parse(parse::Error::Pest(err)) = &self.inner else {
+            return None;
+        };
+
+        Some(err.variant.message().into_owned())
+    }
+}
 
 impl From<parse::Error> for Error {
     fn from(value: parse::Error) -> Self {
-        Self(ErrorImpl::Parse(value))
+        Self {
+            inner: ErrorImpl::Parse(value),
+            offset: 0,
+        }
     }
 }
 
 impl From<eval::Error> for Error {
     fn from(value: eval::Error) -> Self {
-        Self(ErrorImpl::Eval(value))
+        Self {
+            inner: ErrorImpl::Eval(value),
+            offset: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_error_span() {
+        let err = TestSetExpr::parse("(a | b").unwrap_err();
+        assert_eq!(err.span(), Some(6..6));
+        assert!(err.detail().is_some());
+
+        // spans point into the original input
+        let err = TestSetExpr::parse("  all:(a | b").unwrap_err();
+        assert_eq!(err.span(), Some(12..12));
+    }
+
+    #[test]
+    fn test_expr_implicit_skip() {
+        let mut expr = TestSetExpr::parse("all:a | b").unwrap();
+        expr.add_implicit_skip();
+        assert_eq!(expr.to_string(), "all:((a | b) ~ skip())");
+        assert!(expr.all());
+
+        // the normalized form round trips
+        let reparsed = TestSetExpr::parse(expr.to_string()).unwrap();
+        assert_eq!(reparsed.expr(), expr.expr());
+    }
+
+    #[test]
+    fn test_eval_error_no_span() {
+        let err = TestSet::parse_and_evaluate(Context::with_built_ins(), "unknown()").unwrap_err();
+        assert_eq!(err.span(), None);
     }
 }
@@ -2,7 +2,7 @@
 
 use std::borrow::Cow;
 use std::char::CharTryFromError;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 use std::hash::Hash;
 use std::sync::{Arc, LazyLock};
 
@@ -135,6 +135,7 @@ static PRATT_PARSER: LazyLock<PrattParser<Rule>> = LazyLock::new(|| {
         .op(Op::infix(Rule::infix_op_caret, Assoc::Left)
             | Op::infix(Rule::infix_op_xor, Assoc::Left))
         .op(Op::prefix(Rule::prefix_op_excl) | Op::prefix(Rule::prefix_op_not))
+        .op(Op::postfix(Rule::postfix_op_plus) | Op::postfix(Rule::postfix_op_minus))
 });
 
 impl Rule {
@@ -146,6 +147,15 @@ impl Rule {
         })
     }
 
+    /// Turns this rule into the respective postfix operator.
+    fn to_postfix(self) -> Option<PostfixOp> {
+        Some(match self {
+            Rule::postfix_op_plus => PostfixOp::Descendants,
+            Rule::postfix_op_minus => PostfixOp::Ancestors,
+            _ => return None,
+        })
+    }
+
     /// Turns this rule into the respective infix operator.
     fn to_infix(self) -> Option<InfixOp> {
         Some(match self {
@@ -167,6 +177,9 @@ impl Rule {
             Rule::prefix_op => "prefix op",
             Rule::prefix_op_excl => "symbol complement op",
             Rule::prefix_op_not => "literal complement op",
+            Rule::postfix_op => "postfix op",
+            Rule::postfix_op_plus => "descendants op",
+            Rule::postfix_op_minus => "ancestors op",
             Rule::infix_op => "infix op",
             Rule::infix_op_caret => "symbol symmetric difference op",
             Rule::infix_op_amper => "symbol intersection op",
@@ -216,6 +229,9 @@ impl Rule {
             Rule::prefix_op => "<prefix op>",
             Rule::prefix_op_excl => "!",
             Rule::prefix_op_not => "not",
+            Rule::postfix_op => "<postfix op>",
+            Rule::postfix_op_plus => "+",
+            Rule::postfix_op_minus => "-",
             Rule::infix_op => "<infix op>",
             Rule::infix_op_caret => "^",
             Rule::infix_op_amper => "&",
@@ -300,6 +316,26 @@ impl PrefixOp {
     }
 }
 
+/// A unary postfix operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PostfixOp {
+    /// The descendants operator.
+    Descendants,
+
+    /// The ancestors operator.
+    Ancestors,
+}
+
+impl PostfixOp {
+    /// The symbol representing this operator.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Descendants => "+",
+            Self::Ancestors => "-",
+        }
+    }
+}
+
 /// A binary infix operator.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum InfixOp {
@@ -346,6 +382,15 @@ pub enum Expr {
         expr: Arc<Expr>,
     },
 
+    /// A postfix expression.
+    Postfix {
+        /// The unary postfix operator.
+        op: PostfixOp,
+
+        /// The inner expression.
+        expr: Arc<Expr>,
+    },
+
     /// An infix expression.
     Infix {
         /// The binary infix operator.
@@ -359,6 +404,98 @@ pub enum Expr {
     },
 }
 
+impl Display for Atom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "{}", id.as_str()),
+            Self::Num(num) => write!(f, "{num}"),
+            Self::Str(str) => write!(f, "{str:?}"),
+            Self::Pat(pat) => write!(f, "{pat:?}"),
+        }
+    }
+}
+
+impl Expr {
+    /// Collects the operands of nested infix expressions with the given
+    /// associative operator, such that `a | (b | c)` yields `[a, b, c]`.
+    fn flatten<'e>(&'e self, chain: InfixOp, operands: &mut Vec<&'e Expr>) {
+        match self {
+            Self::Infix { op, lhs, rhs } if *op == chain => {
+                lhs.flatten(chain, operands);
+                rhs.flatten(chain, operands);
+            }
+            _ => operands.push(self),
+        }
+    }
+
+    /// Collects the identifiers of all bindings and functions referenced in
+    /// this expression.
+    pub fn identifiers<'e>(&'e self, ids: &mut Vec<&'e Id>) {
+        match self {
+            Self::Atom(Atom::Id(id)) => ids.push(id),
+            Self::Atom(_) => {}
+            Self::Func(func) => {
+                ids.push(&func.id);
+                for arg in &func.args {
+                    arg.identifiers(ids);
+                }
+            }
+            Self::Prefix { expr, .. } | Self::Postfix { expr, .. } => expr.identifiers(ids),
+            Self::Infix { lhs, rhs, .. } => {
+                lhs.identifiers(ids);
+                rhs.identifiers(ids);
+            }
+        }
+    }
+}
+
+/// Displays the expression in a normalized form, infix expressions are always
+/// parenthesized and chains of unions and intersections are flattened.
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Atom(atom) => write!(f, "{atom}"),
+            Self::Func(func) => {
+                write!(f, "{}(", func.id.as_str())?;
+                for (idx, arg) in func.args.iter().enumerate() {
+                    if idx != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{arg}")?;
+                }
+                write!(f, ")")
+            }
+            Self::Prefix { op, expr } => write!(f, "{}{expr}", op.symbol()),
+            // NOTE(tinger): identifiers may end in a minus, so the ancestors
+            // operator must be separated from them to round trip
+            Self::Postfix {
+                op: PostfixOp::Ancestors,
+                expr,
+            } if matches!(**expr, Self::Atom(Atom::Id(_))) => {
+                write!(f, "{expr} {}", PostfixOp::Ancestors.symbol())
+            }
+            Self::Postfix { op, expr } => write!(f, "{expr}{}", op.symbol()),
+            Self::Infix { op, lhs, rhs } => {
+                let mut operands = vec![];
+                if matches!(op, InfixOp::Union | InfixOp::Inter) {
+                    self.flatten(*op, &mut operands);
+                } else {
+                    operands.extend([&**lhs, &**rhs]);
+                }
+
+                write!(f, "(")?;
+                for (idx, operand) in operands.into_iter().enumerate() {
+                    if idx != 0 {
+                        write!(f, " {} ", op.symbol())?;
+                    }
+                    write!(f, "{operand}")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
 /// Parse the given input into a test set expression.
 #[tracing::instrument(ret)]
 pub fn parse(input: &str) -> Result<Expr, Error> {
@@ -396,6 +533,13 @@ fn parse_expr(pair: Pair<Rule>, pratt: &PrattParser<Rule>) -> Result<Expr, Error
             }),
             None => unreachable!("unhandled prefix operator {:?}", op.as_rule()),
         })
+        .map_postfix(|expr, op| match op.as_rule().to_postfix() {
+            Some(op) => Ok(Expr::Postfix {
+                op,
+                expr: Arc::new(expr?),
+            }),
+            None => unreachable!("unhandled postfix operator {:?}", op.as_rule()),
+        })
         .map_infix(|lhs, op, rhs| match op.as_rule().to_infix() {
             Some(op) => Ok(Expr::Infix {
                 op,
@@ -433,6 +577,7 @@ fn parse_pat(pair: Pair<Rule>) -> Result<Pat, Error> {
         "g" | "glob" => Pat::Glob(Glob::new(glob::Pattern::new(&pat)?)),
         "r" | "regex" => Pat::Regex(Regex::new(regex::Regex::new(&pat)?)),
         "e" | "exact" => Pat::Exact(pat.into()),
+        "p" | "path" => Pat::Path(Glob::new(glob::Pattern::new(&pat)?)),
         _ => unreachable!("unhandled kind: {kind:?}"),
     })
 }
@@ -636,6 +781,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_pattern_path() {
+        assert_eq!(
+            parse(r#"path:"integration/**""#).unwrap(),
+            Expr::Atom(Atom::Pat(Pat::Path(Glob::new(
+                glob::Pattern::new("integration/**").unwrap()
+            ))))
+        );
+        assert_eq!(
+            parse("p:a/*").unwrap(),
+            Expr::Atom(Atom::Pat(Pat::Path(Glob::new(
+                glob::Pattern::new("a/*").unwrap()
+            ))))
+        );
+    }
+
+    #[test]
+    fn test_display_normalized() {
+        let display = |input| parse(input).unwrap().to_string();
+
+        assert_eq!(display("a | b | c"), "(a | b | c)");
+        assert_eq!(display("a or (b | c)"), "(a | b | c)");
+        assert_eq!(display("a & b | c"), "((a & b) | c)");
+        assert_eq!(display("not a ~ b ~ c"), "((!a ~ b) ~ c)");
+        assert_eq!(display("!a+ | b-"), "(!a+ | b-)");
+        assert_eq!(display("!a+ | b -"), "(!a+ | b -)");
+        assert_eq!(
+            display(r#"f(1, 'x', e:"a/b")"#),
+            r#"f(1, "x", exact:"a/b")"#
+        );
+    }
+
     #[test]
     fn test_parse_func_no_args() {
         assert_eq!(
@@ -683,6 +860,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_postfix_expression() {
+        assert_eq!(
+            parse("a+").unwrap(),
+            Expr::Postfix {
+                op: PostfixOp::Descendants,
+                expr: Arc::new(Expr::Atom(Atom::Id(Id::new("a").unwrap()))),
+            }
+        );
+
+        // a trailing minus is part of the identifier unless it's separated
+        assert_eq!(
+            parse("a-b-").unwrap(),
+            Expr::Atom(Atom::Id(Id::new("a-b-").unwrap()))
+        );
+        assert_eq!(
+            parse("a-b -").unwrap(),
+            Expr::Postfix {
+                op: PostfixOp::Ancestors,
+                expr: Arc::new(Expr::Atom(Atom::Id(Id::new("a-b").unwrap()))),
+            }
+        );
+        assert_eq!(
+            parse("(a-b)-").unwrap(),
+            Expr::Postfix {
+                op: PostfixOp::Ancestors,
+                expr: Arc::new(Expr::Atom(Atom::Id(Id::new("a-b").unwrap()))),
+            }
+        );
+
+        // postfix operators bind more strongly than prefix operators
+        assert_eq!(
+            parse("!e:'a' +").unwrap(),
+            Expr::Prefix {
+                op: PrefixOp::Not,
+                expr: Arc::new(Expr::Postfix {
+                    op: PostfixOp::Descendants,
+                    expr: Arc::new(Expr::Atom(Atom::Pat(Pat::Exact("a".into())))),
+                }),
+            }
+        );
+    }
+
     #[test]
     fn test_parse_infix_expression() {
         assert_eq!(
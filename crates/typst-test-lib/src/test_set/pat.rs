@@ -1,5 +1,7 @@
 use std::hash::Hash;
 
+use glob::MatchOptions;
+
 use super::eval::{Context, Error, Eval, Value};
 use super::{Glob, Regex};
 use crate::test::Id as TestId;
@@ -15,6 +17,11 @@ pub enum Pat {
 
     /// An exact pattern, matches if the pattern equals the haystack.
     Exact(String),
+
+    /// A path pattern, matches if the glob matches the test's directory path
+    /// relative to the test root or that of any of its parent directories.
+    /// Unlike glob patterns, wildcards do not match path separators.
+    Path(Glob),
 }
 
 impl std::fmt::Debug for Pat {
@@ -23,6 +30,7 @@ impl std::fmt::Debug for Pat {
             Pat::Glob(glob) => ("glob", glob.as_str()),
             Pat::Regex(regex) => ("regex", regex.as_str()),
             Pat::Exact(pat) => ("exact", pat.as_str()),
+            Pat::Path(glob) => ("path", glob.as_str()),
         };
 
         write!(f, "{prefix}:{pat:?}")
@@ -32,16 +40,111 @@ impl std::fmt::Debug for Pat {
 impl Pat {
     /// Returns true if the test id matches this pattern.
     pub fn is_match(&self, id: &TestId) -> bool {
+        self.is_match_str(id.as_str())
+    }
+
+    /// Returns true if the given string matches this pattern, this is used
+    /// for patterns which are not matched against test ids.
+    pub fn is_match_str(&self, haystack: &str) -> bool {
         match self {
-            Self::Glob(pat) => pat.is_match(id),
-            Self::Regex(regex) => regex.is_match(id),
-            Self::Exact(pat) => id.as_str() == pat.as_str(),
+            Self::Glob(pat) => pat.as_glob().matches(haystack),
+            Self::Regex(regex) => regex.as_regex().is_match(haystack),
+            Self::Exact(pat) => haystack == pat.as_str(),
+            Self::Path(pat) => {
+                let options = MatchOptions {
+                    require_literal_separator: true,
+                    ..MatchOptions::new()
+                };
+
+                let mut path = haystack;
+                loop {
+                    if pat.as_glob().matches_with(path, options) {
+                        return true;
+                    }
+
+                    match path.rsplit_once('/') {
+                        Some((parent, _)) => path = parent,
+                        None => return false,
+                    }
+                }
+            }
         }
     }
 }
 
+impl Pat {
+    /// A literal prefix which all test ids matched by this pattern must start
+    /// with, if there is one. This is used to prune tests before matching
+    /// them.
+    pub fn literal_prefix(&self) -> Option<&str> {
+        let prefix = match self {
+            Self::Glob(glob) | Self::Path(glob) => {
+                let pat = glob.as_str();
+                &pat[..pat.find(['*', '?', '[']).unwrap_or(pat.len())]
+            }
+            // NOTE(tinger): regexes are not anchored in general, so there is
+            // no prefix we could extract cheaply
+            Self::Regex(_) => "",
+            Self::Exact(pat) => pat.as_str(),
+        };
+
+        (!prefix.is_empty()).then_some(prefix)
+    }
+}
+
 impl Eval for Pat {
     fn eval(&self, _ctx: &Context) -> Result<Value, Error> {
         Ok(Value::Pat(self.clone()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(pat: &str) -> Pat {
+        Pat::Path(Glob::new(glob::Pattern::new(pat).unwrap()))
+    }
+
+    fn id(id: &str) -> TestId {
+        TestId::new(id).unwrap()
+    }
+
+    #[test]
+    fn test_literal_prefix() {
+        let glob = |pat| Pat::Glob(Glob::new(glob::Pattern::new(pat).unwrap()));
+
+        assert_eq!(glob("a/b*").literal_prefix(), Some("a/b"));
+        assert_eq!(glob("a/[bc]").literal_prefix(), Some("a/"));
+        assert_eq!(glob("**/a").literal_prefix(), None);
+        assert_eq!(
+            path("integration/**").literal_prefix(),
+            Some("integration/")
+        );
+        assert_eq!(Pat::Exact("a/b".into()).literal_prefix(), Some("a/b"));
+        assert_eq!(
+            Pat::Regex(Regex::new(regex::Regex::new("^a").unwrap())).literal_prefix(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_path_is_match() {
+        let pat = path("integration/**");
+        assert!(pat.is_match(&id("integration/a")));
+        assert!(pat.is_match(&id("integration/a/b")));
+        assert!(!pat.is_match(&id("unit/integration/a")));
+
+        // parent directories match their descendants
+        let pat = path("integration");
+        assert!(pat.is_match(&id("integration/a/b")));
+        assert!(!pat.is_match(&id("integration-old/a")));
+
+        // wildcards don't cross separators
+        let pat = path("*/b");
+        assert!(pat.is_match(&id("a/b")));
+        assert!(pat.is_match(&id("a/b/c")));
+        assert!(!pat.is_match(&id("a/x/b")));
+        assert!(Pat::Glob(Glob::new(glob::Pattern::new("*/b").unwrap())).is_match(&id("a/x/b")));
+    }
+}
@@ -31,6 +31,14 @@ pub struct Args {
     #[arg(long, conflicts_with_all = ["ephemeral", "compile_only"])]
     pub no_template: bool,
 
+    /// Compile the references of the default test instead of using the
+    /// bundled ones
+    ///
+    /// The bundled references may differ from the output of the typst
+    /// version in use, this only applies if no test template is used.
+    #[arg(long)]
+    pub compile_default_ref: bool,
+
     #[command(flatten)]
     pub compile: CompileArgs,
 
@@ -52,6 +60,12 @@ pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
 
     let paths = project.paths();
     let id = args.test.clone();
+    let optimize_options = || {
+        args.export
+            .no_optimize_references
+            .not()
+            .then(|| Box::new(DEFAULT_OPTIMIZE_OPTIONS.clone()))
+    };
 
     if let Some(template) = suite.template().filter(|_| !args.no_template) {
         if args.ephemeral {
@@ -81,15 +95,18 @@ pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
                 paths,
                 id,
                 template,
-                Some(Reference::Persistent(
-                    doc,
-                    args.export
-                        .no_optimize_references
-                        .not()
-                        .then(|| Box::new(DEFAULT_OPTIMIZE_OPTIONS.clone())),
-                )),
+                Some(Reference::Persistent(doc, optimize_options())),
             )?;
         };
+    } else if args.compile_default_ref {
+        let world = ctx.world(&args.compile)?;
+        let reference = Reference::compile_default(
+            &world,
+            ppi_to_ppp(args.export.render.pixel_per_inch),
+            optimize_options(),
+        )?;
+
+        Test::create_default_with(paths, id, reference)?;
     } else {
         Test::create_default(paths, id)?;
     }
@@ -0,0 +1,34 @@
+use color_eyre::eyre;
+
+use super::Context;
+
+pub mod schema;
+pub mod show;
+
+#[derive(clap::Args, Debug, Clone)]
+#[group(id = "config-args")]
+pub struct Args {
+    /// The sub command to run
+    #[command(subcommand)]
+    pub cmd: Command,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Show the effective configuration and where each value came from
+    #[command()]
+    Show(show::Args),
+
+    /// Print a JSON schema for config files to stdout
+    #[command()]
+    Schema(schema::Args),
+}
+
+impl Command {
+    pub fn run(&self, ctx: &mut Context) -> eyre::Result<()> {
+        match self {
+            Command::Show(args) => show::run(ctx, args),
+            Command::Schema(args) => schema::run(ctx, args),
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use color_eyre::eyre;
+use serde_json::json;
+
+use crate::cli::Context;
+
+#[derive(clap::Args, Debug, Clone)]
+#[group(id = "config-schema-args")]
+pub struct Args {}
+
+pub fn run(ctx: &mut Context, _args: &Args) -> eyre::Result<()> {
+    serde_json::to_writer_pretty(ctx.ui.stdout(), &config_schema())?;
+    Ok(())
+}
+
+/// Returns a JSON schema describing a config file, this must be kept in sync
+/// with [`lib::config::ConfigLayer`].
+fn config_schema() -> serde_json::Value {
+    let hook = |description: &str| {
+        json!({
+            "description": description,
+            "$ref": "#/$defs/hook",
+        })
+    };
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "typst-test config",
+        "description": "The configuration of typst-test, found in typst-test.toml or the tool.typst-test section of typst.toml.",
+        "type": "object",
+        "additionalProperties": false,
+        "properties": {
+            "test-sets": {
+                "description": "Custom test set definitions.",
+                "type": "object",
+                "additionalProperties": { "type": "string" },
+            },
+            "max-delta": {
+                "description": "The default maximum delta in each channel of a pixel used for comparisons.",
+                "type": "integer",
+                "minimum": 0,
+                "maximum": 255,
+            },
+            "max-deviation": {
+                "description": "The default maximum amount of deviations per page used for comparisons.",
+                "type": "integer",
+                "minimum": 0,
+            },
+            "shift-tolerance": {
+                "description": "The default radius in pixels within which shifted content is tolerated used for comparisons.",
+                "type": "integer",
+                "minimum": 0,
+            },
+            "object-store": {
+                "description": "Whether to store persistent references by content hash in a shared object directory.",
+                "type": "boolean",
+            },
+            "exclude-template": {
+                "description": "Whether to exclude tests within the template directory of a template package.",
+                "type": "boolean",
+                "default": true,
+            },
+            "doc-tests": {
+                "description": "The markdown files relative to the project root from which doc tests are extracted.",
+                "type": "array",
+                "items": { "type": "string" },
+            },
+            "test-script": {
+                "description": "The file name of test scripts.",
+                "type": "string",
+                "default": lib::project::DEFAULT_TEST_SCRIPT,
+            },
+            "ref-script": {
+                "description": "The file name of reference scripts.",
+                "type": "string",
+                "default": lib::project::DEFAULT_REF_SCRIPT,
+            },
+            "init-ignore": {
+                "description": "Additional directories relative to the project root which are ignored in the vcs on init.",
+                "type": "array",
+                "items": { "type": "string" },
+            },
+            "prepare": hook("A hook to run once before all tests."),
+            "prepare-each": hook("A hook to run before each test."),
+            "cleanup": hook("A hook to run once after all tests."),
+            "cleanup-each": hook("A hook to run after each test."),
+        },
+        "$defs": {
+            "hook": {
+                "oneOf": [
+                    {
+                        "description": "The path of an executable relative to the project root.",
+                        "type": "string",
+                    },
+                    {
+                        "description": "A program followed by its arguments, run without a shell.",
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "minItems": 1,
+                    },
+                ],
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    #[test]
+    fn test_config_schema() {
+        let schema = serde_json::to_string(&config_schema()).unwrap();
+        let schema: serde_json::Value = serde_json::from_str(&schema).unwrap();
+
+        let properties: BTreeSet<_> = schema["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+
+        assert_eq!(properties, lib::config::KEYS.iter().copied().collect());
+        assert_eq!(schema["additionalProperties"], false);
+    }
+}
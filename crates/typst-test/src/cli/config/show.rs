@@ -0,0 +1,99 @@
+use std::io::Write;
+
+use color_eyre::eyre;
+use lib::config::{Config, Layer};
+use lib::project::Project;
+use serde::Serialize;
+use termcolor::Color;
+
+use crate::cli::Context;
+use crate::json::ConfigEntryJson;
+use crate::ui;
+
+#[derive(clap::Args, Debug, Clone)]
+#[group(id = "config-show-args")]
+pub struct Args {
+    /// Print a JSON describing the configuration to stdout
+    #[arg(long)]
+    pub json: bool,
+}
+
+pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
+    let project = Project::discover(ctx.root()?, ctx.args.global.root.is_some())?;
+    let config = ctx.config(project.as_ref())?;
+    let entries = resolve_entries(&config)?;
+
+    if args.json {
+        serde_json::to_writer_pretty(ctx.ui.stdout(), &entries)?;
+        return Ok(());
+    }
+
+    let mut w = ctx.ui.stderr();
+
+    for entry in entries {
+        ui::write_bold(&mut w, |w| write!(w, "{}", entry.key))?;
+        write!(w, " = ")?;
+
+        match (entry.value, entry.layer) {
+            (Some(value), Some(layer)) => {
+                write!(w, "{value} ")?;
+                ui::write_colored(&mut w, Color::Cyan, |w| write!(w, "({layer})"))?;
+            }
+            _ => {
+                ui::write_colored(&mut w, Color::Yellow, |w| write!(w, "<unset>"))?;
+            }
+        }
+
+        writeln!(w)?;
+    }
+
+    Ok(())
+}
+
+/// Resolves all config keys to their effective value and the layer they were
+/// found in.
+fn resolve_entries(config: &Config) -> eyre::Result<Vec<ConfigEntryJson>> {
+    fn entry<T: Serialize + ?Sized>(
+        key: &'static str,
+        resolved: Option<(&T, Layer)>,
+    ) -> eyre::Result<ConfigEntryJson> {
+        Ok(match resolved {
+            Some((value, layer)) => ConfigEntryJson {
+                key,
+                value: Some(serde_json::to_value(value)?),
+                layer: Some(layer.as_str()),
+            },
+            None => ConfigEntryJson {
+                key,
+                value: None,
+                layer: None,
+            },
+        })
+    }
+
+    Ok(vec![
+        entry("test-sets", config.resolve(|c| c.test_sets.as_ref()))?,
+        entry("max-delta", config.resolve(|c| c.max_delta.as_ref()))?,
+        entry(
+            "max-deviation",
+            config.resolve(|c| c.max_deviation.as_ref()),
+        )?,
+        entry(
+            "shift-tolerance",
+            config.resolve(|c| c.shift_tolerance.as_ref()),
+        )?,
+        entry("object-store", config.resolve(|c| c.object_store.as_ref()))?,
+        entry(
+            "exclude-template",
+            config.resolve(|c| c.exclude_template.as_ref()),
+        )?,
+        entry("doc-tests", config.resolve(|c| c.doc_tests.as_ref()))?,
+        entry("test-script", config.resolve(|c| c.test_script.as_ref()))?,
+        entry("ref-script", config.resolve(|c| c.ref_script.as_ref()))?,
+        entry("init-ignore", config.resolve(|c| c.init_ignore.as_ref()))?,
+        entry("prepare", config.resolve(|c| c.prepare.as_ref()))?,
+        entry("prepare-each", config.resolve(|c| c.prepare_each.as_ref()))?,
+        entry("cleanup", config.resolve(|c| c.cleanup.as_ref()))?,
+        entry("cleanup-each", config.resolve(|c| c.cleanup_each.as_ref()))?,
+    ])
+}
@@ -0,0 +1,153 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use color_eyre::eyre;
+use lib::runner::Reporter as _;
+use lib::stdx::fmt::Term;
+use lib::test::{DocTest, TestResult};
+use typst::diag::Warned;
+
+use super::{CompileArgs, Context};
+use crate::cli::TestFailure;
+use crate::report::Reporter;
+use crate::world::SystemWorld;
+
+#[derive(clap::Args, Debug, Clone)]
+#[group(id = "doc-test-args")]
+pub struct Args {
+    #[command(flatten)]
+    pub compile: CompileArgs,
+
+    /// The markdown files to extract examples from, relative to the project
+    /// root
+    ///
+    /// Defaults to the files configured in `doc-tests`.
+    #[arg(value_name = "FILE")]
+    pub files: Vec<PathBuf>,
+}
+
+pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
+    let project = ctx.project()?;
+    let config = ctx.config(Some(&project))?;
+
+    let files = if args.files.is_empty() {
+        config
+            .resolve(|c| c.doc_tests.as_ref())
+            .map(|(files, _)| files.iter().map(PathBuf::from).collect())
+            .unwrap_or_default()
+    } else {
+        args.files.clone()
+    };
+
+    if files.is_empty() {
+        ctx.ui
+            .warning("No markdown files given or configured in doc-tests")?;
+        return Ok(());
+    }
+
+    let tests = DocTest::collect(project.paths(), &files)?;
+    let world = ctx.world(&args.compile)?;
+
+    let reporter = Reporter::new(ctx.ui, &project, &world, false)
+        .with_quiet(ctx.args.global.output.quiet)
+        .with_format(ctx.args.global.output.format)
+        .with_sources(tests.iter().map(|test| test.source().clone()));
+
+    let mut failed = 0;
+    for test in &tests {
+        let result = run_doc_test(test, &world, args.compile.promote_warnings);
+
+        if result.is_fail() {
+            failed += 1;
+            reporter.report_test_fail(test.test(), &result, false)?;
+        } else {
+            reporter.report_test_pass(test.test(), &result)?;
+        }
+    }
+
+    if !ctx.args.global.output.quiet {
+        writeln!(
+            ctx.ui.stderr(),
+            "{failed} of {} doc {} failed",
+            tests.len(),
+            Term::simple("test").with(tests.len()),
+        )?;
+    }
+
+    if failed != 0 {
+        eyre::bail!(TestFailure);
+    }
+
+    Ok(())
+}
+
+/// Compiles the given doc test and returns its result.
+fn run_doc_test(test: &DocTest, world: &SystemWorld, promote_warnings: bool) -> TestResult {
+    let mut result = TestResult::new();
+    result.start();
+
+    let Warned { output, warnings } =
+        super::run::compile_only(test.source().clone(), world, promote_warnings);
+
+    result.set_warnings(warnings);
+    match output {
+        Ok(()) => result.set_passed_compilation(),
+        Err(err) => result.set_failed_test_compilation(err),
+    }
+
+    result.end();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use lib::_dev::fs::TempEnv;
+    use lib::project::Paths;
+
+    use super::*;
+    use crate::world::tests::world;
+
+    #[test]
+    fn test_run_doc_test() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file(
+                    "README.md",
+                    "\
+# Usage
+
+```typ
+#let add(a, b) = a + b
+#add(1, 2)
+```
+
+```typ
+#let add(a, b) = a +
+```
+",
+                )
+            },
+            |root| {
+                let tests = DocTest::collect(&Paths::new(root, None), &["README.md"]).unwrap();
+                let world = world(None);
+                let results: Vec<_> = tests
+                    .iter()
+                    .map(|test| {
+                        (
+                            test.test().id().as_str(),
+                            run_doc_test(test, &world, false).is_fail(),
+                        )
+                    })
+                    .collect();
+
+                assert_eq!(
+                    results,
+                    [
+                        ("doc/README-md/line-3", false),
+                        ("doc/README-md/line-8", true),
+                    ]
+                );
+            },
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use std::fmt::Display;
+use std::io::Write;
+
+use color_eyre::eyre;
+use lib::project::{Project, Vcs};
+use lib::test::{LoadReferenceError, Reference, Suite, Test};
+use termcolor::Color;
+
+use super::{Context, OperationFailure};
+use crate::ui;
+
+/// The padding used for the check annotations.
+const CHECK_ANNOT_PADDING: usize = 4;
+
+#[derive(clap::Args, Debug, Clone)]
+#[group(id = "doctor-args")]
+pub struct Args {}
+
+/// The outcome of a single check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Writes checks to stderr and keeps track of failures.
+struct Checklist<'c> {
+    ctx: &'c Context<'c>,
+    failed: bool,
+}
+
+impl Checklist<'_> {
+    fn check(&mut self, status: Status, message: impl Display) -> eyre::Result<()> {
+        let (header, color) = match status {
+            Status::Pass => ("ok", Color::Green),
+            Status::Warn => ("warn", Color::Yellow),
+            Status::Fail => ("fail", Color::Red),
+        };
+
+        self.failed |= status == Status::Fail;
+
+        let mut w = self.ctx.ui.stderr();
+        ui::write_annotated(&mut w, header, color, CHECK_ANNOT_PADDING, |w| {
+            writeln!(w, "{message}")
+        })?;
+
+        Ok(())
+    }
+}
+
+pub fn run(ctx: &mut Context, _args: &Args) -> eyre::Result<()> {
+    let ctx: &Context = ctx;
+    let mut list = Checklist { ctx, failed: false };
+
+    list.check(
+        Status::Pass,
+        format_args!(
+            "{} {} (typst {})",
+            lib::TOOL_NAME,
+            env!("CARGO_PKG_VERSION"),
+            crate::TYPST_VERSION,
+        ),
+    )?;
+
+    match Project::discover(ctx.root()?, ctx.args.global.root.is_some()) {
+        Ok(Some(project)) => check_project(&mut list, &project)?,
+        Ok(None) => list.check(Status::Fail, "No project found")?,
+        Err(err) => list.check(Status::Fail, format_args!("Couldn't read project: {err}"))?,
+    }
+
+    if list.failed {
+        eyre::bail!(OperationFailure);
+    }
+
+    Ok(())
+}
+
+fn check_project(list: &mut Checklist, project: &Project) -> eyre::Result<()> {
+    let paths = project.paths();
+
+    match project.manifest() {
+        Some(manifest) => list.check(
+            Status::Pass,
+            format_args!(
+                "Found manifest for {}:{}",
+                manifest.package.name, manifest.package.version,
+            ),
+        )?,
+        None => list.check(Status::Warn, "No manifest found")?,
+    }
+
+    if !paths.test_root().try_exists()? {
+        list.check(
+            Status::Warn,
+            format_args!("Test root '{}' doesn't exist", paths.test_root().display()),
+        )?;
+        return Ok(());
+    }
+
+    let suite = list.ctx.collect_all_tests(project)?;
+    list.check(
+        Status::Pass,
+        format_args!("Collected {} tests", suite.len()),
+    )?;
+
+    let orphaned = Suite::collect_orphaned_directories(paths)?;
+    if orphaned.is_empty() {
+        list.check(Status::Pass, "No orphaned directories")?;
+    }
+    for dir in orphaned {
+        list.check(
+            Status::Warn,
+            format_args!("Orphaned directory '{}' has no test script", dir.display()),
+        )?;
+    }
+
+    for test in suite.matched().values() {
+        check_references(list, project, test)?;
+    }
+
+    match project.vcs() {
+        Some(vcs) => {
+            let mut consistent = true;
+            for test in suite.matched().values() {
+                consistent &= check_vcs(list, project, vcs, test)?;
+            }
+
+            if consistent {
+                list.check(
+                    Status::Pass,
+                    format_args!("Test directories are correctly ignored in {vcs}"),
+                )?;
+            }
+        }
+        None => list.check(Status::Warn, "No VCS found")?,
+    }
+
+    Ok(())
+}
+
+fn check_references(list: &mut Checklist, project: &Project, test: &Test) -> eyre::Result<()> {
+    if !test.kind().is_persistent() {
+        return Ok(());
+    }
+
+    match Reference::load_persistent(project.paths(), test.id()) {
+        Ok(_) => {}
+        Err(LoadReferenceError::Empty(_)) => list.check(
+            Status::Warn,
+            format_args!("Test {} has no reference pages", test.id()),
+        )?,
+        Err(LoadReferenceError::Gapped { pages, .. }) => list.check(
+            Status::Fail,
+            format_args!(
+                "Test {} has missing reference pages, found only {pages:?}",
+                test.id()
+            ),
+        )?,
+        Err(LoadReferenceError::Load { error, .. }) => list.check(
+            Status::Fail,
+            format_args!("Couldn't load references of test {}: {error}", test.id()),
+        )?,
+        Err(err) => list.check(
+            Status::Fail,
+            format_args!("Couldn't load references of test {}: {err}", test.id()),
+        )?,
+    }
+
+    Ok(())
+}
+
+/// Returns whether the test's directories are consistently ignored.
+fn check_vcs(
+    list: &mut Checklist,
+    project: &Project,
+    vcs: &Vcs,
+    test: &Test,
+) -> eyre::Result<bool> {
+    let paths = project.paths();
+    let mut consistent = true;
+
+    let mut temporary = vec![
+        paths.test_out_dir(test.id()),
+        paths.test_diff_dir(test.id()),
+    ];
+    if test.kind().is_ephemeral() {
+        temporary.push(paths.test_ref_dir(test.id()));
+    }
+
+    for dir in temporary {
+        if dir.try_exists()? && !vcs.is_ignored_dir(&dir)? {
+            consistent = false;
+            list.check(
+                Status::Warn,
+                format_args!("Temporary directory '{}' is not ignored", dir.display()),
+            )?;
+        }
+    }
+
+    let ref_dir = paths.test_ref_dir(test.id());
+    if test.kind().is_persistent() && vcs.is_ignored_dir(&ref_dir)? {
+        consistent = false;
+        list.check(
+            Status::Fail,
+            format_args!("Reference directory '{}' is ignored", ref_dir.display()),
+        )?;
+    }
+
+    Ok(consistent)
+}
@@ -0,0 +1,139 @@
+use std::io::Write;
+
+use color_eyre::eyre;
+use lib::doc::render;
+use lib::project::{InitError, Vcs, VcsKind};
+use lib::test::Reference;
+use termcolor::Color;
+
+use super::{CompileArgs, Context, OperationFailure};
+use crate::{ui, DEFAULT_OPTIMIZE_OPTIONS};
+
+#[derive(clap::Args, Debug, Clone)]
+#[group(id = "init-args")]
+pub struct Args {
+    /// Which vcs to write ignore files for
+    ///
+    /// By default the vcs of the project is detected automatically, if none
+    /// is found no ignore files are written.
+    #[arg(long, value_enum, default_value = "auto")]
+    pub vcs: VcsChoice,
+
+    /// Compile the references of the example test instead of using the
+    /// bundled ones
+    ///
+    /// The bundled references may differ from the output of the typst
+    /// version in use.
+    #[arg(long)]
+    pub compile_default_ref: bool,
+
+    #[command(flatten)]
+    pub compile: CompileArgs,
+}
+
+/// The vcs to write ignore files for.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VcsChoice {
+    /// Use the vcs detected in the project.
+    Auto,
+
+    /// Write `.gitignore` files.
+    Git,
+
+    /// Write ignore patterns to the root `.hgignore` file.
+    #[value(alias = "hg")]
+    Mercurial,
+
+    /// Don't write any ignore files.
+    None,
+}
+
+pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
+    let project = ctx.init_project()?;
+    let paths = project.paths();
+    let root = paths.vcs_root().unwrap_or(paths.project_root());
+
+    let vcs = match args.vcs {
+        VcsChoice::Auto => project.vcs_or_null().clone(),
+        VcsChoice::Git => Vcs::new(root, VcsKind::Git),
+        VcsChoice::Mercurial => Vcs::new(root, VcsKind::Mercurial),
+        VcsChoice::None => Vcs::null(root),
+    };
+
+    let reference = if args.compile_default_ref {
+        let world = ctx.world(&args.compile)?;
+        Some(Reference::compile_default(
+            &world,
+            render::DEFAULT_PIXEL_PER_PT,
+            Some(Box::new(DEFAULT_OPTIMIZE_OPTIONS.clone())),
+        )?)
+    } else {
+        None
+    };
+
+    let test = match project.init_with(&vcs, reference) {
+        Ok(test) => test,
+        Err(InitError::AlreadyInitialized) => {
+            ctx.ui.error_with(|w| {
+                writeln!(
+                    w,
+                    "Test root '{}' already exists and is not empty",
+                    paths.test_root().display()
+                )
+            })?;
+            eyre::bail!(OperationFailure);
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let config = ctx.config(Some(&project))?;
+    if let Some((dirs, _)) = config.resolve(|c| c.init_ignore.as_ref()) {
+        project.ignore_dirs(&vcs, dirs)?;
+    }
+
+    let mut w = ctx.ui.stderr();
+    write!(w, "Initialized test suite with example test ")?;
+    ui::write_test_id(&mut w, test.id())?;
+    writeln!(w)?;
+
+    match vcs.kind() {
+        VcsKind::None => writeln!(w, "No ignore files were written")?,
+        _ => {
+            write!(w, "Temporary directories are ignored in ")?;
+            ui::write_colored(&mut w, Color::Cyan, |w| write!(w, "{vcs}"))?;
+            writeln!(w)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::cli::{Args as CliArgs, Command};
+
+    #[test]
+    fn test_vcs_arg() {
+        let args = CliArgs::try_parse_from(["tt", "init", "--vcs", "hg"]).unwrap();
+        let Command::Init(args) = args.cmd else {
+            panic!("expected init command");
+        };
+        assert_eq!(args.vcs, VcsChoice::Mercurial);
+
+        let args = CliArgs::try_parse_from(["tt", "init"]).unwrap();
+        let Command::Init(args) = args.cmd else {
+            panic!("expected init command");
+        };
+        assert_eq!(args.vcs, VcsChoice::Auto);
+        assert!(!args.compile_default_ref);
+
+        let args = CliArgs::try_parse_from(["tt", "init", "--compile-default-ref"]).unwrap();
+        let Command::Init(args) = args.cmd else {
+            panic!("expected init command");
+        };
+        assert!(args.compile_default_ref);
+    }
+}
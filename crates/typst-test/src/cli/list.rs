@@ -1,7 +1,9 @@
 use std::io::Write;
+use std::path::PathBuf;
 
 use color_eyre::eyre;
-use lib::test::Kind as TestKind;
+use lib::project::Paths;
+use lib::test::{Kind as TestKind, Suite, Test};
 use termcolor::Color;
 
 use super::{Context, FilterArgs};
@@ -16,28 +18,58 @@ pub struct Args {
     #[arg(long)]
     pub json: bool,
 
+    /// Only list tests which are marked as skipped, with their reasons
+    ///
+    /// This implies --no-implicit-skip.
+    #[arg(long)]
+    pub skipped: bool,
+
+    /// Print the paths of the scripts and directories of each test to stdout
+    ///
+    /// This includes the test script, the reference script or directory and
+    /// the output and difference directories, whether they exist or not.
+    #[arg(long, conflicts_with = "json")]
+    pub list_files: bool,
+
     #[command(flatten)]
     pub filter: FilterArgs,
 }
 
 pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
     let project = ctx.project()?;
-    let set = ctx.test_set(&args.filter)?;
+
+    // NOTE(tinger): skipped tests would be filtered out by the implicit skip
+    let set = if args.skipped {
+        ctx.test_set(&FilterArgs {
+            no_implicit_skip: true,
+            ..args.filter.clone()
+        })?
+    } else {
+        ctx.test_set(&args.filter)?
+    };
     let suite = ctx.collect_tests(&project, &set)?;
+    let tests = listed_tests(&suite, args.skipped);
 
     if args.json {
         serde_json::to_writer_pretty(
             ctx.ui.stdout(),
-            &suite
-                .matched()
-                .values()
-                .map(TestJson::new)
-                .collect::<Vec<_>>(),
+            &tests.iter().copied().map(TestJson::new).collect::<Vec<_>>(),
         )?;
 
         return Ok(());
     }
 
+    if args.list_files {
+        let mut w = ctx.ui.stdout();
+        for test in tests {
+            for path in test_files(project.paths(), test) {
+                writeln!(w, "{}", path.display())?;
+            }
+        }
+
+        return Ok(());
+    }
+
     let mut w = ctx.ui.stderr();
 
     ui::write_bold(&mut w, |w| writeln!(w, "Tests"))?;
@@ -46,24 +78,157 @@ pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
 
     // NOTE(tinger): max padding of 50 should be enough for most cases
     let pad = Ord::min(
-        suite
-            .matched()
-            .keys()
-            .map(|id| id.len())
+        tests
+            .iter()
+            .map(|test| test.id().len())
             .max()
             .unwrap_or(usize::MAX),
         50,
     );
 
-    for (id, test) in suite.matched() {
-        write!(w, "{: <pad$} ", id)?;
+    for test in tests {
+        write!(w, "{: <pad$} ", test.id())?;
         let color = match test.kind() {
             TestKind::Ephemeral => Color::Yellow,
             TestKind::Persistent => Color::Green,
             TestKind::CompileOnly => Color::Yellow,
         };
-        ui::write_bold_colored(w, color, |w| writeln!(w, "{}", test.kind().as_str()))?;
+        ui::write_bold_colored(w, color, |w| write!(w, "{}", test.kind().as_str()))?;
+        if test.is_skip() {
+            write!(w, " ")?;
+            ui::write_colored(w, Color::Magenta, |w| write!(w, "skipped"))?;
+            if let Some(reason) = test.skip_reason() {
+                write!(w, ": {reason}")?;
+            }
+        }
+        writeln!(w)?;
     }
 
     Ok(())
 }
+
+/// Returns the matched tests of the given suite which should be listed, if
+/// `skipped` is true, only those which are marked as skipped.
+fn listed_tests(suite: &Suite, skipped: bool) -> Vec<&Test> {
+    suite
+        .matched()
+        .values()
+        .filter(|test| !skipped || test.is_skip())
+        .collect()
+}
+
+/// Returns the paths of the scripts and directories the given test may use.
+fn test_files(paths: &Paths, test: &Test) -> Vec<PathBuf> {
+    let id = test.id();
+    let mut files = vec![test.script(paths)];
+
+    // NOTE(tinger): single-file tests have no directory to put anything in
+    if test.is_single_file() {
+        return files;
+    }
+
+    match test.kind() {
+        TestKind::Ephemeral => {
+            files.push(paths.test_ref_script(id));
+            files.push(paths.test_ref_dir(id));
+        }
+        TestKind::Persistent => files.push(paths.test_ref_dir(id)),
+        TestKind::CompileOnly => {}
+    }
+
+    files.push(paths.test_out_dir(id));
+    files.push(paths.test_diff_dir(id));
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use lib::_dev::fs::TempEnv;
+    use lib::test::Id;
+    use lib::test_set::{eval, TestSet};
+
+    use super::*;
+    use crate::runner::tests::{project, suite, test};
+
+    #[test]
+    fn test_listed_tests_skipped() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/a/test.typ", "/// [skip: flaky]\nHello")
+                    .setup_file("tests/b/test.typ", "Hello")
+                    .setup_file("tests/c/test.typ", "/// [skip]\nHello")
+            },
+            |root| {
+                let project = project(root);
+
+                let suite = suite(&project);
+                let ids = |skipped| {
+                    listed_tests(&suite, skipped)
+                        .into_iter()
+                        .map(|test| (test.id().as_str(), test.skip_reason()))
+                        .collect::<Vec<_>>()
+                };
+
+                assert_eq!(ids(false), [("a", Some("flaky")), ("b", None), ("c", None)]);
+                assert_eq!(ids(true), [("a", Some("flaky")), ("c", None)]);
+
+                // the implicit skip removes them from the matched tests entirely
+                let mut set = TestSet::new(eval::Context::empty(), eval::Set::built_in_all());
+                set.add_implicit_skip();
+                let suite = Suite::collect(project.paths(), &set).unwrap();
+                assert!(listed_tests(&suite, true).is_empty());
+            },
+        );
+    }
+
+    #[test]
+    fn test_test_files() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/compile-only/test.typ", "Hello")
+                    .setup_file("tests/ephemeral/test.typ", "Hello")
+                    .setup_file("tests/ephemeral/ref.typ", "Hello")
+                    .setup_file("tests/persistent/test.typ", "Hello")
+                    .setup_file("tests/persistent/ref/.keep", "")
+                    .setup_file("tests/single.typ", "Hello")
+            },
+            |root| {
+                let project = project(root);
+
+                let paths = project.paths();
+                let files = |id| test_files(paths, &test(&project, id));
+                let id = |id| Id::new(id).unwrap();
+
+                assert_eq!(
+                    files("compile-only"),
+                    [
+                        paths.test_script(&id("compile-only")),
+                        paths.test_out_dir(&id("compile-only")),
+                        paths.test_diff_dir(&id("compile-only")),
+                    ]
+                );
+                assert_eq!(
+                    files("ephemeral"),
+                    [
+                        paths.test_script(&id("ephemeral")),
+                        paths.test_ref_script(&id("ephemeral")),
+                        paths.test_ref_dir(&id("ephemeral")),
+                        paths.test_out_dir(&id("ephemeral")),
+                        paths.test_diff_dir(&id("ephemeral")),
+                    ]
+                );
+                assert_eq!(
+                    files("persistent"),
+                    [
+                        paths.test_script(&id("persistent")),
+                        paths.test_ref_dir(&id("persistent")),
+                        paths.test_out_dir(&id("persistent")),
+                        paths.test_diff_dir(&id("persistent")),
+                    ]
+                );
+                assert_eq!(files("single"), [paths.test_file_script(&id("single"))]);
+                assert!(files("persistent").iter().all(|path| path.is_absolute()));
+            },
+        );
+    }
+}
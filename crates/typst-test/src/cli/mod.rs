@@ -1,6 +1,9 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::AtomicBool;
+use std::time::Duration;
 use std::{env, io};
 
 use chrono::{DateTime, Utc};
@@ -8,23 +11,38 @@ use clap::ColorChoice;
 use color_eyre::eyre;
 use color_eyre::eyre::WrapErr;
 use lib::config::{Config, ConfigLayer};
+use lib::doc::compare::Strategy;
+use lib::doc::render;
 use lib::project::Project;
+use lib::runner::hook::Hooks;
+use lib::runner::{Action, ExportMetadata, RunConfig};
+use lib::stdx::fmt::Term;
 use lib::test::{Id, Suite};
-use lib::test_set::{self, eval, Error as TestSetError, TestSet};
-use termcolor::Color;
+use lib::test_set::{self, eval, Error as TestSetError, TestSet, TestSetExpr};
+use termcolor::{Color, WriteColor};
 use thiserror::Error;
+use typst::foundations::Dict;
 
+use crate::json::ArtifactMetadataJson;
 use crate::kit;
+use crate::report::Format;
 use crate::ui::{self, Ui};
 use crate::world::SystemWorld;
+use crate::DEFAULT_OPTIMIZE_OPTIONS;
 
 pub mod add;
+pub mod config;
+pub mod doc_test;
+pub mod doctor;
+pub mod init;
 pub mod list;
+pub mod prune;
 pub mod remove;
 pub mod run;
 pub mod status;
 pub mod update;
 pub mod util;
+pub mod verify;
 
 /// Whether we received a signal we can gracefully exit from.
 pub static CANCELLED: AtomicBool = AtomicBool::new(false);
@@ -60,15 +78,71 @@ pub struct Context<'a> {
 
     /// The terminal ui.
     pub ui: &'a Ui,
+
+    /// Whether the ambiguous project root hint was already shown.
+    hinted_ambiguous_root: Cell<bool>,
 }
 
 impl<'a> Context<'a> {
     pub fn new(args: &'a Args, ui: &'a Ui) -> Self {
-        Self { args, ui }
+        Self {
+            args,
+            ui,
+            hinted_ambiguous_root: Cell::new(false),
+        }
     }
 }
 
 impl Context<'_> {
+    pub fn warning_orphaned_directories(&self, project: &Project) -> eyre::Result<()> {
+        let orphaned = Suite::collect_orphaned_directories(project.paths())?;
+        if orphaned.is_empty() {
+            return Ok(());
+        }
+
+        self.ui.warning_hinted_with(
+            |w| {
+                writeln!(
+                    w,
+                    "Found {} orphaned {}",
+                    orphaned.len(),
+                    Term::irregular("directory", "directories").with(orphaned.len()),
+                )
+            },
+            |w| {
+                write!(w, "You can remove them using ")?;
+                ui::write_colored(w, Color::Cyan, |w| write!(w, "tt prune"))?;
+                writeln!(w)
+            },
+        )?;
+
+        Ok(())
+    }
+
+    /// Hints at the chosen project root if the nearest enclosing package was
+    /// passed over for an ancestor, this is only shown once.
+    pub fn hint_ambiguous_root(&self, project: &Project) -> io::Result<()> {
+        if self.hinted_ambiguous_root.replace(true) {
+            return Ok(());
+        }
+
+        let candidates = project.root_candidates();
+        self.ui.hint_with(|w| {
+            writeln!(
+                w,
+                "Using project root '{}', the nearest package has no test root, found {} enclosing packages:",
+                project.paths().project_root().display(),
+                candidates.len(),
+            )?;
+            for candidate in candidates {
+                writeln!(w, "{}", candidate.display())?;
+            }
+            write!(w, "You can pass a different project root using ")?;
+            ui::write_colored(w, Color::Cyan, |w| write!(w, "--root <path>"))?;
+            writeln!(w)
+        })
+    }
+
     pub fn error_aborted(&self) -> io::Result<()> {
         self.ui.error_with(|w| writeln!(w, "Operation aborted"))
     }
@@ -89,13 +163,9 @@ impl Context<'_> {
         )
     }
 
-    pub fn error_test_set_failure(&self, error: TestSetError) -> io::Result<()> {
-        self.ui.error_with(|w| {
-            writeln!(
-                w,
-                "Couldn't parse or evaluate test set expression:\n{error:?}",
-            )
-        })
+    pub fn error_test_set_failure(&self, error: TestSetError, expr: &str) -> io::Result<()> {
+        self.ui
+            .error_with(|w| write_test_set_failure(w, &error, expr))
     }
 
     pub fn error_test_already_exists(&self, id: &Id) -> io::Result<()> {
@@ -155,24 +225,55 @@ impl Context<'_> {
         })
     }
 
-    /// Resolve the user and override config layers.
-    pub fn config(&self) -> eyre::Result<Config> {
+    /// Resolve the user and override config layers, as well as the project
+    /// and manifest layers if a project is given.
+    pub fn config(&self, project: Option<&Project>) -> eyre::Result<Config> {
         // TODO(tinger): cli/envar overrides go here
 
         let mut config = Config::new(None);
         config.user = ConfigLayer::collect_user()?;
 
+        if let Some(project) = project {
+            config.project = ConfigLayer::collect_project(project.paths().project_root())?;
+            if let Some(manifest) = project.manifest() {
+                config.manifest = ConfigLayer::from_manifest(manifest)?;
+            }
+        }
+
         Ok(config)
     }
 
     /// Discover the current and ensure it is initialized.
     pub fn project(&self) -> eyre::Result<Project> {
+        self.discover_project(true)
+    }
+
+    /// Discover the project which is about to be initialized, unlike
+    /// [`Context::project`] this always chooses the nearest package.
+    pub fn init_project(&self) -> eyre::Result<Project> {
+        self.discover_project(false)
+    }
+
+    fn discover_project(&self, fallback: bool) -> eyre::Result<Project> {
         let root = self.root()?;
+        let is_project_root = self.args.global.root.is_some();
+
+        let project = if fallback {
+            Project::discover(root, is_project_root)?
+        } else {
+            Project::discover_nearest(root, is_project_root)?
+        };
 
-        let Some(project) = Project::discover(root, self.args.global.root.is_some())? else {
+        let Some(mut project) = project else {
             self.error_no_project()?;
             eyre::bail!(OperationFailure);
         };
+        project.configure(&self.config(Some(&project))?);
+
+        tracing::info!(root = ?project.paths().project_root(), "chose project root");
+        if project.is_fallback_root() {
+            self.hint_ambiguous_root(&project)?;
+        }
 
         Ok(project)
     }
@@ -193,17 +294,26 @@ impl Context<'_> {
                 .map(|(a, b)| eval::Set::built_in_union(a, b, tests))
                 .unwrap_or_default();
 
-            Ok(TestSet::new(eval::Context::empty(), set))
+            let mut set = TestSet::new(eval::Context::empty(), set);
+            if let Some(only) = filter.only {
+                set.add_intersection(only.set());
+            }
+
+            Ok(set)
         } else {
-            let ctx = eval::Context::with_built_ins();
+            let ctx = self.test_set_context()?;
             let mut set = match TestSet::parse_and_evaluate(ctx, &filter.expression) {
                 Ok(set) => set,
                 Err(err) => {
-                    self.error_test_set_failure(err)?;
+                    self.error_test_set_failure(err, &filter.expression)?;
                     eyre::bail!(OperationFailure);
                 }
             };
 
+            if let Some(only) = filter.only {
+                set.add_intersection(only.set());
+            }
+
             if !filter.no_implicit_skip {
                 set.add_implicit_skip();
             }
@@ -212,6 +322,51 @@ impl Context<'_> {
         }
     }
 
+    /// Create the evaluation context for test set expressions, this contains
+    /// the built-ins and the test set aliases merged across all config layers.
+    /// Outside of a project only the aliases of the user config are available.
+    pub fn test_set_context(&self) -> eyre::Result<eval::Context> {
+        let mut ctx = eval::Context::with_built_ins();
+
+        let project = Project::discover(self.root()?, self.args.global.root.is_some())?;
+        let config = self.config(project.as_ref())?;
+        let aliases = config.test_sets();
+        if aliases.is_empty() {
+            return Ok(ctx);
+        }
+
+        let mut parsed = BTreeMap::new();
+        for (name, expr) in aliases {
+            let Ok(id) = test_set::Id::new(name) else {
+                self.ui.error_with(|w| {
+                    writeln!(w, "Test set alias '{name}' is not a valid identifier")
+                })?;
+                eyre::bail!(OperationFailure);
+            };
+
+            match TestSetExpr::parse(expr) {
+                Ok(expr) => {
+                    parsed.insert(id, expr);
+                }
+                Err(err) => {
+                    self.ui.error_with(|w| {
+                        writeln!(w, "In test set alias '{name}':")?;
+                        write_test_set_failure(w, &err, expr)
+                    })?;
+                    eyre::bail!(OperationFailure);
+                }
+            }
+        }
+
+        if let Err(err) = ctx.bind_aliases(&parsed) {
+            self.ui
+                .error_with(|w| writeln!(w, "Couldn't evaluate test set aliases: {err}"))?;
+            eyre::bail!(OperationFailure);
+        }
+
+        Ok(ctx)
+    }
+
     /// Collect and filter tests for the given project.
     pub fn collect_tests(&self, project: &Project, set: &TestSet) -> eyre::Result<Suite> {
         if !util::migrate::collect_old_structure(project.paths(), "self")?.is_empty() {
@@ -219,7 +374,8 @@ impl Context<'_> {
             eyre::bail!(OperationFailure);
         }
 
-        let suite = Suite::collect(project.paths(), set)?;
+        let mut suite = Suite::collect(project.paths(), set)?;
+        exclude_template(project, &self.config(Some(project))?, &mut suite);
 
         Ok(suite)
     }
@@ -242,6 +398,88 @@ impl Context<'_> {
             compile,
         )
     }
+
+    /// Create the runner config shared by all commands which run tests, the
+    /// remaining options are disabled and can be set by the caller.
+    pub fn runner_config(
+        &self,
+        config: &Config,
+        compile: &CompileArgs,
+        run: &RunArgs,
+        action: Action,
+    ) -> eyre::Result<RunConfig<'static>> {
+        Ok(RunConfig {
+            promote_warnings: compile.promote_warnings,
+            hermetic_fonts: self.args.global.fonts.hermetic_fonts,
+            warn_font_fallback: compile.warn_font_fallback,
+            error_on_font_fallback: compile.error_on_font_fallback,
+            optimize: None,
+            export_metadata: None,
+            fail_fast: !run.no_fail_fast,
+            timeout: run.timeout_global,
+            pixel_per_pt: render::DEFAULT_PIXEL_PER_PT,
+            trim_whitespace: false,
+            compare_grayscale: false,
+            object_store: config
+                .resolve(|c| c.object_store.as_ref())
+                .is_some_and(|(v, _)| *v),
+            no_references_ok: false,
+            diff_only_on_failure: false,
+            no_diff: false,
+            low_memory: false,
+            keep_going_on_io_errors: run.keep_going_on_io_errors,
+            clear_temporary_directories: true,
+            hooks: Hooks::from_config(config)?,
+            action,
+            inputs: Dict::new(),
+            cancellation: Some(&CANCELLED),
+        })
+    }
+}
+
+/// Removes the tests within the template directory of a template package from
+/// the given suite unless disabled in the config.
+fn exclude_template(project: &Project, config: &Config, suite: &mut Suite) {
+    let exclude = config
+        .resolve(|c| c.exclude_template.as_ref())
+        .map(|(exclude, _)| *exclude)
+        .unwrap_or(true);
+
+    if !exclude {
+        return;
+    }
+
+    let Some(dir) = project.template_dir() else {
+        return;
+    };
+
+    for id in suite.exclude_dir(project.paths(), &dir) {
+        tracing::debug!(%id, "excluded template test");
+    }
+}
+
+/// Writes a test set error, parse errors include a snippet of the expression
+/// pointing at the error location.
+fn write_test_set_failure<W: WriteColor + ?Sized>(
+    w: &mut W,
+    error: &TestSetError,
+    expr: &str,
+) -> io::Result<()> {
+    let Some(span) = error.span() else {
+        return writeln!(w, "Couldn't evaluate test set expression: {error}");
+    };
+
+    let start = span.start.min(expr.len());
+    let line_start = expr[..start].rfind('\n').map(|idx| idx + 1).unwrap_or(0);
+    let column = expr[line_start..start].chars().count() + 1;
+
+    write!(w, "Couldn't parse test set expression at column {column}")?;
+    match error.detail() {
+        Some(detail) => writeln!(w, ": {detail}")?,
+        None => writeln!(w)?,
+    }
+
+    ui::write_snippet(w, expr, span)
 }
 
 macro_rules! ansi {
@@ -319,6 +557,35 @@ pub struct FilterArgs {
     /// implies `--no-implicit-skip`.
     #[arg(required = false, conflicts_with = "expression")]
     pub tests: Vec<String>,
+
+    /// Only operate on tests of the given kind
+    ///
+    /// Equivalent to wrapping the test set expression in `(...) & kind(...)`.
+    #[arg(long, value_name = "KIND")]
+    pub only: Option<OnlyKind>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum OnlyKind {
+    /// Tests with ephemeral references.
+    Ephemeral,
+
+    /// Tests with persistent references.
+    Persistent,
+
+    /// Tests without references.
+    CompileOnly,
+}
+
+impl OnlyKind {
+    /// The built-in test set which contains all tests of this kind.
+    pub fn set(self) -> eval::Set {
+        match self {
+            OnlyKind::Ephemeral => eval::Set::built_in_ephemeral(),
+            OnlyKind::Persistent => eval::Set::built_in_persistent(),
+            OnlyKind::CompileOnly => eval::Set::built_in_compile_only(),
+        }
+    }
 }
 
 fn parse_source_date_epoch(raw: &str) -> Result<DateTime<Utc>, String> {
@@ -328,11 +595,46 @@ fn parse_source_date_epoch(raw: &str) -> Result<DateTime<Utc>, String> {
     DateTime::from_timestamp(timestamp, 0).ok_or_else(|| "timestamp out of range".to_string())
 }
 
+fn parse_duration(raw: &str) -> Result<Duration, String> {
+    let raw = raw.trim();
+    let split = raw
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(raw.len());
+    let (value, unit) = raw.split_at(split);
+
+    let value: f64 = value
+        .parse()
+        .map_err(|err| format!("duration must start with a number ({err})"))?;
+
+    let seconds = match unit.trim() {
+        "ms" => value / 1000.0,
+        "" | "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 60.0 * 60.0,
+        unit => return Err(format!("unknown duration unit {unit:?}")),
+    };
+
+    Duration::try_from_secs_f64(seconds).map_err(|err| err.to_string())
+}
+
+fn parse_input_pair(raw: &str) -> Result<(String, String), String> {
+    let (key, val) = raw
+        .split_once('=')
+        .ok_or("input must be a key and a value separated by an equal sign")?;
+
+    if key.is_empty() {
+        return Err("the key was missing or empty".to_owned());
+    }
+
+    Ok((key.to_owned(), val.to_owned()))
+}
+
 #[derive(clap::Args, Debug, Clone)]
 pub struct CompileArgs {
     /// The timestamp used for compilation.
     ///
-    /// For more information, see
+    /// Fixes the date returned by `datetime.today()`, which is interpreted as
+    /// UTC unless an offset is given. For more information, see
     /// <https://reproducible-builds.org/specs/source-date-epoch/>.
     #[arg(
         long,
@@ -344,9 +646,28 @@ pub struct CompileArgs {
     )]
     pub now: Option<DateTime<Utc>>,
 
+    /// Add a string key-value pair visible through `sys.inputs` (can be
+    /// repeated)
+    #[arg(
+        long = "input",
+        value_name = "KEY=VALUE",
+        value_parser = parse_input_pair,
+        global = true,
+    )]
+    pub inputs: Vec<(String, String)>,
+
     /// Promote warnings to errors
     #[arg(long, global = true)]
     pub promote_warnings: bool,
+
+    /// Warn about characters which were shaped with a fallback font and add
+    /// the fonts which were actually used to font fallback warnings
+    #[arg(long, global = true)]
+    pub warn_font_fallback: bool,
+
+    /// Promote font fallback warnings to errors
+    #[arg(long, global = true)]
+    pub error_on_font_fallback: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
@@ -384,6 +705,32 @@ pub struct ExportArgs {
     /// Whether to skip optimizing reference images
     #[arg(long, global = true)]
     pub no_optimize_references: bool,
+
+    /// Whether to write a JSON sidecar with the render settings next to
+    /// exported output documents
+    #[arg(long, global = true)]
+    pub export_metadata: bool,
+
+    /// Whether to skip rendering and saving difference documents entirely
+    ///
+    /// Failed comparisons still report which pages differed.
+    #[arg(long, global = true)]
+    pub no_diff: bool,
+}
+
+impl ExportArgs {
+    /// The options to optimize reference documents with, if they are
+    /// optimized.
+    pub fn optimize_options(&self) -> Option<&'static oxipng::Options> {
+        (!self.no_optimize_references).then_some(&*DEFAULT_OPTIMIZE_OPTIONS)
+    }
+
+    /// Serializes the metadata sidecar of exported output documents, if it is
+    /// exported.
+    pub fn export_metadata(&self) -> Option<ExportMetadata> {
+        self.export_metadata
+            .then_some(ArtifactMetadataJson::export as ExportMetadata)
+    }
 }
 
 #[derive(clap::Args, Debug, Clone)]
@@ -392,16 +739,66 @@ pub struct CompareArgs {
     ///
     /// If a single channel (red/green/blue/alpha component) of a pixel differs
     /// by this much between reference and output the pixel is counted as a
-    /// deviation.
-    #[arg(long, default_value_t = 0, global = true)]
-    pub max_delta: u8,
+    /// deviation. Defaults to the configured value or 0.
+    #[arg(long, global = true)]
+    pub max_delta: Option<u8>,
 
     /// The maximum deviation per reference
     ///
     /// If a reference and output image have more than the given deviations it's
-    /// counted as a failure.
-    #[arg(long, default_value_t = 0, global = true)]
-    pub max_deviation: usize,
+    /// counted as a failure. Defaults to the configured value or 0.
+    #[arg(long, global = true)]
+    pub max_deviation: Option<usize>,
+
+    /// The maximum distance in pixels by which content may be shifted
+    ///
+    /// A deviating pixel is not counted if a matching pixel is found within
+    /// this distance in the other page, this tolerates subpixel layout jitter.
+    /// Defaults to the configured value or 0.
+    #[arg(long, global = true, value_name = "PX")]
+    pub shift_tolerance: Option<u32>,
+
+    /// Crop uniformly colored borders before comparing
+    ///
+    /// Pages which only differ in the whitespace surrounding their content
+    /// compare equal, this is useful if the page size changed, but the content
+    /// did not.
+    #[arg(long, global = true)]
+    pub trim_whitespace: bool,
+
+    /// Convert pages to grayscale before comparing
+    ///
+    /// Pages which only differ in color but not in luminance compare equal,
+    /// this is useful to test layout independent of colors.
+    #[arg(long, global = true)]
+    pub compare_grayscale: bool,
+}
+
+impl CompareArgs {
+    /// Resolves the comparison strategy, explicitly passed flags take
+    /// precedence over configured values, which take precedence over the
+    /// defaults.
+    pub fn strategy(&self, config: &Config) -> Strategy {
+        Strategy::new(
+            self.max_delta
+                .or_else(|| config.resolve(|c| c.max_delta.as_ref()).map(|(v, _)| *v))
+                .unwrap_or(0),
+            self.max_deviation
+                .or_else(|| {
+                    config
+                        .resolve(|c| c.max_deviation.as_ref())
+                        .map(|(v, _)| *v)
+                })
+                .unwrap_or(0),
+            self.shift_tolerance
+                .or_else(|| {
+                    config
+                        .resolve(|c| c.shift_tolerance.as_ref())
+                        .map(|(v, _)| *v)
+                })
+                .unwrap_or(0),
+        )
+    }
 }
 
 #[derive(clap::Args, Debug, Clone)]
@@ -413,6 +810,26 @@ pub struct RunArgs {
     /// failure has been detected.
     #[arg(long, global = true)]
     pub no_fail_fast: bool,
+
+    /// Stop running new tests once the whole run took longer than this
+    ///
+    /// Accepts a number of seconds, optionally followed by one of the units
+    /// `ms`, `s`, `m` or `h`. Tests which were not run are reported and fail
+    /// the run.
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        global = true,
+    )]
+    pub timeout_global: Option<Duration>,
+
+    /// Record io errors of individual tests as their failure and keep going
+    ///
+    /// By default an io error, like a transient permission error while
+    /// preparing a test, aborts the whole run.
+    #[arg(long, global = true)]
+    pub keep_going_on_io_errors: bool,
 }
 
 #[derive(clap::Args, Debug, Clone)]
@@ -421,6 +838,14 @@ pub struct FontArgs {
     #[arg(long, global = true)]
     pub ignore_system_fonts: bool,
 
+    /// Use only embedded fonts and those in font paths and fail tests which
+    /// use unavailable font families
+    ///
+    /// This implies --ignore-system-fonts and makes test runs independent of
+    /// the fonts installed on the host.
+    #[arg(long, global = true)]
+    pub hermetic_fonts: bool,
+
     /// Add a directory to read fonts from (can be repeated)
     #[arg(
         long = "font-path",
@@ -470,6 +895,34 @@ pub struct OutputArgs {
     /// corresponds to the log levels ERROR, WARN, INFO, DEBUG, TRACE.
     #[arg(long, short, action = clap::ArgAction::Count, global = true)]
     pub verbose: u8,
+
+    /// Don't report individual tests, only the summary
+    ///
+    /// This does not affect the exit code.
+    #[arg(long, short, global = true)]
+    pub quiet: bool,
+
+    /// Don't print anything, only report the outcome through the exit code
+    ///
+    /// Unlike --quiet, this also omits the summary and errors, it is intended
+    /// for git hooks.
+    #[arg(long, global = true)]
+    pub silent: bool,
+
+    /// The format in which to report test results
+    ///
+    /// The plain format prints one uncolored line per test to stdout, such as
+    /// `PASS <id>`, `FAIL <id> <reason>` or `SKIP <id>`. The json-lines
+    /// format prints one JSON object per finished test to stdout as soon as
+    /// it finished, followed by a summary object.
+    #[arg(long, value_enum, default_value = "human", global = true)]
+    pub format: Format,
+
+    /// Emit GitHub Actions annotations for test failures on stdout
+    ///
+    /// These show up inline in the workflow summary and pull request diffs.
+    #[arg(long, global = true)]
+    pub github_annotations: bool,
 }
 
 /// Run and manage tests for typst projects
@@ -502,6 +955,29 @@ pub enum Command {
     #[command()]
     Update(update::Args),
 
+    /// Check that the stored references of persistent tests are up to date
+    ///
+    /// Compiles and compares all persistent tests without updating or
+    /// exporting anything, this fails if any references are out of date. The
+    /// output and difference directories are left untouched, but hooks are
+    /// still run.
+    #[command()]
+    Verify(verify::Args),
+
+    /// Compile the typst examples in markdown files
+    ///
+    /// Extracts `typ` and `typst` code fences from the given or configured
+    /// markdown files and compiles each as a compile-only test.
+    #[command()]
+    DocTest(doc_test::Args),
+
+    /// Initialize the test suite of the current project
+    ///
+    /// Creates the test root with an example test and ignores its temporary
+    /// directories in the project's vcs.
+    #[command()]
+    Init(init::Args),
+
     /// Add a new test
     ///
     /// The default test simply contains `Hello World`, if a
@@ -513,6 +989,19 @@ pub enum Command {
     #[command(visible_alias = "rm")]
     Remove(remove::Args),
 
+    /// Remove orphaned reference, output and difference directories and
+    /// unreferenced objects
+    #[command()]
+    Prune(prune::Args),
+
+    /// Inspect the configuration
+    #[command()]
+    Config(config::Args),
+
+    /// Check the environment and project for common problems
+    #[command()]
+    Doctor(doctor::Args),
+
     /// Utility commands
     #[command()]
     Util(util::Args),
@@ -521,13 +1010,290 @@ pub enum Command {
 impl Command {
     pub fn run(&self, ctx: &mut Context) -> eyre::Result<()> {
         match self {
+            Command::Init(args) => init::run(ctx, args),
             Command::Add(args) => add::run(ctx, args),
             Command::Remove(args) => remove::run(ctx, args),
             Command::Status(args) => status::run(ctx, args),
             Command::List(args) => list::run(ctx, args),
             Command::Update(args) => update::run(ctx, args),
             Command::Run(args) => run::run(ctx, args),
+            Command::Verify(args) => verify::run(ctx, args),
+            Command::DocTest(args) => doc_test::run(ctx, args),
+            Command::Prune(args) => prune::run(ctx, args),
+            Command::Config(args) => args.cmd.run(ctx),
+            Command::Doctor(args) => doctor::run(ctx, args),
             Command::Util(args) => args.cmd.run(ctx),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use lib::_dev::fs::TempEnv;
+    use termcolor::NoColor;
+
+    use super::*;
+    use crate::runner::tests::{project, suite};
+
+    #[test]
+    fn test_test_set_failure_column() {
+        let expr = "a | (b & c";
+        let error = TestSet::parse_and_evaluate(eval::Context::with_built_ins(), expr).unwrap_err();
+
+        let mut w = NoColor::new(vec![]);
+        write_test_set_failure(&mut w, &error, expr).unwrap();
+
+        let w = String::from_utf8(w.into_inner()).unwrap();
+        assert!(w.starts_with("Couldn't parse test set expression at column 11"));
+        assert!(w.ends_with("a | (b & c\n          ^\n"));
+    }
+
+    #[test]
+    fn test_quiet() {
+        let args = Args::try_parse_from(["tt", "run", "--quiet"]).unwrap();
+        assert!(args.global.output.quiet);
+
+        let args = Args::try_parse_from(["tt", "-q", "update"]).unwrap();
+        assert!(args.global.output.quiet);
+
+        let args = Args::try_parse_from(["tt", "run"]).unwrap();
+        assert!(!args.global.output.quiet);
+    }
+
+    #[test]
+    fn test_inputs() {
+        let args = Args::try_parse_from(["tt", "run", "--input", "a=1", "--input", "b = x=y"]);
+        let Command::Run(run) = args.unwrap().cmd else {
+            unreachable!();
+        };
+
+        assert_eq!(
+            run.compile.inputs,
+            [("a".into(), "1".into()), ("b ".into(), " x=y".into())],
+        );
+
+        assert!(Args::try_parse_from(["tt", "run", "--input", "a"]).is_err());
+        assert!(Args::try_parse_from(["tt", "run", "--input", "=a"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("s").is_err());
+    }
+
+    #[test]
+    fn test_parse_input_pair() {
+        let pair = |key: &str, val: &str| Ok((key.to_owned(), val.to_owned()));
+
+        assert_eq!(parse_input_pair("key=value"), pair("key", "value"));
+        assert_eq!(parse_input_pair("key="), pair("key", ""));
+        assert_eq!(parse_input_pair("url=a?b=c"), pair("url", "a?b=c"));
+        assert_eq!(parse_input_pair(" key = value "), pair(" key ", " value "));
+
+        assert!(parse_input_pair("key").is_err());
+        assert!(parse_input_pair("=value").is_err());
+    }
+
+    #[test]
+    fn test_compare_strategy_precedence() {
+        fn strategy(args: &[&str], config: &Config) -> Strategy {
+            let mut argv = vec!["tt", "run"];
+            argv.extend_from_slice(args);
+
+            let args = Args::try_parse_from(argv).unwrap();
+            let Command::Run(run) = args.cmd else {
+                unreachable!();
+            };
+
+            run.compare.strategy(config)
+        }
+
+        let mut config = Config::new(None);
+        assert_eq!(strategy(&[], &config), Strategy::default());
+
+        config.project = Some(ConfigLayer {
+            max_delta: Some(2),
+            max_deviation: Some(50),
+            ..Default::default()
+        });
+        assert_eq!(
+            strategy(&[], &config),
+            Strategy::Simple {
+                max_delta: 2,
+                max_deviation: 50,
+            },
+        );
+
+        assert_eq!(
+            strategy(&["--max-delta", "5"], &config),
+            Strategy::Simple {
+                max_delta: 5,
+                max_deviation: 50,
+            },
+        );
+
+        assert_eq!(
+            strategy(&["--shift-tolerance", "1"], &config),
+            Strategy::Shift {
+                max_delta: 2,
+                max_deviation: 50,
+                radius: 1,
+            },
+        );
+    }
+
+    #[test]
+    fn test_exclude_template() {
+        const MANIFEST: &str = r#"
+            [package]
+            name = "template"
+            version = "0.1.0"
+            entrypoint = "lib.typ"
+
+            [template]
+            path = "tests/template"
+            entrypoint = "main.typ"
+        "#;
+
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file(lib::project::MANIFEST_FILE, MANIFEST)
+                    .setup_file("tests/template/test.typ", "Hello")
+                    .setup_file("tests/regular/test.typ", "Hello")
+            },
+            |root| {
+                // NOTE(tinger): discovery reads and parses the manifest for us
+                let project = Project::discover(root, false).unwrap().unwrap();
+                let ids = |suite: &Suite| {
+                    suite
+                        .to_entries()
+                        .into_keys()
+                        .map(|id| id.as_str().to_owned())
+                        .collect::<Vec<_>>()
+                };
+
+                let mut excluded = suite(&project);
+                exclude_template(&project, &Config::new(None), &mut excluded);
+                assert_eq!(ids(&excluded), ["regular"],);
+
+                let mut config = Config::new(None);
+                config.project = Some(ConfigLayer {
+                    exclude_template: Some(false),
+                    ..Default::default()
+                });
+
+                let mut included = suite(&project);
+                exclude_template(&project, &config, &mut included);
+                assert_eq!(ids(&included), ["regular", "template"],);
+            },
+        );
+    }
+
+    #[test]
+    fn test_only_kind() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/ephemeral/test.typ", "Hello")
+                    .setup_file("tests/ephemeral/ref.typ", "Hello")
+                    .setup_file("tests/persistent/test.typ", "Hello")
+                    .setup_file("tests/persistent/ref/.keep", "")
+                    .setup_file("tests/compile-only/test.typ", "Hello")
+            },
+            |root| {
+                let project = project(root);
+                let ui = Ui::new(ColorChoice::Never, ColorChoice::Never);
+                let root = root.to_string_lossy().into_owned();
+                let matched = |cli: &[&str]| {
+                    let args = Args::try_parse_from(
+                        ["tt", "--root", &root, "run"].iter().chain(cli).copied(),
+                    )
+                    .unwrap();
+                    let Command::Run(run) = &args.cmd else {
+                        unreachable!();
+                    };
+
+                    let ctx = Context::new(&args, &ui);
+                    let set = ctx.test_set(&run.filter).unwrap();
+                    Suite::collect(project.paths(), &set)
+                        .unwrap()
+                        .matched()
+                        .keys()
+                        .map(|id| id.as_str().to_owned())
+                        .collect::<Vec<_>>()
+                };
+
+                assert_eq!(matched(&["--only", "persistent"]), ["persistent"]);
+                assert_eq!(
+                    matched(&["--only", "persistent"]),
+                    matched(&["--expression", "all() & persistent()"]),
+                );
+                assert_eq!(
+                    matched(&["--only", "compile-only", "ephemeral"]),
+                    Vec::<String>::new()
+                );
+                assert_eq!(
+                    matched(&["--only", "ephemeral", "ephemeral"]),
+                    ["ephemeral"]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn test_test_set_context_merged() {
+        const MANIFEST: &str = r#"
+            [package]
+            name = "aliases"
+            version = "0.1.0"
+            entrypoint = "lib.typ"
+
+            [tool.typst-test.test-sets]
+            smoke = "e:'a'"
+            slow = "e:'a'"
+        "#;
+
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file(lib::project::MANIFEST_FILE, MANIFEST)
+                    .setup_file("typst-test.toml", "[test-sets]\nsmoke = \"e:'b'\"\n")
+                    .setup_file("tests/a/test.typ", "Hello")
+                    .setup_file("tests/b/test.typ", "Hello")
+            },
+            |root| {
+                let project = project(root);
+                let ui = Ui::new(ColorChoice::Never, ColorChoice::Never);
+                let root = root.to_string_lossy().into_owned();
+                let matched = |expr: &str| {
+                    let args =
+                        Args::try_parse_from(["tt", "--root", &root, "run", "-e", expr]).unwrap();
+                    let Command::Run(run) = &args.cmd else {
+                        unreachable!();
+                    };
+
+                    let ctx = Context::new(&args, &ui);
+                    let set = ctx.test_set(&run.filter).unwrap();
+                    Suite::collect(project.paths(), &set)
+                        .unwrap()
+                        .matched()
+                        .keys()
+                        .map(|id| id.as_str().to_owned())
+                        .collect::<Vec<_>>()
+                };
+
+                // the project config overrides `smoke`, but `slow` is still found in
+                // the manifest
+                assert_eq!(matched("smoke"), ["b"]);
+                assert_eq!(matched("slow"), ["a"]);
+            },
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use std::io::Write;
+
+use color_eyre::eyre;
+use lib::doc::store::ObjectStore;
+use lib::stdx::fmt::Term;
+use lib::test::Suite;
+use termcolor::Color;
+
+use super::{Context, OperationFailure};
+use crate::ui;
+
+#[derive(clap::Args, Debug, Clone)]
+#[group(id = "prune-args")]
+pub struct Args {
+    /// Whether to the skip confirmation prompt
+    #[arg(long, short)]
+    pub force: bool,
+}
+
+pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
+    let project = ctx.project()?;
+    let paths = project.paths();
+    let store = ObjectStore::new(paths.objects_dir());
+
+    let orphaned = Suite::collect_orphaned_directories(paths)?.len();
+    let unreferenced = store
+        .collect_unreferenced_objects(&paths.test_root())?
+        .len();
+
+    if orphaned == 0 && unreferenced == 0 {
+        writeln!(
+            ctx.ui.stderr(),
+            "No orphaned directories or unreferenced objects found"
+        )?;
+        return Ok(());
+    }
+
+    let confirmed = args.force
+        || ctx.ui.prompt_yes_no(
+            format!(
+                "confirm deletion of {orphaned} orphaned {} and {unreferenced} unreferenced {}",
+                Term::irregular("directory", "directories").with(orphaned),
+                Term::simple("object").with(unreferenced),
+            ),
+            false,
+        )?;
+
+    if !confirmed {
+        ctx.error_aborted()?;
+        eyre::bail!(OperationFailure);
+    }
+
+    let removed_dirs = Suite::prune_orphaned_directories(paths)?;
+    let removed_objects = store.prune_unreferenced_objects(&paths.test_root())?;
+
+    let mut w = ctx.ui.stderr();
+    for path in removed_dirs.iter().chain(&removed_objects) {
+        ui::write_ident(&mut w, |w| writeln!(w, "{}", path.display()))?;
+    }
+
+    write!(w, "Removed ")?;
+    ui::write_bold_colored(&mut w, Color::Green, |w| {
+        write!(w, "{}", removed_dirs.len())
+    })?;
+    write!(
+        w,
+        " orphaned {} and ",
+        Term::irregular("directory", "directories").with(removed_dirs.len())
+    )?;
+    ui::write_bold_colored(&mut w, Color::Green, |w| {
+        write!(w, "{}", removed_objects.len())
+    })?;
+    writeln!(
+        w,
+        " unreferenced {}",
+        Term::simple("object").with(removed_objects.len())
+    )?;
+
+    Ok(())
+}
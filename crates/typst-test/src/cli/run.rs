@@ -1,15 +1,31 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
 use std::ops::Not;
+use std::path::PathBuf;
 
 use color_eyre::eyre;
-use lib::doc::compare::Strategy;
+use ecow::EcoVec;
+use lib::doc::compile;
 use lib::doc::render::{self, Origin};
+use lib::project::Project;
+use lib::runner::{Action, RunConfig};
+use lib::stdx::fmt::Term;
+use lib::test::{Id, Suite, SuiteResult, Test};
+use lib::test_set::{Error as TestSetError, TestSet};
+use typst::diag::{Severity, Warned};
+use typst::syntax::Source;
 
 use super::{
-    CompareArgs, CompileArgs, Context, Direction, ExportArgs, FilterArgs, RunArgs, CANCELLED,
+    CompareArgs, CompileArgs, Context, Direction, ExportArgs, FilterArgs, OperationFailure, RunArgs,
 };
 use crate::cli::TestFailure;
+use crate::html;
+use crate::json::RunJson;
 use crate::report::Reporter;
-use crate::runner::{Action, Runner, RunnerConfig};
+use crate::runner::{Outcomes, Runner};
+use crate::ui::{self, Ui};
+use crate::world::SystemWorld;
 
 #[derive(clap::Args, Debug, Clone)]
 #[group(id = "run-args")]
@@ -36,13 +52,94 @@ pub struct Args {
 
     #[command(flatten)]
     pub filter: FilterArgs,
+
+    /// Treat persistent tests without references as compile-only tests
+    ///
+    /// This is useful while authoring new tests, but may hide accidentally
+    /// deleted references.
+    #[arg(long)]
+    pub no_references_ok: bool,
+
+    /// Only render and export difference documents for failed comparisons
+    #[arg(long, conflicts_with = "no_export")]
+    pub export_diff_only_on_failure: bool,
+
+    /// Render and compare pages one at a time to reduce peak memory usage
+    ///
+    /// This is useful for very large documents, but implies --no-export.
+    #[arg(long)]
+    pub low_memory: bool,
+
+    /// Fail the test run if any of the given tests are marked as skipped
+    ///
+    /// This catches tests which were skipped accidentally, the skipped tests
+    /// are not run.
+    #[arg(long)]
+    pub fail_on_skip: bool,
+
+    /// Write a self-contained HTML report of all failed tests to this file
+    ///
+    /// The report embeds the exported reference, output and difference pages
+    /// of each failed test.
+    #[arg(long, value_name = "FILE")]
+    pub html: Option<PathBuf>,
+
+    /// Run the matched tests this many times and report tests with differing
+    /// results as flaky
+    ///
+    /// Reports and exit code refer to the last run, but the test run fails if
+    /// any run failed.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..),
+    )]
+    pub repeat: u32,
+
+    /// List this many of the slowest tests after the summary
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub slowest: usize,
+
+    /// Compile the manifest entrypoint of the package as a compile-only test
+    ///
+    /// This catches errors in the package itself, such as broken imports,
+    /// even if no test covers them.
+    #[arg(long)]
+    pub include_entrypoint: bool,
+
+    /// Print a JSON report of the test run to stdout
+    ///
+    /// The report includes the environment the tests were run in, such as the
+    /// typst version, timestamp, inputs and a hash of the available fonts.
+    #[arg(long)]
+    pub json: bool,
 }
 
 pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
     let project = ctx.project()?;
     let set = ctx.test_set(&args.filter)?;
-    let suite = ctx.collect_tests(&project, &set)?;
+    let mut suite = ctx.collect_tests(&project, &set)?;
+    ctx.warning_orphaned_directories(&project)?;
+
+    if args.include_entrypoint {
+        include_entrypoint(ctx, &project, &mut suite)?;
+    }
+
+    // NOTE(tinger): skipped tests are usually removed by the implicit skip, so
+    // we look for them using the same test set without it
+    let skipped = if args.fail_on_skip {
+        let filter = FilterArgs {
+            no_implicit_skip: true,
+            ..args.filter.clone()
+        };
+        skipped_tests(&suite, &ctx.test_set(&filter)?)?
+    } else {
+        vec![]
+    };
+
     let world = ctx.world(&args.compile)?;
+    let config = ctx.config(Some(&project))?;
 
     let origin = args
         .export
@@ -54,38 +151,383 @@ pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
         })
         .unwrap_or_default();
 
-    let runner = Runner::new(
-        &project,
-        &suite,
-        &world,
-        RunnerConfig {
-            promote_warnings: args.compile.promote_warnings,
-            optimize: !args.export.no_optimize_references,
-            fail_fast: !args.run.no_fail_fast,
-            pixel_per_pt: render::ppi_to_ppp(args.export.render.pixel_per_inch),
-            action: Action::Run {
-                strategy: args.no_compare.not().then_some(Strategy::Simple {
-                    max_delta: args.compare.max_delta,
-                    max_deviation: args.compare.max_deviation,
-                }),
-                export: !args.no_export,
-                origin,
-            },
-            cancellation: &CANCELLED,
-        },
-    );
+    let action = Action::Run {
+        strategy: args
+            .no_compare
+            .not()
+            .then(|| args.compare.strategy(&config)),
+        export: !args.no_export && !args.low_memory,
+        origin,
+    };
+    let runner_config = RunConfig {
+        optimize: args.export.optimize_options(),
+        export_metadata: args.export.export_metadata(),
+        pixel_per_pt: render::ppi_to_ppp(args.export.render.pixel_per_inch),
+        trim_whitespace: args.compare.trim_whitespace,
+        compare_grayscale: args.compare.compare_grayscale,
+        no_references_ok: args.no_references_ok,
+        diff_only_on_failure: args.export_diff_only_on_failure,
+        no_diff: args.export.no_diff,
+        low_memory: args.low_memory,
+        ..ctx.runner_config(&config, &args.compile, &args.run, action)?
+    };
 
     let reporter = Reporter::new(
         ctx.ui,
         &project,
         &world,
         ctx.ui.can_live_report() && ctx.args.global.output.verbose == 0,
-    );
-    let result = runner.run(&reporter)?;
+    )
+    .with_quiet(ctx.args.global.output.quiet)
+    .with_format(ctx.args.global.output.format)
+    .with_github_annotations(ctx.args.global.output.github_annotations)
+    .with_slowest(args.slowest);
+
+    let results = run_repeated(
+        &project,
+        &suite,
+        &world,
+        &runner_config,
+        &reporter,
+        args.repeat,
+    )?;
+
+    let outcomes = Outcomes::collect(&results);
+    let flaky = flaky_tests(&outcomes);
+    let failed = results.iter().any(|result| !result.is_complete_pass());
+    let result = results.last().expect("the suite is run at least once");
+
+    if let Some(path) = &args.html {
+        let mut w = BufWriter::new(File::create(path)?);
+        html::write_report(&mut w, &project, &suite, result, &outcomes)?;
+        w.flush()?;
+    }
 
-    if !result.is_complete_pass() {
+    if args.json {
+        serde_json::to_writer_pretty(ctx.ui.stdout(), &RunJson::new(&world, result, &outcomes))?;
+    }
+
+    report_flaky(ctx.ui, &flaky)?;
+
+    if failed {
         eyre::bail!(TestFailure);
     }
 
+    fail_on_skip(ctx.ui, &skipped)
+}
+
+/// The id of the test synthesized for the manifest entrypoint of a package.
+const ENTRYPOINT_ID: &str = "entrypoint";
+
+/// Creates a compile-only test for the manifest entrypoint of the given
+/// package or returns `None` if the project is not a package.
+fn entrypoint_test(project: &Project) -> Option<Test> {
+    let package = project.manifest_package_info()?;
+    let id = Id::new(ENTRYPOINT_ID).expect("the entrypoint id is valid");
+
+    Some(Test::new_script(id, package.entrypoint.clone()))
+}
+
+/// Adds the manifest entrypoint of the package to the given suite, such that
+/// it is run and reported like any other compile-only test.
+fn include_entrypoint(ctx: &Context, project: &Project, suite: &mut Suite) -> eyre::Result<()> {
+    let Some(test) = entrypoint_test(project) else {
+        ctx.ui
+            .warning("The project is not a package, --include-entrypoint has no effect")?;
+        return Ok(());
+    };
+
+    if suite.insert_matched(test).is_some() {
+        ctx.ui.error_hinted_with(
+            |w| writeln!(w, "A test named {ENTRYPOINT_ID:?} already exists"),
+            |w| writeln!(w, "Rename it to include the package entrypoint"),
+        )?;
+        eyre::bail!(OperationFailure);
+    }
+
     Ok(())
 }
+
+/// Compiles the given source as a compile-only test, the returned warnings
+/// exclude those which were promoted to errors.
+pub(super) fn compile_only(
+    source: Source,
+    world: &SystemWorld,
+    promote_warnings: bool,
+) -> Warned<Result<(), compile::Error>> {
+    let Warned { output, warnings } = compile::compile(source, world);
+
+    let mut errors: EcoVec<_> = match output {
+        Ok(_) => EcoVec::new(),
+        Err(err) => err.0,
+    };
+
+    let warnings = if promote_warnings {
+        errors.extend(warnings.into_iter().map(|mut warning| {
+            warning.severity = Severity::Error;
+            warning.with_hint("this warning was promoted to an error")
+        }));
+        EcoVec::new()
+    } else {
+        warnings
+    };
+
+    let output = if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(compile::Error(errors))
+    };
+
+    Warned { output, warnings }
+}
+
+/// Runs the given suite the given number of times, this stops early if the
+/// run was cancelled. Returns the results of all runs in order.
+fn run_repeated(
+    project: &Project,
+    suite: &Suite,
+    world: &SystemWorld,
+    config: &RunConfig<'_>,
+    reporter: &Reporter,
+    repeat: u32,
+) -> eyre::Result<Vec<SuiteResult>> {
+    let mut results = vec![];
+    for _ in 0..repeat {
+        let runner = Runner::new(project, suite, world, config.clone());
+        results.push(runner.run(reporter)?);
+
+        if config.is_cancelled() {
+            break;
+        }
+    }
+
+    Ok(results)
+}
+
+/// Returns the ids of all tests which passed in some and failed in other of
+/// the repeated runs, see [`Outcomes::collect`].
+fn flaky_tests(outcomes: &BTreeMap<Id, Outcomes>) -> Vec<Id> {
+    outcomes
+        .iter()
+        .filter(|(_, outcomes)| outcomes.is_flaky())
+        .map(|(id, _)| id.clone())
+        .collect()
+}
+
+/// Reports the given flaky tests, if there are any.
+fn report_flaky(ui: &Ui, flaky: &[Id]) -> io::Result<()> {
+    if flaky.is_empty() {
+        return Ok(());
+    }
+
+    ui.warning_with(|w| {
+        writeln!(
+            w,
+            "Found {} flaky {}, their results differed between runs",
+            flaky.len(),
+            Term::simple("test").with(flaky.len()),
+        )?;
+        for id in flaky {
+            ui::write_test_id(w, id)?;
+            writeln!(w)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Returns the ids of all skipped tests in the given suite which are
+/// contained in the given test set, regardless of whether they were matched.
+fn skipped_tests(suite: &Suite, set: &TestSet) -> Result<Vec<Id>, TestSetError> {
+    let mut set = set.clone();
+    set.bind_tests(suite.to_entries().into_values());
+
+    let mut skipped = vec![];
+    for test in suite.matched().values().chain(suite.filtered().values()) {
+        if test.is_skip() && set.contains(test)? {
+            skipped.push(test.id().clone());
+        }
+    }
+
+    skipped.sort();
+    Ok(skipped)
+}
+
+/// Reports the given skipped tests and fails if there are any.
+fn fail_on_skip(ui: &Ui, skipped: &[Id]) -> eyre::Result<()> {
+    if skipped.is_empty() {
+        return Ok(());
+    }
+
+    ui.error_with(|w| {
+        writeln!(
+            w,
+            "Found {} {} marked as skipped, but --fail-on-skip was given",
+            skipped.len(),
+            Term::simple("test").with(skipped.len()),
+        )?;
+        for id in skipped {
+            ui::write_test_id(w, id)?;
+            writeln!(w)?;
+        }
+
+        Ok(())
+    })?;
+
+    eyre::bail!(TestFailure);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use lib::_dev::fs::TempEnv;
+    use lib::test_set::eval;
+    use termcolor::ColorChoice;
+
+    use super::*;
+    use crate::runner::tests::{config, project, suite};
+    use crate::world::tests::world;
+
+    #[test]
+    fn test_fail_on_skip() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/skipped/test.typ", "/// [skip]\nHello")
+                    .setup_file("tests/other/test.typ", "Hello")
+            },
+            |root| {
+                // the suite as it is collected with the implicit skip
+                let mut set = TestSet::new(eval::Context::empty(), eval::Set::built_in_all());
+                set.add_implicit_skip();
+                let suite = Suite::collect(project(root).paths(), &set).unwrap();
+                assert!(!suite.matched().contains_key("skipped"));
+
+                let ui = Ui::new(ColorChoice::Never, ColorChoice::Never);
+
+                // without the flag no skipped tests are looked up
+                assert!(fail_on_skip(&ui, &[]).is_ok());
+
+                let all = TestSet::new(eval::Context::empty(), eval::Set::built_in_all());
+                let skipped = skipped_tests(&suite, &all).unwrap();
+                assert_eq!(skipped, [Id::new("skipped").unwrap()]);
+
+                let err = fail_on_skip(&ui, &skipped).unwrap_err();
+                assert!(err.is::<TestFailure>());
+            },
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_flaky_tests() {
+        use std::os::unix::fs::PermissionsExt;
+
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/stable/test.typ", "Hello")
+                    .setup_file("tests/broken/test.typ", "Hello")
+                    .setup_file("tests/broken/ref.typ", "World")
+                    .setup_file("tests/flaky/test.typ", "/// [prepare: ./toggle.sh]\nHello")
+                    .setup_file("tests/flaky/ref.typ", "World")
+                    .setup_file(
+                        "tests/flaky/toggle.sh",
+                        "#!/bin/sh\n\
+                        if grep -q Hello tests/flaky/ref.typ\n\
+                        then echo World > tests/flaky/ref.typ\n\
+                        else echo Hello > tests/flaky/ref.typ\n\
+                        fi\n",
+                    )
+            },
+            |root| {
+                let hook = root.join("tests/flaky/toggle.sh");
+                fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+
+                let (project, world) = (project(root), world(None));
+                let ui = Ui::new(ColorChoice::Never, ColorChoice::Never);
+                let reporter = Reporter::new(&ui, &project, &world, false).with_quiet(true);
+
+                // the hook flips the reference, so the flaky test passes on odd
+                // runs
+                let suite = suite(&project);
+                let results =
+                    run_repeated(&project, &suite, &world, &config(), &reporter, 4).unwrap();
+                assert_eq!(results.len(), 4);
+
+                let id = |id| Id::new(id).unwrap();
+                let outcomes = Outcomes::collect(&results);
+                assert_eq!(
+                    outcomes[&id("flaky")],
+                    Outcomes {
+                        passed: 2,
+                        failed: 2
+                    }
+                );
+                assert_eq!(
+                    outcomes[&id("stable")],
+                    Outcomes {
+                        passed: 4,
+                        failed: 0
+                    }
+                );
+                assert_eq!(
+                    outcomes[&id("broken")],
+                    Outcomes {
+                        passed: 0,
+                        failed: 4
+                    }
+                );
+
+                assert_eq!(flaky_tests(&outcomes), [id("flaky")]);
+                assert_eq!(flaky_tests(&Outcomes::collect(&results[..1])), []);
+            },
+        );
+    }
+
+    #[test]
+    fn test_entrypoint_test() {
+        const MANIFEST: &str = r#"
+            [package]
+            name = "package"
+            version = "0.1.0"
+            entrypoint = "src/lib.typ"
+        "#;
+
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file(lib::project::MANIFEST_FILE, MANIFEST)
+                    .setup_file("src/lib.typ", "#let add(a, b) = a + b")
+                    .setup_file("tests/add/test.typ", "Hello")
+            },
+            |root| {
+                let ui = Ui::new(ColorChoice::Never, ColorChoice::Never);
+                let world = world(None);
+                let run = || {
+                    let project = Project::discover(root, false).unwrap().unwrap();
+                    let mut suite = suite(&project);
+                    let test = entrypoint_test(&project).unwrap();
+                    assert!(suite.insert_matched(test).is_none());
+
+                    let reporter = Reporter::new(&ui, &project, &world, false).with_quiet(true);
+                    Runner::new(&project, &suite, &world, config())
+                        .run(&reporter)
+                        .unwrap()
+                };
+
+                let result = run();
+                assert!(result.is_complete_pass());
+                assert!(result.results()[&Id::new(ENTRYPOINT_ID).unwrap()].is_pass());
+
+                // the failure is part of the regular results
+                fs::write(root.join("src/lib.typ"), "#let add(a, b) = a +").unwrap();
+                let result = run();
+                assert!(!result.is_complete_pass());
+                assert!(result.results()[&Id::new(ENTRYPOINT_ID).unwrap()].is_fail());
+                assert!(result.results()[&Id::new("add").unwrap()].is_pass());
+
+                // projects without a manifest have no entrypoint
+                assert!(entrypoint_test(&project(root)).is_none());
+            },
+        );
+    }
+}
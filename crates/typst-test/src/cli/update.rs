@@ -1,11 +1,22 @@
+use std::collections::BTreeSet;
+use std::io;
+
 use color_eyre::eyre;
+use lib::doc;
 use lib::doc::render::{self, Origin};
+use lib::project::Project;
+use lib::runner::{Action, RunConfig};
+use lib::test::{Kind, Suite};
 use lib::test_set::eval;
 
-use super::{CompileArgs, Context, Direction, ExportArgs, FilterArgs, RunArgs, CANCELLED};
+use super::{CompileArgs, Context, Direction, ExportArgs, FilterArgs, RunArgs};
 use crate::cli::TestFailure;
 use crate::report::Reporter;
-use crate::runner::{Action, Runner, RunnerConfig};
+use crate::runner::Runner;
+
+/// The default maximum fraction of pixels which may change before an update
+/// is refused.
+pub const DEFAULT_MAX_CHANGE: f64 = 0.5;
 
 #[derive(clap::Args, Debug, Clone)]
 #[group(id = "update-args")]
@@ -16,6 +27,26 @@ pub struct Args {
     #[command(flatten)]
     pub export: ExportArgs,
 
+    /// The maximum fraction of pixels which may change before an update is
+    /// refused
+    ///
+    /// This guards against accidentally accepting a broken render as the new
+    /// reference.
+    #[arg(long, default_value_t = DEFAULT_MAX_CHANGE, value_name = "FRACTION")]
+    pub max_change: f64,
+
+    /// Update references regardless of how much they change
+    #[arg(long, short)]
+    pub force: bool,
+
+    /// Only create references for tests which have none, existing
+    /// references are left untouched
+    ///
+    /// This includes compile-only tests, which become persistent tests, and
+    /// persistent tests with an empty reference directory.
+    #[arg(long, conflicts_with = "force")]
+    pub update_missing: bool,
+
     #[command(flatten)]
     pub run: RunArgs,
 
@@ -26,32 +57,45 @@ pub struct Args {
 pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
     let project = ctx.project()?;
     let mut set = ctx.test_set(&args.filter)?;
-    set.add_intersection(eval::Set::built_in_persistent());
-    let suite = ctx.collect_tests(&project, &set)?;
+    let suite = if args.update_missing {
+        set.add_intersection(eval::Set::built_in_kind([
+            Kind::Persistent,
+            Kind::CompileOnly,
+        ]));
+        let candidates = ctx.collect_tests(&project, &set)?;
+        set.add_intersection(missing_references(&project, &candidates)?);
+        ctx.collect_tests(&project, &set)?
+    } else {
+        set.add_intersection(eval::Set::built_in_persistent());
+        ctx.collect_tests(&project, &set)?
+    };
     let world = ctx.world(&args.compile)?;
+    let config = ctx.config(Some(&project))?;
 
+    let action = Action::Update {
+        export: true,
+        max_change: (!args.force).then_some(args.max_change),
+        create_missing: args.update_missing,
+        origin: args
+            .export
+            .render
+            .direction
+            .map(|dir| match dir {
+                Direction::Ltr => Origin::TopLeft,
+                Direction::Rtl => Origin::TopRight,
+            })
+            .unwrap_or_default(),
+    };
     let runner = Runner::new(
         &project,
         &suite,
         &world,
-        RunnerConfig {
-            promote_warnings: args.compile.promote_warnings,
-            optimize: !args.export.no_optimize_references,
-            fail_fast: !args.run.no_fail_fast,
+        RunConfig {
+            optimize: args.export.optimize_options(),
+            export_metadata: args.export.export_metadata(),
             pixel_per_pt: render::ppi_to_ppp(args.export.render.pixel_per_inch),
-            action: Action::Update {
-                export: true,
-                origin: args
-                    .export
-                    .render
-                    .direction
-                    .map(|dir| match dir {
-                        Direction::Ltr => Origin::TopLeft,
-                        Direction::Rtl => Origin::TopRight,
-                    })
-                    .unwrap_or_default(),
-            },
-            cancellation: &CANCELLED,
+            no_diff: args.export.no_diff,
+            ..ctx.runner_config(&config, &args.compile, &args.run, action)?
         },
     );
 
@@ -60,7 +104,10 @@ pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
         &project,
         &world,
         ctx.ui.can_live_report() && ctx.args.global.output.verbose == 0,
-    );
+    )
+    .with_quiet(ctx.args.global.output.quiet)
+    .with_format(ctx.args.global.output.format)
+    .with_github_annotations(ctx.args.global.output.github_annotations);
     let result = runner.run(&reporter)?;
 
     if !result.is_complete_pass() {
@@ -69,3 +116,103 @@ pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
 
     Ok(())
 }
+
+/// Returns a set containing all matched tests in the given suite which have
+/// no references. Compile-only tests only get their reference directory once
+/// they were updated successfully, see [`Action::Update`].
+fn missing_references(project: &Project, suite: &Suite) -> io::Result<eval::Set> {
+    let paths = project.paths();
+
+    let mut missing = BTreeSet::new();
+    for (id, test) in suite.matched() {
+        // NOTE(tinger): single-file tests can't have references
+        if test.is_single_file() {
+            continue;
+        }
+
+        if doc::has_pages(paths.test_ref_dir(id))? {
+            continue;
+        }
+
+        missing.insert(id.clone());
+    }
+
+    Ok(eval::Set::new(move |_, test| {
+        Ok(missing.contains(test.id()))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use lib::_dev::fs::TempEnv;
+    use lib::test::Id;
+    use lib::test_set::TestSet;
+
+    use super::*;
+    use crate::runner::tests::{config, project, run_test, test};
+
+    #[test]
+    fn test_missing_references() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/existing/test.typ", "Hello")
+                    .setup_file("tests/existing/ref/.keep", "")
+                    .setup_file("tests/empty/test.typ", "Hello")
+                    .setup_file("tests/empty/ref/.keep", "")
+                    .setup_file("tests/new/test.typ", "Hello")
+                    .setup_file("tests/ephemeral/test.typ", "Hello")
+                    .setup_file("tests/ephemeral/ref.typ", "Hello")
+                    .setup_file("tests/single.typ", "Hello")
+                    .setup_file("tests/broken/test.typ", "#panic()")
+            },
+            |root| {
+                let project = project(root);
+
+                let update = RunConfig {
+                    action: Action::Update {
+                        export: false,
+                        max_change: Some(DEFAULT_MAX_CHANGE),
+                        origin: Origin::default(),
+                        create_missing: true,
+                    },
+                    ..config()
+                };
+                assert!(run_test(&project, update.clone(), "existing").is_pass());
+
+                let existing = root.join("tests/existing/ref/1.png");
+                let reference = fs::read(&existing).unwrap();
+                fs::write(root.join("tests/existing/test.typ"), "World").unwrap();
+
+                let mut set = TestSet::new(
+                    eval::Context::empty(),
+                    eval::Set::built_in_kind([Kind::Persistent, Kind::CompileOnly]),
+                );
+                let candidates = Suite::collect(project.paths(), &set).unwrap();
+                set.add_intersection(missing_references(&project, &candidates).unwrap());
+
+                let suite = Suite::collect(project.paths(), &set).unwrap();
+                assert_eq!(
+                    suite.matched().keys().map(Id::as_str).collect::<Vec<_>>(),
+                    ["broken", "empty", "new"]
+                );
+
+                // nothing is created before the tests are updated
+                assert!(!root.join("tests/new/ref").exists());
+
+                assert!(!run_test(&project, update.clone(), "broken").is_pass());
+                assert!(run_test(&project, update.clone(), "empty").is_pass());
+                assert!(run_test(&project, update.clone(), "new").is_pass());
+
+                // a test which failed to compile is left as it was
+                assert!(!root.join("tests/broken/ref").exists());
+                assert!(test(&project, "new").kind().is_persistent());
+                assert!(root.join("tests/empty/ref/1.png").exists());
+                assert!(root.join("tests/new/ref/1.png").exists());
+                assert_eq!(fs::read(existing).unwrap(), reference);
+                assert!(!root.join("tests/single").exists());
+            },
+        );
+    }
+}
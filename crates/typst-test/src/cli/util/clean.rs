@@ -1,26 +1,63 @@
 use std::io::Write;
 
 use color_eyre::eyre;
-use lib::stdx::fmt::Term;
+use lib::stdx;
+use lib::stdx::fmt::{ByteSize, Term};
 use termcolor::Color;
 
-use super::Context;
+use crate::cli::Context;
 use crate::ui;
 
-pub fn run(ctx: &mut Context) -> eyre::Result<()> {
+#[derive(clap::Args, Debug, Clone)]
+#[group(id = "util-clean-args")]
+pub struct Args {
+    /// Only report which artifacts would be removed and their size
+    #[arg(long, short = 'n')]
+    pub dry_run: bool,
+}
+
+pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
     let project = ctx.project()?;
+    let paths = project.paths();
     let suite = ctx.collect_all_tests(&project)?;
 
     let len = suite.matched().len();
 
+    let mut size = 0;
+    let mut files = 0;
     for test in suite.matched().values() {
-        test.create_temporary_directories(project.paths(), project.vcs())?;
+        let mut dirs = vec![
+            paths.test_out_dir(test.id()),
+            paths.test_diff_dir(test.id()),
+        ];
+        if test.kind().is_ephemeral() {
+            dirs.push(paths.test_ref_dir(test.id()));
+        }
+
+        for dir in dirs {
+            size += stdx::fs::dir_size(&dir)?;
+            files += stdx::fs::count_files(&dir)?;
+        }
+    }
+
+    if !args.dry_run {
+        for test in suite.matched().values() {
+            test.delete_temporary_directories(paths)?;
+        }
     }
 
     let mut w = ctx.ui.stderr();
-    write!(w, "Removed temporary directories for")?;
+    if args.dry_run {
+        write!(w, "Would remove temporary directories for ")?;
+    } else {
+        write!(w, "Removed temporary directories for ")?;
+    }
     ui::write_colored(&mut w, Color::Green, |w| write!(w, "{len}"))?;
-    writeln!(w, " {}", Term::simple("test").with(len))?;
+    write!(w, " {} (", Term::simple("test").with(len))?;
+    ui::write_bold(&mut w, |w| write!(w, "{files}"))?;
+    write!(w, " {}, ", Term::simple("file").with(files))?;
+    ui::write_bold(&mut w, |w| write!(w, "{}", ByteSize(size)))?;
+    writeln!(w, ")")?;
 
     Ok(())
 }
@@ -5,6 +5,7 @@ use typst::text::FontStyle;
 
 use crate::cli::Context;
 use crate::json::{FontJson, FontVariantJson};
+use crate::kit::FontSource;
 use crate::ui::Indented;
 use crate::{kit, ui};
 
@@ -15,33 +16,49 @@ pub struct Args {
     #[arg(long)]
     pub variants: bool,
 
-    /// Print a JSON describing the project to stdout
+    /// Print a JSON describing the fonts to stdout
     #[arg(long)]
     pub json: bool,
 }
 
 pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
+    let font_paths = &ctx.args.global.fonts.font_paths;
     let fonts = kit::fonts_from_args(&ctx.args.global.fonts);
 
     let fonts = fonts
         .book
         .families()
-        .map(|(name, info)| FontJson {
-            name,
-            variants: if args.variants {
-                info.map(|info| FontVariantJson {
-                    style: match info.variant.style {
-                        FontStyle::Normal => "normal",
-                        FontStyle::Italic => "italic",
-                        FontStyle::Oblique => "oblique",
-                    },
-                    weight: info.variant.weight.to_number(),
-                    stretch: info.variant.stretch.to_ratio().get(),
+        .map(|(name, _)| {
+            let variants = fonts
+                .book
+                .select_family(&name.to_lowercase())
+                .filter_map(|idx| {
+                    let info = fonts.book.info(idx)?;
+                    let path = fonts.fonts[idx].path();
+
+                    Some(FontVariantJson {
+                        style: match info.variant.style {
+                            FontStyle::Normal => "normal",
+                            FontStyle::Italic => "italic",
+                            FontStyle::Oblique => "oblique",
+                        },
+                        weight: info.variant.weight.to_number(),
+                        stretch: info.variant.stretch.to_ratio().get(),
+                        source: FontSource::classify(path, font_paths).as_str(),
+                        path,
+                    })
                 })
-                .collect()
-            } else {
-                vec![]
-            },
+                .collect::<Vec<_>>();
+
+            let mut sources: Vec<_> = variants.iter().map(|v| v.source).collect();
+            sources.sort_unstable();
+            sources.dedup();
+
+            FontJson {
+                name,
+                sources,
+                variants: if args.variants { variants } else { vec![] },
+            }
         })
         .collect::<Vec<_>>();
 
@@ -56,15 +73,21 @@ pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
 
     let mut w = Indented::new(w, 2);
     for font in fonts {
-        ui::write_ident(&mut w, |w| writeln!(w, "{}", font.name))?;
+        ui::write_ident(&mut w, |w| write!(w, "{}", font.name))?;
+        writeln!(w, " ({})", font.sources.join(", "))?;
 
         let mut w = Indented::new(&mut w, 2);
         for variant in &font.variants {
-            writeln!(
+            write!(
                 w,
-                "Style: {}, Weight: {}, Stretch: {}",
-                variant.style, variant.weight, variant.stretch
+                "Style: {}, Weight: {}, Stretch: {}, Source: {}",
+                variant.style, variant.weight, variant.stretch, variant.source,
             )?;
+
+            match variant.path {
+                Some(path) => writeln!(w, " ({})", path.display())?,
+                None => writeln!(w)?,
+            }
         }
     }
 
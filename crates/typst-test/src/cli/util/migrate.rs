@@ -4,10 +4,12 @@ use std::io::Write;
 use std::path::{Path, PathBuf};
 
 use color_eyre::eyre::{self, WrapErr};
+use lib::doc;
 use lib::project::Paths;
 use lib::stdx;
-use lib::test::Id;
-use termcolor::Color;
+use lib::stdx::fmt::Term;
+use lib::test::{Id, Suite};
+use termcolor::{Color, WriteColor};
 
 use crate::cli::Context;
 use crate::ui;
@@ -24,48 +26,115 @@ pub struct Args {
     pub name: String,
 }
 
+/// The directory in which the flat layout stored test scripts.
+const FLAT_SCRIPT_DIR: &str = "typ";
+
+/// The directory in which the flat layout stored persistent references.
+const FLAT_REF_DIR: &str = "ref";
+
+/// The directories in which the flat layout stored temporary documents.
+const FLAT_TEMPORARY_DIRS: [&str; 2] = ["out", "diff"];
+
 pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
     let project = ctx.project()?;
     let paths = project.paths();
     let mut w = ctx.ui.stderr();
 
-    let mappings = collect_old_structure(paths, &args.name)?;
+    let flat = collect_flat_structure(paths)?;
+    let mut has_colission = false;
+    let mut migrated = 0;
 
-    if mappings.is_empty() {
-        writeln!(w, "No tests need to be moved")?;
-        return Ok(());
-    }
+    if !flat.is_empty() {
+        if args.confirm {
+            writeln!(w, "Moving tests from the flat layout:")?;
+        } else {
+            writeln!(w, "These tests would be moved from the flat layout:")?;
+        }
 
-    if args.confirm {
-        writeln!(w, "Moving tests:")?;
-    } else {
-        writeln!(w, "These tests would be moved:")?;
+        for (script, (new, collision)) in &flat {
+            if *collision {
+                ui::write_bold_colored(&mut w, Color::Red, |w| write!(w, "*"))?;
+                write!(w, " ")?;
+            } else {
+                write!(w, "  ")?;
+            }
+            write!(
+                w,
+                "{} -> ",
+                Path::new(FLAT_SCRIPT_DIR).join(script).display()
+            )?;
+            ui::write_test_id(&mut w, new)?;
+            writeln!(w)?;
+        }
+
+        writeln!(w)?;
+
+        if args.confirm {
+            let mut migrated_scripts = vec![];
+            for (script, (new, collision)) in &flat {
+                if !*collision {
+                    migrate_flat_test(paths, script, new)?;
+                    migrated_scripts.push(script.as_path());
+                    migrated += 1;
+                } else {
+                    has_colission = true;
+                }
+            }
+
+            remove_flat_leftovers(paths, &migrated_scripts)?;
+        }
     }
 
-    for (old, (new, collision)) in &mappings {
-        if *collision {
-            ui::write_bold_colored(&mut w, Color::Red, |w| write!(w, "*"))?;
-            write!(w, " ")?;
+    // NOTE(tinger): tests from the flat layout may be nested in each other
+    // after moving them, so we collect the nested tests afterwards
+    let mappings = collect_old_structure(paths, &args.name)?;
+
+    if !mappings.is_empty() {
+        if args.confirm {
+            writeln!(w, "Moving tests:")?;
         } else {
-            write!(w, "  ")?;
+            writeln!(w, "These tests would be moved:")?;
         }
-        ui::write_test_id(&mut w, old)?;
-        write!(w, " -> ")?;
-        ui::write_test_id(&mut w, new)?;
+
+        for (old, (new, collision)) in &mappings {
+            if *collision {
+                ui::write_bold_colored(&mut w, Color::Red, |w| write!(w, "*"))?;
+                write!(w, " ")?;
+            } else {
+                write!(w, "  ")?;
+            }
+            ui::write_test_id(&mut w, old)?;
+            write!(w, " -> ")?;
+            ui::write_test_id(&mut w, new)?;
+            writeln!(w)?;
+        }
+
         writeln!(w)?;
     }
 
-    writeln!(w)?;
-
-    let mut has_colission = false;
     for (old, (new, collision)) in &mappings {
-        if !*collision {
-            migrate_test(paths, old, new)?;
-        } else {
+        if *collision {
             has_colission = true;
+        } else if args.confirm {
+            migrate_test(paths, old, new)?;
+            migrated += 1;
         }
     }
 
+    let suite = ctx.collect_all_tests(&project)?;
+    let converted = convert_legacy_references(&mut w, paths, &suite, !args.confirm)?;
+
+    if flat.is_empty() && mappings.is_empty() && converted == 0 {
+        writeln!(w, "No tests need to be migrated")?;
+        return Ok(());
+    }
+
+    if args.confirm {
+        write!(w, "Migrated ")?;
+        ui::write_bold_colored(&mut w, Color::Green, |w| write!(w, "{migrated}"))?;
+        writeln!(w, " {}", Term::simple("test").with(migrated))?;
+    }
+
     if has_colission {
         ctx.ui.hint_with(|w| {
             ui::write_bold_colored(w, Color::Red, |w| write!(w, "*"))?;
@@ -97,6 +166,206 @@ pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
     Ok(())
 }
 
+/// Converts the legacy reference pages of all persistent tests to the current
+/// page naming scheme, returns the number of converted reference directories.
+fn convert_legacy_references<W: WriteColor>(
+    w: &mut W,
+    paths: &Paths,
+    suite: &Suite,
+    dry_run: bool,
+) -> eyre::Result<usize> {
+    let mut converted = 0;
+    for test in suite.matched().values() {
+        if !test.kind().is_persistent() {
+            continue;
+        }
+
+        let mut dirs = vec![paths.test_ref_dir(test.id())];
+        dirs.extend(
+            test.variants()
+                .iter()
+                .map(|variant| paths.test_ref_variant_dir(test.id(), variant)),
+        );
+
+        for dir in dirs {
+            if !dir.try_exists()? {
+                continue;
+            }
+
+            let renamed = doc::convert_legacy_pages(&dir, dry_run)?;
+            if renamed.is_empty() {
+                continue;
+            }
+
+            if converted == 0 {
+                if dry_run {
+                    writeln!(w, "These references would be converted:")?;
+                } else {
+                    writeln!(w, "Converting references:")?;
+                }
+            }
+
+            converted += 1;
+            write!(w, "  ")?;
+            ui::write_test_id(w, test.id())?;
+            writeln!(w, " ({})", dir.display())?;
+            for (old, new) in renamed {
+                writeln!(w, "    {old} -> {new}.png")?;
+            }
+        }
+    }
+
+    if converted != 0 {
+        writeln!(w)?;
+    }
+
+    Ok(converted)
+}
+
+/// Collects the scripts of the flat layout, where test scripts were stored as
+/// `typ/<id>.typ` with references in `ref/<id>/`, relative to the test root.
+///
+/// Returns the script paths relative to the script directory with their new
+/// id and whether a test with that id already exists.
+pub fn collect_flat_structure(paths: &Paths) -> eyre::Result<BTreeMap<PathBuf, (Id, bool)>> {
+    let script_dir = paths.test_root().join(FLAT_SCRIPT_DIR);
+    let mut mappings = BTreeMap::new();
+
+    if !script_dir.try_exists()? {
+        return Ok(mappings);
+    }
+
+    collect_flat_structure_inner(paths, &script_dir, &script_dir, &mut mappings)?;
+
+    Ok(mappings)
+}
+
+fn collect_flat_structure_inner(
+    paths: &Paths,
+    script_dir: &Path,
+    path: &Path,
+    mappings: &mut BTreeMap<PathBuf, (Id, bool)>,
+) -> eyre::Result<()> {
+    for entry in fs::read_dir(path).wrap_err_with(|| format!("{path:?}"))? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if entry.metadata()?.is_dir() {
+            collect_flat_structure_inner(paths, script_dir, &path, mappings)?;
+            continue;
+        }
+
+        if !path.extension().is_some_and(|ext| ext == "typ") {
+            continue;
+        }
+
+        let script = path.strip_prefix(script_dir)?.to_path_buf();
+        let id = Id::new_from_path(script.with_extension(""))?;
+        let collision = paths.test_script(&id).try_exists()?;
+
+        mappings.insert(script, (id, collision));
+    }
+
+    Ok(())
+}
+
+fn migrate_flat_test(paths: &Paths, script: &Path, new: &Id) -> eyre::Result<()> {
+    let test_root = paths.test_root();
+
+    let test_dir = paths.test_dir(new);
+    stdx::fs::create_dir(&test_dir, true).wrap_err(format!("creating {test_dir:?}"))?;
+
+    let old_script = test_root.join(FLAT_SCRIPT_DIR).join(script);
+    let new_script = paths.test_script(new);
+    fs::rename(&old_script, &new_script)
+        .wrap_err(format!("moving {old_script:?} to {new_script:?}"))?;
+
+    // NOTE(tinger): references of nested tests are stored in sub directories
+    // of the parent's references, so we only move the pages themselves
+    let old_ref_dir = test_root.join(FLAT_REF_DIR).join(script.with_extension(""));
+    if old_ref_dir.try_exists()? {
+        let new_ref_dir = paths.test_ref_dir(new);
+        stdx::fs::create_dir(&new_ref_dir, true).wrap_err(format!("creating {new_ref_dir:?}"))?;
+
+        for entry in fs::read_dir(&old_ref_dir)? {
+            let entry = entry?;
+            if !entry.metadata()?.is_file() {
+                continue;
+            }
+
+            let old = entry.path();
+            let new = new_ref_dir.join(entry.file_name());
+            fs::rename(&old, &new).wrap_err(format!("moving {old:?} to {new:?}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the leftovers of the given migrated flat tests, these are the
+/// temporary pages of each test and any directories of the flat layout which
+/// became empty by migrating them.
+///
+/// The directories of the flat layout may also be regular test directories,
+/// so only what belongs to the given scripts is touched.
+fn remove_flat_leftovers(paths: &Paths, migrated: &[&Path]) -> eyre::Result<()> {
+    let test_root = paths.test_root();
+
+    for script in migrated {
+        let id_path = script.with_extension("");
+
+        for dir in FLAT_TEMPORARY_DIRS {
+            let base = test_root.join(dir);
+            let dir = base.join(&id_path);
+            remove_pages(&dir)?;
+            remove_empty_ancestors(&base, &dir)?;
+        }
+
+        let base = test_root.join(FLAT_REF_DIR);
+        remove_empty_ancestors(&base, &base.join(&id_path))?;
+
+        let base = test_root.join(FLAT_SCRIPT_DIR);
+        if let Some(dir) = base.join(script).parent() {
+            remove_empty_ancestors(&base, dir)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the page images directly within the given directory.
+fn remove_pages(dir: &Path) -> eyre::Result<()> {
+    if !dir.try_exists()? {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.metadata()?.is_file() && path.extension().is_some_and(|ext| ext == "png") {
+            fs::remove_file(&path).wrap_err(format!("removing {path:?}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the given directory and its ancestors up to and including `base`
+/// as long as they are empty.
+fn remove_empty_ancestors(base: &Path, dir: &Path) -> eyre::Result<()> {
+    for dir in dir.ancestors().take_while(|dir| dir.starts_with(base)) {
+        if dir.try_exists()? {
+            if fs::read_dir(dir)?.next().is_some() {
+                break;
+            }
+
+            fs::remove_dir(dir).wrap_err(format!("removing {dir:?}"))?;
+        }
+    }
+
+    Ok(())
+}
+
 pub fn collect_old_structure(
     paths: &Paths,
     migration_name: &str,
@@ -183,3 +452,143 @@ fn migrate_test(paths: &Paths, old: &Id, new: &Id) -> eyre::Result<()> {
     stdx::fs::remove_dir(&diff_dir, true).wrap_err(format!("removing to {diff_dir:?}"))?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use lib::_dev::fs::TempEnv;
+
+    use super::*;
+    use crate::runner::tests::{project, suite};
+
+    #[test]
+    fn test_migrate_flat_layout() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/typ/a.typ", "A")
+                    .setup_file("tests/typ/b/c.typ", "C")
+                    .setup_file("tests/ref/a/1.png", "a1")
+                    .setup_file("tests/ref/a/2.png", "a2")
+                    .setup_file("tests/ref/b/c/1.png", "c1")
+                    .setup_file("tests/out/a/1.png", "out")
+                    .setup_file("tests/diff/a/1.png", "diff")
+            },
+            |root| {
+                let project = project(root);
+                let paths = project.paths();
+
+                let flat = collect_flat_structure(paths).unwrap();
+                assert_eq!(
+                    flat.values().map(|(id, _)| id.as_str()).collect::<Vec<_>>(),
+                    ["a", "b/c"],
+                );
+                assert!(flat.values().all(|(_, collision)| !collision));
+
+                for (script, (id, _)) in &flat {
+                    migrate_flat_test(paths, script, id).unwrap();
+                }
+                let scripts: Vec<_> = flat.keys().map(PathBuf::as_path).collect();
+                remove_flat_leftovers(paths, &scripts).unwrap();
+
+                let root = paths.test_root();
+                let read = |path: &str| fs::read_to_string(root.join(path)).unwrap();
+                assert_eq!(read("a/test.typ"), "A");
+                assert_eq!(read("a/ref/1.png"), "a1");
+                assert_eq!(read("a/ref/2.png"), "a2");
+                assert_eq!(read("b/c/test.typ"), "C");
+                assert_eq!(read("b/c/ref/1.png"), "c1");
+
+                for dir in ["typ", "ref", "out", "diff"] {
+                    assert!(!root.join(dir).exists(), "{dir} was not removed");
+                }
+
+                assert!(collect_flat_structure(paths).unwrap().is_empty());
+            },
+        );
+    }
+
+    #[test]
+    fn test_migrate_flat_layout_keeps_tests() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/typ/a.typ", "A")
+                    .setup_file("tests/typ/b.typ", "B")
+                    .setup_file("tests/out/a/1.png", "out")
+                    .setup_file("tests/out/b/1.png", "out")
+                    .setup_file("tests/diff/a/1.png", "diff")
+                    .setup_file("tests/out/real/test.typ", "Real")
+                    .setup_file("tests/diff/real/test.typ", "Real")
+                    .setup_file("tests/diff/real/ref/1.png", "ref")
+            },
+            |root| {
+                let project = project(root);
+                let paths = project.paths();
+
+                // only a is migrated, b is left behind as if it collided
+                let a = Id::new("a").unwrap();
+                migrate_flat_test(paths, Path::new("a.typ"), &a).unwrap();
+                remove_flat_leftovers(paths, &[Path::new("a.typ")]).unwrap();
+
+                let root = paths.test_root();
+                assert!(!root.join("out/a").exists());
+                assert!(!root.join("diff/a").exists());
+                assert!(root.join("typ/b.typ").exists());
+                assert!(root.join("out/b/1.png").exists());
+                assert!(root.join("out/real/test.typ").exists());
+                assert!(root.join("diff/real/test.typ").exists());
+                assert!(root.join("diff/real/ref/1.png").exists());
+            },
+        );
+    }
+
+    #[test]
+    fn test_convert_legacy_references() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/legacy/test.typ", "Hello")
+                    .setup_file("tests/legacy/ref/page-1.png", "a")
+                    .setup_file("tests/legacy/ref/page-2.png", "b")
+                    .setup_file("tests/current/test.typ", "Hello")
+                    .setup_file("tests/current/ref/1.png", "c")
+            },
+            |root| {
+                let project = project(root);
+                let paths = project.paths();
+                let suite = suite(&project);
+
+                let mut w = termcolor::NoColor::new(vec![]);
+                assert_eq!(
+                    convert_legacy_references(&mut w, paths, &suite, false).unwrap(),
+                    1
+                );
+
+                let w = String::from_utf8(w.into_inner()).unwrap();
+                assert!(w.contains("page-1.png -> 1.png"));
+                assert!(w.contains("page-2.png -> 2.png"));
+                assert!(!w.contains("current"));
+
+                let root = paths.test_root();
+                let read = |path: &str| fs::read_to_string(root.join(path)).unwrap();
+                assert_eq!(read("legacy/ref/1.png"), "a");
+                assert_eq!(read("legacy/ref/2.png"), "b");
+                assert_eq!(read("current/ref/1.png"), "c");
+            },
+        );
+    }
+
+    #[test]
+    fn test_migrate_flat_layout_collision() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/typ/a.typ", "old")
+                    .setup_file("tests/a/test.typ", "new")
+            },
+            |root| {
+                let project = project(root);
+                let paths = project.paths();
+
+                let flat = collect_flat_structure(paths).unwrap();
+                assert_eq!(flat.get(Path::new("a.typ")).map(|(_, c)| *c), Some(true));
+            },
+        );
+    }
+}
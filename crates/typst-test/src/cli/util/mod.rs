@@ -6,6 +6,8 @@ pub mod about;
 pub mod clean;
 pub mod fonts;
 pub mod migrate;
+pub mod repair_refs;
+pub mod test_set;
 
 #[derive(clap::Args, Debug, Clone)]
 #[group(id = "util-args")]
@@ -23,7 +25,7 @@ pub enum Command {
 
     /// Remove test output artifacts
     #[command()]
-    Clean,
+    Clean(clean::Args),
 
     /// List all available fonts
     #[command()]
@@ -32,15 +34,28 @@ pub enum Command {
     /// Migrate the test structure to the new version
     #[command()]
     Migrate(migrate::Args),
+
+    /// Renumber reference pages which are not contiguous
+    #[command()]
+    RepairRefs(repair_refs::Args),
+
+    /// Explain which tests a test set expression matches
+    ///
+    /// Prints the expression in a normalized form and lists the tests it
+    /// matches in the current project.
+    #[command()]
+    TestSet(test_set::Args),
 }
 
 impl Command {
     pub fn run(&self, ctx: &mut Context) -> eyre::Result<()> {
         match self {
             Command::About => about::run(ctx),
-            Command::Clean => clean::run(ctx),
+            Command::Clean(args) => clean::run(ctx, args),
             Command::Fonts(args) => fonts::run(ctx, args),
             Command::Migrate(args) => migrate::run(ctx, args),
+            Command::RepairRefs(args) => repair_refs::run(ctx, args),
+            Command::TestSet(args) => test_set::run(ctx, args),
         }
     }
 }
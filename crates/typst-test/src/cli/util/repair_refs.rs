@@ -0,0 +1,76 @@
+use std::io::Write;
+
+use color_eyre::eyre;
+use lib::doc;
+use lib::stdx::fmt::Term;
+use termcolor::Color;
+
+use crate::cli::Context;
+use crate::ui;
+
+#[derive(clap::Args, Debug, Clone)]
+#[group(id = "util-repair-refs-args")]
+pub struct Args {
+    /// Only report which pages would be renumbered
+    #[arg(long, short = 'n')]
+    pub dry_run: bool,
+}
+
+pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
+    let project = ctx.project()?;
+    let paths = project.paths();
+    let suite = ctx.collect_all_tests(&project)?;
+
+    let mut w = ctx.ui.stderr();
+
+    let mut repaired = 0;
+    for test in suite.matched().values() {
+        if !test.kind().is_persistent() {
+            continue;
+        }
+
+        let dirs = if test.variants().is_empty() {
+            vec![paths.test_ref_dir(test.id())]
+        } else {
+            test.variants()
+                .iter()
+                .map(|variant| paths.test_ref_variant_dir(test.id(), variant))
+                .collect()
+        };
+
+        for dir in dirs {
+            if !dir.try_exists()? {
+                continue;
+            }
+
+            let renamed = doc::repair_page_numbers(&dir, args.dry_run)?;
+            if renamed.is_empty() {
+                continue;
+            }
+
+            repaired += 1;
+            ui::write_ident(&mut w, |w| writeln!(w, "{}", dir.display()))?;
+            for (old, new) in renamed {
+                writeln!(w, "  {old} -> {new}")?;
+            }
+        }
+    }
+
+    write!(
+        w,
+        "{} ",
+        if args.dry_run {
+            "Would repair"
+        } else {
+            "Repaired"
+        }
+    )?;
+    ui::write_bold_colored(&mut w, Color::Green, |w| write!(w, "{repaired}"))?;
+    writeln!(
+        w,
+        " reference {}",
+        Term::irregular("directory", "directories").with(repaired)
+    )?;
+
+    Ok(())
+}
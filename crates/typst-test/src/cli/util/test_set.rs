@@ -0,0 +1,159 @@
+use std::io::{self, Write};
+
+use color_eyre::eyre;
+use lib::stdx::fmt::Term;
+use lib::test::Suite;
+use lib::test_set::TestSetExpr;
+use termcolor::{Color, WriteColor};
+
+use crate::cli::{Context, FilterArgs, OperationFailure};
+use crate::ui::{self, Indented};
+
+#[derive(clap::Args, Debug, Clone)]
+#[group(id = "util-test-set-args")]
+pub struct Args {
+    /// The test set expression to explain
+    pub expression: String,
+
+    /// Don't automatically remove tests marked as skip
+    #[arg(short = 'S', long)]
+    pub no_implicit_skip: bool,
+
+    /// Print the parsed syntax tree of the expression
+    ///
+    /// This prints both the raw syntax tree and the normalized form in which
+    /// chains of unions and intersections are flattened, which helps to debug
+    /// operator precedence.
+    #[arg(long)]
+    pub dump_ast: bool,
+}
+
+pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
+    let project = ctx.project()?;
+
+    let mut expr = match TestSetExpr::parse(&args.expression) {
+        Ok(expr) => expr,
+        Err(err) => {
+            ctx.error_test_set_failure(err, &args.expression)?;
+            eyre::bail!(OperationFailure);
+        }
+    };
+
+    let set = ctx.test_set(&FilterArgs {
+        expression: args.expression.clone(),
+        no_implicit_skip: args.no_implicit_skip,
+        tests: vec![],
+        only: None,
+    })?;
+    let suite = ctx.collect_tests(&project, &set)?;
+
+    let mut w = ctx.ui.stderr();
+    if args.dump_ast {
+        write_ast(&mut w, &expr)?;
+    }
+
+    if !args.no_implicit_skip {
+        expr.add_implicit_skip();
+    }
+    write_explanation(&mut w, &expr.to_string(), &suite)?;
+
+    Ok(())
+}
+
+/// Writes the raw syntax tree and the flattened form of the given expression.
+fn write_ast<W: WriteColor + ?Sized>(w: &mut W, expr: &TestSetExpr) -> io::Result<()> {
+    writeln!(w, "Syntax tree:")?;
+    writeln!(Indented::new(&mut *w, 2), "{:#?}", expr.expr())?;
+
+    write!(w, "Flattened: ")?;
+    ui::write_colored(w, Color::Cyan, |w| write!(w, "{expr}"))?;
+    writeln!(w)
+}
+
+/// Writes the normalized expression and the tests it matched in the given
+/// suite.
+fn write_explanation<W: WriteColor + ?Sized>(
+    w: &mut W,
+    normalized: &str,
+    suite: &Suite,
+) -> io::Result<()> {
+    write!(w, "Expression: ")?;
+    ui::write_colored(w, Color::Cyan, |w| write!(w, "{normalized}"))?;
+    writeln!(w)?;
+
+    let matched = suite.matched().len();
+    write!(w, "Matched ")?;
+    ui::write_bold(w, |w| write!(w, "{matched}"))?;
+    writeln!(
+        w,
+        " of {} {}",
+        suite.len(),
+        Term::simple("test").with(suite.len())
+    )?;
+
+    let mut w = Indented::new(w, 2);
+    for id in suite.matched().keys() {
+        ui::write_test_id(&mut w, id)?;
+        writeln!(w)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use lib::_dev::fs::TempEnv;
+    use lib::test_set::{eval, TestSet};
+    use termcolor::NoColor;
+
+    use super::*;
+    use crate::runner::tests::project;
+
+    #[test]
+    fn test_dump_ast_associativity() {
+        let expr = TestSetExpr::parse("a & b ~ c").unwrap();
+
+        let mut w = NoColor::new(vec![]);
+        write_ast(&mut w, &expr).unwrap();
+
+        let w = String::from_utf8(w.into_inner()).unwrap();
+        let (tree, flattened) = w.split_once("Flattened: ").unwrap();
+
+        // the intersection binds more strongly and is the left operand
+        let diff = tree.find("op: Diff").unwrap();
+        let inter = tree.find("op: Inter").unwrap();
+        assert!(tree.starts_with("Syntax tree:\n  Infix {"));
+        assert!(diff < inter);
+        assert!(tree[inter..].find("\"a\"").unwrap() < tree[inter..].find("\"b\"").unwrap());
+        assert_eq!(flattened, "((a & b) ~ c)\n");
+    }
+
+    #[test]
+    fn test_explain_union() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/a/test.typ", "Hello")
+                    .setup_file("tests/b/test.typ", "Hello")
+                    .setup_file("tests/c/test.typ", "Hello")
+            },
+            |root| {
+                let project = project(root);
+
+                let input = "e:a | exact:b";
+                let expr = TestSetExpr::parse(input).unwrap();
+                let set =
+                    TestSet::parse_and_evaluate(eval::Context::with_built_ins(), input).unwrap();
+                let suite = Suite::collect(project.paths(), &set).unwrap();
+
+                let mut w = NoColor::new(vec![]);
+                write_explanation(&mut w, &expr.to_string(), &suite).unwrap();
+
+                let w = String::from_utf8(w.into_inner()).unwrap();
+                assert_eq!(
+                    w,
+                    "Expression: (exact:\"a\" | exact:\"b\")\nMatched 2 of 3 tests\n  a\n  b\n"
+                );
+            },
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use std::io::{self, Write};
+
+use color_eyre::eyre;
+use lib::config::Config;
+use lib::doc::render::{self, Origin};
+use lib::runner::{Action, RunConfig};
+use lib::stdx::fmt::Term;
+use lib::test::{Id, SuiteResult, TestResultKind};
+
+use super::{CompareArgs, CompileArgs, Context, FilterArgs, OnlyKind, RenderArgs, RunArgs};
+use crate::cli::TestFailure;
+use crate::report::Reporter;
+use crate::runner::Runner;
+use crate::ui::{self, Ui};
+
+#[derive(clap::Args, Debug, Clone)]
+#[group(id = "verify-args")]
+pub struct Args {
+    #[command(flatten)]
+    pub compile: CompileArgs,
+
+    #[command(flatten)]
+    pub compare: CompareArgs,
+
+    #[command(flatten)]
+    pub render: RenderArgs,
+
+    #[command(flatten)]
+    pub run: RunArgs,
+
+    #[command(flatten)]
+    pub filter: FilterArgs,
+}
+
+pub fn run(ctx: &mut Context, args: &Args) -> eyre::Result<()> {
+    let project = ctx.project()?;
+
+    // NOTE(tinger): only persistent tests have stored references to verify
+    let filter = FilterArgs {
+        only: Some(OnlyKind::Persistent),
+        ..args.filter.clone()
+    };
+    let set = ctx.test_set(&filter)?;
+    let suite = ctx.collect_tests(&project, &set)?;
+
+    let world = ctx.world(&args.compile)?;
+    let config = ctx.config(Some(&project))?;
+
+    let runner_config = runner_config(ctx, args, &config)?;
+
+    let reporter = Reporter::new(
+        ctx.ui,
+        &project,
+        &world,
+        ctx.ui.can_live_report() && ctx.args.global.output.verbose == 0,
+    )
+    .with_quiet(ctx.args.global.output.quiet)
+    .with_format(ctx.args.global.output.format)
+    .with_github_annotations(ctx.args.global.output.github_annotations);
+
+    let runner = Runner::new(&project, &suite, &world, runner_config);
+    let result = runner.run(&reporter)?;
+
+    report_out_of_date(ctx.ui, &out_of_date_tests(&result))?;
+
+    if !result.is_complete_pass() {
+        eyre::bail!(TestFailure);
+    }
+
+    Ok(())
+}
+
+/// Creates the runner config for verifying, this never exports any documents
+/// and leaves the temporary directories of the tests untouched. Hooks are
+/// still run, as tests may depend on them for compilation.
+fn runner_config(ctx: &Context, args: &Args, config: &Config) -> eyre::Result<RunConfig<'static>> {
+    let action = Action::Run {
+        strategy: Some(args.compare.strategy(config)),
+        export: false,
+        origin: Origin::default(),
+    };
+
+    Ok(RunConfig {
+        pixel_per_pt: render::ppi_to_ppp(args.render.pixel_per_inch),
+        trim_whitespace: args.compare.trim_whitespace,
+        compare_grayscale: args.compare.compare_grayscale,
+        no_diff: true,
+        clear_temporary_directories: false,
+        ..ctx.runner_config(config, &args.compile, &args.run, action)?
+    })
+}
+
+/// Returns the ids of all tests whose output didn't match their stored
+/// references.
+fn out_of_date_tests(result: &SuiteResult) -> Vec<&Id> {
+    result
+        .results()
+        .iter()
+        .filter(|(_, result)| matches!(result.kind(), Some(TestResultKind::FailedComparison(_))))
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// Reports the given tests with out of date references, if there are any.
+fn report_out_of_date(ui: &Ui, out_of_date: &[&Id]) -> io::Result<()> {
+    if out_of_date.is_empty() {
+        return Ok(());
+    }
+
+    ui.error_hinted_with(
+        |w| {
+            writeln!(
+                w,
+                "The references of {} {} are out of date",
+                out_of_date.len(),
+                Term::simple("test").with(out_of_date.len()),
+            )?;
+            for id in out_of_date {
+                ui::write_test_id(w, id)?;
+                writeln!(w)?;
+            }
+
+            Ok(())
+        },
+        |w| {
+            writeln!(
+                w,
+                "Use 'tt update' to update them if the changes are intentional"
+            )
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use clap::Parser;
+    use lib::_dev::fs::{Setup, TempEnv};
+    use lib::project::Project;
+    use termcolor::ColorChoice;
+
+    use super::*;
+    use crate::cli::update::DEFAULT_MAX_CHANGE;
+    use crate::cli::{Args as CliArgs, Command};
+    use crate::runner::tests::{config, project, run_test, suite};
+    use crate::world::tests::world;
+
+    /// Sets up a project with two persistent tests.
+    fn setup(root: &mut Setup) -> &mut Setup {
+        root.setup_file("tests/fresh/test.typ", "Hello")
+            .setup_file("tests/fresh/ref/.keep", "")
+            .setup_file("tests/stale/test.typ", "Hello")
+            .setup_file("tests/stale/ref/.keep", "")
+    }
+
+    /// Updates the references of both tests and makes one of them stale.
+    fn stale_project(root: &Path) -> Project {
+        let project = project(root);
+        let update = RunConfig {
+            action: Action::Update {
+                export: false,
+                max_change: Some(DEFAULT_MAX_CHANGE),
+                origin: Origin::default(),
+                create_missing: false,
+            },
+            ..config()
+        };
+        assert!(run_test(&project, update.clone(), "fresh").is_pass());
+        assert!(run_test(&project, update, "stale").is_pass());
+
+        fs::write(root.join("tests/stale/test.typ"), "World").unwrap();
+        project
+    }
+
+    /// Parses the arguments of the verify command for the given project root.
+    fn verify_args(root: &Path) -> CliArgs {
+        let root = root.to_string_lossy().into_owned();
+        CliArgs::try_parse_from(["tt", "--root", &root, "--quiet", "verify"]).unwrap()
+    }
+
+    #[test]
+    fn test_out_of_date_tests() {
+        TempEnv::run_no_check(setup, |root| {
+            let project = stale_project(root);
+
+            let ui = Ui::new(ColorChoice::Never, ColorChoice::Never);
+            let cli = verify_args(root);
+            let Command::Verify(args) = &cli.cmd else {
+                unreachable!();
+            };
+            let ctx = Context::new(&cli, &ui);
+            let config = ctx.config(Some(&project)).unwrap();
+            let config = runner_config(&ctx, args, &config).unwrap();
+
+            assert!(config.no_diff);
+            assert!(!config.clear_temporary_directories);
+            assert!(matches!(config.action, Action::Run { export: false, .. }));
+
+            let (suite, world) = (suite(&project), world(None));
+            let reporter = Reporter::new(&ui, &project, &world, false).with_quiet(true);
+            let result = Runner::new(&project, &suite, &world, config)
+                .run(&reporter)
+                .unwrap();
+
+            assert!(!result.is_complete_pass());
+            assert_eq!(out_of_date_tests(&result), [&Id::new("stale").unwrap()]);
+        });
+    }
+
+    #[test]
+    fn test_verify_leaves_temporary_directories() {
+        TempEnv::run_no_check(setup, |root| {
+            stale_project(root);
+            for dir in ["tests/stale/out", "tests/stale/diff"] {
+                fs::create_dir_all(root.join(dir)).unwrap();
+                fs::write(root.join(dir).join("1.png"), "previous").unwrap();
+            }
+
+            let ui = Ui::new(ColorChoice::Never, ColorChoice::Never);
+            let cli = verify_args(root);
+            let Command::Verify(args) = &cli.cmd else {
+                unreachable!();
+            };
+            let mut ctx = Context::new(&cli, &ui);
+
+            let err = run(&mut ctx, args).unwrap_err();
+            assert!(err.is::<TestFailure>());
+
+            // nothing is exported or cleared when verifying
+            for dir in ["tests/stale/out", "tests/stale/diff"] {
+                assert_eq!(
+                    fs::read_to_string(root.join(dir).join("1.png")).unwrap(),
+                    "previous"
+                );
+            }
+        });
+    }
+}
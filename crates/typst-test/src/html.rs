@@ -0,0 +1,409 @@
+//! Self-contained HTML reports for reviewing failed tests.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use lib::doc::compare::{self, PageError};
+use lib::doc::{self, Document};
+use lib::project::Project;
+use lib::stdx::fmt::Term;
+use lib::stdx::result::ResultEx;
+use lib::test::{Id, Suite, SuiteResult, Test, TestResult, TestResultKind};
+
+use crate::runner::Outcomes;
+
+/// The inline style sheet of the report.
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; }
+section { border-top: 1px solid #ccc; padding: 1em 0; }
+pre { background: #f4f4f4; padding: 0.5em; white-space: pre-wrap; }
+table { border-collapse: collapse; }
+th, td { padding: 0.25em 0.5em; text-align: left; vertical-align: top; }
+img { max-width: 300px; border: 1px solid #ccc; }
+";
+
+/// Writes an HTML report of all failed tests in the given result, the report
+/// embeds the reference, output and difference pages of each test as base64
+/// encoded PNG images.
+///
+/// Pages are only included if they were exported during the test run. If the
+/// suite was run repeatedly, the given result is that of the last run and the
+/// tests which were flaky across all runs are listed before the failures.
+pub fn write_report<W: Write>(
+    w: &mut W,
+    project: &Project,
+    suite: &Suite,
+    result: &SuiteResult,
+    outcomes: &BTreeMap<Id, Outcomes>,
+) -> io::Result<()> {
+    writeln!(w, "<!DOCTYPE html>")?;
+    writeln!(w, "<html>")?;
+    writeln!(w, "<head>")?;
+    writeln!(w, "<meta charset=\"utf-8\">")?;
+    writeln!(w, "<title>{} report</title>", lib::TOOL_NAME)?;
+    writeln!(w, "<style>\n{STYLE}</style>")?;
+    writeln!(w, "</head>")?;
+    writeln!(w, "<body>")?;
+    writeln!(
+        w,
+        "<h1>{} of {} tests failed</h1>",
+        result.failed(),
+        result.run(),
+    )?;
+
+    write_flaky(w, outcomes)?;
+
+    for (id, test_result) in result.results() {
+        if !test_result.is_fail() {
+            continue;
+        }
+
+        let Some(test) = suite.matched().get(id) else {
+            continue;
+        };
+
+        write_test(w, project, test, test_result)?;
+    }
+
+    writeln!(w, "</body>")?;
+    writeln!(w, "</html>")?;
+
+    Ok(())
+}
+
+/// Writes the list of tests which passed in some and failed in other runs, if
+/// there are any.
+fn write_flaky<W: Write>(w: &mut W, outcomes: &BTreeMap<Id, Outcomes>) -> io::Result<()> {
+    let flaky: Vec<_> = outcomes
+        .iter()
+        .filter(|(_, outcomes)| outcomes.is_flaky())
+        .collect();
+
+    if flaky.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(w, "<section class=\"flaky\">")?;
+    writeln!(
+        w,
+        "<h2>{} flaky {}</h2>",
+        flaky.len(),
+        Term::simple("test").with(flaky.len()),
+    )?;
+    writeln!(w, "<ul>")?;
+    for (id, outcomes) in flaky {
+        writeln!(
+            w,
+            "<li>{} failed {} of {} runs</li>",
+            escape(id.as_str()),
+            outcomes.failed,
+            outcomes.passed + outcomes.failed,
+        )?;
+    }
+    writeln!(w, "</ul>")?;
+    writeln!(w, "</section>")?;
+
+    Ok(())
+}
+
+fn write_test<W: Write>(
+    w: &mut W,
+    project: &Project,
+    test: &Test,
+    result: &TestResult,
+) -> io::Result<()> {
+    let paths = project.paths();
+
+    writeln!(
+        w,
+        "<section class=\"test\" id=\"{}\">",
+        escape(test.id().as_str())
+    )?;
+    write!(w, "<h2>{}", escape(test.id().as_str()))?;
+    if let Some(variant) = result.variant() {
+        write!(w, " (variant {})", escape(variant))?;
+    }
+    writeln!(w, "</h2>")?;
+
+    match result.kind() {
+        Some(TestResultKind::FailedCompilation { error, reference }) => {
+            writeln!(
+                w,
+                "<p>Compilation of {} failed</p>",
+                if *reference { "reference" } else { "test" },
+            )?;
+
+            writeln!(w, "<pre>")?;
+            for diagnostic in error.0.iter().chain(result.warnings()) {
+                writeln!(
+                    w,
+                    "{:?}: {}",
+                    diagnostic.severity,
+                    escape(&diagnostic.message)
+                )?;
+                for hint in &diagnostic.hints {
+                    writeln!(w, "  hint: {}", escape(hint))?;
+                }
+            }
+            writeln!(w, "</pre>")?;
+        }
+        Some(TestResultKind::FailedComparison(compare::Error {
+            output,
+            reference,
+            pages,
+        })) => {
+            if output != reference {
+                writeln!(w, "<p>Expected {reference} pages, got {output} pages</p>")?;
+            }
+
+            for (p, e) in pages {
+                let p = p + 1;
+                match e {
+                    PageError::Dimensions { output, reference } => writeln!(
+                        w,
+                        "<p>Page {p} had different dimensions, \
+                        output: {output}, reference: {reference}</p>",
+                    )?,
+                    PageError::SimpleDeviations { deviations } => {
+                        writeln!(w, "<p>Page {p} had {deviations} deviations</p>")?
+                    }
+                }
+            }
+        }
+        Some(TestResultKind::FailedUpdate { change }) => {
+            writeln!(
+                w,
+                "<p>Update would have changed {:.1}% of the reference</p>",
+                change * 100.0,
+            )?;
+        }
+        Some(TestResultKind::FailedHook {
+            hook,
+            code,
+            stdout,
+            stderr,
+        }) => {
+            match code {
+                Some(code) => writeln!(
+                    w,
+                    "<p>Hook {} failed with exit code {code}</p>",
+                    escape(hook)
+                )?,
+                None => writeln!(w, "<p>Hook {} was terminated by a signal</p>", escape(hook))?,
+            }
+
+            for output in [stdout, stderr] {
+                if !output.trim().is_empty() {
+                    writeln!(w, "<pre>{}</pre>", escape(output))?;
+                }
+            }
+        }
+        Some(TestResultKind::FailedIo { error, .. }) => {
+            writeln!(w, "<p>An io error occurred</p>")?;
+            writeln!(w, "<pre>{}</pre>", escape(error))?;
+        }
+        _ => {}
+    }
+
+    let (ref_dir, out_dir, diff_dir) = match result.variant() {
+        Some(variant) => (
+            paths.test_ref_variant_dir(test.id(), variant),
+            paths.test_out_variant_dir(test.id(), variant),
+            paths.test_diff_variant_dir(test.id(), variant),
+        ),
+        None => (
+            paths.test_ref_dir(test.id()),
+            paths.test_out_dir(test.id()),
+            paths.test_diff_dir(test.id()),
+        ),
+    };
+
+    // NOTE(tinger): persistent references may be stored in the object store,
+    // so we load them like the runner does instead of reading the directory
+    let references = if test.kind().is_persistent() {
+        let reference = match result.variant() {
+            Some(variant) => test.load_variant_reference_documents(paths, variant),
+            None => test.load_reference_documents(paths),
+        };
+
+        match reference {
+            Ok(Some(doc)) => encode_pages(&doc)?,
+            _ => vec![],
+        }
+    } else {
+        read_pages(&ref_dir)?
+    };
+    let outputs = read_pages(&out_dir)?;
+    let diffs = read_pages(&diff_dir)?;
+
+    let len = [references.len(), outputs.len(), diffs.len()]
+        .into_iter()
+        .max()
+        .unwrap_or(0);
+
+    if len != 0 {
+        writeln!(w, "<table>")?;
+        writeln!(
+            w,
+            "<tr><th>Page</th><th>Reference</th><th>Output</th><th>Difference</th></tr>"
+        )?;
+        for idx in 0..len {
+            write!(w, "<tr><td>{}</td>", idx + 1)?;
+            for pages in [&references, &outputs, &diffs] {
+                write!(w, "<td>")?;
+                if let Some(Some(png)) = pages.get(idx) {
+                    write!(
+                        w,
+                        "<img src=\"data:image/png;base64,{}\">",
+                        STANDARD.encode(png)
+                    )?;
+                }
+                write!(w, "</td>")?;
+            }
+            writeln!(w, "</tr>")?;
+        }
+        writeln!(w, "</table>")?;
+    }
+
+    writeln!(w, "</section>")?;
+
+    Ok(())
+}
+
+/// Reads the PNG pages in the given directory by their page number, missing
+/// pages are `None`, a missing directory has no pages.
+fn read_pages(dir: &Path) -> io::Result<Vec<Option<Vec<u8>>>> {
+    let Some(paths) = doc::page_paths(dir).ignore_io_kind(io::ErrorKind::NotFound)? else {
+        return Ok(vec![]);
+    };
+
+    let len = paths.keys().next_back().copied().unwrap_or(0);
+    let mut pages = vec![None; len];
+    for (page, path) in paths {
+        pages[page - 1] = Some(fs::read(path)?);
+    }
+
+    Ok(pages)
+}
+
+/// Encodes the pages of the given document as PNGs.
+fn encode_pages(doc: &Document) -> io::Result<Vec<Option<Vec<u8>>>> {
+    doc.buffers()
+        .iter()
+        .map(|page| {
+            page.encode_png()
+                .map(Some)
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+        })
+        .collect()
+}
+
+/// Escapes the given text for use in HTML text and attribute values.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use lib::_dev::fs::TempEnv;
+
+    use super::*;
+    use crate::runner::tests::{config, project, run_test, suite};
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("<a href=\"x\">&'</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn test_write_report() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/pass/test.typ", "Hello")
+                    .setup_file("tests/pass/ref.typ", "Hello")
+                    .setup_file("tests/fail-a/test.typ", "Hello")
+                    .setup_file("tests/fail-a/ref.typ", "World")
+                    .setup_file("tests/fail-b/test.typ", "Hello")
+                    .setup_file("tests/fail-b/ref.typ", "Foo")
+            },
+            |root| {
+                let project = project(root);
+                let suite = suite(&project);
+                let mut result = SuiteResult::new(&suite);
+                for id in ["pass", "fail-a", "fail-b"] {
+                    let test_result = run_test(&project, config(), id);
+                    result.set_test_result(Id::new(id).unwrap(), test_result);
+                }
+
+                let mut w = vec![];
+                write_report(&mut w, &project, &suite, &result, &BTreeMap::new()).unwrap();
+                let html = String::from_utf8(w).unwrap();
+
+                assert_eq!(html.matches("<section class=\"test\"").count(), 2);
+                assert!(!html.contains("<section class=\"flaky\""));
+                assert!(html.contains("id=\"fail-a\""));
+                assert!(html.contains("id=\"fail-b\""));
+                assert!(!html.contains("id=\"pass\""));
+
+                // reference, output and difference of each failed test
+                let images = html.matches("<img src=\"data:image/png;base64,").count();
+                assert_eq!(images, 6);
+                for img in html.split("<img src=\"data:image/png;base64,").skip(1) {
+                    let (data, _) = img.split_once('"').unwrap();
+                    assert!(STANDARD.decode(data).unwrap().starts_with(b"\x89PNG"));
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_write_flaky() {
+        let outcomes = BTreeMap::from([
+            (
+                Id::new("flaky").unwrap(),
+                Outcomes {
+                    passed: 3,
+                    failed: 1,
+                },
+            ),
+            (
+                Id::new("stable").unwrap(),
+                Outcomes {
+                    passed: 4,
+                    failed: 0,
+                },
+            ),
+        ]);
+
+        let mut w = vec![];
+        write_flaky(&mut w, &outcomes).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "<section class=\"flaky\">\n\
+            <h2>1 flaky test</h2>\n\
+            <ul>\n\
+            <li>flaky failed 1 of 4 runs</li>\n\
+            </ul>\n\
+            </section>\n"
+        );
+    }
+}
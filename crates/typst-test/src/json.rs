@@ -1,10 +1,21 @@
 //! Common report PODs for stable JSON representation of internal entities.
 
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use lib::doc::{render, Document};
 use lib::project::Project;
-use lib::test::{Suite, Test};
+use lib::test::{Id, Suite, SuiteResult, Test, TestResult, TestResultKind};
 use serde::Serialize;
+use typst::foundations::Dict;
+use typst::World;
 use typst_syntax::package::PackageVersion;
 
+use crate::runner::Outcomes;
+use crate::world::SystemWorld;
+
 #[derive(Debug, Serialize)]
 pub struct ProjectJson<'p, 's> {
     pub package: Option<PackageJson<'p>>,
@@ -37,6 +48,8 @@ pub struct PackageJson<'p> {
 pub struct TestJson<'t> {
     pub id: &'t str,
     pub kind: &'static str,
+    pub skipped: bool,
+    pub skip_reason: Option<&'t str>,
 }
 
 impl<'t> TestJson<'t> {
@@ -44,21 +57,233 @@ impl<'t> TestJson<'t> {
         Self {
             id: test.id().as_str(),
             kind: test.kind().as_str(),
+            skipped: test.is_skip(),
+            skip_reason: test.skip_reason(),
         }
     }
 }
 
 #[derive(Debug, Serialize)]
-pub struct FontVariantJson {
+pub struct FontVariantJson<'f> {
     pub style: &'static str,
     pub weight: u16,
     pub stretch: f64,
+    pub source: &'static str,
+    pub path: Option<&'f Path>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct FontJson<'f> {
     pub name: &'f str,
-    pub variants: Vec<FontVariantJson>,
+    pub sources: Vec<&'static str>,
+    pub variants: Vec<FontVariantJson<'f>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConfigEntryJson {
+    pub key: &'static str,
+    pub value: Option<serde_json::Value>,
+    pub layer: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+pub struct RunJson<'w, 's> {
+    pub environment: EnvironmentJson<'w>,
+    #[serde(flatten)]
+    pub summary: SummaryJson,
+    pub tests: Vec<RunTestJson<'s>>,
+}
+
+impl<'w, 's> RunJson<'w, 's> {
+    /// Creates the report of the last of possibly repeated runs, the outcomes
+    /// of each test across all runs are included with its result.
+    pub fn new(
+        world: &'w SystemWorld,
+        result: &'s SuiteResult,
+        outcomes: &BTreeMap<Id, Outcomes>,
+    ) -> Self {
+        Self {
+            environment: EnvironmentJson::new(world),
+            summary: SummaryJson::new(result),
+            tests: result
+                .results()
+                .iter()
+                .map(|(id, result)| RunTestJson {
+                    result: TestResultJson::new(id, result),
+                    repetitions: RepetitionsJson::new(
+                        outcomes.get(id).copied().unwrap_or_default(),
+                    ),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct RunTestJson<'s> {
+    #[serde(flatten)]
+    pub result: TestResultJson<'s>,
+    pub repetitions: RepetitionsJson,
+}
+
+#[derive(Serialize)]
+pub struct RepetitionsJson {
+    pub passed: usize,
+    pub failed: usize,
+    pub flaky: bool,
+}
+
+impl RepetitionsJson {
+    pub fn new(outcomes: Outcomes) -> Self {
+        Self {
+            passed: outcomes.passed,
+            failed: outcomes.failed,
+            flaky: outcomes.is_flaky(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SummaryJson {
+    pub total: usize,
+    pub filtered: usize,
+    pub passed: usize,
+    pub failed: FailedJson,
+    pub duration: DurationJson,
+}
+
+impl SummaryJson {
+    pub fn new(result: &SuiteResult) -> Self {
+        let mut failed = FailedJson {
+            compilation: 0,
+            comparison: 0,
+            otherwise: 0,
+        };
+
+        for result in result.results().values() {
+            match result.kind() {
+                Some(TestResultKind::FailedCompilation { .. }) => failed.compilation += 1,
+                Some(TestResultKind::FailedComparison(_)) => failed.comparison += 1,
+                Some(
+                    TestResultKind::FailedUpdate { .. }
+                    | TestResultKind::FailedHook { .. }
+                    | TestResultKind::FailedIo { .. },
+                ) => failed.otherwise += 1,
+                _ => {}
+            }
+        }
+
+        Self {
+            total: result.total(),
+            filtered: result.filtered(),
+            passed: result.passed(),
+            failed,
+            duration: DurationJson::new(result.duration()),
+        }
+    }
+}
+
+/// A single line of the `json-lines` report format, each line is tagged with
+/// its type.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum JsonLine<'s> {
+    Test(TestResultJson<'s>),
+    Summary(SummaryJson),
+}
+
+/// The inputs of a test run which influence its outcome, recorded to make
+/// failures reproducible.
+// NOTE(tinger): typst compilation is deterministic, so there is no seed or
+// similar state to record here
+#[derive(Serialize)]
+pub struct EnvironmentJson<'w> {
+    pub typst_test: &'static str,
+    pub typst: &'static str,
+    pub timestamp: Option<i64>,
+    pub inputs: &'w Dict,
+    pub fonts: String,
+}
+
+impl<'w> EnvironmentJson<'w> {
+    pub fn new(world: &'w SystemWorld) -> Self {
+        Self {
+            typst_test: env!("CARGO_PKG_VERSION"),
+            typst: crate::TYPST_VERSION,
+            timestamp: world.now().map(|now| now.timestamp()),
+            inputs: world.inputs(),
+            fonts: format!("{:032x}", typst::utils::hash128(world.book())),
+        }
+    }
+}
+
+/// The settings an exported document was rendered with, this makes exported
+/// artifacts self-describing.
+#[derive(Serialize)]
+pub struct ArtifactMetadataJson {
+    pub typst_test: &'static str,
+    pub typst: &'static str,
+    pub pixel_per_inch: f32,
+    pub pixel_per_pt: f32,
+    pub pages: Vec<PageSizeJson>,
+}
+
+impl ArtifactMetadataJson {
+    pub fn new(doc: &Document, pixel_per_pt: f32) -> Self {
+        Self {
+            typst_test: env!("CARGO_PKG_VERSION"),
+            typst: crate::TYPST_VERSION,
+            pixel_per_inch: render::ppp_to_ppi(pixel_per_pt),
+            pixel_per_pt,
+            pages: doc
+                .buffers()
+                .iter()
+                .map(|page| PageSizeJson {
+                    width: page.width(),
+                    height: page.height(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Serializes the metadata of the given document, this is passed to the
+    /// runner to write the metadata sidecar of exported documents.
+    pub fn export(doc: &Document, pixel_per_pt: f32) -> io::Result<Vec<u8>> {
+        Ok(serde_json::to_vec_pretty(&Self::new(doc, pixel_per_pt))?)
+    }
+}
+
+#[derive(Serialize)]
+pub struct PageSizeJson {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Serialize)]
+pub struct TestResultJson<'s> {
+    pub id: &'s str,
+    pub variant: Option<&'s str>,
+    pub status: &'static str,
+    pub duration: DurationJson,
+}
+
+impl<'s> TestResultJson<'s> {
+    pub fn new(id: &'s Id, result: &'s TestResult) -> Self {
+        Self {
+            id: id.as_str(),
+            variant: result.variant(),
+            status: if result.is_pass() {
+                "passed"
+            } else if result.is_fail() {
+                "failed"
+            } else if result.is_filtered() {
+                "filtered"
+            } else {
+                "cancelled"
+            },
+            duration: DurationJson::new(result.duration()),
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -73,3 +298,87 @@ pub struct DurationJson {
     pub seconds: u64,
     pub nanoseconds: u32,
 }
+
+impl DurationJson {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            seconds: duration.as_secs(),
+            nanoseconds: duration.subsec_nanos(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lib::_dev::fs::TempEnv;
+    use lib::_dev::result::{failed, suite_result};
+
+    use super::*;
+    use crate::runner::tests::{project, test};
+    use crate::world::tests::world;
+
+    #[test]
+    fn test_test_json_skip_reason() {
+        TempEnv::run_no_check(
+            |root| {
+                root.setup_file("tests/reason/test.typ", "/// [skip: flaky]\nHello")
+                    .setup_file("tests/no-reason/test.typ", "/// [skip]\nHello")
+                    .setup_file("tests/run/test.typ", "Hello")
+            },
+            |root| {
+                let project = project(root);
+                let json = |id| serde_json::to_value(TestJson::new(&test(&project, id))).unwrap();
+
+                let reason = json("reason");
+                assert_eq!(reason["skipped"], true);
+                assert_eq!(reason["skip_reason"], "flaky");
+
+                let no_reason = json("no-reason");
+                assert_eq!(no_reason["skipped"], true);
+                assert_eq!(no_reason["skip_reason"], serde_json::Value::Null);
+
+                let run = json("run");
+                assert_eq!(run["skipped"], false);
+                assert_eq!(run["skip_reason"], serde_json::Value::Null);
+            },
+        );
+    }
+
+    #[test]
+    fn test_run_json_environment() {
+        let world = world(chrono::DateTime::from_timestamp(1234567890, 0));
+        let result = SuiteResult::new(&Suite::new());
+
+        let json = serde_json::to_value(RunJson::new(&world, &result, &BTreeMap::new())).unwrap();
+        let environment = &json["environment"];
+
+        assert_eq!(environment["typst_test"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(environment["typst"], crate::TYPST_VERSION);
+        assert_eq!(environment["timestamp"], 1234567890);
+        assert!(environment["inputs"].is_object());
+        assert_eq!(environment["fonts"].as_str().unwrap().len(), 32);
+        assert_eq!(json["tests"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_run_json_repetitions() {
+        let world = world(None);
+        let result = suite_result(&[("flaky", Some(failed(Duration::ZERO)))]);
+        let outcomes = BTreeMap::from([(
+            Id::new("flaky").unwrap(),
+            Outcomes {
+                passed: 2,
+                failed: 1,
+            },
+        )]);
+
+        let json = serde_json::to_value(RunJson::new(&world, &result, &outcomes)).unwrap();
+        let test = &json["tests"][0];
+        assert_eq!(test["id"], "flaky");
+        assert_eq!(test["status"], "failed");
+        assert_eq!(
+            test["repetitions"],
+            serde_json::json!({ "passed": 2, "failed": 1, "flaky": true })
+        );
+    }
+}
@@ -1,6 +1,7 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use color_eyre::eyre;
+use typst::foundations::{Dict, IntoValue};
 use typst_kit::download::Downloader;
 use typst_kit::fonts::{FontSearcher, Fonts};
 use typst_kit::package::PackageStorage;
@@ -19,6 +20,11 @@ pub fn world(
         fonts_from_args(font_args),
         package_storage_from_args(package_args),
         compile_args.now,
+        compile_args
+            .inputs
+            .iter()
+            .map(|(key, value)| (key.as_str().into(), value.as_str().into_value()))
+            .collect::<Dict>(),
     )?;
 
     Ok(world)
@@ -45,17 +51,77 @@ pub fn fonts_from_args(args: &FontArgs) -> Fonts {
     let _span = tracing::debug_span!(
         "searching for fonts",
         paths = ?args.font_paths,
-        include_system_fonts = ?!args.ignore_system_fonts,
+        include_system_fonts = ?!(args.ignore_system_fonts || args.hermetic_fonts),
     );
 
     let mut searcher = FontSearcher::new();
 
     #[cfg(feature = "embed-fonts")]
     searcher.include_embedded_fonts(true);
-    searcher.include_system_fonts(!args.ignore_system_fonts);
+    searcher.include_system_fonts(!(args.ignore_system_fonts || args.hermetic_fonts));
 
     let fonts = searcher.search_with(args.font_paths.iter().map(PathBuf::as_path));
 
     tracing::debug!(fonts = ?fonts.fonts.len(), "collected fonts");
     fonts
 }
+
+/// Where a font was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FontSource {
+    /// The font is embedded in the binary.
+    Embedded,
+
+    /// The font was found in one of the directories given by `--font-path`.
+    FontPath,
+
+    /// The font was found in the system font directories.
+    System,
+}
+
+impl FontSource {
+    /// Classifies a font by its path, fonts without a path are embedded.
+    pub fn classify(path: Option<&Path>, font_paths: &[PathBuf]) -> Self {
+        match path {
+            None => Self::Embedded,
+            Some(path) if font_paths.iter().any(|dir| path.starts_with(dir)) => Self::FontPath,
+            Some(_) => Self::System,
+        }
+    }
+
+    /// Returns a kebab-case string representing this source.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Embedded => "embedded",
+            Self::FontPath => "font-path",
+            Self::System => "system",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_font_source_classify() {
+        let font_paths = [PathBuf::from("fonts"), PathBuf::from("/opt/fonts")];
+
+        assert_eq!(
+            FontSource::classify(None, &font_paths),
+            FontSource::Embedded
+        );
+        assert_eq!(
+            FontSource::classify(Some(Path::new("fonts/a.ttf")), &font_paths),
+            FontSource::FontPath,
+        );
+        assert_eq!(
+            FontSource::classify(Some(Path::new("/opt/fonts/b/c.otf")), &font_paths),
+            FontSource::FontPath,
+        );
+        assert_eq!(
+            FontSource::classify(Some(Path::new("/usr/share/fonts/d.ttf")), &font_paths),
+            FontSource::System,
+        );
+    }
+}
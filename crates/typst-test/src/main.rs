@@ -20,6 +20,7 @@ use crate::cli::{Args, OperationFailure, TestFailure};
 use crate::ui::Ui;
 
 mod cli;
+mod html;
 mod json;
 mod kit;
 mod report;
@@ -27,23 +28,29 @@ mod runner;
 mod ui;
 mod world;
 
+/// The version of typst this binary was built against.
+pub const TYPST_VERSION: &str = "0.12.0";
+
 /// The default optimization options to use.
 pub static DEFAULT_OPTIMIZE_OPTIONS: Lazy<oxipng::Options> =
     Lazy::new(oxipng::Options::max_compression);
 
 fn main() -> ExitCode {
-    match main_impl() {
+    let args = Args::parse();
+    let silent = args.global.output.silent;
+
+    match main_impl(args) {
         Ok(code) => code,
         Err(err) => {
-            eprintln!("{err:?}");
+            if !silent {
+                eprintln!("{err:?}");
+            }
             ExitCode::from(cli::EXIT_ERROR)
         }
     }
 }
 
-fn main_impl() -> eyre::Result<ExitCode> {
-    let args = Args::parse();
-
+fn main_impl(args: Args) -> eyre::Result<ExitCode> {
     color_eyre::install()?;
 
     let cc = match args.global.output.color {
@@ -52,7 +59,11 @@ fn main_impl() -> eyre::Result<ExitCode> {
         clap::ColorChoice::Never => termcolor::ColorChoice::Never,
     };
 
-    let ui = Ui::new(cc, cc);
+    let ui = if args.global.output.silent {
+        Ui::silent()
+    } else {
+        Ui::new(cc, cc)
+    };
 
     // this is a hack, termcolor does not expose any way for us to easily reuse
     // their internal mechanism of checking whether the given stream is color
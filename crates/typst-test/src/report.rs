@@ -1,27 +1,41 @@
 //! Live reporting of test progress.
 
+use std::collections::HashMap;
 use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use codespan_reporting::diagnostic::{Diagnostic, Label};
+use codespan_reporting::files;
 use codespan_reporting::term;
-use color_eyre::eyre;
 use ecow::eco_format;
 use lib::doc::compare::{self, PageError};
+use lib::doc::{self, SaveStats};
 use lib::project::Project;
-use lib::stdx::fmt::Term;
-use lib::test::{SuiteResult, Test, TestResult, TestResultKind};
+use lib::runner::{self, Reporter as _};
+use lib::stdx::fmt::{ByteSize, Term};
+use lib::test::{Id, Suite, SuiteResult, Test, TestResult, TestResultKind};
+use serde::Serialize;
 use termcolor::{Color, WriteColor};
 use typst::diag::{Severity, SourceDiagnostic};
-use typst::WorldExt;
-use typst_syntax::{FileId, Span};
+use typst::{World, WorldExt};
+use typst_syntax::{FileId, Source, Span};
 
-use crate::ui::{self, Ui};
+use crate::json::{JsonLine, SummaryJson, TestResultJson};
+use crate::ui::{self, Indented, Ui};
 use crate::world::SystemWorld;
 
 /// The padding to use for annotations while test run reporting.
 const RUN_ANNOT_PADDING: usize = 10;
 
+/// The inner width of the live progress bar.
+const PROGRESS_BAR_WIDTH: usize = 20;
+
+/// The maximum width of the id column in the failure table, longer ids are
+/// truncated.
+const FAILURE_ID_MAX_WIDTH: usize = 40;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum When {
     Never,
@@ -32,6 +46,28 @@ pub enum When {
     Always,
 }
 
+/// The format in which test results are reported.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// Colored and annotated output with diagnostics for humans.
+    #[default]
+    Human,
+
+    /// One uncolored line per test on stdout, stable for scripts.
+    ///
+    /// Lines are of the form `PASS <id>`, `FAIL <id> <reason>`, `SKIP <id>`
+    /// for tests with a skip annotation or `CANCEL <id>` for tests which were
+    /// not run because the run was aborted. Tests filtered out by the test set
+    /// are not written.
+    Plain,
+
+    /// One JSON object per test on stdout, written as soon as the test
+    /// finished, followed by a summary object.
+    ///
+    /// Each object has a `type` field which is either `test` or `summary`.
+    JsonLines,
+}
+
 /// A reporter for test output and test run status reporting.
 pub struct Reporter<'ui, 'p> {
     ui: &'ui Ui,
@@ -39,9 +75,14 @@ pub struct Reporter<'ui, 'p> {
     world: &'p SystemWorld,
 
     live: bool,
+    quiet: bool,
+    format: Format,
+    github_annotations: bool,
+    slowest: usize,
     warnings: When,
     errors: bool,
     diagnostic_config: term::Config,
+    sources: HashMap<FileId, Source>,
 }
 
 impl<'ui, 'p> Reporter<'ui, 'p> {
@@ -51,6 +92,10 @@ impl<'ui, 'p> Reporter<'ui, 'p> {
             project,
             world,
             live,
+            quiet: false,
+            format: Format::Human,
+            github_annotations: false,
+            slowest: 0,
             warnings: When::Always,
             errors: true,
             diagnostic_config: term::Config {
@@ -58,13 +103,56 @@ impl<'ui, 'p> Reporter<'ui, 'p> {
                 tab_width: 2,
                 ..Default::default()
             },
+            sources: HashMap::new(),
         }
     }
 }
 
-impl Reporter<'_, '_> {
+impl<'ui, 'p> Reporter<'ui, 'p> {
+    /// Whether to omit the start of a test run and reports of individual
+    /// tests, the summary of a test run is still reported.
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// The format in which to report tests, the plain format disables live
+    /// reporting.
+    pub fn with_format(mut self, format: Format) -> Self {
+        self.format = format;
+        self.live &= format == Format::Human;
+        self
+    }
+
+    /// Whether to additionally emit GitHub Actions workflow commands on stdout
+    /// for test failures.
+    pub fn with_github_annotations(mut self, github_annotations: bool) -> Self {
+        self.github_annotations = github_annotations;
+        self
+    }
+
+    /// How many of the slowest tests to list after the summary of a test run.
+    pub fn with_slowest(mut self, slowest: usize) -> Self {
+        self.slowest = slowest;
+        self
+    }
+
+    /// In-memory sources which diagnostics may point into, these are used for
+    /// sources which don't exist on disk, like those of doc tests.
+    pub fn with_sources(mut self, sources: impl IntoIterator<Item = Source>) -> Self {
+        self.sources
+            .extend(sources.into_iter().map(|source| (source.id(), source)));
+        self
+    }
+}
+
+impl runner::Reporter for Reporter<'_, '_> {
     /// Reports the start of a test run.
-    pub fn report_start(&self, result: &SuiteResult) -> io::Result<()> {
+    fn report_start(&self, result: &SuiteResult) -> io::Result<()> {
+        if self.format != Format::Human || self.quiet {
+            return Ok(());
+        }
+
         let mut w = self.ui.stderr();
 
         ui::write_annotated(&mut w, "Starting", Color::Green, RUN_ANNOT_PADDING, |w| {
@@ -87,159 +175,89 @@ impl Reporter<'_, '_> {
     }
 
     /// Reports the end of a test run.
-    pub fn report_end(&self, result: &SuiteResult) -> io::Result<()> {
-        let mut w = self.ui.stderr();
-
-        let color = if result.failed() == 0 {
-            Color::Green
-        } else if result.passed() == 0 {
-            Color::Red
-        } else {
-            Color::Yellow
-        };
-
-        writeln!(w, "{:─>RUN_ANNOT_PADDING$}", "")?;
-
-        ui::write_annotated(&mut w, "Summary", color, RUN_ANNOT_PADDING, |w| {
-            write!(w, "[")?;
-            ui::write_colored(
-                w,
-                duration_color(
-                    result
-                        .duration()
-                        .checked_div(result.run() as u32)
-                        .unwrap_or_default(),
-                ),
-                |w| write_duration(w, result.duration()),
-            )?;
-            write!(w, "] ")?;
+    fn report_end(&self, suite: &Suite, result: &SuiteResult) -> io::Result<()> {
+        if self.format == Format::Plain {
+            if self.quiet {
+                return Ok(());
+            }
 
-            ui::write_bold(w, |w| write!(w, "{}", result.run()))?;
-            write!(w, "/")?;
-            ui::write_bold(w, |w| write!(w, "{}", result.expected()))?;
-            write!(w, " tests run: ")?;
+            let mut w = self.ui.stdout();
+            for (id, result) in result.results() {
+                if result.is_pass() || result.is_fail() {
+                    continue;
+                }
 
-            if result.passed() == result.total() {
-                ui::write_bold(w, |w| write!(w, "all {}", result.passed()))?;
-                write!(w, " ")?;
-                ui::write_colored(w, Color::Green, |w| write!(w, "passed"))?;
-            } else if result.failed() == result.total() {
-                ui::write_bold(w, |w| write!(w, "all {}", result.failed()))?;
-                write!(w, " ")?;
-                ui::write_colored(w, Color::Red, |w| write!(w, "failed"))?;
-            } else {
-                ui::write_bold(w, |w| write!(w, "{}", result.passed()))?;
-                write!(w, " ")?;
-                ui::write_colored(w, Color::Green, |w| write!(w, "passed"))?;
+                // NOTE(tinger): tests filtered out by the test set were never
+                // meant to run, only those skipped by annotation are reported
+                let test = suite.matched().get(id).or(suite.filtered().get(id));
+                if result.is_filtered() && !test.is_some_and(Test::is_skip) {
+                    continue;
+                }
 
-                write!(w, ", ")?;
-                ui::write_bold(w, |w| write!(w, "{}", result.failed()))?;
-                write!(w, " ")?;
-                ui::write_colored(w, Color::Red, |w| write!(w, "failed"))?;
+                write_plain_line(&mut w, id, result, test.and_then(Test::skip_reason))?;
             }
 
-            if result.filtered() != 0 {
-                write!(w, ", ")?;
-                ui::write_bold(w, |w| write!(w, "{}", result.filtered()))?;
-                write!(w, " ")?;
-                ui::write_colored(w, Color::Yellow, |w| write!(w, "filtered"))?;
-            }
+            return Ok(());
+        }
 
-            if result.skipped() != 0 {
-                write!(w, ", ")?;
-                ui::write_bold(w, |w| write!(w, "{}", result.skipped()))?;
-                write!(w, " ")?;
-                ui::write_colored(w, Color::Yellow, |w| write!(w, "skipped"))?;
-            }
+        if self.format == Format::JsonLines {
+            return write_json_line(
+                &mut self.ui.stdout(),
+                &JsonLine::Summary(SummaryJson::new(result)),
+            );
+        }
 
-            writeln!(w)?;
+        let mut w = self.ui.stderr();
 
-            Ok(())
-        })?;
+        writeln!(w, "{:─>RUN_ANNOT_PADDING$}", "")?;
 
-        // TODO(tinger): report failures, mean and avg time
+        write_summary(&mut w, result)?;
+        write_failures(&mut w, result)?;
+        write_save_stats(&mut w, result.save_stats())?;
+        write_slowest(&mut w, result, self.slowest)?;
+
+        // TODO(tinger): report mean and avg time
 
         Ok(())
     }
 
     /// Clears the last line, i.e the status output.
-    pub fn clear_status(&self) -> io::Result<()> {
-        if !self.live {
-            return Ok(());
-        }
-
-        write!(self.ui.stderr(), "\x1B[0F\x1B[0J")
+    fn clear_status(&self) -> io::Result<()> {
+        clear_status(&mut self.ui.stderr(), self.live)
     }
 
     /// Reports the current status of an ongoing test run.
-    pub fn report_status(&self, result: &SuiteResult) -> io::Result<()> {
-        if !self.live {
+    fn report_status(&self, result: &SuiteResult) -> io::Result<()> {
+        if self.format != Format::Human {
             return Ok(());
         }
 
-        let mut w = self.ui.stderr();
-
-        let duration = result.timestamp().elapsed();
-
-        ui::write_annotated(&mut w, "", Color::Black, RUN_ANNOT_PADDING, |w| {
-            write!(w, "[")?;
-            ui::write_colored(
-                w,
-                duration_color(
-                    duration
-                        .checked_div(result.run() as u32)
-                        .unwrap_or_default(),
-                ),
-                |w| write_duration(w, duration),
-            )?;
-            write!(w, "] ")?;
-
-            ui::write_bold(w, |w| write!(w, "{}", result.run()))?;
-            write!(w, "/")?;
-            ui::write_bold(w, |w| write!(w, "{}", result.expected()))?;
-            write!(w, " tests run: ")?;
+        write_status(&mut self.ui.stderr(), result, self.live)
+    }
 
-            if result.passed() == result.total() {
-                ui::write_bold(w, |w| write!(w, "all {}", result.passed()))?;
-                write!(w, " ")?;
-                ui::write_colored(w, Color::Green, |w| write!(w, "passed"))?;
-            } else if result.failed() == result.total() {
-                ui::write_bold(w, |w| write!(w, "all {}", result.failed()))?;
-                write!(w, " ")?;
-                ui::write_colored(w, Color::Red, |w| write!(w, "failed"))?;
-            } else {
-                ui::write_bold(w, |w| write!(w, "{}", result.passed()))?;
-                write!(w, " ")?;
-                ui::write_colored(w, Color::Green, |w| write!(w, "passed"))?;
+    /// Report that a test has passed.
+    fn report_test_pass(&self, test: &Test, result: &TestResult) -> io::Result<()> {
+        if self.quiet {
+            return Ok(());
+        }
 
-                write!(w, ", ")?;
-                ui::write_bold(w, |w| write!(w, "{}", result.failed()))?;
-                write!(w, " ")?;
-                ui::write_colored(w, Color::Red, |w| write!(w, "failed"))?;
+        match self.format {
+            Format::Human => {}
+            Format::Plain => {
+                writeln!(self.ui.stdout(), "PASS {}", test.id())?;
+                return Ok(());
             }
-
-            if result.filtered() != 0 {
-                write!(w, ", ")?;
-                ui::write_bold(w, |w| write!(w, "{}", result.filtered()))?;
-                write!(w, " ")?;
-                ui::write_colored(w, Color::Yellow, |w| write!(w, "filtered"))?;
+            Format::JsonLines => {
+                write_json_line(
+                    &mut self.ui.stdout(),
+                    &JsonLine::Test(TestResultJson::new(test.id(), result)),
+                )?;
+                return Ok(());
             }
+        }
 
-            writeln!(w)?;
-
-            Ok(())
-        })?;
-
-        Ok(())
-    }
+        let (duration, warnings) = (result.duration(), result.warnings());
 
-    /// Report that a test has passed.
-    pub fn report_test_pass(
-        &self,
-        test: &Test,
-        duration: Duration,
-        warnings: &[SourceDiagnostic],
-    ) -> eyre::Result<()> {
         ui::write_annotated(
             &mut self.ui.stderr(),
             "pass",
@@ -249,7 +267,7 @@ impl Reporter<'_, '_> {
                 write!(w, "[")?;
                 ui::write_colored(w, duration_color(duration), |w| write_duration(w, duration))?;
                 write!(w, "] ")?;
-                ui::write_test_id(w, test.id())?;
+                self.write_test_id(w, test)?;
                 writeln!(w)?;
 
                 self.write_diagnostics(
@@ -270,12 +288,35 @@ impl Reporter<'_, '_> {
     }
 
     /// Report that a test has failed and show its output and failure reason.
-    pub fn report_test_fail(
+    fn report_test_fail(
         &self,
         test: &Test,
         result: &TestResult,
         diff_hint: bool,
-    ) -> eyre::Result<()> {
+    ) -> io::Result<()> {
+        if self.github_annotations {
+            self.report_github_annotations(test, result)?;
+        }
+
+        if self.quiet {
+            return Ok(());
+        }
+
+        match self.format {
+            Format::Human => {}
+            Format::Plain => {
+                write_plain_line(&mut self.ui.stdout(), test.id(), result, None)?;
+                return Ok(());
+            }
+            Format::JsonLines => {
+                write_json_line(
+                    &mut self.ui.stdout(),
+                    &JsonLine::Test(TestResultJson::new(test.id(), result)),
+                )?;
+                return Ok(());
+            }
+        }
+
         ui::write_annotated(
             &mut self.ui.stderr(),
             "fail",
@@ -287,7 +328,12 @@ impl Reporter<'_, '_> {
                     write_duration(w, result.duration())
                 })?;
                 write!(w, "] ")?;
-                ui::write_test_id(w, test.id())?;
+                self.write_test_id(w, test)?;
+                if let Some(variant) = result.variant() {
+                    write!(w, " (variant ")?;
+                    ui::write_ident(w, |w| write!(w, "{variant}"))?;
+                    write!(w, ")")?;
+                }
                 writeln!(w)?;
 
                 match result.kind() {
@@ -308,23 +354,26 @@ impl Reporter<'_, '_> {
                             if self.errors { &error.0 } else { &[] },
                         )?;
                     }
-                    Some(TestResultKind::FailedComparison(compare::Error {
-                        output,
-                        reference,
-                        pages,
-                    })) => {
+                    Some(TestResultKind::FailedComparison(error)) => {
+                        let compare::Error {
+                            output,
+                            reference,
+                            pages,
+                        } = error;
+
                         if output != reference {
-                            writeln!(
-                                w,
-                                "Expected {reference} {}, got {output} {}",
-                                Term::simple("page").with(*reference),
-                                Term::simple("page").with(*output),
-                            )?;
+                            write_page_count_mismatch(w, *output, *reference)?;
+                        }
+
+                        let scale = error.uniform_scale();
+                        if let Some(scale) = scale {
+                            write_scale_mismatch(w, scale)?;
                         }
 
                         for (p, e) in pages {
                             let p = p + 1;
                             match e {
+                                PageError::Dimensions { .. } if scale.is_some() => {}
                                 PageError::Dimensions { output, reference } => {
                                     writeln!(w, "Page {p} had different dimensions")?;
                                     w.write_with(2, |w| {
@@ -342,16 +391,45 @@ impl Reporter<'_, '_> {
                             }
                         }
 
-                        if diff_hint {
+                        let dir = match result.variant() {
+                            Some(variant) => self
+                                .project
+                                .paths()
+                                .test_diff_variant_dir(test.id(), variant),
+                            None => self.project.paths().test_diff_dir(test.id()),
+                        };
+
+                        // NOTE(tinger): diffs may not have been exported at
+                        // all, so we only point to them if there are any
+                        if diff_hint && doc::has_pages(&dir)? {
                             ui::write_hint_with(w, None, |w| {
-                                writeln!(
-                                    w,
-                                    "Diff images have been saved at '{}'",
-                                    self.project.paths().test_diff_dir(test.id()).display()
-                                )
+                                write!(w, "Diff images have been saved at '")?;
+                                ui::write_path(w, &dir)?;
+                                writeln!(w, "'")
                             })?;
                         }
                     }
+                    Some(TestResultKind::FailedUpdate { change }) => {
+                        writeln!(
+                            w,
+                            "Update would have changed {:.1}% of the reference",
+                            change * 100.0,
+                        )?;
+                        ui::write_hint_with(w, None, |w| {
+                            writeln!(w, "Pass --force to update it regardless")
+                        })?;
+                    }
+                    Some(TestResultKind::FailedHook {
+                        hook,
+                        code,
+                        stdout,
+                        stderr,
+                    }) => {
+                        write_hook_failure(w, hook, *code, stdout, stderr)?;
+                    }
+                    Some(TestResultKind::FailedIo { error, .. }) => {
+                        writeln!(w, "An io error occurred: {error}")?;
+                    }
                     _ => unreachable!(),
                 }
 
@@ -362,54 +440,781 @@ impl Reporter<'_, '_> {
         Ok(())
     }
 
+    /// Report that the global timeout was exceeded and list the tests which
+    /// were not run.
+    fn report_timeout(&self, timeout: Duration, result: &SuiteResult) -> io::Result<()> {
+        if self.quiet {
+            return Ok(());
+        }
+
+        let pending: Vec<_> = result
+            .results()
+            .iter()
+            .filter(|(_, result)| result.is_cancelled())
+            .map(|(id, _)| id)
+            .collect();
+
+        self.ui.warning_with(|w| {
+            writeln!(
+                w,
+                "Global timeout of {timeout:?} exceeded, {} {} didn't complete",
+                pending.len(),
+                Term::simple("test").with(pending.len()),
+            )?;
+
+            for id in pending {
+                write!(w, "  ")?;
+                ui::write_test_id(w, id)?;
+                writeln!(w)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Report that a hook which runs once for all tests has failed and show
+    /// its output.
+    fn report_hook_fail(
+        &self,
+        hook: &str,
+        code: Option<i32>,
+        stdout: &str,
+        stderr: &str,
+    ) -> io::Result<()> {
+        self.clear_status()?;
+        self.ui
+            .error_with(|w| write_hook_failure(w, hook, code, stdout, stderr))
+    }
+}
+
+impl Reporter<'_, '_> {
+    /// Emits GitHub Actions error annotations for a failed test.
+    fn report_github_annotations(&self, test: &Test, result: &TestResult) -> io::Result<()> {
+        let mut w = self.ui.stdout();
+        let paths = self.project.paths();
+
+        // NOTE(tinger): GitHub expects paths relative to the repository root
+        let base = paths.vcs_root().unwrap_or(paths.project_root());
+        let relative = |path: PathBuf| {
+            path.strip_prefix(base)
+                .map(Path::to_path_buf)
+                .unwrap_or(path)
+        };
+
+        let test_script = relative(test.script(paths));
+        let name = match result.variant() {
+            Some(variant) => format!("{} ({variant})", test.id()),
+            None => test.id().to_string(),
+        };
+
+        match result.kind() {
+            Some(TestResultKind::FailedCompilation { error, .. }) => {
+                for diagnostic in &error.0 {
+                    let location = diagnostic.span.id().and_then(|id| {
+                        let source = self.world.source(id).ok()?;
+                        let range = self.world.range(diagnostic.span)?;
+                        let path = id.vpath().resolve(paths.project_root())?;
+
+                        Some((
+                            relative(path),
+                            source.byte_to_line(range.start)? + 1,
+                            source.byte_to_column(range.start)? + 1,
+                        ))
+                    });
+
+                    let message = format!("{name}: {}", diagnostic.message);
+                    match &location {
+                        Some((path, line, col)) => write_github_annotation(
+                            &mut w,
+                            "error",
+                            Some(path),
+                            Some((*line, *col)),
+                            &message,
+                        )?,
+                        None => write_github_annotation(
+                            &mut w,
+                            "error",
+                            Some(&test_script),
+                            None,
+                            &message,
+                        )?,
+                    }
+                }
+            }
+            Some(TestResultKind::FailedComparison(compare::Error {
+                output,
+                reference,
+                pages,
+            })) => {
+                let mut message = format!("{name}: comparison failed");
+
+                if output != reference {
+                    message.push_str(&format!(
+                        "\nExpected {reference} {}, got {output} {}\n{}",
+                        Term::simple("page").with(*reference),
+                        Term::simple("page").with(*output),
+                        page_count_mismatch(*output, *reference),
+                    ));
+                }
+
+                for (p, e) in pages {
+                    let p = p + 1;
+                    match e {
+                        PageError::Dimensions { .. } => {
+                            message.push_str(&format!("\nPage {p} had different dimensions"));
+                        }
+                        PageError::SimpleDeviations { deviations } => {
+                            message.push_str(&format!(
+                                "\nPage {p} had {deviations} {}",
+                                Term::simple("deviation").with(*deviations),
+                            ));
+                        }
+                    }
+                }
+
+                write_github_annotation(&mut w, "error", Some(&test_script), None, &message)?;
+            }
+            Some(TestResultKind::FailedUpdate { change }) => {
+                let message = format!(
+                    "{name}: update would have changed {:.1}% of the reference",
+                    change * 100.0,
+                );
+
+                write_github_annotation(&mut w, "error", Some(&test_script), None, &message)?;
+            }
+            Some(TestResultKind::FailedHook { hook, stderr, .. }) => {
+                let mut message = format!("{name}: hook '{hook}' failed");
+                if !stderr.trim().is_empty() {
+                    message.push('\n');
+                    message.push_str(stderr.trim_end());
+                }
+
+                write_github_annotation(&mut w, "error", Some(&test_script), None, &message)?;
+            }
+            Some(TestResultKind::FailedIo { error, .. }) => {
+                let message = format!("{name}: an io error occurred: {error}");
+                write_github_annotation(&mut w, "error", Some(&test_script), None, &message)?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Writes the id of the given test as a hyperlink to its test script, if
+    /// the writer supports hyperlinks.
+    fn write_test_id<W: WriteColor + ?Sized>(&self, w: &mut W, test: &Test) -> io::Result<()> {
+        let script = test.script(self.project.paths());
+        ui::write_file_link(w, &script, |w| ui::write_test_id(w, test.id()))
+    }
+
     fn write_diagnostics<W: WriteColor>(
         &self,
         writer: &mut W,
         warnings: &[SourceDiagnostic],
         errors: &[SourceDiagnostic],
     ) -> io::Result<()> {
-        // TODO(tinger): don't use io::ErrorKind::Other
+        let files = DiagnosticFiles {
+            world: self.world,
+            sources: &self.sources,
+        };
+
+        write_diagnostics(
+            writer,
+            &self.diagnostic_config,
+            &files,
+            warnings.iter().chain(errors),
+        )
+    }
+}
+
+/// The files diagnostics are resolved in, in-memory sources take precedence
+/// over the sources of the world.
+struct DiagnosticFiles<'a> {
+    world: &'a SystemWorld,
+    sources: &'a HashMap<FileId, Source>,
+}
+
+impl DiagnosticFiles<'_> {
+    fn lookup(&self, id: FileId) -> Result<Source, files::Error> {
+        match self.sources.get(&id) {
+            Some(source) => Ok(source.clone()),
+            None => self.world.source(id).map_err(|_| files::Error::FileMissing),
+        }
+    }
+
+    fn range(&self, span: Span) -> Option<Range<usize>> {
+        self.lookup(span.id()?).ok()?.range(span)
+    }
+
+    fn label(&self, span: Span) -> Option<Label<FileId>> {
+        Some(Label::primary(span.id()?, self.range(span)?))
+    }
+}
+
+impl<'a> files::Files<'a> for DiagnosticFiles<'_> {
+    type FileId = FileId;
+    type Name = String;
+    type Source = Source;
+
+    fn name(&'a self, id: FileId) -> Result<String, files::Error> {
+        files::Files::name(self.world, id)
+    }
+
+    fn source(&'a self, id: FileId) -> Result<Source, files::Error> {
+        self.lookup(id)
+    }
+
+    fn line_index(&'a self, id: FileId, given: usize) -> Result<usize, files::Error> {
+        let source = self.lookup(id)?;
+        source
+            .byte_to_line(given)
+            .ok_or_else(|| files::Error::IndexTooLarge {
+                given,
+                max: source.len_bytes(),
+            })
+    }
+
+    fn line_range(&'a self, id: FileId, given: usize) -> Result<Range<usize>, files::Error> {
+        let source = self.lookup(id)?;
+        source
+            .line_to_range(given)
+            .ok_or_else(|| files::Error::LineTooLarge {
+                given,
+                max: source.len_lines(),
+            })
+    }
 
-        for diagnostic in warnings.iter().chain(errors) {
-            let diag = match diagnostic.severity {
-                Severity::Error => Diagnostic::error(),
-                Severity::Warning => Diagnostic::warning(),
+    fn column_number(&'a self, id: FileId, _: usize, given: usize) -> Result<usize, files::Error> {
+        let source = self.lookup(id)?;
+        source.byte_to_column(given).ok_or_else(|| {
+            let max = source.len_bytes();
+            if given <= max {
+                files::Error::InvalidCharBoundary { given }
+            } else {
+                files::Error::IndexTooLarge { given, max }
             }
-            .with_message(diagnostic.message.clone())
-            .with_notes(
-                diagnostic
-                    .hints
-                    .iter()
-                    .map(|e| (eco_format!("hint: {e}")).into())
-                    .collect(),
-            )
-            .with_labels(
-                resolve_label(self.world, diagnostic.span)
-                    .into_iter()
-                    .collect(),
-            );
+        })
+    }
+}
+
+/// Renders the given diagnostics like the typst CLI, the source line a
+/// diagnostic points to is shown with its span marked by carets. Diagnostics
+/// whose span can't be resolved are rendered without source context.
+fn write_diagnostics<'d, W: WriteColor>(
+    w: &mut W,
+    config: &term::Config,
+    files: &DiagnosticFiles,
+    diagnostics: impl IntoIterator<Item = &'d SourceDiagnostic>,
+) -> io::Result<()> {
+    // TODO(tinger): don't use io::ErrorKind::Other
 
-            term::emit(writer, &self.diagnostic_config, self.world, &diag)
+    for diagnostic in diagnostics {
+        let diag = match diagnostic.severity {
+            Severity::Error => Diagnostic::error(),
+            Severity::Warning => Diagnostic::warning(),
+        }
+        .with_message(diagnostic.message.clone())
+        .with_notes(
+            diagnostic
+                .hints
+                .iter()
+                .map(|e| (eco_format!("hint: {e}")).into())
+                .collect(),
+        )
+        .with_labels(files.label(diagnostic.span).into_iter().collect());
+
+        term::emit(w, config, files, &diag)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+
+        // Stacktrace-like helper diagnostics.
+        for point in &diagnostic.trace {
+            let message = point.v.to_string();
+            let help = Diagnostic::help()
+                .with_message(message)
+                .with_labels(files.label(point.span).into_iter().collect());
+
+            term::emit(w, config, files, &help)
                 .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        }
+    }
 
-            // Stacktrace-like helper diagnostics.
-            for point in &diagnostic.trace {
-                let message = point.v.to_string();
-                let help = Diagnostic::help()
-                    .with_message(message)
-                    .with_labels(resolve_label(self.world, point.span).into_iter().collect());
+    Ok(())
+}
+
+/// Writes the line of the given test result in the plain format, this never
+/// writes any color or style codes. Filtered results are written as skipped
+/// with the given skip reason, cancelled results are written as cancelled.
+fn write_plain_line<W: Write + ?Sized>(
+    w: &mut W,
+    id: &Id,
+    result: &TestResult,
+    skip_reason: Option<&str>,
+) -> io::Result<()> {
+    if result.is_pass() {
+        writeln!(w, "PASS {id}")
+    } else if result.is_fail() {
+        writeln!(w, "FAIL {id} {}", plain_reason(result))
+    } else if result.is_cancelled() {
+        writeln!(w, "CANCEL {id}")
+    } else if let Some(reason) = skip_reason {
+        writeln!(w, "SKIP {id} {reason}")
+    } else {
+        writeln!(w, "SKIP {id}")
+    }
+}
+
+/// Writes the given value as a single line of JSON and flushes the writer, such
+/// that it can be consumed while the test run is still ongoing.
+fn write_json_line<W: Write + ?Sized, T: Serialize>(w: &mut W, value: &T) -> io::Result<()> {
+    serde_json::to_writer(&mut *w, value)?;
+    writeln!(w)?;
+    w.flush()
+}
 
-                term::emit(writer, &self.diagnostic_config, self.world, &help)
-                    .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+/// Returns a single line failure reason of the given test result for the
+/// plain format.
+fn plain_reason(result: &TestResult) -> String {
+    let reason = match result.kind() {
+        Some(TestResultKind::FailedCompilation { reference, .. }) => if *reference {
+            "compilation of reference failed"
+        } else {
+            "compilation failed"
+        }
+        .to_owned(),
+        Some(TestResultKind::FailedComparison(compare::Error {
+            output,
+            reference,
+            pages,
+        })) => {
+            if output != reference {
+                format!("page count differed (expected {reference}, got {output})")
+            } else {
+                let pages: Vec<_> = pages.iter().map(|(idx, _)| (idx + 1).to_string()).collect();
+                format!("pages differed: {}", pages.join(","))
             }
         }
+        Some(TestResultKind::FailedUpdate { change }) => {
+            format!(
+                "update would change {:.1}% of the reference",
+                change * 100.0
+            )
+        }
+        Some(TestResultKind::FailedHook { hook, .. }) => format!("hook '{hook}' failed"),
+        Some(TestResultKind::FailedIo { .. }) => "io error".to_owned(),
+        _ => "unknown".to_owned(),
+    };
+
+    match result.variant() {
+        Some(variant) => format!("(variant {variant}) {reason}"),
+        None => reason,
+    }
+}
+
+/// Writes the summary of a test run, tests which were not run because the run
+/// was cancelled are counted separately.
+fn write_summary<W: WriteColor + ?Sized>(w: &mut W, result: &SuiteResult) -> io::Result<()> {
+    let color = if result.failed() == 0 {
+        Color::Green
+    } else if result.passed() == 0 {
+        Color::Red
+    } else {
+        Color::Yellow
+    };
+
+    ui::write_annotated(w, "Summary", color, RUN_ANNOT_PADDING, |w| {
+        write!(w, "[")?;
+        ui::write_colored(
+            w,
+            duration_color(
+                result
+                    .duration()
+                    .checked_div(result.run() as u32)
+                    .unwrap_or_default(),
+            ),
+            |w| write_duration(w, result.duration()),
+        )?;
+        write!(w, "] ")?;
+
+        ui::write_bold(w, |w| write!(w, "{}", result.run()))?;
+        write!(w, "/")?;
+        ui::write_bold(w, |w| write!(w, "{}", result.expected()))?;
+        write!(w, " tests run: ")?;
+
+        if result.passed() == result.total() {
+            ui::write_bold(w, |w| write!(w, "all {}", result.passed()))?;
+            write!(w, " ")?;
+            ui::write_colored(w, Color::Green, |w| write!(w, "passed"))?;
+        } else if result.failed() == result.total() {
+            ui::write_bold(w, |w| write!(w, "all {}", result.failed()))?;
+            write!(w, " ")?;
+            ui::write_colored(w, Color::Red, |w| write!(w, "failed"))?;
+        } else {
+            ui::write_bold(w, |w| write!(w, "{}", result.passed()))?;
+            write!(w, " ")?;
+            ui::write_colored(w, Color::Green, |w| write!(w, "passed"))?;
+
+            write!(w, ", ")?;
+            ui::write_bold(w, |w| write!(w, "{}", result.failed()))?;
+            write!(w, " ")?;
+            ui::write_colored(w, Color::Red, |w| write!(w, "failed"))?;
+        }
+
+        if result.filtered() != 0 {
+            write!(w, ", ")?;
+            ui::write_bold(w, |w| write!(w, "{}", result.filtered()))?;
+            write!(w, " ")?;
+            ui::write_colored(w, Color::Yellow, |w| write!(w, "filtered"))?;
+        }
+
+        if result.cancelled() != 0 {
+            write!(w, ", ")?;
+            ui::write_bold(w, |w| write!(w, "{}", result.cancelled()))?;
+            write!(w, " ")?;
+            ui::write_colored(w, Color::Yellow, |w| write!(w, "not run"))?;
+        }
+
+        writeln!(w)?;
+
+        Ok(())
+    })
+}
+
+/// Writes a table of the failed tests of a test run and their failure reasons.
+///
+/// The id column is padded to the longest id, but at most to
+/// [`FAILURE_ID_MAX_WIDTH`], longer ids are truncated with an ellipsis and
+/// written in full below their row.
+fn write_failures<W: WriteColor + ?Sized>(w: &mut W, result: &SuiteResult) -> io::Result<()> {
+    let failed: Vec<_> = result
+        .results()
+        .iter()
+        .filter(|(_, result)| result.is_fail())
+        .collect();
+
+    if failed.is_empty() {
+        return Ok(());
+    }
+
+    let width = failed
+        .iter()
+        .map(|(id, _)| id.as_str().chars().count())
+        .max()
+        .unwrap_or_default()
+        .min(FAILURE_ID_MAX_WIDTH);
+
+    ui::write_annotated(w, "Failed", Color::Red, RUN_ANNOT_PADDING, |w| {
+        for (id, result) in failed {
+            let len = id.as_str().chars().count();
+            let truncated = len > width;
+
+            if truncated {
+                let head: String = id.as_str().chars().take(width - 1).collect();
+                ui::write_bold_colored(w, Color::Blue, |w| write!(w, "{head}…"))?;
+            } else {
+                ui::write_test_id(w, id)?;
+                write!(w, "{: >pad$}", "", pad = width - len)?;
+            }
+
+            write!(w, "  ")?;
+            ui::write_colored(w, Color::Red, |w| write!(w, "{}", plain_reason(result)))?;
+            writeln!(w)?;
+
+            if truncated {
+                write!(w, "  ")?;
+                ui::write_test_id(w, id)?;
+                writeln!(w)?;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// Writes the sizes of the references written during a test run and the
+/// savings of optimization, if any references were written.
+fn write_save_stats<W: WriteColor + ?Sized>(w: &mut W, stats: SaveStats) -> io::Result<()> {
+    if stats.written == 0 {
+        return Ok(());
+    }
+
+    ui::write_annotated(w, "Written", Color::Cyan, RUN_ANNOT_PADDING, |w| {
+        ui::write_bold(w, |w| write!(w, "{}", ByteSize(stats.written)))?;
+        write!(w, " of references")?;
+
+        let saved = stats.saved();
+        if saved != 0 {
+            write!(w, ", optimization saved ")?;
+            ui::write_bold(w, |w| write!(w, "{}", ByteSize(saved)))?;
+            write!(
+                w,
+                " ({:.1}%)",
+                saved as f64 / stats.unoptimized as f64 * 100.0,
+            )?;
+        }
+
+        writeln!(w)
+    })
+}
+
+/// Returns at most `n` of the tests which were run with their durations,
+/// slowest first.
+fn slowest_tests(result: &SuiteResult, n: usize) -> Vec<(&Id, Duration)> {
+    let mut tests: Vec<_> = result
+        .results()
+        .iter()
+        .filter(|(_, result)| result.is_pass() || result.is_fail())
+        .map(|(id, result)| (id, result.duration()))
+        .collect();
+
+    tests.sort_by(|(_, a), (_, b)| b.cmp(a));
+    tests.truncate(n);
+    tests
+}
+
+/// Writes the `n` slowest tests of a test run, nothing is written if `n` is
+/// `0` or no tests were run.
+fn write_slowest<W: WriteColor + ?Sized>(
+    w: &mut W,
+    result: &SuiteResult,
+    n: usize,
+) -> io::Result<()> {
+    let slowest = slowest_tests(result, n);
+    if slowest.is_empty() {
+        return Ok(());
+    }
+
+    ui::write_annotated(w, "Slowest", Color::Cyan, RUN_ANNOT_PADDING, |w| {
+        for (id, duration) in slowest {
+            write!(w, "[")?;
+            ui::write_colored(w, duration_color(duration), |w| write_duration(w, duration))?;
+            write!(w, "] ")?;
+            ui::write_test_id(w, id)?;
+            writeln!(w)?;
+        }
 
         Ok(())
+    })
+}
+
+/// Writes the exit code and captured output of a failed hook.
+fn write_hook_failure<W: WriteColor>(
+    w: &mut Indented<W>,
+    hook: &str,
+    code: Option<i32>,
+    stdout: &str,
+    stderr: &str,
+) -> io::Result<()> {
+    match code {
+        Some(code) => writeln!(w, "Hook '{hook}' failed with exit code {code}")?,
+        None => writeln!(w, "Hook '{hook}' was terminated by a signal")?,
     }
+
+    for (name, output) in [("stdout", stdout), ("stderr", stderr)] {
+        if output.trim().is_empty() {
+            continue;
+        }
+
+        writeln!(w, "{name}:")?;
+        w.write_with(2, |w| {
+            for line in output.trim_end().lines() {
+                writeln!(w, "{line}")?;
+            }
+
+            Ok(())
+        })?;
+    }
+
+    Ok(())
 }
 
-fn resolve_label(world: &SystemWorld, span: Span) -> Option<Label<FileId>> {
-    Some(Label::primary(span.id()?, world.range(span)?))
+/// Clears the last line written by [`write_status`], this is a no-op if the
+/// status is not `live`.
+fn clear_status<W: Write + ?Sized>(w: &mut W, live: bool) -> io::Result<()> {
+    if !live {
+        return Ok(());
+    }
+
+    ui::clear_last_lines(w, 1)
+}
+
+/// Writes the live status line of an ongoing test run, this is a no-op if
+/// the status is not `live`, i.e. if the output is not a terminal.
+fn write_status<W: WriteColor + ?Sized>(
+    w: &mut W,
+    result: &SuiteResult,
+    live: bool,
+) -> io::Result<()> {
+    if !live {
+        return Ok(());
+    }
+
+    let duration = result.timestamp().elapsed();
+
+    ui::write_annotated(w, "", Color::Black, RUN_ANNOT_PADDING, |w| {
+        write_progress_bar(w, result.run(), result.expected(), PROGRESS_BAR_WIDTH)?;
+        write!(w, " [")?;
+        ui::write_colored(
+            w,
+            duration_color(
+                duration
+                    .checked_div(result.run() as u32)
+                    .unwrap_or_default(),
+            ),
+            |w| write_duration(w, duration),
+        )?;
+        write!(w, "] ")?;
+
+        ui::write_bold(w, |w| write!(w, "{}", result.run()))?;
+        write!(w, "/")?;
+        ui::write_bold(w, |w| write!(w, "{}", result.expected()))?;
+        write!(w, " tests run: ")?;
+
+        if result.passed() == result.total() {
+            ui::write_bold(w, |w| write!(w, "all {}", result.passed()))?;
+            write!(w, " ")?;
+            ui::write_colored(w, Color::Green, |w| write!(w, "passed"))?;
+        } else if result.failed() == result.total() {
+            ui::write_bold(w, |w| write!(w, "all {}", result.failed()))?;
+            write!(w, " ")?;
+            ui::write_colored(w, Color::Red, |w| write!(w, "failed"))?;
+        } else {
+            ui::write_bold(w, |w| write!(w, "{}", result.passed()))?;
+            write!(w, " ")?;
+            ui::write_colored(w, Color::Green, |w| write!(w, "passed"))?;
+
+            write!(w, ", ")?;
+            ui::write_bold(w, |w| write!(w, "{}", result.failed()))?;
+            write!(w, " ")?;
+            ui::write_colored(w, Color::Red, |w| write!(w, "failed"))?;
+        }
+
+        if result.filtered() != 0 {
+            write!(w, ", ")?;
+            ui::write_bold(w, |w| write!(w, "{}", result.filtered()))?;
+            write!(w, " ")?;
+            ui::write_colored(w, Color::Yellow, |w| write!(w, "filtered"))?;
+        }
+
+        writeln!(w)?;
+
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+/// Writes a progress bar of the given inner width showing how many of the
+/// total steps are done.
+fn write_progress_bar<W: WriteColor + ?Sized>(
+    w: &mut W,
+    done: usize,
+    total: usize,
+    width: usize,
+) -> io::Result<()> {
+    let filled = if total == 0 {
+        width
+    } else {
+        Ord::min(done * width / total, width)
+    };
+
+    write!(w, "[")?;
+    ui::write_colored(w, Color::Green, |w| write!(w, "{:=>filled$}", ""))?;
+    write!(w, "{: >rest$}]", "", rest = width - filled)
+}
+
+/// Describes which pages of the output are extra or missing compared to the
+/// reference, assuming that changes happened at the end of the document.
+fn page_count_mismatch(output: usize, reference: usize) -> String {
+    let (prefix, noun, from, to) = if output > reference {
+        ("Output has", "extra page", reference, output)
+    } else {
+        ("Output is missing", "page", output, reference)
+    };
+
+    let diff = to - from;
+    let what = Term::simple(noun).with(diff);
+
+    if diff == 1 {
+        format!("{prefix} {diff} {what} at the end (page {to})")
+    } else {
+        format!(
+            "{prefix} {diff} {what} at the end (pages {}-{to})",
+            from + 1,
+        )
+    }
+}
+
+/// Writes a page count mismatch between output and reference including a hint
+/// on how to accept intentional changes.
+fn write_page_count_mismatch<W: WriteColor + ?Sized>(
+    w: &mut W,
+    output: usize,
+    reference: usize,
+) -> io::Result<()> {
+    writeln!(
+        w,
+        "Expected {reference} {}, got {output} {}",
+        Term::simple("page").with(reference),
+        Term::simple("page").with(output),
+    )?;
+    writeln!(w, "{}", page_count_mismatch(output, reference))?;
+
+    ui::write_hint_with(w, None, |w| {
+        write!(w, "if this change is intentional, use '")?;
+        ui::write_colored(w, Color::Cyan, |w| write!(w, "tt update"))?;
+        writeln!(w, "' to accept it")
+    })
+}
+
+/// Writes a GitHub Actions workflow command annotation such as
+/// `::error file=tests/a/test.typ,line=1,col=2::message`.
+/// Writes a dedicated error for pages which differ only by a uniform scale,
+/// which is most likely caused by references rendered at a different ppi.
+fn write_scale_mismatch<W: WriteColor + ?Sized>(w: &mut W, scale: f64) -> io::Result<()> {
+    writeln!(
+        w,
+        "Output pages are {scale:.2}x the size of the reference pages",
+    )?;
+    ui::write_hint_with(w, None, |w| {
+        writeln!(
+            w,
+            "the references were likely rendered at a different pixel per inch, \
+            use 'tt update' to re-render them or pass the matching --ppi"
+        )
+    })
+}
+
+fn write_github_annotation<W: Write + ?Sized>(
+    w: &mut W,
+    level: &str,
+    file: Option<&Path>,
+    location: Option<(usize, usize)>,
+    message: &str,
+) -> io::Result<()> {
+    fn escape_data(s: &str) -> String {
+        s.replace('%', "%25")
+            .replace('\r', "%0D")
+            .replace('\n', "%0A")
+    }
+
+    fn escape_property(s: &str) -> String {
+        escape_data(s).replace(':', "%3A").replace(',', "%2C")
+    }
+
+    write!(w, "::{level}")?;
+
+    if let Some(file) = file {
+        // NOTE(tinger): GitHub always expects forward slashes
+        let file = file.to_string_lossy().replace('\\', "/");
+        write!(w, " file={}", escape_property(&file))?;
+
+        if let Some((line, col)) = location {
+            write!(w, ",line={line},col={col}")?;
+        }
+    }
+
+    writeln!(w, "::{}", escape_data(message))
 }
 
 /// Writes a padded duration in human readable form
@@ -434,3 +1239,393 @@ fn duration_color(duration: Duration) -> Color {
         _ => Color::Red,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use lib::_dev::result::{failed, passed, suite_result};
+    use lib::doc::compile;
+    use termcolor::{Ansi, NoColor};
+
+    use typst::diag::Warned;
+    use typst_syntax::VirtualPath;
+
+    use super::*;
+    use crate::runner::tests::project;
+    use crate::world::tests::world;
+
+    #[test]
+    fn test_save_stats() {
+        let mut a = passed(Duration::ZERO);
+        a.add_save_stats(SaveStats {
+            written: 600,
+            unoptimized: 1000,
+        });
+        let mut b = passed(Duration::ZERO);
+        b.add_save_stats(SaveStats {
+            written: 2048,
+            unoptimized: 2048,
+        });
+        let result = suite_result(&[("a", Some(a)), ("b", Some(b)), ("pending", None)]);
+
+        let stats = result.save_stats();
+        assert_eq!(stats.written, 2648);
+        assert_eq!(stats.unoptimized, 3048);
+
+        let mut w = NoColor::new(vec![]);
+        write_save_stats(&mut w, stats).unwrap();
+
+        let w = String::from_utf8(w.into_inner()).unwrap();
+        assert!(
+            w.contains(&format!("{} of references", ByteSize(2648))),
+            "{w}"
+        );
+    }
+
+    #[test]
+    fn test_report_start_quiet() {
+        let project = project(Path::new("."));
+        let world = world(None);
+        let result = suite_result(&[("a", None)]);
+
+        let ui = Ui::captured(false);
+        Reporter::new(&ui, &project, &world, false)
+            .report_start(&result)
+            .unwrap();
+        assert!(ui.captured_stderr().contains("Starting"));
+
+        let ui = Ui::captured(false);
+        Reporter::new(&ui, &project, &world, false)
+            .with_quiet(true)
+            .report_start(&result)
+            .unwrap();
+        assert_eq!(ui.captured_stderr(), "");
+        assert_eq!(ui.captured_stdout(), "");
+    }
+
+    #[test]
+    fn test_summary_partial() {
+        let result = suite_result(&[
+            ("pass", Some(passed(Duration::ZERO))),
+            ("fail", Some(failed(Duration::ZERO))),
+            ("pending", None),
+        ]);
+
+        let mut w = NoColor::new(vec![]);
+        write_summary(&mut w, &result).unwrap();
+
+        let w = String::from_utf8(w.into_inner()).unwrap();
+        assert!(
+            w.ends_with("2/3 tests run: 1 passed, 1 failed, 1 not run\n"),
+            "{w}"
+        );
+    }
+
+    #[test]
+    fn test_diagnostics_source_context() {
+        let world = world(None);
+        let source = Source::new(
+            FileId::new(None, VirtualPath::new("tests/syntax/test.typ")),
+            "Hello\n#let x = (1, 2\nWorld\n".into(),
+        );
+
+        let Warned { output, .. } = compile::compile(source.clone(), &world);
+        let errors = output.unwrap_err().0;
+
+        let sources = HashMap::from([(source.id(), source)]);
+        let files = DiagnosticFiles {
+            world: &world,
+            sources: &sources,
+        };
+        let config = term::Config {
+            display_style: term::DisplayStyle::Rich,
+            ..Default::default()
+        };
+
+        let mut w = NoColor::new(vec![]);
+        write_diagnostics(&mut w, &config, &files, &errors).unwrap();
+
+        let w = String::from_utf8(w.into_inner()).unwrap();
+        let lines: Vec<_> = w.lines().collect();
+        assert!(lines[0].starts_with("error:"), "{w}");
+
+        // the offending line is followed by the carets marking the span
+        let line = lines
+            .iter()
+            .position(|line| line.ends_with("#let x = (1, 2"))
+            .unwrap();
+        assert!(lines[line + 1].contains('^'), "{w}");
+    }
+
+    #[test]
+    fn test_failures_aligned() {
+        const LONG: &str = "a/very/deeply/nested/module/with/a-long-test-name";
+
+        let result = suite_result(&[
+            ("short", Some(failed(Duration::ZERO))),
+            ("medium-length", Some(failed(Duration::ZERO))),
+            (LONG, Some(failed(Duration::ZERO))),
+            ("pass", Some(passed(Duration::ZERO))),
+        ]);
+
+        let mut w = NoColor::new(vec![]);
+        write_failures(&mut w, &result).unwrap();
+
+        let w = String::from_utf8(w.into_inner()).unwrap();
+        let lines: Vec<_> = w.lines().collect();
+        assert_eq!(lines.len(), 4, "{w}");
+        assert!(!w.contains("pass"));
+
+        // the reasons of all rows start in the same column, the ellipsis of
+        // truncated ids is a multi-byte char, so we count chars, not bytes
+        let column = |line: &str| {
+            let idx = line.find("pages differed").unwrap();
+            line[..idx].chars().count()
+        };
+        let rows: Vec<_> = lines
+            .iter()
+            .filter(|line| line.contains("pages differed"))
+            .collect();
+        assert_eq!(rows.len(), 3);
+        assert!(rows.iter().all(|row| column(row) == column(rows[0])));
+
+        // long ids are truncated and written in full below their row
+        let long = lines.iter().position(|line| line.contains('…')).unwrap();
+        assert!(!lines[long].contains(LONG));
+        assert!(lines[long].contains(&format!("{}…", &LONG[..FAILURE_ID_MAX_WIDTH - 1])));
+        assert!(lines[long + 1].ends_with(LONG));
+    }
+
+    #[test]
+    fn test_slowest_tests() {
+        let result = suite_result(&[
+            ("a", Some(passed(Duration::from_millis(20)))),
+            ("b", Some(failed(Duration::from_millis(30)))),
+            ("c", Some(passed(Duration::from_millis(10)))),
+            ("pending", None),
+        ]);
+
+        let ids = |n| {
+            slowest_tests(&result, n)
+                .into_iter()
+                .map(|(id, _)| id.as_str().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids(2), ["b", "a"]);
+
+        // tests which were not run are not listed
+        assert_eq!(ids(10), ["b", "a", "c"]);
+
+        assert!(slowest_tests(&result, 0).is_empty());
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let result = suite_result(&[
+            ("pass", Some(passed(Duration::ZERO))),
+            ("fail", Some(failed(Duration::ZERO))),
+        ]);
+
+        let mut w = vec![];
+        for id in ["pass", "fail"] {
+            let id = Id::new(id).unwrap();
+            write_json_line(
+                &mut w,
+                &JsonLine::Test(TestResultJson::new(&id, &result.results()[&id])),
+            )
+            .unwrap();
+        }
+        write_json_line(&mut w, &JsonLine::Summary(SummaryJson::new(&result))).unwrap();
+
+        let w = String::from_utf8(w).unwrap();
+        let lines: Vec<serde_json::Value> = w
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), result.results().len() + 1);
+        assert_eq!(lines[0]["type"], "test");
+        assert_eq!(lines[0]["id"], "pass");
+        assert_eq!(lines[0]["status"], "passed");
+        assert_eq!(lines[1]["type"], "test");
+        assert_eq!(lines[1]["id"], "fail");
+        assert_eq!(lines[1]["status"], "failed");
+        assert_eq!(lines[2]["type"], "summary");
+        assert_eq!(lines[2]["passed"], 1);
+        assert_eq!(lines[2]["failed"]["comparison"], 1);
+    }
+
+    #[test]
+    fn test_hook_failure() {
+        let mut w = Indented::new(NoColor::new(vec![]), 0);
+        write_hook_failure(&mut w, "setup.sh", Some(3), "", "missing fixture\nabort\n").unwrap();
+
+        let w = String::from_utf8(w.into_inner().into_inner()).unwrap();
+        assert_eq!(
+            w,
+            "Hook 'setup.sh' failed with exit code 3\nstderr:\n  missing fixture\n  abort\n"
+        );
+    }
+
+    #[test]
+    fn test_status_not_live() {
+        let result = SuiteResult::new(&Suite::new());
+        let mut w = NoColor::new(vec![]);
+
+        write_status(&mut w, &result, false).unwrap();
+        clear_status(&mut w, false).unwrap();
+
+        assert!(w.into_inner().is_empty());
+    }
+
+    #[test]
+    fn test_status_live_no_color() {
+        let result = SuiteResult::new(&Suite::new());
+        let mut w = NoColor::new(vec![]);
+
+        write_status(&mut w, &result, true).unwrap();
+
+        let w = w.into_inner();
+        let str = std::str::from_utf8(&w).unwrap();
+        assert!(!str.contains('\x1B'));
+        assert!(str.contains("0/0 tests run"));
+    }
+
+    #[test]
+    fn test_page_count_mismatch_extra_pages() {
+        let mut w = NoColor::new(vec![]);
+        write_page_count_mismatch(&mut w, 3, 1).unwrap();
+
+        let w = String::from_utf8(w.into_inner()).unwrap();
+        assert!(w.contains("Expected 1 page, got 3 pages"));
+        assert!(w.contains("Output has 2 extra pages at the end (pages 2-3)"));
+        assert!(w.contains("if this change is intentional, use 'tt update' to accept it"));
+    }
+
+    #[test]
+    fn test_page_count_mismatch_missing_pages() {
+        let mut w = NoColor::new(vec![]);
+        write_page_count_mismatch(&mut w, 2, 3).unwrap();
+
+        let w = String::from_utf8(w.into_inner()).unwrap();
+        assert!(w.contains("Expected 3 pages, got 2 pages"));
+        assert!(w.contains("Output is missing 1 page at the end (page 3)"));
+        assert!(w.contains("if this change is intentional, use 'tt update' to accept it"));
+    }
+
+    #[test]
+    fn test_scale_mismatch() {
+        let mut w = NoColor::new(vec![]);
+        write_scale_mismatch(&mut w, 2.0).unwrap();
+
+        let w = String::from_utf8(w.into_inner()).unwrap();
+        assert!(w.contains("Output pages are 2.00x the size of the reference pages"));
+        assert!(w.contains("rendered at a different pixel per inch"));
+    }
+
+    #[test]
+    fn test_plain_reason() {
+        let mut result = TestResult::new();
+        result.set_failed_comparison(compare::Error {
+            output: 3,
+            reference: 3,
+            pages: vec![
+                (0, PageError::SimpleDeviations { deviations: 1 }),
+                (2, PageError::SimpleDeviations { deviations: 4 }),
+            ],
+        });
+        assert_eq!(plain_reason(&result), "pages differed: 1,3");
+
+        result.set_failed_comparison(compare::Error {
+            output: 2,
+            reference: 1,
+            pages: vec![],
+        });
+        assert_eq!(
+            plain_reason(&result),
+            "page count differed (expected 1, got 2)"
+        );
+
+        result.set_variant(Some("dark".into()));
+        result.set_failed_update(0.5);
+        assert_eq!(
+            plain_reason(&result),
+            "(variant dark) update would change 50.0% of the reference"
+        );
+    }
+
+    #[test]
+    fn test_plain_line_no_color() {
+        let id = Id::new("fruit/apple").unwrap();
+
+        let mut pass = TestResult::new();
+        pass.set_passed_compilation();
+        pass.set_passed_comparison();
+
+        let mut fail = TestResult::new();
+        fail.set_failed_update(0.25);
+
+        let skip = TestResult::filtered();
+        let cancel = TestResult::new();
+
+        // NOTE(tinger): this is what `--color always` writes to
+        let mut w = Ansi::new(vec![]);
+        write_plain_line(&mut w, &id, &pass, None).unwrap();
+        write_plain_line(&mut w, &id, &fail, None).unwrap();
+        write_plain_line(&mut w, &id, &skip, None).unwrap();
+        write_plain_line(&mut w, &id, &skip, Some("flaky")).unwrap();
+        write_plain_line(&mut w, &id, &cancel, None).unwrap();
+
+        let w = String::from_utf8(w.into_inner()).unwrap();
+        assert!(!w.contains('\x1B'));
+        assert_eq!(
+            w,
+            "PASS fruit/apple\n\
+            FAIL fruit/apple update would change 25.0% of the reference\n\
+            SKIP fruit/apple\n\
+            SKIP fruit/apple flaky\n\
+            CANCEL fruit/apple\n"
+        );
+    }
+
+    #[test]
+    fn test_github_annotation() {
+        let annotation = |file, location, message| {
+            let mut w = vec![];
+            write_github_annotation(&mut w, "error", file, location, message).unwrap();
+            String::from_utf8(w).unwrap()
+        };
+
+        assert_eq!(
+            annotation(None, None, "Hello World"),
+            "::error::Hello World\n",
+        );
+        assert_eq!(
+            annotation(Some(Path::new("tests/a/test.typ")), None, "failed"),
+            "::error file=tests/a/test.typ::failed\n",
+        );
+        assert_eq!(
+            annotation(
+                Some(Path::new("tests/a,b/test.typ")),
+                Some((3, 5)),
+                "50% failed\nPage 1 differed",
+            ),
+            "::error file=tests/a%2Cb/test.typ,line=3,col=5::50%25 failed%0APage 1 differed\n",
+        );
+    }
+
+    #[test]
+    fn test_progress_bar() {
+        let bar = |done, total| {
+            let mut w = NoColor::new(vec![]);
+            write_progress_bar(&mut w, done, total, 10).unwrap();
+            String::from_utf8(w.into_inner()).unwrap()
+        };
+
+        assert_eq!(bar(0, 4), "[          ]");
+        assert_eq!(bar(1, 4), "[==        ]");
+        assert_eq!(bar(2, 4), "[=====     ]");
+        assert_eq!(bar(4, 4), "[==========]");
+        assert_eq!(bar(0, 0), "[==========]");
+    }
+}
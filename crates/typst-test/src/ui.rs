@@ -2,6 +2,9 @@
 
 use std::fmt::{Debug, Display};
 use std::io::{BufRead, IsTerminal, Stdin, StdinLock, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
 use std::{fmt, io};
 
 use color_eyre::eyre;
@@ -28,6 +31,32 @@ pub struct Ui {
 
     /// The unlocked stderr stream.
     stderr: StandardStream,
+
+    /// Whether all output is discarded.
+    silent: bool,
+
+    /// The buffers which capture the output instead of the standard streams.
+    capture: Option<Capture>,
+}
+
+/// Buffers for the captured output of a [`Ui`].
+#[derive(Debug, Default)]
+struct Capture {
+    stdout: Mutex<Vec<u8>>,
+    stderr: Mutex<Vec<u8>>,
+}
+
+/// A locked output stream of a [`Ui`], all output is discarded if the ui is
+/// silent.
+pub enum StreamLock<'a> {
+    /// A locked standard stream.
+    Stream(StandardStreamLock<'a>),
+
+    /// A stream which discards all output.
+    Silent,
+
+    /// A locked buffer capturing uncolored output.
+    Captured(MutexGuard<'a, Vec<u8>>),
 }
 
 /// Returns whether or not a given output stream is connected to a terminal.
@@ -48,28 +77,88 @@ impl Ui {
             stdin: io::stdin(),
             stdout: StandardStream::stdout(check_terminal(io::stdout(), out)),
             stderr: StandardStream::stderr(check_terminal(io::stderr(), err)),
+            silent: false,
+            capture: None,
+        }
+    }
+
+    /// Creates a new [`Ui`] which discards all output, this is used to only
+    /// communicate through the exit code.
+    pub fn silent() -> Self {
+        Self {
+            silent: true,
+            ..Self::new(ColorChoice::Never, ColorChoice::Never)
         }
     }
 
+    /// Creates a new [`Ui`] which writes uncolored output into buffers instead
+    /// of the standard streams, see [`Ui::captured_stdout`] and
+    /// [`Ui::captured_stderr`].
+    #[cfg(test)]
+    pub fn captured(silent: bool) -> Self {
+        Self {
+            silent,
+            capture: Some(Capture::default()),
+            ..Self::new(ColorChoice::Never, ColorChoice::Never)
+        }
+    }
+
+    /// The output written to stdout so far, this is empty if the ui doesn't
+    /// capture its output.
+    #[cfg(test)]
+    pub fn captured_stdout(&self) -> String {
+        self.capture
+            .as_ref()
+            .map(|c| String::from_utf8(c.stdout.lock().unwrap().clone()).unwrap())
+            .unwrap_or_default()
+    }
+
+    /// The output written to stderr so far, this is empty if the ui doesn't
+    /// capture its output.
+    #[cfg(test)]
+    pub fn captured_stderr(&self) -> String {
+        self.capture
+            .as_ref()
+            .map(|c| String::from_utf8(c.stderr.lock().unwrap().clone()).unwrap())
+            .unwrap_or_default()
+    }
+
+    /// Whether this ui discards all output.
+    pub fn is_silent(&self) -> bool {
+        self.silent
+    }
+
     /// Returns an exclusive lock to stdin.
     pub fn stdin(&self) -> StdinLock<'_> {
         self.stdin.lock()
     }
 
     /// Returns an exclusive lock to stdout.
-    pub fn stdout(&self) -> StandardStreamLock<'_> {
-        self.stdout.lock()
+    pub fn stdout(&self) -> StreamLock<'_> {
+        if self.silent {
+            StreamLock::Silent
+        } else if let Some(capture) = &self.capture {
+            StreamLock::Captured(capture.stdout.lock().unwrap())
+        } else {
+            StreamLock::Stream(self.stdout.lock())
+        }
     }
 
     /// Returns an exclusive lock to stderr.
-    pub fn stderr(&self) -> StandardStreamLock<'_> {
-        self.stderr.lock()
+    pub fn stderr(&self) -> StreamLock<'_> {
+        if self.silent {
+            StreamLock::Silent
+        } else if let Some(capture) = &self.capture {
+            StreamLock::Captured(capture.stderr.lock().unwrap())
+        } else {
+            StreamLock::Stream(self.stderr.lock())
+        }
     }
 
     /// Writes the given closure with an error annotation header.
     pub fn error_with(
         &self,
-        f: impl FnOnce(&mut Indented<&mut StandardStreamLock<'_>>) -> io::Result<()>,
+        f: impl FnOnce(&mut Indented<&mut StreamLock<'_>>) -> io::Result<()>,
     ) -> io::Result<()> {
         write_error_with(&mut self.stderr(), ANNOTATION_MAX_PADDING, f)
     }
@@ -77,7 +166,7 @@ impl Ui {
     /// Writes the given closure with a warning annotation header.
     pub fn warning_with(
         &self,
-        f: impl FnOnce(&mut Indented<&mut StandardStreamLock<'_>>) -> io::Result<()>,
+        f: impl FnOnce(&mut Indented<&mut StreamLock<'_>>) -> io::Result<()>,
     ) -> io::Result<()> {
         write_warning_with(&mut self.stderr(), ANNOTATION_MAX_PADDING, f)
     }
@@ -85,7 +174,7 @@ impl Ui {
     /// Writes the given closure with a hint annotation header.
     pub fn hint_with(
         &self,
-        f: impl FnOnce(&mut Indented<&mut StandardStreamLock<'_>>) -> io::Result<()>,
+        f: impl FnOnce(&mut Indented<&mut StreamLock<'_>>) -> io::Result<()>,
     ) -> io::Result<()> {
         write_hint_with(&mut self.stderr(), ANNOTATION_MAX_PADDING, f)
     }
@@ -93,8 +182,8 @@ impl Ui {
     /// Writes the given closure with an error annotation header.
     pub fn error_hinted_with(
         &self,
-        f: impl FnOnce(&mut Indented<&mut StandardStreamLock<'_>>) -> io::Result<()>,
-        h: impl FnOnce(&mut Indented<&mut StandardStreamLock<'_>>) -> io::Result<()>,
+        f: impl FnOnce(&mut Indented<&mut StreamLock<'_>>) -> io::Result<()>,
+        h: impl FnOnce(&mut Indented<&mut StreamLock<'_>>) -> io::Result<()>,
     ) -> io::Result<()> {
         write_error_with(&mut self.stderr(), ANNOTATION_MAX_PADDING, f)?;
         write_hint_with(&mut self.stderr(), ANNOTATION_MAX_PADDING, h)
@@ -103,8 +192,8 @@ impl Ui {
     /// Writes the given closure with a warning annotation header.
     pub fn warning_hinted_with(
         &self,
-        f: impl FnOnce(&mut Indented<&mut StandardStreamLock<'_>>) -> io::Result<()>,
-        h: impl FnOnce(&mut Indented<&mut StandardStreamLock<'_>>) -> io::Result<()>,
+        f: impl FnOnce(&mut Indented<&mut StreamLock<'_>>) -> io::Result<()>,
+        h: impl FnOnce(&mut Indented<&mut StreamLock<'_>>) -> io::Result<()>,
     ) -> io::Result<()> {
         write_warning_with(&mut self.stderr(), ANNOTATION_MAX_PADDING, f)?;
         write_hint_with(&mut self.stderr(), ANNOTATION_MAX_PADDING, h)
@@ -138,7 +227,7 @@ impl Ui {
     /// Whether a live status report can be printed and cleared using ANSI
     /// escape codes.
     pub fn can_live_report(&self) -> bool {
-        io::stderr().is_terminal()
+        !self.silent && self.capture.is_none() && io::stderr().is_terminal()
     }
 
     /// Whether a prompt can be displayed and confirmed by the user.
@@ -224,6 +313,67 @@ impl Ui {
     }
 }
 
+impl Write for StreamLock<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Stream(stream) => stream.write(buf),
+            Self::Silent => Ok(buf.len()),
+            Self::Captured(buffer) => buffer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Stream(stream) => stream.flush(),
+            Self::Silent | Self::Captured(_) => Ok(()),
+        }
+    }
+}
+
+impl WriteColor for StreamLock<'_> {
+    fn supports_color(&self) -> bool {
+        match self {
+            Self::Stream(stream) => stream.supports_color(),
+            Self::Silent | Self::Captured(_) => false,
+        }
+    }
+
+    fn set_color(&mut self, spec: &ColorSpec) -> io::Result<()> {
+        match self {
+            Self::Stream(stream) => stream.set_color(spec),
+            Self::Silent | Self::Captured(_) => Ok(()),
+        }
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        match self {
+            Self::Stream(stream) => stream.reset(),
+            Self::Silent | Self::Captured(_) => Ok(()),
+        }
+    }
+
+    fn is_synchronous(&self) -> bool {
+        match self {
+            Self::Stream(stream) => stream.is_synchronous(),
+            Self::Silent | Self::Captured(_) => false,
+        }
+    }
+
+    fn set_hyperlink(&mut self, link: &HyperlinkSpec) -> io::Result<()> {
+        match self {
+            Self::Stream(stream) => stream.set_hyperlink(link),
+            Self::Silent | Self::Captured(_) => Ok(()),
+        }
+    }
+
+    fn supports_hyperlinks(&self) -> bool {
+        match self {
+            Self::Stream(stream) => stream.supports_hyperlinks(),
+            Self::Silent | Self::Captured(_) => false,
+        }
+    }
+}
+
 /// Executes the given closure with custom set and reset style closures.
 pub fn write_with<W: WriteColor + ?Sized>(
     w: &mut W,
@@ -375,6 +525,79 @@ pub fn write_test_id<W: WriteColor + ?Sized>(w: &mut W, id: &Id) -> io::Result<(
     Ok(())
 }
 
+/// Executes the given closure such that its output is an OSC 8 hyperlink to
+/// the given file, if the writer supports hyperlinks. Otherwise the output is
+/// written as is.
+pub fn write_file_link<W: WriteColor + ?Sized>(
+    w: &mut W,
+    path: &Path,
+    f: impl FnOnce(&mut W) -> io::Result<()>,
+) -> io::Result<()> {
+    if !w.supports_hyperlinks() {
+        return f(w);
+    }
+
+    let uri = file_uri(path);
+    w.set_hyperlink(&HyperlinkSpec::open(uri.as_bytes()))?;
+    f(w)?;
+    w.set_hyperlink(&HyperlinkSpec::close())?;
+
+    Ok(())
+}
+
+/// Writes the given path, as a hyperlink if the writer supports it.
+pub fn write_path<W: WriteColor + ?Sized>(w: &mut W, path: &Path) -> io::Result<()> {
+    write_file_link(w, path, |w| write!(w, "{}", path.display()))
+}
+
+/// Returns the `file://` URI of the given absolute path, bytes which are not
+/// allowed in a URI path are percent encoded.
+fn file_uri(path: &Path) -> String {
+    // NOTE(tinger): URIs always use forward slashes and windows paths need
+    // another slash before their drive letter
+    let path = path.to_string_lossy().replace('\\', "/");
+
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+
+    for byte in path.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b':' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            byte => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    uri
+}
+
+/// Writes the line of the given source which contains the start of the given
+/// byte span, followed by a line of carets marking the span.
+pub fn write_snippet<W: WriteColor + ?Sized>(
+    w: &mut W,
+    source: &str,
+    span: Range<usize>,
+) -> io::Result<()> {
+    let start = span.start.min(source.len());
+    let line_start = source[..start].rfind('\n').map(|idx| idx + 1).unwrap_or(0);
+    let line_end = source[start..]
+        .find('\n')
+        .map(|idx| start + idx)
+        .unwrap_or(source.len());
+
+    let end = span.end.clamp(start, line_end);
+    let column = source[line_start..start].chars().count();
+    let width = source[start..end].chars().count().max(1);
+
+    writeln!(w, "{}", &source[line_start..line_end])?;
+    write!(w, "{:column$}", "")?;
+    write_bold_colored(w, Color::Red, |w| write!(w, "{}", "^".repeat(width)))?;
+    writeln!(w)
+}
+
 /// Counts the lines this writer wrote since the last reset.
 #[derive(Debug)]
 pub struct Counted<W> {
@@ -612,10 +835,47 @@ fn assert_traits() {
 #[cfg(test)]
 mod tests {
     use insta::assert_snapshot;
-    use termcolor::Ansi;
+    use termcolor::{Ansi, NoColor};
 
     use super::*;
 
+    #[test]
+    fn test_write_snippet() {
+        let mut w = NoColor::new(vec![]);
+        write_snippet(&mut w, "a |\nb & (c", 8..10).unwrap();
+
+        let w = String::from_utf8(w.into_inner()).unwrap();
+        assert_eq!(w, "b & (c\n    ^^\n");
+    }
+
+    #[test]
+    fn test_write_path_hyperlink() {
+        let path = Path::new("/tmp/my project/tests/a/test.typ");
+
+        let mut w = Ansi::new(vec![]);
+        write_path(&mut w, path).unwrap();
+        let w = String::from_utf8(w.into_inner()).unwrap();
+        assert_eq!(
+            w,
+            "\x1B]8;;file:///tmp/my%20project/tests/a/test.typ\x1B\\\
+            /tmp/my project/tests/a/test.typ\
+            \x1B]8;;\x1B\\"
+        );
+
+        let mut w = NoColor::new(vec![]);
+        write_path(&mut w, path).unwrap();
+        let w = String::from_utf8(w.into_inner()).unwrap();
+        assert_eq!(w, "/tmp/my project/tests/a/test.typ");
+    }
+
+    #[test]
+    fn test_file_uri_windows() {
+        assert_eq!(
+            file_uri(Path::new("C:\\Users\\tinger\\test.typ")),
+            "file:///C:/Users/tinger/test.typ"
+        );
+    }
+
     #[test]
     fn test_counted() {
         let mut w = Counted::new(vec![]);
@@ -9,9 +9,9 @@ use std::sync::{Mutex, OnceLock};
 use std::{fs, io, mem};
 
 use chrono::{DateTime, Datelike, FixedOffset, Local, Utc};
-use lib::library::augmented_default_library;
+use lib::library::augmented_library;
 use typst::diag::{FileError, FileResult};
-use typst::foundations::{Bytes, Datetime};
+use typst::foundations::{Bytes, Datetime, Dict};
 use typst::syntax::{FileId, Source};
 use typst::text::{Font, FontBook};
 use typst::utils::LazyHash;
@@ -26,6 +26,8 @@ pub struct SystemWorld {
     workdir: Option<PathBuf>,
     /// The root relative to which absolute paths are resolved.
     root: PathBuf,
+    /// The inputs available through `sys.inputs`.
+    inputs: Dict,
     /// Typst's standard library.
     library: LazyHash<Library>,
     /// Metadata about discovered fonts.
@@ -49,6 +51,7 @@ impl SystemWorld {
         fonts: Fonts,
         package_storage: PackageStorage,
         now: Option<DateTime<Utc>>,
+        inputs: Dict,
     ) -> io::Result<Self> {
         let now = match now {
             Some(time) => Now::Fixed(time),
@@ -58,7 +61,10 @@ impl SystemWorld {
         Ok(Self {
             workdir: std::env::current_dir().ok(),
             root,
-            library: LazyHash::new(augmented_default_library()),
+            library: LazyHash::new(augmented_library(|builder| {
+                builder.with_inputs(inputs.clone())
+            })),
+            inputs,
             book: LazyHash::new(fonts.book),
             fonts: fonts.fonts,
             slots: Mutex::new(HashMap::new()),
@@ -72,6 +78,19 @@ impl SystemWorld {
         &self.root
     }
 
+    /// The inputs available through `sys.inputs`.
+    pub fn inputs(&self) -> &Dict {
+        &self.inputs
+    }
+
+    /// The fixed date and time used for compilation, if it was given.
+    pub fn now(&self) -> Option<DateTime<Utc>> {
+        match self.now {
+            Now::Fixed(time) => Some(time),
+            Now::System(_) => None,
+        }
+    }
+
     /// The current working directory.
     pub fn workdir(&self) -> &Path {
         self.workdir.as_deref().unwrap_or(Path::new("."))
@@ -126,8 +145,11 @@ impl World for SystemWorld {
             Now::System(time) => time.get_or_init(Utc::now),
         };
 
-        // The time with the specified UTC offset, or within the local time zone.
+        // The time with the specified UTC offset, or within the local time
+        // zone. A fixed time is always interpreted as UTC to keep dates
+        // reproducible across machines.
         let with_offset = match offset {
+            None if matches!(self.now, Now::Fixed(_)) => now.fixed_offset(),
             None => now.with_timezone(&Local).fixed_offset(),
             Some(hours) => {
                 let seconds = i32::try_from(hours).ok()?.checked_mul(3600)?;
@@ -386,3 +408,43 @@ impl<'a> codespan_reporting::files::Files<'a> for SystemWorld {
         })
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use lib::doc::compile;
+    use typst_kit::download::Downloader;
+    use typst_kit::fonts::FontSearcher;
+
+    use super::*;
+
+    pub fn world(now: Option<DateTime<Utc>>) -> SystemWorld {
+        SystemWorld::new(
+            PathBuf::from("."),
+            FontSearcher::new().include_system_fonts(false).search(),
+            PackageStorage::new(None, None, Downloader::new("typst-test")),
+            now,
+            Dict::new(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_today_fixed() {
+        // 2009-02-13T23:31:30Z
+        let world = world(DateTime::from_timestamp(1234567890, 0));
+
+        assert_eq!(world.today(None), Datetime::from_ymd(2009, 2, 13));
+        assert_eq!(world.today(Some(0)), Datetime::from_ymd(2009, 2, 13));
+        assert_eq!(world.today(Some(1)), Datetime::from_ymd(2009, 2, 14));
+    }
+
+    #[test]
+    fn test_today_fixed_compile() {
+        let world = world(DateTime::from_timestamp(1234567890, 0));
+
+        let source = Source::detached(
+            "#assert.eq(datetime.today(), datetime(year: 2009, month: 2, day: 13))",
+        );
+        assert!(compile::compile(source, &world).output.is_ok());
+    }
+}